@@ -1,65 +1,447 @@
 use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection};
+use rusqlite::{named_params, params, Connection, OpenFlags, OptionalExtension};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
 
+/// Archive::open の挙動を指定するオプション
+#[derive(Debug, Clone, Copy)]
+pub struct OpenOptions {
+    /// 読み取り専用で開く
+    pub read_only: bool,
+    /// データベースファイルが存在しない場合に作成する
+    pub create_if_missing: bool,
+    /// スキーマを最新の状態に更新する
+    pub auto_migrate: bool,
+    /// ロックの解放を待つ時間
+    pub busy_timeout: Duration,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            create_if_missing: true,
+            auto_migrate: true,
+            busy_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// アーカイブを開く際のエラー
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// データベースファイルが存在しない
+    NotFound(PathBuf),
+    /// アーカイブのテーブルが作成されていない
+    Uninitialized(PathBuf),
+    /// データベースを作成するディレクトリを作成できなかった
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// 整合性チェックに失敗した
+    Corrupted {
+        path: PathBuf,
+        detail: String,
+    },
+    Sqlite(rusqlite::Error),
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::NotFound(path) => {
+                write!(
+                    f,
+                    "database not found at {}; run `init` first",
+                    path.display()
+                )
+            }
+            ArchiveError::Uninitialized(path) => {
+                write!(
+                    f,
+                    "database {} is not initialized; run `init` first",
+                    path.display()
+                )
+            }
+            ArchiveError::CreateDir { path, source } => {
+                write!(
+                    f,
+                    "failed to create directory {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            ArchiveError::Corrupted { path, detail } => {
+                write!(f, "database {} is corrupted: {}", path.display(), detail)
+            }
+            ArchiveError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ArchiveError::Sqlite(e) => Some(e),
+            ArchiveError::CreateDir { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ArchiveError {
+    fn from(e: rusqlite::Error) -> Self {
+        ArchiveError::Sqlite(e)
+    }
+}
+
+const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS archives (
+        name TEXT NOT NULL UNIQUE,
+        path TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        body TEXT NOT NULL,
+        checksum TEXT NOT NULL,
+        message TEXT,
+        user TEXT,
+        quarantined INTEGER NOT NULL DEFAULT 0,
+        cold_location TEXT,
+        cold_bytes INTEGER,
+        PRIMARY KEY (path, created_at)
+    );
+    CREATE INDEX IF NOT EXISTS archives_path_idx ON archives (path);
+    CREATE INDEX IF NOT EXISTS archives_created_at_idx ON archives (created_at);
+    CREATE TABLE IF NOT EXISTS tags (
+        name TEXT NOT NULL REFERENCES archives (name) ON UPDATE CASCADE ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (name, tag)
+    );
+    CREATE INDEX IF NOT EXISTS tags_tag_idx ON tags (tag);
+"#;
+
+/// EntryFilter で絞り込む条件
+/// 名前付きパラメーター `:tag` と `:user`、`:since` と `:until` に EntryFilter の値を渡す
+const ENTRY_FILTER: &str = "(:tag IS NULL OR EXISTS (SELECT 1 FROM tags WHERE tags.name = archives.name AND tags.tag = :tag)) \
+     AND (:user IS NULL OR archives.user IS NULL OR archives.user = :user) \
+     AND (:since IS NULL OR archives.created_at >= :since) \
+     AND (:until IS NULL OR archives.created_at <= :until)";
+
+/// アーカイブの一覧を取得するメソッドに共通の絞り込み条件
+///
+/// 一覧を返すメソッドはすべてこの条件を受け取り、ENTRY_FILTER で絞り込む。
+/// 既定値はすべてのアーカイブを対象にする。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryFilter {
+    /// このタグが付いたアーカイブに限る
+    pub tag: Option<String>,
+    /// このユーザーが登録したアーカイブに限る
+    /// 登録したユーザーが記録されていないアーカイブ (記録を始める前のもの) は常に含める
+    pub user: Option<String>,
+    /// この日時以降に登録されたアーカイブに限る
+    pub since: Option<DateTime<Utc>>,
+    /// この日時以前に登録されたアーカイブに限る
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl EntryFilter {
+    /// ENTRY_FILTER の `:since` に渡す値
+    /// created_at は RFC3339 の文字列として保存しているため、同じ形式の文字列で比較する
+    fn since_param(&self) -> Option<String> {
+        self.since.map(|since| since.to_rfc3339())
+    }
+
+    /// ENTRY_FILTER の `:until` に渡す値
+    fn until_param(&self) -> Option<String> {
+        self.until.map(|until| until.to_rfc3339())
+    }
+}
+
+/// 一覧を並べる列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    Path,
+    #[value(name = "created_at")]
+    CreatedAt,
+    Name,
+}
+
+/// 一覧の並び順
+/// 既定値は作成日時の新しい順
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    /// 降順に並べる
+    pub desc: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            key: SortKey::CreatedAt,
+            desc: true,
+        }
+    }
+}
+
+impl Sort {
+    /// パスの昇順 同じパスのものは新しい順に並べる
+    pub const PATH: Self = Self {
+        key: SortKey::Path,
+        desc: false,
+    };
+
+    /// ORDER BY に置く句
+    /// 列と向きは固定の文字列から選び、利用者の入力を SQL に埋め込まない
+    /// key の値が同じものは ENTRY_ORDER と同様に、実行ごとに順序が変わらないよう並べる
+    fn order_clause(&self) -> String {
+        let direction = if self.desc { "DESC" } else { "ASC" };
+        match self.key {
+            SortKey::Path => format!("path {}, {}", direction, ENTRY_ORDER),
+            SortKey::CreatedAt => format!("created_at {0}, name {0}", direction),
+            SortKey::Name => format!("name {}, created_at DESC", direction),
+        }
+    }
+}
+
+/// 一覧のうち取得する範囲と並び順
+/// sort の順で offset 件を飛ばし、その後の最大 limit 件を取得する
+/// 既定値はすべてを作成日時の新しい順に取得する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Page {
+    pub limit: Option<NonZeroUsize>,
+    pub offset: usize,
+    pub sort: Sort,
+}
+
+/// Page で範囲を絞る句
+/// 名前付きパラメーター `:limit` と `:offset` に Page の値を渡す
+const PAGE_CLAUSE: &str = "LIMIT :limit OFFSET :offset";
+
+impl Page {
+    /// PAGE_CLAUSE の `:limit` に渡す値
+    /// SQLite は負の LIMIT を上限なしとして扱う
+    fn limit_param(&self) -> i64 {
+        self.limit.map_or(-1, |limit| limit.get() as i64)
+    }
+
+    /// PAGE_CLAUSE の `:offset` に渡す値
+    fn offset_param(&self) -> i64 {
+        self.offset as i64
+    }
+
+    /// 同じ並び順ですべてを取得する範囲
+    /// データベースから読み出してから絞り込む場合に、絞り込む前の一覧を取得するために使う
+    fn unbounded(&self) -> Page {
+        Page {
+            sort: self.sort,
+            ..Page::default()
+        }
+    }
+
+    /// データベースから読み出してから絞り込んだ一覧に、PAGE_CLAUSE と同じ範囲を適用する
+    fn apply<T>(&self, entries: Vec<T>) -> Vec<T> {
+        let limit = self.limit.map_or(usize::MAX, NonZeroUsize::get);
+        entries.into_iter().skip(self.offset).take(limit).collect()
+    }
+}
+
+/// アーカイブを並べる順序
+/// 同じ日時に登録されたアーカイブでも実行ごとに順序が変わらないよう、名前を比較して順序を決める
+const ENTRY_ORDER: &str = "created_at DESC, name DESC";
+
+/// .env ファイルのアーカイブ
+///
+/// アーカイブを返すメソッドは、特に断りがない限り作成日時の新しい順に並べ、
+/// 同じ日時のものは名前の降順に並べる (ULID の名前は作成順に並ぶ)
 pub struct Archive {
     database_path: PathBuf,
+    timeout: Option<Duration>,
+    options: OpenOptions,
+    /// 登録するアーカイブに記録するユーザー
+    user: Option<String>,
 }
 
 impl Archive {
+    #[allow(dead_code)]
+    #[deprecated(note = "use Archive::open to validate the database upfront")]
     pub fn new(database_path: PathBuf) -> Self {
-        Self { database_path }
-    }
-
-    /// データベースを初期化する
-    pub async fn initialize(&self) -> anyhow::Result<()> {
-        let query = r#"
-            CREATE TABLE IF NOT EXISTS archives (
-                name TEXT NOT NULL UNIQUE,
-                path TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                body TEXT NOT NULL,
-                checksum TEXT NOT NULL,
-                PRIMARY KEY (path, created_at)
-            );
-            CREATE INDEX IF NOT EXISTS archives_path_idx ON archives (path);
-            CREATE INDEX IF NOT EXISTS archives_created_at_idx ON archives (created_at);
-        "#;
-        let conn = Connection::open(&self.database_path)?;
-        conn.execute_batch(query)?;
+        Self {
+            database_path,
+            timeout: None,
+            options: OpenOptions::default(),
+            user: None,
+        }
+    }
 
-        Ok(())
+    /// データベースを開き、必要に応じて作成・スキーマの更新を行った上で整合性を確認する
+    pub fn open(database_path: PathBuf, options: OpenOptions) -> Result<Self, ArchiveError> {
+        if !database_path.exists() {
+            if options.read_only || !options.create_if_missing {
+                return Err(ArchiveError::NotFound(database_path));
+            }
+            // SQLite はファイルを作成できても、ないディレクトリは作成しない
+            if let Some(dir) = database_path.parent() {
+                std::fs::create_dir_all(dir).map_err(|source| ArchiveError::CreateDir {
+                    path: dir.to_path_buf(),
+                    source,
+                })?;
+            }
+        }
+        let archive = Self {
+            database_path,
+            timeout: None,
+            options,
+            user: None,
+        };
+        let conn = Self::open_connection(&archive.database_path, options)?;
+        if options.auto_migrate && !options.read_only {
+            migrate(&conn)?;
+        }
+        archive.health_check(&conn)?;
+        Ok(archive)
     }
 
-    /// env_file_path の内容が、最新のアーカイブと同じかどうかをチェックする
-    pub async fn check_is_same_as_latest(&self, env_file_path: &Path) -> anyhow::Result<bool> {
-        let checksum = crate::digest::file_checksum(env_file_path).await?;
-        let conn = Connection::open(&self.database_path)?;
-        let mut stmt = conn.prepare(
-            "SELECT checksum FROM archives WHERE path = ?1 ORDER BY created_at DESC LIMIT 1",
-        )?;
-        let rows = stmt.query_map([env_file_path.to_string_lossy()], |row| {
-            row.get::<_, String>(0)
-        })?;
+    /// データベース操作のタイムアウトを設定する
+    /// タイムアウトするとクエリが中断され、is_timeout が true になるエラーを返す
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-        if let Some(row) = rows.into_iter().next() {
-            let row = row?;
-            return Ok(row == checksum);
-        }
-        Ok(false)
+    /// 登録するアーカイブに、登録したユーザーとして user を記録する
+    pub fn with_user(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
     }
 
-    /// env_file_path の内容が、name で指定したアーカイブと同じかどうかをチェックする
-    pub async fn check_is_same_by_name(
+    /// 登録するアーカイブの ArchiveEntry を作る
+    fn new_entry(
         &self,
+        path: &Path,
+        body: &str,
+        now: DateTime<Utc>,
         name: &str,
-        env_file_path: &Path,
-    ) -> anyhow::Result<bool> {
-        let checksum = crate::digest::file_checksum(env_file_path).await?;
-        let conn = Connection::open(&self.database_path)?;
-        let mut stmt = conn.prepare(
-            "SELECT checksum FROM archives WHERE name = ?1 ORDER BY created_at DESC LIMIT 1",
+        checksum: String,
+        message: Option<&str>,
+    ) -> ArchiveEntry {
+        ArchiveEntry {
+            name: name.to_string(),
+            path: path.to_string_lossy().to_string(),
+            created_at: now,
+            checksum,
+            message: message.map(str::to_string),
+            user: self.user.clone(),
+            quarantined: false,
+            size: body.len() as u64,
+        }
+    }
+
+    fn open_connection(database_path: &Path, options: OpenOptions) -> rusqlite::Result<Connection> {
+        let mut flags = OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        if options.read_only {
+            flags |= OpenFlags::SQLITE_OPEN_READ_ONLY;
+        } else {
+            flags |= OpenFlags::SQLITE_OPEN_READ_WRITE;
+            if options.create_if_missing {
+                flags |= OpenFlags::SQLITE_OPEN_CREATE;
+            }
+        }
+        let conn = Connection::open_with_flags(database_path, flags)?;
+        conn.busy_timeout(options.busy_timeout)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        Ok(conn)
+    }
+
+    /// 壊れたファイルや初期化されていないデータベースを開いた直後に検出する
+    fn health_check(&self, conn: &Connection) -> Result<(), ArchiveError> {
+        let result: String = conn.query_row("PRAGMA quick_check(1)", [], |row| row.get(0))?;
+        if result != "ok" {
+            return Err(ArchiveError::Corrupted {
+                path: self.database_path.clone(),
+                detail: result,
+            });
+        }
+        if !has_archives_table(conn)? {
+            return Err(ArchiveError::Uninitialized(self.database_path.clone()));
+        }
+        Ok(())
+    }
+
+    /// database_path のデータベースが存在し、アーカイブのテーブルが作成されているかどうか
+    /// 読み取り専用で開くため、データベースがなくても作成しない
+    pub fn is_initialized(database_path: &Path) -> Result<bool, ArchiveError> {
+        if !database_path.exists() {
+            return Ok(false);
+        }
+        let conn = Connection::open_with_flags(
+            database_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
         )?;
+        Ok(has_archives_table(&conn)?)
+    }
+
+    /// データベースに接続する
+    /// タイムアウトが設定されている場合は、接続を開く処理と、開いた接続を使い終わるまでを監視する
+    fn connect(&self) -> anyhow::Result<WatchedConnection> {
+        let Some(timeout) = self.timeout else {
+            return Ok(WatchedConnection {
+                conn: Self::open_connection(&self.database_path, self.options)?,
+                watchdog: None,
+            });
+        };
+        // ネットワーク上のファイルシステムなどで開く処理が止まっても待ち続けないよう、別のスレッドで開く
+        let (sender, receiver) = mpsc::channel();
+        let database_path = self.database_path.clone();
+        let options = self.options;
+        std::thread::spawn(move || {
+            let _ = sender.send(Self::open_connection(&database_path, options));
+        });
+        let conn = match receiver.recv_timeout(timeout) {
+            Ok(conn) => conn?,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Err(interrupted("timed out opening the database").into())
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                anyhow::bail!("failed to open {}", self.database_path.display())
+            }
+        };
+        let watchdog = Watchdog::start(conn.get_interrupt_handle(), timeout);
+        Ok(WatchedConnection {
+            conn,
+            watchdog: Some(watchdog),
+        })
+    }
+
+    /// path の最新のアーカイブのチェックサム
+    /// 隔離されているアーカイブは内容が信用できないため比べる対象にしない
+    /// path のアーカイブがなければ None を返す
+    pub fn latest_checksum(&self, path: &Path) -> anyhow::Result<Option<String>> {
+        let conn = self.connect()?;
+        let checksum = conn
+            .query_row(
+                &format!(
+                    "SELECT checksum FROM archives WHERE path = ?1 AND NOT quarantined ORDER BY {} LIMIT 1",
+                    ENTRY_ORDER
+                ),
+                [path.to_string_lossy()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(checksum)
+    }
+
+    /// 読み込み済みの内容 body が、name で指定したアーカイブと同じかどうかをチェックする
+    pub async fn check_is_same_by_name(&self, name: &str, body: &[u8]) -> anyhow::Result<bool> {
+        let checksum = crate::digest::bytes_checksum(body);
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT checksum FROM archives WHERE name = ?1 ORDER BY {} LIMIT 1",
+            ENTRY_ORDER
+        ))?;
         let rows = stmt.query_map([name], |row| row.get::<_, String>(0))?;
 
         if let Some(row) = rows.into_iter().next() {
@@ -70,188 +452,1549 @@ impl Archive {
     }
 
     /// env_file_path の内容を、パスと時刻と共にアーカイブに登録する
+    /// message を指定するとアーカイブの説明として保存する
+    /// ファイルは 1 度だけ読み込み、チェックサムは読み込んだ本文から計算する
     pub async fn push(
         &self,
         env_file_path: &Path,
         now: DateTime<Utc>,
         name: &str,
+        message: Option<&str>,
     ) -> anyhow::Result<()> {
         let body = tokio::fs::read_to_string(env_file_path).await?;
-        let checksum = crate::digest::file_checksum(env_file_path).await?;
+        self.push_body(env_file_path, &body, now, name, message)
+            .await
+    }
+
+    /// エクスポートしたアーカイブを、名前や作成日時を含めてそのまま登録する
+    /// 同じ名前のアーカイブが既にある場合、overwrite ならタグごと置き換え、そうでなければ登録しない
+    pub async fn restore(
+        &self,
+        entry: &ArchiveEntry,
+        body: &str,
+        tags: &[String],
+        overwrite: bool,
+    ) -> anyhow::Result<Restored> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        let restored = if !exists(&tx, &entry.name)? {
+            Restored::Inserted
+        } else if overwrite {
+            tx.execute("DELETE FROM tags WHERE name = ?1", [&entry.name])?;
+            tx.execute("DELETE FROM archives WHERE name = ?1", [&entry.name])?;
+            Restored::Replaced
+        } else {
+            return Ok(Restored::Skipped);
+        };
+        insert(&tx, entry, body)?;
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (name, tag) VALUES (?1, ?2)",
+                params![entry.name, tag],
+            )?;
+        }
+        tx.commit()?;
+        Ok(restored)
+    }
+
+    /// ファイルを介さずに body を path の内容としてアーカイブに登録する
+    pub async fn push_body(
+        &self,
+        path: &Path,
+        body: &str,
+        now: DateTime<Utc>,
+        name: &str,
+        message: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let checksum = crate::digest::bytes_checksum(body.as_bytes());
 
-        let conn = Connection::open(&self.database_path)?;
+        let conn = self.connect()?;
+        insert_new(
+            &conn,
+            &self.new_entry(path, body, now, name, checksum, message),
+            body,
+        )?;
+
+        Ok(())
+    }
+
+    /// push_body と同様に登録するが、name のアーカイブが既にある場合はその内容を置き換える
+    /// 置き換えたアーカイブのタグは残し、隔離は解除する
+    pub async fn push_body_overwrite(
+        &self,
+        path: &Path,
+        body: &str,
+        now: DateTime<Utc>,
+        name: &str,
+        message: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let checksum = crate::digest::bytes_checksum(body.as_bytes());
+        let entry = self.new_entry(path, body, now, name, checksum, message);
+
+        let conn = self.connect()?;
         conn.execute(
             r#"
-            INSERT INTO archives (name, path, created_at, body, checksum)
-            VALUES (?1, ?2, ?3, ?4, ?5)
-        "#,
+                INSERT INTO archives (name, path, created_at, body, checksum, message, user, quarantined)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ON CONFLICT (name) DO UPDATE SET
+                    path = excluded.path,
+                    created_at = excluded.created_at,
+                    body = excluded.body,
+                    checksum = excluded.checksum,
+                    message = excluded.message,
+                    user = excluded.user,
+                    quarantined = excluded.quarantined,
+                    cold_location = NULL,
+                    cold_bytes = NULL
+            "#,
             params![
-                name,
-                env_file_path.to_string_lossy(),
-                now.to_rfc3339(),
+                entry.name,
+                entry.path,
+                entry.created_at.to_rfc3339(),
                 body,
-                checksum,
+                entry.checksum,
+                entry.message,
+                entry.user,
+                entry.quarantined,
             ],
         )?;
 
         Ok(())
     }
 
-    pub async fn list_all(&self) -> anyhow::Result<Vec<ArchiveEntry>> {
-        let conn = Connection::open(&self.database_path)?;
-        let mut stmt = conn.prepare("SELECT name, path, created_at, checksum FROM archives")?;
+    /// 複数のファイルの内容を 1 つのトランザクションでまとめて登録する
+    /// いずれかの登録に失敗した場合は、どのファイルも登録しない
+    pub fn push_many(&self, requests: Vec<PushRequest>) -> anyhow::Result<()> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        for request in requests {
+            let entry = self.new_entry(
+                &request.path,
+                &request.body,
+                request.created_at,
+                &request.name,
+                request.checksum,
+                None,
+            );
+            insert(&tx, &entry, &request.body).map_err(|e| {
+                anyhow::Error::from(e).context(format!(
+                    "failed to push {} as {}, no files were pushed",
+                    request.path.display(),
+                    request.name
+                ))
+            })?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// push_body と同様に登録するが、name が既に使われている場合は `-2`, `-3`, ... と
+    /// 連番を付けた名前で登録し直す
+    /// 実際に登録した名前を返す
+    pub async fn push_body_with_rename(
+        &self,
+        path: &Path,
+        body: &str,
+        now: DateTime<Utc>,
+        name: &str,
+        message: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let checksum = crate::digest::bytes_checksum(body.as_bytes());
+
+        // 事前に名前の存在を確認すると並行して push された場合に競合するため、
+        // 制約違反を捕まえて次の名前で登録し直す
+        let conn = self.connect()?;
+        let mut entry = self.new_entry(path, body, now, name, checksum, message);
+        for n in 1..=MAX_RENAME_ATTEMPTS {
+            if n > 1 {
+                entry.name = format!("{}-{}", name, n);
+            }
+            match insert(&conn, &entry, body) {
+                Ok(()) => return Ok(entry.name),
+                Err(e) if is_name_conflict(&e) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        anyhow::bail!(
+            "name {} and its suffixed names up to -{} are already in use",
+            name,
+            MAX_RENAME_ATTEMPTS
+        )
+    }
+
+    /// アーカイブの名前を変更し、変更後のアーカイブを返す
+    pub async fn rename(&self, old_name: &str, new_name: &str) -> anyhow::Result<ArchiveEntry> {
+        {
+            let conn = self.connect()?;
+            match conn.execute(
+                "UPDATE archives SET name = ?2 WHERE name = ?1",
+                params![old_name, new_name],
+            ) {
+                Ok(0) => anyhow::bail!("archive {} not found", old_name),
+                Ok(_) => {}
+                Err(e) if is_name_conflict(&e) => {
+                    anyhow::bail!("archive name {} is already in use", new_name)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.entry(new_name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("archive {} not found", new_name))
+    }
+
+    /// アーカイブを隔離する、または隔離を解除する
+    pub async fn set_quarantined(&self, name: &str, quarantined: bool) -> anyhow::Result<()> {
+        let conn = self.connect()?;
+        let updated = conn.execute(
+            "UPDATE archives SET quarantined = ?2 WHERE name = ?1",
+            params![name, quarantined],
+        )?;
+        if updated == 0 {
+            anyhow::bail!("archive {} not found", name);
+        }
+        Ok(())
+    }
+
+    /// アーカイブにタグを付ける
+    /// 既に付いているタグは無視する
+    pub async fn add_tags(&self, name: &str, tags: &[String]) -> anyhow::Result<()> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        if !exists(&tx, name)? {
+            anyhow::bail!("archive {} not found", name);
+        }
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (name, tag) VALUES (?1, ?2)",
+                params![name, tag],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// アーカイブからタグを外す
+    pub async fn remove_tag(&self, name: &str, tag: &str) -> anyhow::Result<()> {
+        let conn = self.connect()?;
+        if !exists(&conn, name)? {
+            anyhow::bail!("archive {} not found", name);
+        }
+        let removed = conn.execute(
+            "DELETE FROM tags WHERE name = ?1 AND tag = ?2",
+            params![name, tag],
+        )?;
+        if removed == 0 {
+            anyhow::bail!("archive {} is not tagged with {}", name, tag);
+        }
+        Ok(())
+    }
+
+    /// アーカイブに付いているタグを名前順に取得する
+    pub async fn tags(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare("SELECT tag FROM tags WHERE name = ?1 ORDER BY tag")?;
+        let tags = stmt
+            .query_map([name], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(tags)
+    }
+
+    /// すべてのアーカイブのタグを、アーカイブの名前ごとにタグの名前順で取得する
+    pub async fn tags_by_name(&self) -> anyhow::Result<HashMap<String, Vec<String>>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare("SELECT name, tag FROM tags ORDER BY name, tag")?;
         let rows = stmt.query_map([], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-            ))
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
         })?;
-
-        let mut archives = Vec::new();
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
         for row in rows {
-            let (name, path, created_at, checksum) = row?;
-            archives.push(ArchiveEntry {
-                name,
-                path,
-                created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
-                checksum,
-            });
+            let (name, tag) = row?;
+            tags.entry(name).or_default().push(tag);
         }
+        Ok(tags)
+    }
 
+    /// filter に一致するアーカイブをすべて取得する
+    /// パスの昇順に並べ、同じパスのものは新しい順に並べる
+    /// この順序はデータベースの VACUUM や import の後も変わらない
+    /// コマンドは list_all_paged を使い、テストで全件を確かめるためだけに使う
+    #[cfg(test)]
+    pub async fn list_all(&self, filter: &EntryFilter) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let page = Page {
+            sort: Sort::PATH,
+            ..Page::default()
+        };
+        self.list_all_paged(filter, &page).await
+    }
+
+    /// filter に一致するアーカイブのうち page の範囲を取得する
+    pub async fn list_all_paged(
+        &self,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM archives WHERE {} ORDER BY {} {}",
+            ENTRY_COLUMNS,
+            ENTRY_FILTER,
+            page.sort.order_clause(),
+            PAGE_CLAUSE
+        ))?;
+        let params = named_params! {
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+            ":limit": page.limit_param(),
+            ":offset": page.offset_param(),
+        };
+        let archives = stmt
+            .query_map(params, entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
         Ok(archives)
     }
 
-    #[allow(dead_code)]
-    pub async fn list_in_path(&self, path: &Path) -> anyhow::Result<Vec<ArchiveEntry>> {
-        let conn = Connection::open(&self.database_path)?;
-        let mut stmt = conn
-            .prepare("SELECT name, path, created_at, checksum FROM archives WHERE path LIKE ?1")?;
-        let rows = stmt.query_map([format!("{}%", path.to_string_lossy())], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-            ))
-        })?;
+    /// path 配下のアーカイブのうち filter に一致するものから page の範囲を取得する
+    /// パスの大文字と小文字は区別する
+    /// page.sort の値が同じものも常に同じ順に並ぶため、範囲を変えて取得しても重複や抜けはない
+    pub async fn list_in_path(
+        &self,
+        path: &Path,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM archives WHERE substr(path, 1, length(:path)) = :path AND {} ORDER BY {} {}",
+            ENTRY_COLUMNS,
+            ENTRY_FILTER,
+            page.sort.order_clause(),
+            PAGE_CLAUSE
+        ))?;
+        let params = named_params! {
+            ":path": path.to_string_lossy(),
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+            ":limit": page.limit_param(),
+            ":offset": page.offset_param(),
+        };
+        let archives = stmt
+            .query_map(params, entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(archives)
+    }
 
-        let mut archives = Vec::new();
-        for row in rows {
-            let (name, path, created_at, checksum) = row?;
-            archives.push(ArchiveEntry {
-                name,
-                path,
-                created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
-                checksum,
-            });
-        }
+    /// list_in_path と同じものをパスの大文字と小文字を区別せずに取得する
+    /// SQLite の LOWER は ASCII 以外の文字を変換しないため、filter に一致するものをすべて読み出してから絞り込む
+    pub async fn list_in_path_ignore_case(
+        &self,
+        path: &Path,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let prefix = path.to_string_lossy().to_lowercase();
+        let archives = self.list_all_paged(filter, &page.unbounded()).await?;
+        Ok(page.apply(
+            archives
+                .into_iter()
+                .filter(|entry| entry.path.to_lowercase().starts_with(&prefix))
+                .collect(),
+        ))
+    }
+
+    /// path 配下のパスごとに最新のアーカイブを、そのパスのアーカイブの件数と共に取得する
+    /// list_latest_per_path と同様に、隔離されているアーカイブは除き、その前のアーカイブを最新とする
+    /// page はパスごとにまとめた一覧に適用する
+    pub async fn list_latest_in_path(
+        &self,
+        path: &Path,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<LatestEntry>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            r#"
+            SELECT {names}, versions FROM (
+                SELECT {columns},
+                    ROW_NUMBER() OVER (PARTITION BY path ORDER BY {order}) AS rank,
+                    COUNT(*) OVER (PARTITION BY path) AS versions
+                FROM archives
+                WHERE substr(path, 1, length(:path)) = :path AND NOT quarantined AND {filter}
+            )
+            WHERE rank = 1
+            ORDER BY {sort}
+            {page}
+            "#,
+            columns = ENTRY_COLUMNS,
+            names = ENTRY_COLUMN_NAMES,
+            order = ENTRY_ORDER,
+            filter = ENTRY_FILTER,
+            sort = page.sort.order_clause(),
+            page = PAGE_CLAUSE
+        ))?;
+        let params = named_params! {
+            ":path": path.to_string_lossy(),
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+            ":limit": page.limit_param(),
+            ":offset": page.offset_param(),
+        };
+        let archives = stmt
+            .query_map(params, |row| {
+                Ok(LatestEntry {
+                    entry: entry_from_row(row)?,
+                    versions: row.get(ENTRY_COLUMN_COUNT)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(archives)
+    }
 
+    /// list_latest_in_path と同じものをパスの大文字と小文字を区別せずに取得する
+    /// list_in_path_ignore_case と同様に、すべてのパスの最新のものを読み出してから絞り込む
+    pub async fn list_latest_in_path_ignore_case(
+        &self,
+        path: &Path,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<LatestEntry>> {
+        let prefix = path.to_string_lossy().to_lowercase();
+        let archives = self
+            .list_latest_in_path(Path::new(""), filter, &page.unbounded())
+            .await?;
+        Ok(page.apply(
+            archives
+                .into_iter()
+                .filter(|latest| latest.entry.path.to_lowercase().starts_with(&prefix))
+                .collect(),
+        ))
+    }
+
+    /// filter に一致するアーカイブのうちパスごとに最新のものを 1 件ずつ、パスの順に取得する
+    /// prefix を指定した場合はその配下のパスに限る パスの大文字と小文字は区別する
+    /// 隔離されているアーカイブは除き、その前のアーカイブを最新とする
+    pub async fn list_latest_per_path(
+        &self,
+        prefix: Option<&Path>,
+        filter: &EntryFilter,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            r#"
+            SELECT {names} FROM (
+                SELECT {columns},
+                    ROW_NUMBER() OVER (PARTITION BY path ORDER BY {order}) AS rank
+                FROM archives
+                WHERE substr(path, 1, length(:path)) = :path AND NOT quarantined AND {filter}
+            )
+            WHERE rank = 1
+            ORDER BY path
+            "#,
+            columns = ENTRY_COLUMNS,
+            names = ENTRY_COLUMN_NAMES,
+            order = ENTRY_ORDER,
+            filter = ENTRY_FILTER
+        ))?;
+        let prefix = prefix.map(Path::to_string_lossy).unwrap_or_default();
+        let params = named_params! {
+            ":path": prefix,
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let archives = stmt
+            .query_map(params, entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
         Ok(archives)
     }
 
-    #[allow(dead_code)]
-    pub async fn find_by_path(&self, path: &Path) -> anyhow::Result<Vec<ArchiveEntry>> {
-        let conn = Connection::open(&self.database_path)?;
-        let mut stmt = conn.prepare(
-            "SELECT name, path, created_at, body, checksum FROM archives WHERE path = ?1 ORDER BY created_at DESC",
-        )?;
-        let rows = stmt.query_map([path.to_string_lossy()], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-            ))
-        })?;
+    /// path に一致するアーカイブのうち filter に一致するものを新しい順に取得する
+    pub async fn find_by_path(
+        &self,
+        path: &Path,
+        filter: &EntryFilter,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM archives WHERE path = :path AND {} ORDER BY {}",
+            ENTRY_COLUMNS, ENTRY_FILTER, ENTRY_ORDER
+        ))?;
+        let params = named_params! {
+            ":path": path.to_string_lossy(),
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let archives = stmt
+            .query_map(params, entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(archives)
+    }
 
-        let mut archives = Vec::new();
-        for row in rows {
-            let row = row?;
-            archives.push(ArchiveEntry {
-                name: row.0,
-                path: row.1,
-                created_at: DateTime::parse_from_rfc3339(&row.2)?.with_timezone(&Utc),
-                checksum: row.3,
-            });
-        }
+    /// 名前が prefix で始まるアーカイブを名前の順に取得する
+    /// シェルの補完でアーカイブの名前を候補にするために使う
+    pub async fn find_by_name_prefix(&self, prefix: &str) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM archives WHERE substr(name, 1, length(:prefix)) = :prefix ORDER BY name",
+            ENTRY_COLUMNS
+        ))?;
+        let archives = stmt
+            .query_map(named_params! { ":prefix": prefix }, entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
         Ok(archives)
     }
 
-    /// name に一致するアーカイブを取得する
+    /// path に一致するアーカイブのうち filter に一致するものを本文のバイト数と共に新しい順に取得する
+    /// limit が None の場合は全件を返す
+    pub async fn history(
+        &self,
+        path: &Path,
+        limit: Option<usize>,
+        filter: &EntryFilter,
+    ) -> anyhow::Result<Vec<(ArchiveEntry, u64)>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, {} FROM archives WHERE path = :path AND {} ORDER BY {} LIMIT :limit",
+            ENTRY_COLUMNS, BODY_BYTES, ENTRY_FILTER, ENTRY_ORDER
+        ))?;
+        // SQLite では LIMIT に負数を渡すと無制限になる
+        let limit = limit.map_or(-1, |limit| limit as i64);
+        let params = named_params! {
+            ":path": path.to_string_lossy(),
+            ":limit": limit,
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let history = stmt
+            .query_map(params, |row| {
+                Ok((entry_from_row(row)?, row.get::<_, u64>(ENTRY_COLUMN_COUNT)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(history)
+    }
+
+    /// path と filter に一致するアーカイブのうち、作成日時が cutoff より前で最新のものを取得する
+    /// 隔離されているアーカイブは除く
+    pub async fn find_by_path_before(
+        &self,
+        path: &Path,
+        cutoff: DateTime<Utc>,
+        filter: &EntryFilter,
+    ) -> anyhow::Result<Option<ArchiveEntry>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM archives WHERE path = :path AND created_at < :cutoff AND NOT quarantined AND {} ORDER BY {} LIMIT 1",
+            ENTRY_COLUMNS, ENTRY_FILTER, ENTRY_ORDER
+        ))?;
+        let params = named_params! {
+            ":path": path.to_string_lossy(),
+            ":cutoff": cutoff.to_rfc3339(),
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let entry = stmt.query_row(params, entry_from_row).optional()?;
+        Ok(entry)
+    }
+
+    /// name に一致するアーカイブを本文と共に取得する
+    /// 本文がコールドストレージにあり読み出せない場合は cold::ColdBodyUnavailable のエラーを返す
     pub async fn get(&self, name: &str) -> anyhow::Result<Option<(ArchiveEntry, String)>> {
-        let conn = Connection::open(&self.database_path)?;
-        let mut stmt = conn.prepare(
-            "SELECT name, path, created_at, body, checksum FROM archives WHERE name = ?1 ORDER BY created_at DESC",
-        )?;
-        let rows = stmt.query_map([name], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-                row.get::<_, String>(4)?,
-            ))
-        })?;
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, {} FROM archives WHERE name = ?1 ORDER BY {}",
+            ENTRY_COLUMNS, BODY_COLUMNS, ENTRY_ORDER
+        ))?;
+        let archive = stmt.query_row([name], entry_and_body_from_row).optional()?;
+        archive.map(StoredBody::load).transpose()
+    }
 
-        let mut archives = Vec::new();
-        for row in rows {
-            let row = row?;
-            archives.push((
-                ArchiveEntry {
-                    name: row.0,
-                    path: row.1,
-                    created_at: DateTime::parse_from_rfc3339(&row.2)?.with_timezone(&Utc),
-                    checksum: row.4,
-                },
-                row.3,
-            ));
-        }
-        Ok(archives.into_iter().next())
+    /// name に一致するアーカイブを本文なしで取得する
+    /// 本文がコールドストレージにあっても読み出さない
+    pub async fn entry(&self, name: &str) -> anyhow::Result<Option<ArchiveEntry>> {
+        let conn = self.connect()?;
+        let entry = conn
+            .query_row(
+                &format!("SELECT {} FROM archives WHERE name = ?1", ENTRY_COLUMNS),
+                [name],
+                entry_from_row,
+            )
+            .optional()?;
+        Ok(entry)
     }
 
-    /// ファイルパスに keyword が部分一致するアーカイブを取得する
-    pub async fn search(&self, keyword: &str) -> anyhow::Result<Vec<ArchiveEntry>> {
-        let conn = Connection::open(&self.database_path)?;
-        let mut stmt = conn.prepare(
-            "SELECT name, path, created_at, checksum FROM archives WHERE path LIKE ?1 ORDER BY path, created_at DESC",
+    /// filter に一致するアーカイブを集計する
+    /// top_paths にはアーカイブの件数が多い順に top 件までのパスを入れる
+    pub async fn summary(&self, top: usize, filter: &EntryFilter) -> anyhow::Result<Summary> {
+        let conn = self.connect()?;
+        let params = named_params! {
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let (entries, paths, bytes, oldest, newest) = conn.query_row(
+            &format!(
+                r#"
+                SELECT
+                    COUNT(*),
+                    COUNT(DISTINCT path),
+                    COALESCE(SUM({}), 0),
+                    MIN(created_at),
+                    MAX(created_at)
+                FROM archives
+                WHERE {}
+                "#,
+                BODY_BYTES, ENTRY_FILTER
+            ),
+            params,
+            |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            },
         )?;
-        let rows = stmt.query_map([format!("%{}%", keyword)], |row| {
+        let parse = |created_at: Option<String>| {
+            created_at
+                .map(|created_at| {
+                    DateTime::parse_from_rfc3339(&created_at).map(|t| t.with_timezone(&Utc))
+                })
+                .transpose()
+        };
+
+        let mut stmt = conn.prepare(&format!(
+            r#"
+            SELECT path, COUNT(*) AS versions
+            FROM archives
+            WHERE {}
+            GROUP BY path
+            ORDER BY versions DESC, path
+            LIMIT :top
+            "#,
+            ENTRY_FILTER
+        ))?;
+        let params = named_params! {
+            ":top": top as i64,
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let top_paths = stmt
+            .query_map(params, |row| {
+                Ok(PathVersions {
+                    path: row.get(0)?,
+                    versions: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Summary {
+            entries,
+            paths,
+            bytes,
+            oldest: parse(oldest)?,
+            newest: parse(newest)?,
+            top_paths,
+        })
+    }
+
+    /// 登録件数と本文のバイト数を 15 分単位の時間帯ごとに集計する
+    /// 15 分単位にしておくと、どのタイムゾーンの日や月の境界にも揃えて再集計できる
+    pub async fn growth_slots(&self, filter: &EntryFilter) -> anyhow::Result<Vec<GrowthSlot>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            r#"
+            SELECT
+                strftime('%Y-%m-%dT%H:', created_at)
+                    || printf('%02d', CAST(strftime('%M', created_at) AS INTEGER) / 15 * 15) AS slot,
+                COUNT(*),
+                SUM({})
+            FROM archives
+            WHERE {}
+            GROUP BY slot
+            ORDER BY slot
+            "#,
+            BODY_BYTES, ENTRY_FILTER
+        ))?;
+        let params = named_params! {
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let rows = stmt.query_map(params, |row| {
             Ok((
                 row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
             ))
         })?;
 
-        let mut archives = Vec::new();
+        let mut slots = Vec::new();
         for row in rows {
-            let row = row?;
-            archives.push(ArchiveEntry {
-                name: row.0,
-                path: row.1,
-                created_at: DateTime::parse_from_rfc3339(&row.2)?.with_timezone(&Utc),
-                checksum: row.3,
+            let (slot, entries, bytes) = row?;
+            slots.push(GrowthSlot {
+                start: chrono::NaiveDateTime::parse_from_str(&slot, "%Y-%m-%dT%H:%M")?.and_utc(),
+                entries,
+                bytes,
             });
         }
-        Ok(archives)
+        Ok(slots)
     }
-}
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ArchiveEntry {
-    pub name: String,
-    pub path: String,
-    pub created_at: DateTime<Utc>,
-    pub checksum: String,
-}
+    /// filter に一致するアーカイブのパスを重複なしにパスの順で取得する
+    /// pattern は LIKE のパターンで、`\` でエスケープする
+    /// 一覧を少しずつ表示するため、offset 件を飛ばして limit 件までを返す
+    pub async fn list_paths(
+        &self,
+        pattern: &str,
+        offset: usize,
+        limit: usize,
+        filter: &EntryFilter,
+    ) -> anyhow::Result<Vec<String>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT DISTINCT path FROM archives WHERE path LIKE :pattern ESCAPE '\\' AND {} \
+             ORDER BY path LIMIT :limit OFFSET :offset",
+            ENTRY_FILTER
+        ))?;
+        let params = named_params! {
+            ":pattern": pattern,
+            ":limit": limit as i64,
+            ":offset": offset as i64,
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let paths = stmt
+            .query_map(params, |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(paths)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+    /// ファイルパスに keyword が部分一致するアーカイブのうち filter に一致するものを新しい順に取得する
+    /// コマンドは search_keywords を使い、テストで確かめるためだけに使う
+    #[cfg(test)]
+    pub async fn search(
+        &self,
+        keyword: &str,
+        filter: &EntryFilter,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        self.search_keywords(&[keyword], &[], filter, &Page::default())
+            .await
+    }
+
+    /// ファイルパスに keywords のすべてが部分一致し、excludes のいずれも含まないアーカイブのうち
+    /// filter に一致するものから page の範囲を取得する
+    pub async fn search_keywords(
+        &self,
+        keywords: &[&str],
+        excludes: &[&str],
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        self.select_by_keywords(keywords, excludes, filter, page, |conditions| {
+            format!(
+                "SELECT {} FROM archives WHERE {}{} ORDER BY {} {}",
+                ENTRY_COLUMNS,
+                conditions,
+                ENTRY_FILTER,
+                page.sort.order_clause(),
+                PAGE_CLAUSE
+            )
+        })
+    }
+
+    /// search_keywords に一致するアーカイブのうちパスごとに最新のものを 1 件ずつ取得する
+    /// list_latest_per_path と同様に、隔離されているアーカイブは除き、その前のアーカイブを最新とする
+    pub async fn search_latest(
+        &self,
+        keywords: &[&str],
+        excludes: &[&str],
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        self.select_by_keywords(keywords, excludes, filter, page, |conditions| {
+            format!(
+                r#"
+                SELECT {names} FROM (
+                    SELECT {columns},
+                        ROW_NUMBER() OVER (PARTITION BY path ORDER BY {order}) AS rank
+                    FROM archives
+                    WHERE {conditions}NOT quarantined AND {filter}
+                )
+                WHERE rank = 1
+                ORDER BY {sort}
+                {page}
+                "#,
+                columns = ENTRY_COLUMNS,
+                names = ENTRY_COLUMN_NAMES,
+                order = ENTRY_ORDER,
+                conditions = conditions,
+                filter = ENTRY_FILTER,
+                sort = page.sort.order_clause(),
+                page = PAGE_CLAUSE
+            )
+        })
+    }
+
+    /// keywords と excludes でファイルパスを絞り込む SQL を実行する
+    /// select には絞り込みの条件が `... AND ` の形で渡されるため、続けて ENTRY_FILTER を置く
+    /// select は最後に PAGE_CLAUSE を置く
+    fn select_by_keywords(
+        &self,
+        keywords: &[&str],
+        excludes: &[&str],
+        filter: &EntryFilter,
+        page: &Page,
+        select: impl FnOnce(&str) -> String,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let keyword_names = (0..keywords.len())
+            .map(|i| format!(":keyword{}", i))
+            .collect::<Vec<_>>();
+        let exclude_names = (0..excludes.len())
+            .map(|i| format!(":exclude{}", i))
+            .collect::<Vec<_>>();
+        let conditions = keyword_names
+            .iter()
+            .map(|name| format!("instr(path, {}) > 0", name))
+            .chain(
+                exclude_names
+                    .iter()
+                    .map(|name| format!("instr(path, {}) = 0", name)),
+            )
+            .map(|condition| format!("{} AND ", condition))
+            .collect::<String>();
+
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&select(&conditions))?;
+        let (since, until) = (filter.since_param(), filter.until_param());
+        let (limit, offset) = (page.limit_param(), page.offset_param());
+        let mut params: Vec<(&str, &dyn rusqlite::ToSql)> = vec![
+            (":tag", &filter.tag),
+            (":user", &filter.user),
+            (":since", &since),
+            (":until", &until),
+            (":limit", &limit),
+            (":offset", &offset),
+        ];
+        params.extend(
+            keyword_names
+                .iter()
+                .zip(keywords)
+                .chain(exclude_names.iter().zip(excludes))
+                .map(|(name, value)| (name.as_str(), value as &dyn rusqlite::ToSql)),
+        );
+        let archives = stmt
+            .query_map(params.as_slice(), entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(archives)
+    }
+
+    /// ファイルパスが正規表現 regexes のすべてに一致し、excludes のいずれにも一致しないアーカイブを
+    /// search と同じ順に取得する
+    /// SQLite は正規表現を評価できないため、filter に一致するものをすべて読み出してから絞り込む
+    /// latest の場合は search_latest と同様にパスごとに最新のものに限る
+    pub async fn search_regex(
+        &self,
+        regexes: &[regex::Regex],
+        excludes: &[regex::Regex],
+        latest: bool,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let archives = self.search_base(latest, filter, page).await?;
+        Ok(page.apply(
+            archives
+                .into_iter()
+                .filter(|entry| {
+                    regexes.iter().all(|regex| regex.is_match(&entry.path))
+                        && !excludes.iter().any(|regex| regex.is_match(&entry.path))
+                })
+                .collect(),
+        ))
+    }
+
+    /// search_keywords と同じものをファイルパスの大文字と小文字を区別せずに取得する
+    /// list_in_path_ignore_case と同様に、filter に一致するものをすべて読み出してから絞り込む
+    pub async fn search_ignore_case(
+        &self,
+        keywords: &[&str],
+        excludes: &[&str],
+        latest: bool,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let lowercase = |words: &[&str]| {
+            words
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<_>>()
+        };
+        let (keywords, excludes) = (lowercase(keywords), lowercase(excludes));
+        let archives = self.search_base(latest, filter, page).await?;
+        Ok(page.apply(
+            archives
+                .into_iter()
+                .filter(|entry| {
+                    let path = entry.path.to_lowercase();
+                    keywords.iter().all(|keyword| path.contains(keyword))
+                        && !excludes.iter().any(|exclude| path.contains(exclude))
+                })
+                .collect(),
+        ))
+    }
+
+    /// search_regex と search_ignore_case で絞り込む前のアーカイブを page の順にすべて取得する
+    /// latest の場合は search_latest と同様にパスごとに最新のものに限る
+    async fn search_base(
+        &self,
+        latest: bool,
+        filter: &EntryFilter,
+        page: &Page,
+    ) -> anyhow::Result<Vec<ArchiveEntry>> {
+        let page = page.unbounded();
+        if latest {
+            self.search_latest(&[], &[], filter, &page).await
+        } else {
+            self.search_keywords(&[], &[], filter, &page).await
+        }
+    }
+
+    /// filter に一致するアーカイブを本文と共に取得する
+    /// keyword を指定した場合は search と同様にファイルパスに部分一致するものに限る
+    pub async fn list_with_body(
+        &self,
+        keyword: Option<&str>,
+        filter: &EntryFilter,
+    ) -> anyhow::Result<Vec<(ArchiveEntry, String)>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, {} FROM archives WHERE instr(path, :keyword) > 0 AND {} ORDER BY {}",
+            ENTRY_COLUMNS, BODY_COLUMNS, ENTRY_FILTER, ENTRY_ORDER
+        ))?;
+        let params = named_params! {
+            ":keyword": keyword.unwrap_or_default(),
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let archives = stmt
+            .query_map(params, entry_and_body_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        archives.into_iter().map(StoredBody::load).collect()
+    }
+
+    /// 本文が LIKE のパターン like に一致するアーカイブを、本文と共に新しい順に 1 件ずつ f に渡す
+    /// like が None の場合は filter に一致するすべてのアーカイブを渡す
+    /// コールドストレージに移した本文はデータベースでは照合できないため、常に渡す
+    /// 隔離されているアーカイブは渡さない
+    /// latest の場合は list_latest_per_path と同様にパスごとに最新のものだけをパスの順に渡す
+    /// 全件をメモリに読み込まないよう、行を読み出すたびに f を呼ぶ
+    /// コールドストレージの本文を読み出せない場合は、本文の代わりにエラーを渡して続ける
+    pub async fn for_each_body_like(
+        &self,
+        like: Option<&str>,
+        latest: bool,
+        filter: &EntryFilter,
+        mut f: impl FnMut(
+            ArchiveEntry,
+            Result<String, crate::cold::ColdBodyUnavailable>,
+        ) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        const BODY_LIKE: &str =
+            "(:like IS NULL OR body LIKE :like ESCAPE '\\' OR cold_location IS NOT NULL)";
+        let conn = self.connect()?;
+        let sql = if latest {
+            format!(
+                r#"
+                SELECT {names}, {body} FROM (
+                    SELECT {columns}, {body},
+                        ROW_NUMBER() OVER (PARTITION BY path ORDER BY {order}) AS rank
+                    FROM archives
+                    WHERE NOT quarantined AND {filter}
+                )
+                WHERE rank = 1 AND {like}
+                ORDER BY path
+                "#,
+                columns = ENTRY_COLUMNS,
+                names = ENTRY_COLUMN_NAMES,
+                body = BODY_COLUMNS,
+                order = ENTRY_ORDER,
+                filter = ENTRY_FILTER,
+                like = BODY_LIKE
+            )
+        } else {
+            format!(
+                "SELECT {}, {} FROM archives WHERE NOT quarantined AND {} AND {} ORDER BY {}",
+                ENTRY_COLUMNS, BODY_COLUMNS, BODY_LIKE, ENTRY_FILTER, ENTRY_ORDER
+            )
+        };
+        let mut stmt = conn.prepare(&sql)?;
+        let params = named_params! {
+            ":like": like,
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        };
+        let mut rows = stmt.query(params)?;
+        while let Some(row) = rows.next()? {
+            let (entry, body) = StoredBody::try_load(entry_and_body_from_row(row)?);
+            f(entry, body)?;
+            conn.rearm();
+        }
+        Ok(())
+    }
+
+    /// prefix で始まるパスのアーカイブのうち filter に一致するものの件数
+    /// prefix が None の場合はすべてのパスを数える
+    pub async fn count(&self, prefix: Option<&Path>, filter: &EntryFilter) -> anyhow::Result<u64> {
+        let conn = self.connect()?;
+        let prefix = prefix.map(Path::to_string_lossy).unwrap_or_default();
+        let count = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM archives WHERE substr(path, 1, length(:path)) = :path AND {}",
+                ENTRY_FILTER
+            ),
+            named_params! {
+                ":path": prefix,
+                ":tag": filter.tag,
+                ":user": filter.user,
+                ":since": filter.since_param(),
+                ":until": filter.until_param(),
+            },
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// prefix で始まるパスのアーカイブのうち filter に一致するものを本文と共に 1 件ずつ f に渡す
+    /// prefix が None の場合はすべてのパスを対象にする
+    /// 全件をメモリに読み込まないよう、行を読み出すたびに f を呼ぶ
+    /// f がエラーを返した場合はそこで読み出しをやめる
+    /// コールドストレージの本文を読み出せない場合は、本文の代わりにエラーを渡して続ける
+    pub async fn for_each_with_body(
+        &self,
+        prefix: Option<&Path>,
+        filter: &EntryFilter,
+        mut f: impl FnMut(
+            ArchiveEntry,
+            Result<String, crate::cold::ColdBodyUnavailable>,
+        ) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, {} FROM archives WHERE substr(path, 1, length(:path)) = :path AND {} ORDER BY {}",
+            ENTRY_COLUMNS, BODY_COLUMNS, ENTRY_FILTER, ENTRY_ORDER
+        ))?;
+        let prefix = prefix.map(Path::to_string_lossy).unwrap_or_default();
+        let mut rows = stmt.query(named_params! {
+            ":path": prefix,
+            ":tag": filter.tag,
+            ":user": filter.user,
+            ":since": filter.since_param(),
+            ":until": filter.until_param(),
+        })?;
+        while let Some(row) = rows.next()? {
+            let (entry, body) = StoredBody::try_load(entry_and_body_from_row(row)?);
+            f(entry, body)?;
+            conn.rearm();
+        }
+        Ok(())
+    }
+
+    /// created_at が cutoff より前のアーカイブの本文を dir のブロブに移し、データベースから取り除く
+    /// 既に移したアーカイブと、隔離されているアーカイブは移さない
+    /// 移したアーカイブの名前を返す
+    pub async fn move_to_cold(
+        &self,
+        cutoff: DateTime<Utc>,
+        dir: &Path,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.connect()?;
+        let mut names = Vec::new();
+        {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT name, checksum, body FROM archives \
+                 WHERE created_at < ?1 AND cold_location IS NULL AND NOT quarantined ORDER BY {}",
+                ENTRY_ORDER
+            ))?;
+            let mut rows = stmt.query([cutoff.to_rfc3339()])?;
+            while let Some(row) = rows.next()? {
+                let checksum: String = row.get(1)?;
+                let body: String = row.get(2)?;
+                crate::cold::write(dir, &checksum, &body)?;
+                names.push(row.get::<_, String>(0)?);
+                conn.rearm();
+            }
+        }
+        // ブロブをすべて書き込んでから本文を取り除く
+        let tx = conn.transaction()?;
+        for name in &names {
+            tx.execute(
+                "UPDATE archives SET body = '', cold_location = ?2, cold_bytes = LENGTH(CAST(body AS BLOB)) \
+                 WHERE name = ?1",
+                params![name, dir.to_string_lossy()],
+            )?;
+        }
+        tx.commit()?;
+        // 取り除いた本文の領域を解放してデータベースファイルを小さくする
+        // VACUUM はデータベース全体を書き直すため、大きさに比例する時間をタイムアウトで中断しない
+        if !names.is_empty() {
+            conn.unwatch();
+            conn.execute_batch("VACUUM")?;
+        }
+        Ok(names)
+    }
+
+    /// コールドストレージに移した本文をすべてデータベースに戻す
+    /// 戻したブロブは、他に同じブロブを使うアーカイブがなければ削除する
+    /// 戻したアーカイブの名前を返す
+    pub async fn restore_from_cold(&self) -> anyhow::Result<Vec<String>> {
+        let conn = self.connect()?;
+        let cold = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT name, checksum, cold_location FROM archives \
+                 WHERE cold_location IS NOT NULL ORDER BY {}",
+                ENTRY_ORDER
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    PathBuf::from(row.get::<_, String>(2)?),
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let mut names = Vec::new();
+        for (name, checksum, dir) in cold {
+            let body = crate::cold::read(&dir, &name, &checksum)?;
+            conn.execute(
+                "UPDATE archives SET body = ?2, cold_location = NULL, cold_bytes = NULL WHERE name = ?1",
+                params![name, body],
+            )?;
+            let shared: bool = conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM archives WHERE cold_location = ?1 AND checksum = ?2)",
+                params![dir.to_string_lossy(), checksum],
+                |row| row.get(0),
+            )?;
+            if !shared {
+                std::fs::remove_file(crate::cold::blob_path(&dir, &checksum))?;
+            }
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// アーカイブをタグごと削除する
+    pub async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        let conn = self.connect()?;
+        if conn.execute("DELETE FROM archives WHERE name = ?1", [name])? == 0 {
+            anyhow::bail!("archive {} not found", name);
+        }
+        Ok(())
+    }
+
+    /// アーカイブのメッセージを設定し、変更後のアーカイブを返す
+    /// message が None の場合はメッセージを消す
+    pub async fn annotate(
+        &self,
+        name: &str,
+        message: Option<&str>,
+    ) -> anyhow::Result<ArchiveEntry> {
+        {
+            let conn = self.connect()?;
+            let updated = conn.execute(
+                "UPDATE archives SET message = ?2 WHERE name = ?1",
+                params![name, message],
+            )?;
+            if updated == 0 {
+                anyhow::bail!("archive {} not found", name);
+            }
+        }
+        self.entry(name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("archive {} not found", name))
+    }
+}
+
+/// ArchiveEntry として読み出す列
+/// size は BODY_BYTES と同じく本文のバイト数
+const ENTRY_COLUMNS: &str = "name, path, created_at, checksum, message, user, quarantined, \
+     COALESCE(cold_bytes, LENGTH(CAST(body AS BLOB))) AS size";
+/// ENTRY_COLUMNS を選択したサブクエリーから、同じ列を読み出すときの列の名前
+const ENTRY_COLUMN_NAMES: &str =
+    "name, path, created_at, checksum, message, user, quarantined, size";
+/// ENTRY_COLUMNS の列数
+/// ENTRY_COLUMNS に続けて選択した列はこの位置から始まる
+const ENTRY_COLUMN_COUNT: usize = 8;
+/// 本文として読み出す列
+/// 本文をコールドストレージに移したアーカイブは body が空になり、cold_location にブロブのディレクトリが入る
+const BODY_COLUMNS: &str = "body, cold_location";
+/// 本文のバイト数
+/// 本文をコールドストレージに移したアーカイブは、移したときに記録したバイト数を使う
+const BODY_BYTES: &str = "COALESCE(cold_bytes, LENGTH(CAST(body AS BLOB)))";
+
+/// データベースから読み出した本文
+enum StoredBody {
+    Inline(String),
+    /// コールドストレージのディレクトリにある
+    Cold(PathBuf),
+}
+
+impl StoredBody {
+    /// 本文がコールドストレージにあればブロブから読み出す
+    fn load((entry, body): (ArchiveEntry, StoredBody)) -> anyhow::Result<(ArchiveEntry, String)> {
+        let (entry, body) = Self::try_load((entry, body));
+        Ok((entry, body?))
+    }
+
+    /// load と同様に読み出すが、ブロブを読み出せない場合も entry を返す
+    fn try_load(
+        (entry, body): (ArchiveEntry, StoredBody),
+    ) -> (
+        ArchiveEntry,
+        Result<String, crate::cold::ColdBodyUnavailable>,
+    ) {
+        let body = match body {
+            StoredBody::Inline(body) => Ok(body),
+            StoredBody::Cold(dir) => crate::cold::read(&dir, &entry.name, &entry.checksum),
+        };
+        (entry, body)
+    }
+}
+
+/// ENTRY_COLUMNS に続けて BODY_COLUMNS を選択した行を読み出す
+fn entry_and_body_from_row(row: &rusqlite::Row) -> rusqlite::Result<(ArchiveEntry, StoredBody)> {
+    let entry = entry_from_row(row)?;
+    let body = match row.get::<_, Option<String>>(ENTRY_COLUMN_COUNT + 1)? {
+        Some(dir) => StoredBody::Cold(PathBuf::from(dir)),
+        None => StoredBody::Inline(row.get(ENTRY_COLUMN_COUNT)?),
+    };
+    Ok((entry, body))
+}
+
+/// ENTRY_COLUMNS の順に選択した行を ArchiveEntry にする
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<ArchiveEntry> {
+    let created_at: String = row.get(2)?;
+    let created_at = DateTime::parse_from_rfc3339(&created_at)
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+        })?
+        .with_timezone(&Utc);
+    Ok(ArchiveEntry {
+        name: row.get(0)?,
+        path: row.get(1)?,
+        created_at,
+        checksum: row.get(3)?,
+        message: row.get(4)?,
+        user: row.get(5)?,
+        quarantined: row.get(6)?,
+        size: row.get(7)?,
+    })
+}
+
+/// タイムアウトを監視しているデータベース接続
+struct WatchedConnection {
+    conn: Connection,
+    watchdog: Option<Watchdog>,
+}
+
+impl WatchedConnection {
+    /// 1 件ずつ読み出す操作で、次の 1 件までの処理に改めてタイムアウトまでの時間を与える
+    fn rearm(&self) {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.rearm();
+        }
+    }
+
+    /// この接続のタイムアウトの監視をやめる
+    fn unwatch(&mut self) {
+        self.watchdog = None;
+    }
+}
+
+impl std::ops::Deref for WatchedConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl std::ops::DerefMut for WatchedConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+}
+
+/// 開始または最後に rearm してから一定時間内に破棄されなかった場合にデータベース接続の処理を中断する
+struct Watchdog {
+    rearm: mpsc::Sender<()>,
+}
+
+impl Watchdog {
+    fn start(interrupt: rusqlite::InterruptHandle, timeout: Duration) -> Self {
+        let (rearm, rearmed) = mpsc::channel::<()>();
+        std::thread::spawn(move || loop {
+            match rearmed.recv_timeout(timeout) {
+                Ok(()) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    interrupt.interrupt();
+                    return;
+                }
+                // Watchdog が破棄されると送信側が閉じられて Disconnected になる
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        });
+        Self { rearm }
+    }
+
+    fn rearm(&self) {
+        let _ = self.rearm.send(());
+    }
+}
+
+/// is_timeout が true になる、中断されたデータベース操作のエラー
+fn interrupted(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_INTERRUPT),
+        Some(message.to_string()),
+    )
+}
+
+/// 登録しようとした名前のアーカイブが既にある
+#[derive(Debug)]
+pub struct NameAlreadyExists {
+    pub name: String,
+}
+
+impl std::fmt::Display for NameAlreadyExists {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "archive name {} already exists", self.name)
+    }
+}
+
+impl std::error::Error for NameAlreadyExists {}
+
+/// error がデータベース操作のタイムアウトによるものかどうか
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(e, _))
+            if e.code == rusqlite::ErrorCode::OperationInterrupted
+    )
+}
+
+/// name のアーカイブが存在するかどうか
+fn exists(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM archives WHERE name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+}
+
+/// スキーマを作成し、古いデータベースを最新のスキーマに更新する
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(SCHEMA)?;
+    // message 列は後から追加したため、追加前に作成されたデータベースには列がない
+    if !has_column(conn, "archives", "message")? {
+        conn.execute_batch("ALTER TABLE archives ADD COLUMN message TEXT")?;
+    }
+    // user 列も同様に後から追加した
+    if !has_column(conn, "archives", "user")? {
+        conn.execute_batch("ALTER TABLE archives ADD COLUMN user TEXT")?;
+    }
+    if !has_column(conn, "archives", "quarantined")? {
+        conn.execute_batch(
+            "ALTER TABLE archives ADD COLUMN quarantined INTEGER NOT NULL DEFAULT 0",
+        )?;
+    }
+    if !has_column(conn, "archives", "cold_location")? {
+        conn.execute_batch(
+            "ALTER TABLE archives ADD COLUMN cold_location TEXT; \
+             ALTER TABLE archives ADD COLUMN cold_bytes INTEGER;",
+        )?;
+    }
+    Ok(())
+}
+
+fn has_archives_table(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'archives')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        [table, column],
+        |row| row.get(0),
+    )
+}
+
+/// push_body_with_rename で連番を付けて登録を試みる上限
+const MAX_RENAME_ATTEMPTS: usize = 100;
+
+fn insert(conn: &Connection, entry: &ArchiveEntry, body: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        r#"
+            INSERT INTO archives (name, path, created_at, body, checksum, message, user, quarantined)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        "#,
+        params![
+            entry.name,
+            entry.path,
+            entry.created_at.to_rfc3339(),
+            body,
+            entry.checksum,
+            entry.message,
+            entry.user,
+            entry.quarantined,
+        ],
+    )?;
+    Ok(())
+}
+
+/// insert と同様に登録するが、name が既に使われている場合は NameAlreadyExists を返す
+fn insert_new(conn: &Connection, entry: &ArchiveEntry, body: &str) -> anyhow::Result<()> {
+    insert(conn, entry, body).map_err(|e| {
+        if is_name_conflict(&e) {
+            anyhow::Error::new(NameAlreadyExists {
+                name: entry.name.clone(),
+            })
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// name 列の UNIQUE 制約違反かどうか
+fn is_name_conflict(error: &rusqlite::Error) -> bool {
+    match error {
+        rusqlite::Error::SqliteFailure(e, Some(message)) => {
+            e.code == rusqlite::ErrorCode::ConstraintViolation && message.contains("archives.name")
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub path: String,
+    pub created_at: DateTime<Utc>,
+    pub checksum: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// 登録した OS のユーザー
+    #[serde(default)]
+    pub user: Option<String>,
+    /// 本文がチェックサムと一致しなかったため隔離されている
+    #[serde(default)]
+    pub quarantined: bool,
+    /// 本文のバイト数
+    /// 本文から求まるため、エクスポートするマニフェストには含めない
+    #[serde(default, skip_serializing)]
+    pub size: u64,
+}
+
+/// Archive::push_many で登録するファイルの内容
+#[derive(Debug, Clone)]
+pub struct PushRequest {
+    pub path: PathBuf,
+    pub body: String,
+    /// body のチェックサム
+    pub checksum: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Archive::restore の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restored {
+    /// 新しく登録した
+    Inserted,
+    /// 同じ名前のアーカイブを置き換えた
+    Replaced,
+    /// 同じ名前のアーカイブがあるため登録しなかった
+    Skipped,
+}
+
+/// Archive::summary の結果
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Summary {
+    /// アーカイブの件数
+    pub entries: u64,
+    /// アーカイブ元のパスの数
+    pub paths: u64,
+    /// 本文のバイト数の合計
+    pub bytes: u64,
+    /// 最も古いアーカイブの作成日時 アーカイブがなければ None
+    pub oldest: Option<DateTime<Utc>>,
+    /// 最も新しいアーカイブの作成日時 アーカイブがなければ None
+    pub newest: Option<DateTime<Utc>>,
+    pub top_paths: Vec<PathVersions>,
+}
+
+/// パスごとに最新のアーカイブと、そのパスのアーカイブの件数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatestEntry {
+    pub entry: ArchiveEntry,
+    pub versions: u64,
+}
+
+/// パスごとのアーカイブの件数
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PathVersions {
+    pub path: String,
+    pub versions: u64,
+}
+
+/// 15 分単位の時間帯ごとの登録件数と本文のバイト数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrowthSlot {
+    pub start: DateTime<Utc>,
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs;
+
+    fn tagged(tag: &str) -> EntryFilter {
+        EntryFilter {
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        }
+    }
 
     async fn create_dot_env_file(files: &[(PathBuf, &str)]) {
         for file in files {
@@ -266,111 +2009,1813 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn check_is_same_as_latest_最新のアーカイブと同じならtrueを異なるならfalseを返す() {
+    async fn openはcreate_if_missingならデータベースを作成して初期化する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        assert!(database_path.exists());
+        assert!(archive
+            .list_all(&EntryFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn openはcreate_if_missingならないディレクトリも作成する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("var/lib/env-archive/team.db");
+
+        Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        assert!(database_path.is_file());
+
+        // ディレクトリを作成できない場合
+        let blocked = tmp_dir.path().join("file");
+        std::fs::write(&blocked, "").unwrap();
+        let error = Archive::open(blocked.join("sub/team.db"), OpenOptions::default())
+            .err()
+            .unwrap();
+        assert!(matches!(error, ArchiveError::CreateDir { .. }), "{}", error);
+        assert!(error.to_string().starts_with(&format!(
+            "failed to create directory {}",
+            blocked.join("sub").display()
+        )));
+    }
+
+    #[test]
+    fn openはcreate_if_missingでなければデータベースを作成しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+
+        for options in [
+            OpenOptions {
+                create_if_missing: false,
+                ..Default::default()
+            },
+            OpenOptions {
+                read_only: true,
+                ..Default::default()
+            },
+        ] {
+            let error = Archive::open(database_path.clone(), options).err().unwrap();
+            assert!(matches!(error, ArchiveError::NotFound(_)));
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "database not found at {}; run `init` first",
+                    database_path.display()
+                )
+            );
+            assert!(!database_path.exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn openはread_onlyなら読み取りだけができる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        Archive::open(database_path.clone(), OpenOptions::default())
+            .unwrap()
+            .push(&env_file_path, Utc::now(), "test-name", None)
+            .await
+            .unwrap();
+
+        let options = OpenOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let archive = Archive::open(database_path, options).unwrap();
+        assert_eq!(
+            archive
+                .list_all(&EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(archive
+            .push(&env_file_path, Utc::now(), "another-name", None)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn is_initializedはデータベースを作成せずにテーブルの有無を確かめる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+
+        assert!(!Archive::is_initialized(&database_path).unwrap());
+        assert!(!database_path.exists());
+
+        fs::File::create(&database_path).unwrap();
+        assert!(!Archive::is_initialized(&database_path).unwrap());
+
+        Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        assert!(Archive::is_initialized(&database_path).unwrap());
+
+        fs::write(&database_path, "not a database").unwrap();
+        assert!(Archive::is_initialized(&database_path).is_err());
+    }
+
+    #[test]
+    fn openはauto_migrateでなければ未初期化のデータベースをエラーにする() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        fs::File::create(&database_path).unwrap();
+
+        let options = OpenOptions {
+            auto_migrate: false,
+            ..Default::default()
+        };
+        let result = Archive::open(database_path.clone(), options);
+        assert!(matches!(result, Err(ArchiveError::Uninitialized(_))));
+
+        Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        Archive::open(database_path, options).unwrap();
+    }
+
+    #[test]
+    fn openはbusy_timeoutを接続に設定する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let options = OpenOptions {
+            busy_timeout: Duration::from_millis(1234),
+            ..Default::default()
+        };
+        let archive = Archive::open(tmp_dir.path().join("test.db"), options).unwrap();
+
+        let conn = archive.connect().unwrap();
+        let busy_timeout: i64 = conn
+            .query_row("PRAGMA busy_timeout", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(busy_timeout, 1234);
+    }
+
+    #[test]
+    fn openはデータベースでないファイルをエラーにする() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        fs::write(&database_path, "this is not a sqlite database file").unwrap();
+
+        let result = Archive::open(database_path, OpenOptions::default());
+        assert!(matches!(result, Err(ArchiveError::Sqlite(_))));
+    }
+
+    #[tokio::test]
+    async fn latest_checksum_パスの最新のアーカイブのチェックサムを返す() {
         let tmp_dir = tempfile::tempdir().unwrap();
         let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
 
         let env_file_path = tmp_dir.path().join(".env");
         create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
 
         let now = Utc::now();
         archive
-            .push(&env_file_path, now, "test-name")
+            .push(&env_file_path, now, "test-name", None)
             .await
             .unwrap();
 
-        let is_same = archive
-            .check_is_same_as_latest(&env_file_path)
-            .await
-            .unwrap();
-        assert!(is_same);
+        let checksum = crate::digest::bytes_checksum(b"FOO=BAR");
+        assert_eq!(
+            archive.latest_checksum(&env_file_path).unwrap(),
+            Some(checksum)
+        );
 
         let env_file_path = tmp_dir.path().join("test_a").join(".env");
         create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
 
-        let is_same = archive
-            .check_is_same_as_latest(&env_file_path)
+        assert_eq!(archive.latest_checksum(&env_file_path).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn latest_checksumは隔離されているアーカイブを除く() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        archive
+            .push(&env_file_path, Utc::now(), "test-name", None)
             .await
             .unwrap();
-        assert!(!is_same);
+        archive.set_quarantined("test-name", true).await.unwrap();
+
+        // 唯一のアーカイブが隔離されていれば、同じ内容でも登録し直せるように None を返す
+        assert_eq!(archive.latest_checksum(&env_file_path).unwrap(), None);
     }
 
     #[tokio::test]
     async fn check_is_same_by_name_nameに一致するアーカイブと同じならtrueを異なるならfalseを返す() {
         let tmp_dir = tempfile::tempdir().unwrap();
         let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
 
         let env_file_path = tmp_dir.path().join(".env");
         create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
 
         let now = Utc::now();
         archive
-            .push(&env_file_path, now, "test-name")
+            .push(&env_file_path, now, "test-name", None)
             .await
             .unwrap();
 
         let is_same = archive
-            .check_is_same_by_name("test-name", &env_file_path)
+            .check_is_same_by_name("test-name", b"FOO=BAR")
             .await
             .unwrap();
         assert!(is_same);
 
-        let env_file_path = tmp_dir.path().join("test_a").join(".env");
-        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAA")]).await;
+        let is_same = archive
+            .check_is_same_by_name("test-name", b"FOO=BAA")
+            .await
+            .unwrap();
+        assert!(!is_same);
+    }
+
+    #[tokio::test]
+    async fn pushの最中にファイルが書き換えられてもチェックサムは保存した本文と一致する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+
+        // 読み込みとチェックサムの計算の間に書き換わるよう、別のスレッドで内容を書き換え続ける
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let writer = {
+            let stop = stop.clone();
+            let env_file_path = env_file_path.clone();
+            std::thread::spawn(move || {
+                let mut i = 0;
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    std::fs::write(&env_file_path, format!("FOO={}\n", "X".repeat(i % 4096)))
+                        .unwrap();
+                    i += 1;
+                }
+            })
+        };
+        for i in 0..50 {
+            archive
+                .push(&env_file_path, Utc::now(), &format!("test-{}", i), None)
+                .await
+                .unwrap();
+        }
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        writer.join().unwrap();
+
+        for i in 0..50 {
+            let (entry, body) = archive.get(&format!("test-{}", i)).await.unwrap().unwrap();
+            assert_eq!(
+                entry.checksum,
+                crate::digest::bytes_checksum(body.as_bytes())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn pushするとdbに保存される() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+
+        let now = Utc::now();
+        archive
+            .push(&env_file_path, now, "test-name", None)
+            .await
+            .unwrap();
+
+        let conn = Connection::open(&database_path).unwrap();
+        let mut stmt = conn.prepare("SELECT * FROM archives").unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .unwrap();
+
+        let row = rows.into_iter().next().unwrap().unwrap();
+        assert_eq!(row.0, "test-name");
+        assert_eq!(row.1, env_file_path.to_string_lossy());
+        assert_eq!(row.2, now.to_rfc3339());
+        assert_eq!(row.3, "FOO=BAR");
+    }
+
+    #[tokio::test]
+    async fn push_manyは1件でも登録できなければすべて登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        let request = |path: &str, name: &str| PushRequest {
+            path: tmp_dir.path().join(path),
+            body: format!("PATH={}", path),
+            checksum: crate::digest::bytes_checksum(format!("PATH={}", path).as_bytes()),
+            name: name.to_string(),
+            created_at: now,
+        };
+
+        let error = archive
+            .push_many(vec![
+                request("a/.env", "a"),
+                request("b/.env", "b"),
+                request("c/.env", "a"),
+            ])
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains(&format!("{} as a", tmp_dir.path().join("c/.env").display())));
+        assert!(archive
+            .list_all(&EntryFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        archive
+            .push_many(vec![request("a/.env", "a"), request("b/.env", "b")])
+            .unwrap();
+        let (entry, body) = archive.get("b").await.unwrap().unwrap();
+        assert_eq!(entry.path, tmp_dir.path().join("b/.env").to_string_lossy());
+        assert_eq!(body, "PATH=b/.env");
+        assert_eq!(
+            entry.checksum,
+            crate::digest::bytes_checksum(b"PATH=b/.env")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn push_body_with_renameを並行して実行しても名前が重複しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_files = (0..8)
+            .map(|i| {
+                (
+                    tmp_dir.path().join(format!("test_{}", i)).join(".env"),
+                    "FOO=BAR",
+                )
+            })
+            .collect::<Vec<_>>();
+        create_dot_env_file(&env_files).await;
+
+        let now = Utc::now();
+        let handles = env_files
+            .into_iter()
+            .map(|(env_file_path, _)| {
+                let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+                tokio::spawn(async move {
+                    archive
+                        .push_body_with_rename(&env_file_path, "FOO=BAR", now, "deploy", None)
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut names = Vec::new();
+        for handle in handles {
+            names.push(handle.await.unwrap());
+        }
+        names.sort();
+
+        let mut expected = vec!["deploy".to_string()];
+        expected.extend((2..=8).map(|n| format!("deploy-{}", n)));
+        expected.sort();
+        assert_eq!(names, expected);
+        assert_eq!(
+            archive
+                .list_all(&EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            8
+        );
+    }
+
+    #[tokio::test]
+    async fn タイムアウトを超えたデータベース操作は中断される() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path, OpenOptions::default())
+            .unwrap()
+            .with_timeout(Duration::from_millis(100));
+
+        let conn = archive.connect().unwrap();
+        conn.create_scalar_function(
+            "sleep_ms",
+            1,
+            rusqlite::functions::FunctionFlags::SQLITE_UTF8,
+            |ctx| {
+                std::thread::sleep(Duration::from_millis(ctx.get::<u64>(0)?));
+                Ok(0)
+            },
+        )
+        .unwrap();
+        let started = std::time::Instant::now();
+        let result = conn
+            .query_row(
+                r#"
+                WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 1000)
+                SELECT SUM(sleep_ms(10)) FROM c
+                "#,
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(anyhow::Error::from);
+
+        assert!(is_timeout(&result.unwrap_err()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn 開く処理が止まったデータベースはタイムアウトで諦める() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        // 書き込む側がいない FIFO を読み取り専用で開こうとすると止まる
+        let fifo = tmp_dir.path().join("fifo.db");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let archive = Archive {
+            database_path: fifo,
+            timeout: Some(Duration::from_millis(100)),
+            options: OpenOptions {
+                read_only: true,
+                ..Default::default()
+            },
+            user: None,
+        };
+
+        let started = std::time::Instant::now();
+        let result = archive.connect().map(|_| ());
+        assert!(is_timeout(&result.unwrap_err()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn for_each_with_bodyなど1件ずつ読み出す操作は全体がタイムアウトを超えても中断しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let archive = Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default())
+            .unwrap()
+            .with_timeout(Duration::from_millis(200));
+        for i in 0..4 {
+            archive
+                .push_body(
+                    Path::new("/srv/api/.env"),
+                    "A=1\n",
+                    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, i).unwrap(),
+                    &format!("entry-{}", i),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let slow = |_, _| {
+            std::thread::sleep(Duration::from_millis(100));
+            Ok(())
+        };
+        archive
+            .for_each_with_body(None, &EntryFilter::default(), slow)
+            .await
+            .unwrap();
+        archive
+            .for_each_body_like(None, false, &EntryFilter::default(), slow)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn pageは並び順のうちoffset件を飛ばしてlimit件を取得する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        let pushes = [
+            ("/srv/b/.env", "b-old", 4),
+            ("/srv/a/.env", "a-old", 3),
+            ("/srv/b/.env", "b-new", 2),
+            ("/srv/a/.env", "a-new", 1),
+        ];
+        for (path, name, hours_ago) in pushes {
+            archive
+                .push_body(
+                    Path::new(path),
+                    "FOO=1\n",
+                    now - chrono::Duration::hours(hours_ago),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let filter = EntryFilter::default();
+        let page = |limit: usize, offset: usize| Page {
+            limit: NonZeroUsize::new(limit),
+            offset,
+            ..Page::default()
+        };
+        let names = |archives: Vec<ArchiveEntry>| {
+            archives
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+
+        // list_all は新しい順に並べるため、limit は最近のものから数える
+        assert_eq!(
+            names(archive.list_all_paged(&filter, &page(2, 0)).await.unwrap()),
+            ["a-new", "b-new"]
+        );
+        assert_eq!(
+            names(archive.list_all_paged(&filter, &page(2, 3)).await.unwrap()),
+            ["b-old"]
+        );
+        assert!(archive
+            .list_all_paged(&filter, &page(2, 10))
+            .await
+            .unwrap()
+            .is_empty());
+        // limit を指定しなければ offset 以降をすべて取得する
+        assert_eq!(
+            names(archive.list_all_paged(&filter, &page(0, 1)).await.unwrap()),
+            ["b-new", "a-old", "b-old"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .list_in_path(Path::new("/srv/b"), &filter, &page(1, 1))
+                    .await
+                    .unwrap()
+            ),
+            ["b-old"]
+        );
+
+        assert_eq!(
+            names(
+                archive
+                    .search_keywords(&["/srv/"], &[], &filter, &page(3, 0))
+                    .await
+                    .unwrap()
+            ),
+            ["a-new", "b-new", "a-old"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search_ignore_case(&["/SRV/"], &[], false, &filter, &page(2, 1))
+                    .await
+                    .unwrap()
+            ),
+            ["b-new", "a-old"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search_latest(&["/srv/"], &[], &filter, &page(1, 1))
+                    .await
+                    .unwrap()
+            ),
+            ["b-new"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sortは指定した列と向きで並べ同じ値のものは新しい順に並べる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        let pushes = [
+            ("/srv/a/.env", "d-name", 4),
+            ("/srv/b/.env", "c-name", 3),
+            ("/srv/c/.env", "a-name", 2),
+            ("/srv/a/.env", "b-name", 1),
+        ];
+        for (path, name, hours_ago) in pushes {
+            archive
+                .push_body(
+                    Path::new(path),
+                    "FOO=1\n",
+                    now - chrono::Duration::hours(hours_ago),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let filter = EntryFilter::default();
+        let sorted = |key: SortKey, desc: bool| Page {
+            sort: Sort { key, desc },
+            ..Page::default()
+        };
+        let names = |archives: Vec<ArchiveEntry>| {
+            archives
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+        let list = |page: Page| {
+            let archive = &archive;
+            let filter = &filter;
+            async move { names(archive.list_all_paged(filter, &page).await.unwrap()) }
+        };
+
+        assert_eq!(
+            list(Page::default()).await,
+            ["b-name", "a-name", "c-name", "d-name"]
+        );
+        assert_eq!(
+            list(sorted(SortKey::CreatedAt, true)).await,
+            list(Page::default()).await
+        );
+        assert_eq!(
+            list(sorted(SortKey::CreatedAt, false)).await,
+            ["d-name", "c-name", "a-name", "b-name"]
+        );
+        assert_eq!(
+            list(sorted(SortKey::Path, false)).await,
+            ["b-name", "d-name", "c-name", "a-name"]
+        );
+        assert_eq!(
+            list(sorted(SortKey::Path, true)).await,
+            ["a-name", "c-name", "b-name", "d-name"]
+        );
+        assert_eq!(
+            list(sorted(SortKey::Name, false)).await,
+            ["a-name", "b-name", "c-name", "d-name"]
+        );
+        assert_eq!(
+            list(sorted(SortKey::Name, true)).await,
+            ["d-name", "c-name", "b-name", "a-name"]
+        );
+
+        // データベースから読み出してから絞り込むものも、パスごとにまとめたものも同じ順に並べる
+        assert_eq!(
+            names(
+                archive
+                    .search_ignore_case(
+                        &["/SRV/"],
+                        &["/c/"],
+                        false,
+                        &filter,
+                        &sorted(SortKey::Name, false)
+                    )
+                    .await
+                    .unwrap()
+            ),
+            ["b-name", "c-name", "d-name"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search_latest(&["/srv/"], &[], &filter, &sorted(SortKey::Path, true))
+                    .await
+                    .unwrap()
+            ),
+            ["a-name", "c-name", "b-name"]
+        );
+        assert_eq!(
+            archive
+                .list_latest_in_path(Path::new("/srv/"), &filter, &sorted(SortKey::Name, false))
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|latest| latest.entry.name)
+                .collect::<Vec<_>>(),
+            ["a-name", "b-name", "c-name"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sinceとuntilは境界の日時を含めて登録日時で絞り込む() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
+        let at = |day: u32, nano: u32| {
+            Utc.with_ymd_and_hms(2024, 6, day, 0, 0, 0).unwrap()
+                + chrono::Duration::nanoseconds(nano.into())
+        };
+        let pushes = [
+            ("may-31", at(1, 0) - chrono::Duration::nanoseconds(1)),
+            ("jun-01", at(1, 0)),
+            ("jun-01-later", at(1, 500_000_000)),
+            ("jun-08", at(8, 0)),
+            ("jun-08-later", at(8, 1)),
+        ];
+        for (name, created_at) in pushes {
+            archive
+                .push_body(
+                    Path::new(&format!("/srv/{}/.env", name)),
+                    "FOO=1\n",
+                    created_at,
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let filter = EntryFilter {
+            since: Some(at(1, 0)),
+            until: Some(at(8, 0)),
+            ..Default::default()
+        };
+        let names = |archives: Vec<ArchiveEntry>| {
+            archives
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(archive.list_all(&filter).await.unwrap()),
+            ["jun-01-later", "jun-01", "jun-08"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search_keywords(&["/srv/"], &["later"], &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["jun-08", "jun-01"]
+        );
+        let until_only = EntryFilter {
+            until: Some(at(1, 0)),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(archive.list_all(&until_only).await.unwrap()),
+            ["jun-01", "may-31"]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_allするとdbに保存されたすべてのアーカイブの一覧が取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_files = [
+            (tmp_dir.path().join(".env"), "FOO=FIRST"),
+            (tmp_dir.path().join("test_a").join(".env"), "FOO=SECOND"),
+            (
+                tmp_dir.path().join("test_b").join("internal").join(".env"),
+                "FOO=THIRD",
+            ),
+        ];
+        create_dot_env_file(&env_files).await;
+
+        let now = Utc::now();
+        for (n, (env_file_path, _)) in env_files.iter().enumerate() {
+            archive
+                .push(env_file_path, now, n.to_string().as_str(), None)
+                .await
+                .unwrap();
+        }
+
+        let archives = archive.list_all(&EntryFilter::default()).await.unwrap();
+        assert_eq!(archives.len(), 3);
+        for (
+            i,
+            ArchiveEntry {
+                name,
+                path,
+                created_at,
+                checksum,
+                message,
+                user,
+                quarantined,
+                size,
+            },
+        ) in archives.iter().enumerate()
+        {
+            // パスの昇順に並ぶ
+            let n = i;
+            assert_eq!(name, &n.to_string());
+            assert_eq!(path, &env_files[n].0.to_string_lossy());
+            assert_eq!(created_at, &now);
+            assert_eq!(
+                checksum,
+                &crate::digest::bytes_checksum(env_files[n].1.as_bytes())
+            );
+            assert_eq!(message, &None);
+            assert_eq!(user, &None);
+            assert!(!quarantined);
+            assert_eq!(*size, env_files[n].1.len() as u64);
+        }
+    }
+
+    #[tokio::test]
+    async fn 一覧のsizeはマルチバイト文字を含む本文のバイト数になる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
+        let body = "GREETING=こんにちは\nEMOJI=🔑\n";
+        archive
+            .push_body(Path::new("/srv/api/.env"), body, Utc::now(), "api", None)
+            .await
+            .unwrap();
+        let filter = EntryFilter::default();
+        let page = Page::default();
+        let size = body.len() as u64;
+        assert_ne!(size, body.chars().count() as u64);
+
+        assert_eq!(archive.list_all(&filter).await.unwrap()[0].size, size);
+        assert_eq!(
+            archive
+                .list_in_path(Path::new("/srv/"), &filter, &page)
+                .await
+                .unwrap()[0]
+                .size,
+            size
+        );
+        assert_eq!(
+            archive
+                .search_keywords(&["api"], &[], &filter, &page)
+                .await
+                .unwrap()[0]
+                .size,
+            size
+        );
+        assert_eq!(
+            archive
+                .list_latest_in_path(Path::new("/srv/"), &filter, &page)
+                .await
+                .unwrap()[0]
+                .entry
+                .size,
+            size
+        );
+    }
+
+    #[tokio::test]
+    async fn list_in_pathするとpath配下のアーカイブの一覧が取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_files = [
+            (tmp_dir.path().join(".env"), "FOO=FIRST"),
+            (tmp_dir.path().join("test_a").join(".env"), "FOO=SECOND"),
+            (
+                tmp_dir.path().join("test_b").join("internal").join(".env"),
+                "FOO=THIRD",
+            ),
+        ];
+        create_dot_env_file(&env_files).await;
+
+        let now = Utc::now();
+        for (n, (env_file_path, _)) in env_files.iter().enumerate() {
+            archive
+                .push(env_file_path, now, n.to_string().as_str(), None)
+                .await
+                .unwrap();
+        }
+
+        let archives = archive
+            .list_in_path(tmp_dir.path(), &EntryFilter::default(), &Page::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 3);
+        for (
+            i,
+            ArchiveEntry {
+                name,
+                path,
+                created_at,
+                checksum,
+                message,
+                user,
+                quarantined,
+                size,
+            },
+        ) in archives.iter().enumerate()
+        {
+            // 同じ日時に登録したものは名前の降順に並ぶ
+            let n = env_files.len() - 1 - i;
+            assert_eq!(name, &n.to_string());
+            assert_eq!(path, &env_files[n].0.to_string_lossy());
+            assert_eq!(created_at, &now);
+            assert_eq!(
+                checksum,
+                &crate::digest::bytes_checksum(env_files[n].1.as_bytes())
+            );
+            assert_eq!(message, &None);
+            assert_eq!(user, &None);
+            assert!(!quarantined);
+            assert_eq!(*size, env_files[n].1.len() as u64);
+        }
+
+        let archives = archive
+            .list_in_path(
+                &tmp_dir.path().join("test_a"),
+                &EntryFilter::default(),
+                &Page::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].name, "1");
+        assert_eq!(archives[0].path, env_files[1].0.to_string_lossy());
+        assert_eq!(archives[0].created_at, now);
+    }
+
+    #[tokio::test]
+    async fn list_latest_in_pathはpath配下のパスごとに最新のアーカイブと件数を新しい順に取得する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        let pushes = [
+            ("/srv/api/.env", "api-1", 5),
+            ("/srv/web/.env", "web-1", 4),
+            ("/srv/api/.env", "api-2", 3),
+            ("/srv/Web/.env", "Web-1", 2),
+            ("/other/.env", "other-1", 1),
+        ];
+        for (path, name, hours_ago) in pushes {
+            archive
+                .push_body(
+                    Path::new(path),
+                    "FOO=1\n",
+                    now - chrono::Duration::hours(hours_ago),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let filter = EntryFilter::default();
+        let latest = |archives: Vec<LatestEntry>| {
+            archives
+                .into_iter()
+                .map(|latest| (latest.entry.name, latest.versions))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            latest(
+                archive
+                    .list_latest_in_path(Path::new("/srv/"), &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            [
+                ("Web-1".to_string(), 1),
+                ("api-2".to_string(), 2),
+                ("web-1".to_string(), 1)
+            ]
+        );
+        let page = Page {
+            limit: NonZeroUsize::new(1),
+            offset: 1,
+            ..Page::default()
+        };
+        assert_eq!(
+            latest(
+                archive
+                    .list_latest_in_path(Path::new("/srv/"), &filter, &page)
+                    .await
+                    .unwrap()
+            ),
+            [("api-2".to_string(), 2)]
+        );
+        assert_eq!(
+            latest(
+                archive
+                    .list_latest_in_path_ignore_case(
+                        Path::new("/SRV/WEB"),
+                        &filter,
+                        &Page::default()
+                    )
+                    .await
+                    .unwrap()
+            ),
+            [("Web-1".to_string(), 1), ("web-1".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_latest_per_pathするとパスごとに最新のアーカイブだけが取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join("test_a").join(".env");
+        let other_file_path = tmp_dir.path().join("test_b").join(".env");
+        create_dot_env_file(&[
+            (env_file_path.clone(), "FOO=BAR"),
+            (other_file_path.clone(), "FOO=BAZ"),
+        ])
+        .await;
+
+        let now = Utc::now();
+        for (name, minutes_ago) in [("old", 20), ("newest", 0), ("middle", 10)] {
+            archive
+                .push(
+                    &env_file_path,
+                    now - chrono::Duration::minutes(minutes_ago),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        archive
+            .push(&other_file_path, now, "other", None)
+            .await
+            .unwrap();
+
+        let archives = archive
+            .list_latest_per_path(None, &EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 2);
+        assert_eq!(archives[0].name, "newest");
+        assert_eq!(archives[0].created_at, now);
+        assert_eq!(archives[1].name, "other");
+
+        let archives = archive
+            .list_latest_per_path(
+                Some(&tmp_dir.path().join("test_a")),
+                &EntryFilter::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].name, "newest");
+    }
+
+    #[tokio::test]
+    async fn list_latest_per_pathのprefixはlikeのワイルドカードや大文字小文字の違いに一致しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path, OpenOptions::default()).unwrap();
+
+        let now = Utc::now();
+        for (dir, name) in [("a_b", "match"), ("A_B", "upper"), ("axb", "wildcard")] {
+            archive
+                .push_body(
+                    &tmp_dir.path().join(dir).join(".env"),
+                    "A=1",
+                    now,
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let archives = archive
+            .list_latest_per_path(Some(&tmp_dir.path().join("a_b")), &EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            archives
+                .iter()
+                .map(|entry| entry.name.as_str())
+                .collect::<Vec<_>>(),
+            ["match"]
+        );
+        let archives = archive
+            .list_latest_per_path(Some(&tmp_dir.path().join("a%")), &EntryFilter::default())
+            .await
+            .unwrap();
+        assert!(archives.is_empty());
+    }
+
+    #[tokio::test]
+    async fn find_by_pathするとpathに一致するアーカイブの一覧が取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_files = [
+            (tmp_dir.path().join(".env"), "FOO=FIRST"),
+            (tmp_dir.path().join("test_a").join(".env"), "FOO=SECOND"),
+            (
+                tmp_dir.path().join("test_b").join("internal").join(".env"),
+                "FOO=THIRD",
+            ),
+        ];
+        create_dot_env_file(&env_files).await;
+
+        let now = Utc::now();
+        for (n, (env_file_path, _)) in env_files.iter().enumerate() {
+            archive
+                .push(env_file_path, now, n.to_string().as_str(), None)
+                .await
+                .unwrap();
+        }
+
+        let archives = archive
+            .find_by_path(&tmp_dir.path().join(".env"), &EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        for (i, archive) in archives.iter().enumerate() {
+            assert_eq!(archive.name, i.to_string());
+            assert_eq!(archive.path, env_files[i].0.to_string_lossy());
+            assert_eq!(archive.created_at, now);
+        }
+
+        let archives = archive
+            .find_by_path(
+                &tmp_dir.path().join("test_a").join(".env"),
+                &EntryFilter::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].name, "1");
+        assert_eq!(archives[0].path, env_files[1].0.to_string_lossy());
+        assert_eq!(archives[0].created_at, now);
+    }
+
+    #[tokio::test]
+    async fn historyするとpathの全バージョンがサイズ付きで新しい順に取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        let base = Utc::now();
+        for (i, body) in ["FOO=1", "FOO=22", "FOO=333"].iter().enumerate() {
+            create_dot_env_file(&[(env_file_path.clone(), body)]).await;
+            archive
+                .push(
+                    &env_file_path,
+                    base + chrono::Duration::minutes(i as i64),
+                    &format!("v{}", i),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let history = archive
+            .history(&env_file_path, None, &EntryFilter::default())
+            .await
+            .unwrap();
+        let names: Vec<_> = history.iter().map(|(e, _)| e.name.as_str()).collect();
+        assert_eq!(names, ["v2", "v1", "v0"]);
+        let sizes: Vec<_> = history.iter().map(|(_, size)| *size).collect();
+        assert_eq!(sizes, [7, 6, 5]);
+        assert_eq!(
+            history[2].0.checksum,
+            crate::digest::bytes_checksum(b"FOO=1")
+        );
+
+        let history = archive
+            .history(&env_file_path, Some(2), &EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].0.name, "v2");
+
+        let history = archive
+            .history(
+                &tmp_dir.path().join("missing"),
+                None,
+                &EntryFilter::default(),
+            )
+            .await
+            .unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn renameするとアーカイブの名前が変わる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        let now = Utc::now();
+        archive
+            .push(&env_file_path, now, "old", None)
+            .await
+            .unwrap();
+
+        let entry = archive.rename("old", "new").await.unwrap();
+        assert_eq!(entry.name, "new");
+        assert_eq!(entry.path, env_file_path.to_string_lossy());
+        assert_eq!(entry.created_at, now);
+        assert!(archive.get("old").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn renameで変更先の名前が使用済みまたは変更元がない場合はエラーになる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        let now = Utc::now();
+        archive
+            .push(
+                &env_file_path,
+                now - chrono::Duration::minutes(1),
+                "first",
+                None,
+            )
+            .await
+            .unwrap();
+        archive
+            .push(&env_file_path, now, "second", None)
+            .await
+            .unwrap();
+
+        let error = archive.rename("first", "second").await.unwrap_err();
+        assert_eq!(error.to_string(), "archive name second is already in use");
+        assert!(archive.get("first").await.unwrap().is_some());
+
+        let error = archive.rename("missing", "third").await.unwrap_err();
+        assert_eq!(error.to_string(), "archive missing not found");
+    }
+
+    #[tokio::test]
+    async fn タグを付けたアーカイブに絞り込める() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_files = [
+            (tmp_dir.path().join("prod").join(".env"), "FOO=PROD"),
+            (tmp_dir.path().join("staging").join(".env"), "FOO=STAGING"),
+        ];
+        create_dot_env_file(&env_files).await;
+        let now = Utc::now();
+        archive
+            .push(&env_files[0].0, now, "prod", None)
+            .await
+            .unwrap();
+        archive
+            .push(&env_files[1].0, now, "staging", None)
+            .await
+            .unwrap();
+
+        let tags = ["prod".to_string(), "important".to_string()];
+        archive.add_tags("prod", &tags).await.unwrap();
+        archive.add_tags("prod", &tags[..1]).await.unwrap();
+        archive
+            .add_tags("staging", &["important".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(archive.tags("prod").await.unwrap(), ["important", "prod"]);
+
+        let names = |archives: Vec<ArchiveEntry>| {
+            let mut names: Vec<_> = archives.into_iter().map(|e| e.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(archive.list_all(&tagged("prod")).await.unwrap()),
+            ["prod"]
+        );
+        assert_eq!(
+            names(archive.list_all(&tagged("important")).await.unwrap()),
+            ["prod", "staging"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .list_in_path(tmp_dir.path(), &tagged("prod"), &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["prod"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search("staging", &tagged("important"))
+                    .await
+                    .unwrap()
+            ),
+            ["staging"]
+        );
+        assert!(archive
+            .search("staging", &tagged("prod"))
+            .await
+            .unwrap()
+            .is_empty());
+
+        archive.remove_tag("prod", "important").await.unwrap();
+        assert_eq!(
+            names(archive.list_all(&tagged("important")).await.unwrap()),
+            ["staging"]
+        );
+        let error = archive.remove_tag("prod", "important").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "archive prod is not tagged with important"
+        );
+        let error = archive.add_tags("missing", &tags).await.unwrap_err();
+        assert_eq!(error.to_string(), "archive missing not found");
+    }
+
+    #[tokio::test]
+    async fn renameするとタグも引き継がれる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        archive
+            .push(&env_file_path, Utc::now(), "old", None)
+            .await
+            .unwrap();
+        archive
+            .add_tags("old", &["prod".to_string()])
+            .await
+            .unwrap();
+
+        archive.rename("old", "new").await.unwrap();
+        assert_eq!(archive.tags("new").await.unwrap(), ["prod"]);
+        assert!(archive.tags("old").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn restoreは同じ名前のアーカイブがあればoverwriteの場合だけタグごと置き換える() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        archive
+            .push(&env_file_path, Utc::now(), "test-name", None)
+            .await
+            .unwrap();
+        archive
+            .add_tags("test-name", &["old".to_string()])
+            .await
+            .unwrap();
+
+        let entry = ArchiveEntry {
+            name: "test-name".to_string(),
+            path: "/home/user/api/.env".to_string(),
+            created_at: Utc::now() - chrono::Duration::days(1),
+            checksum: crate::digest::bytes_checksum(b"FOO=IMPORTED"),
+            message: None,
+            user: None,
+            quarantined: false,
+            size: 12,
+        };
+        let tags = ["new".to_string()];
+        let restored = archive
+            .restore(&entry, "FOO=IMPORTED", &tags, false)
+            .await
+            .unwrap();
+        assert_eq!(restored, Restored::Skipped);
+        assert_eq!(
+            archive.get("test-name").await.unwrap().unwrap().1,
+            "FOO=BAR"
+        );
+
+        let restored = archive
+            .restore(&entry, "FOO=IMPORTED", &tags, true)
+            .await
+            .unwrap();
+        assert_eq!(restored, Restored::Replaced);
+        assert_eq!(
+            archive.get("test-name").await.unwrap(),
+            Some((entry, "FOO=IMPORTED".to_string()))
+        );
+        assert_eq!(archive.tags("test-name").await.unwrap(), ["new"]);
+    }
+
+    #[tokio::test]
+    async fn ユーザーで絞り込むとすべての一覧で他のユーザーのアーカイブが除かれる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let env_file_path = tmp_dir.path().join("project").join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        let now = Utc::now();
+        for (i, user) in ["alice", "bob"].into_iter().enumerate() {
+            let archive = Archive::open(database_path.clone(), OpenOptions::default())
+                .unwrap()
+                .with_user(Some(user.to_string()));
+            archive
+                .push(
+                    &env_file_path,
+                    now - chrono::Duration::minutes(2 - i as i64),
+                    user,
+                    None,
+                )
+                .await
+                .unwrap();
+            archive
+                .add_tags(user, &["shared".to_string()])
+                .await
+                .unwrap();
+        }
+
+        let archive = Archive::open(database_path, OpenOptions::default()).unwrap();
+        let alice = EntryFilter {
+            user: Some("alice".to_string()),
+            ..Default::default()
+        };
+        let all = EntryFilter::default();
+        let names =
+            |archives: Vec<ArchiveEntry>| archives.into_iter().map(|e| e.name).collect::<Vec<_>>();
+        let dir = tmp_dir.path();
+
+        assert_eq!(names(archive.list_all(&alice).await.unwrap()), ["alice"]);
+        assert_eq!(
+            names(archive.list_all(&all).await.unwrap()),
+            ["bob", "alice"]
+        );
+        let alice_tagged = EntryFilter {
+            tag: Some("shared".to_string()),
+            ..alice.clone()
+        };
+        assert_eq!(
+            names(archive.list_all(&alice_tagged).await.unwrap()),
+            ["alice"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .list_in_path(dir, &alice, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["alice"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .list_in_path(dir, &all, &Page::default())
+                    .await
+                    .unwrap()
+            )
+            .len(),
+            2
+        );
+        assert_eq!(
+            names(archive.search("project", &alice).await.unwrap()),
+            ["alice"]
+        );
+        assert_eq!(
+            names(archive.search("project", &all).await.unwrap()).len(),
+            2
+        );
+        assert_eq!(
+            names(archive.list_latest_per_path(None, &alice).await.unwrap()),
+            ["alice"]
+        );
+        assert_eq!(
+            names(archive.list_latest_per_path(None, &all).await.unwrap()),
+            ["bob"]
+        );
+        assert_eq!(
+            names(archive.find_by_path(&env_file_path, &alice).await.unwrap()),
+            ["alice"]
+        );
+        assert_eq!(
+            names(archive.find_by_path(&env_file_path, &all).await.unwrap()).len(),
+            2
+        );
+        let history = archive.history(&env_file_path, None, &alice).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].0.user.as_deref(), Some("alice"));
+        assert_eq!(
+            archive
+                .history(&env_file_path, None, &all)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        let before = archive
+            .find_by_path_before(&env_file_path, now, &alice)
+            .await
+            .unwrap();
+        assert_eq!(before.unwrap().name, "alice");
+        let before = archive
+            .find_by_path_before(&env_file_path, now, &all)
+            .await
+            .unwrap();
+        assert_eq!(before.unwrap().name, "bob");
+        assert_eq!(archive.list_with_body(None, &alice).await.unwrap().len(), 1);
+        assert_eq!(archive.list_with_body(None, &all).await.unwrap().len(), 2);
+        let summary = archive.summary(10, &alice).await.unwrap();
+        assert_eq!((summary.entries, summary.top_paths[0].versions), (1, 1));
+        assert_eq!(archive.summary(10, &all).await.unwrap().entries, 2);
+        assert_eq!(archive.growth_slots(&alice).await.unwrap()[0].entries, 1);
+    }
+
+    #[tokio::test]
+    async fn タグ導入前のデータベースを開くとタグのテーブルが追加される() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        Connection::open(&database_path)
+            .unwrap()
+            .execute_batch(
+                r#"
+                CREATE TABLE archives (
+                    name TEXT NOT NULL UNIQUE,
+                    path TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    checksum TEXT NOT NULL,
+                    PRIMARY KEY (path, created_at)
+                );
+                INSERT INTO archives VALUES ('legacy', '/tmp/.env', '2024-01-01T00:00:00+00:00', 'FOO=BAR', 'x');
+                "#,
+            )
+            .unwrap();
+
+        let archive = Archive::open(database_path, OpenOptions::default()).unwrap();
+        archive
+            .add_tags("legacy", &["before-migration".to_string()])
+            .await
+            .unwrap();
+        let archives = archive.list_all(&tagged("before-migration")).await.unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].name, "legacy");
+        assert_eq!(archives[0].message, None);
+        assert_eq!(archives[0].user, None);
+        // 登録したユーザーが記録されていないアーカイブはユーザーで絞り込んでも含まれる
+        let filter = EntryFilter {
+            user: Some("alice".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(archive.list_all(&filter).await.unwrap(), archives);
+
+        let entry = archive.annotate("legacy", Some("imported")).await.unwrap();
+        assert_eq!(entry.message.as_deref(), Some("imported"));
+    }
+
+    #[tokio::test]
+    async fn find_by_name_prefixは名前が前方一致するアーカイブを名前の順に返す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let now = Utc::now();
+        for (path, name) in [
+            ("/srv/b/.env", "prod-b"),
+            ("/srv/a/.env", "prod-a"),
+            ("/srv/c/.env", "staging"),
+            ("/srv/d/.env", "prod_%"),
+        ] {
+            archive
+                .push_body(Path::new(path), "FOO=1\n", now, name, None)
+                .await
+                .unwrap();
+        }
+
+        let archive = &archive;
+        let names = |prefix| async move {
+            archive
+                .find_by_name_prefix(prefix)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.name, entry.path))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names("prod-").await,
+            [
+                ("prod-a".to_string(), "/srv/a/.env".to_string()),
+                ("prod-b".to_string(), "/srv/b/.env".to_string())
+            ]
+        );
+        // LIKE のワイルドカードとして扱わない
+        assert_eq!(names("prod_").await.len(), 1);
+        assert_eq!(names("").await.len(), 4);
+        assert!(names("x").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_allは登録した順序によらずパスの昇順かつ新しい順に並べる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let at = |day| Utc.with_ymd_and_hms(2024, 6, day, 0, 0, 0).unwrap();
+        let pushes = [
+            ("/srv/b/.env", at(2), "b-2"),
+            ("/srv/a/.env", at(1), "a-1"),
+            ("/srv/c/.env", at(3), "c-3"),
+            ("/srv/a/.env", at(3), "a-3"),
+            ("/srv/b/.env", at(1), "b-1"),
+            ("/srv/a/.env", at(2), "a-2"),
+        ];
+        for (path, created_at, name) in pushes {
+            archive
+                .push_body(Path::new(path), "FOO=1\n", created_at, name, None)
+                .await
+                .unwrap();
+        }
+
+        let names = archive
+            .list_all(&EntryFilter::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a-3", "a-2", "a-1", "b-2", "b-1", "c-3"]);
+    }
+
+    #[tokio::test]
+    async fn 同じ日時に登録したアーカイブは常に同じ順序で取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let now = Utc::now();
+        for name in ["c", "a", "d", "b"] {
+            let env_file_path = tmp_dir.path().join(name).join(".env");
+            create_dot_env_file(&[(env_file_path.clone(), name)]).await;
+            archive.push(&env_file_path, now, name, None).await.unwrap();
+        }
+        let older = tmp_dir.path().join("e").join(".env");
+        create_dot_env_file(&[(older.clone(), "e")]).await;
+        archive
+            .push(&older, now - chrono::Duration::seconds(1), "e", None)
+            .await
+            .unwrap();
+
+        let names = |archives: Vec<ArchiveEntry>| -> Vec<String> {
+            archives.into_iter().map(|e| e.name).collect()
+        };
+        for _ in 0..3 {
+            assert_eq!(
+                names(archive.list_all(&EntryFilter::default()).await.unwrap()),
+                ["a", "b", "c", "d", "e"]
+            );
+            assert_eq!(
+                names(
+                    archive
+                        .list_in_path(tmp_dir.path(), &EntryFilter::default(), &Page::default())
+                        .await
+                        .unwrap()
+                ),
+                ["d", "c", "b", "a", "e"]
+            );
+            assert_eq!(
+                names(
+                    archive
+                        .search(".env", &EntryFilter::default())
+                        .await
+                        .unwrap()
+                ),
+                ["d", "c", "b", "a", "e"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn pushやannotateで設定したメッセージが取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
+        archive
+            .push(
+                &env_file_path,
+                Utc::now(),
+                "test-name",
+                Some("before rotating DB password"),
+            )
+            .await
+            .unwrap();
+
+        let (entry, _) = archive.get("test-name").await.unwrap().unwrap();
+        assert_eq!(
+            entry.message.as_deref(),
+            Some("before rotating DB password")
+        );
+
+        let entry = archive
+            .annotate("test-name", Some("rotated"))
+            .await
+            .unwrap();
+        assert_eq!(entry.message.as_deref(), Some("rotated"));
+        assert_eq!(
+            archive.list_all(&EntryFilter::default()).await.unwrap()[0]
+                .message
+                .as_deref(),
+            Some("rotated")
+        );
+
+        let entry = archive.annotate("test-name", None).await.unwrap();
+        assert_eq!(entry.message, None);
+
+        let error = archive.annotate("missing", Some("x")).await.unwrap_err();
+        assert_eq!(error.to_string(), "archive missing not found");
+    }
+
+    #[tokio::test]
+    async fn list_with_bodyするとkeywordに一致するアーカイブが本文と共に取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let env_files = [
+            (tmp_dir.path().join("api").join(".env"), "FOO=API"),
+            (tmp_dir.path().join("web").join(".env"), "FOO=WEB"),
+        ];
+        create_dot_env_file(&env_files).await;
+        let now = Utc::now();
+        archive
+            .push(&env_files[0].0, now, "api", None)
+            .await
+            .unwrap();
+        archive
+            .push(&env_files[1].0, now, "web", None)
+            .await
+            .unwrap();
+
+        let archives = archive
+            .list_with_body(None, &EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 2);
 
-        let is_same = archive
-            .check_is_same_by_name("test-name", &env_file_path)
+        let archives = archive
+            .list_with_body(Some("api"), &EntryFilter::default())
             .await
             .unwrap();
-        assert!(!is_same);
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].0.name, "api");
+        assert_eq!(archives[0].1, "FOO=API");
     }
 
     #[tokio::test]
-    async fn pushするとdbに保存される() {
+    async fn find_by_path_beforeするとcutoffより前で最新のアーカイブが取得できる() {
         let tmp_dir = tempfile::tempdir().unwrap();
         let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
-        let env_file_path = tmp_dir.path().join(".env");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
 
+        let env_file_path = tmp_dir.path().join(".env");
         create_dot_env_file(&[(env_file_path.clone(), "FOO=BAR")]).await;
 
-        let now = Utc::now();
-        archive
-            .push(&env_file_path, now, "test-name")
+        let base = Utc::now();
+        for (n, days_ago) in [30, 20, 10].iter().enumerate() {
+            archive
+                .push(
+                    &env_file_path,
+                    base - chrono::Duration::days(*days_ago),
+                    n.to_string().as_str(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let cutoff = base - chrono::Duration::days(15);
+        let entry = archive
+            .find_by_path_before(&env_file_path, cutoff, &EntryFilter::default())
             .await
+            .unwrap()
             .unwrap();
+        assert_eq!(entry.name, "1");
 
-        let conn = Connection::open(&database_path).unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM archives").unwrap();
-        let rows = stmt
-            .query_map([], |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, String>(3)?,
-                ))
-            })
+        let cutoff = base - chrono::Duration::days(20);
+        let entry = archive
+            .find_by_path_before(&env_file_path, cutoff, &EntryFilter::default())
+            .await
+            .unwrap()
             .unwrap();
+        assert_eq!(entry.name, "0");
 
-        let row = rows.into_iter().next().unwrap().unwrap();
-        assert_eq!(row.0, "test-name");
-        assert_eq!(row.1, env_file_path.to_string_lossy());
-        assert_eq!(row.2, now.to_rfc3339());
-        assert_eq!(row.3, "FOO=BAR");
+        let cutoff = base - chrono::Duration::days(30);
+        let entry = archive
+            .find_by_path_before(&env_file_path, cutoff, &EntryFilter::default())
+            .await
+            .unwrap();
+        assert!(entry.is_none());
     }
 
     #[tokio::test]
-    async fn list_allするとdbに保存されたすべてのアーカイブの一覧が取得できる() {
+    async fn getするとnameに一致するアーカイブが取得できる() {
         let tmp_dir = tempfile::tempdir().unwrap();
         let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
 
         let env_files = [
             (tmp_dir.path().join(".env"), "FOO=FIRST"),
@@ -385,95 +3830,302 @@ mod tests {
         let now = Utc::now();
         for (n, (env_file_path, _)) in env_files.iter().enumerate() {
             archive
-                .push(env_file_path, now, n.to_string().as_str())
+                .push(env_file_path, now, n.to_string().as_str(), None)
                 .await
                 .unwrap();
         }
 
-        let archives = archive.list_all().await.unwrap();
-        assert_eq!(archives.len(), 3);
-        for (
-            i,
-            ArchiveEntry {
-                name,
-                path,
-                created_at,
-                checksum,
-            },
-        ) in archives.iter().enumerate()
-        {
-            assert_eq!(name, &i.to_string());
-            assert_eq!(path, &env_files[i].0.to_string_lossy());
-            assert_eq!(created_at, &now);
-            assert_eq!(
-                checksum,
-                &crate::digest::file_checksum(&env_files[i].0).await.unwrap()
-            );
-        }
+        let (entry, body) = archive.get("1").await.unwrap().unwrap();
+        assert_eq!(entry.name, "1");
+        assert_eq!(entry.path, env_files[1].0.to_string_lossy());
+        assert_eq!(entry.created_at, now);
+        assert_eq!(body, env_files[1].1);
     }
 
     #[tokio::test]
-    async fn list_in_pathするとpath配下のアーカイブの一覧が取得できる() {
+    async fn growth_slotsすると15分単位の登録件数とバイト数が取得できる() {
         let tmp_dir = tempfile::tempdir().unwrap();
         let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
 
         let env_files = [
-            (tmp_dir.path().join(".env"), "FOO=FIRST"),
-            (tmp_dir.path().join("test_a").join(".env"), "FOO=SECOND"),
-            (
-                tmp_dir.path().join("test_b").join("internal").join(".env"),
-                "FOO=THIRD",
-            ),
+            (tmp_dir.path().join("test_a").join(".env"), "FOO=あ"),
+            (tmp_dir.path().join("test_b").join(".env"), "FOO=BAR"),
+            (tmp_dir.path().join("test_c").join(".env"), "FOO=BAZ"),
         ];
         create_dot_env_file(&env_files).await;
 
+        let timestamps = [
+            Utc.with_ymd_and_hms(2024, 1, 31, 14, 50, 0).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 31, 14, 59, 59).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 31, 15, 0, 0).unwrap(),
+        ];
+        for (n, ((env_file_path, _), now)) in env_files.iter().zip(timestamps).enumerate() {
+            archive
+                .push(env_file_path, now, n.to_string().as_str(), None)
+                .await
+                .unwrap();
+        }
+
+        let slots = archive.growth_slots(&EntryFilter::default()).await.unwrap();
+        assert_eq!(
+            slots,
+            vec![
+                GrowthSlot {
+                    start: Utc.with_ymd_and_hms(2024, 1, 31, 14, 45, 0).unwrap(),
+                    entries: 2,
+                    bytes: 14,
+                },
+                GrowthSlot {
+                    start: Utc.with_ymd_and_hms(2024, 1, 31, 15, 0, 0).unwrap(),
+                    entries: 1,
+                    bytes: 7,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn summaryするとアーカイブ全体の件数とバイト数とパスごとの件数が取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+
+        let empty = archive.summary(10, &EntryFilter::default()).await.unwrap();
+        assert_eq!((empty.entries, empty.bytes, empty.oldest), (0, 0, None));
+        assert!(empty.top_paths.is_empty());
+
+        let oldest = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let pushes = [
+            ("a", "FOO=あ"),
+            ("a", "FOO=BAR"),
+            ("b", "B=1"),
+            ("a", "A"),
+            ("c", "C"),
+        ];
+        for (i, (dir, body)) in pushes.into_iter().enumerate() {
+            archive
+                .push_body(
+                    &tmp_dir.path().join(dir).join(".env"),
+                    body,
+                    oldest + chrono::Duration::days(i as i64),
+                    &i.to_string(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let summary = archive.summary(2, &EntryFilter::default()).await.unwrap();
+        assert_eq!(summary.entries, 5);
+        assert_eq!(summary.paths, 3);
+        assert_eq!(summary.bytes, 7 + 7 + 3 + 1 + 1);
+        assert_eq!(summary.oldest, Some(oldest));
+        assert_eq!(summary.newest, Some(oldest + chrono::Duration::days(4)));
+        let path = |dir: &str| {
+            tmp_dir
+                .path()
+                .join(dir)
+                .join(".env")
+                .to_string_lossy()
+                .to_string()
+        };
+        assert_eq!(
+            summary.top_paths,
+            vec![
+                PathVersions {
+                    path: path("a"),
+                    versions: 3,
+                },
+                PathVersions {
+                    path: path("b"),
+                    versions: 1,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn 隔離されているアーカイブはパスごとの最新として扱わない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        let env_path = tmp_dir.path().join(".env");
         let now = Utc::now();
-        for (n, (env_file_path, _)) in env_files.iter().enumerate() {
+        for (i, name) in ["older", "newer"].into_iter().enumerate() {
             archive
-                .push(env_file_path, now, n.to_string().as_str())
+                .push_body(
+                    &env_path,
+                    name,
+                    now + chrono::Duration::seconds(i as i64),
+                    name,
+                    None,
+                )
                 .await
                 .unwrap();
         }
 
-        let archives = archive.list_in_path(tmp_dir.path()).await.unwrap();
-        assert_eq!(archives.len(), 3);
-        for (
-            i,
-            ArchiveEntry {
-                name,
-                path,
-                created_at,
-                checksum,
-            },
-        ) in archives.iter().enumerate()
-        {
-            assert_eq!(name, &i.to_string());
-            assert_eq!(path, &env_files[i].0.to_string_lossy());
-            assert_eq!(created_at, &now);
-            assert_eq!(
-                checksum,
-                &crate::digest::file_checksum(&env_files[i].0).await.unwrap()
-            );
+        archive.set_quarantined("newer", true).await.unwrap();
+        let latest = archive
+            .list_latest_per_path(None, &EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].name, "older");
+        let (entry, _) = archive.get("newer").await.unwrap().unwrap();
+        assert!(entry.quarantined);
+        let before = archive
+            .find_by_path_before(
+                &env_path,
+                now + chrono::Duration::days(1),
+                &EntryFilter::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(before.name, "older");
+
+        archive.set_quarantined("newer", false).await.unwrap();
+        let latest = archive
+            .list_latest_per_path(None, &EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(latest[0].name, "newer");
+        assert!(archive.set_quarantined("missing", true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn for_each_with_bodyはすべてのアーカイブを本文と共に渡しdeleteはタグごと削除する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        for name in ["a", "b", "c"] {
+            archive
+                .push_body(
+                    &tmp_dir.path().join(name).join(".env"),
+                    &format!("NAME={}", name),
+                    now,
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
         }
+        archive.add_tags("b", &["prod".to_string()]).await.unwrap();
 
-        let archives = archive
-            .list_in_path(&tmp_dir.path().join("test_a"))
+        let all = EntryFilter::default();
+        let mut visited = Vec::new();
+        archive
+            .for_each_with_body(None, &all, |entry, body| {
+                visited.push((entry.name, body?));
+                Ok(())
+            })
             .await
             .unwrap();
-        assert_eq!(archives.len(), 1);
-        assert_eq!(archives[0].name, "1");
-        assert_eq!(archives[0].path, env_files[1].0.to_string_lossy());
-        assert_eq!(archives[0].created_at, now);
+        assert_eq!(
+            visited,
+            [("c", "NAME=c"), ("b", "NAME=b"), ("a", "NAME=a")]
+                .map(|(name, body)| (name.to_string(), body.to_string()))
+        );
+        assert_eq!(archive.count(None, &all).await.unwrap(), 3);
+
+        // パスの前方一致と登録日時で対象を絞り込める
+        let prefix = tmp_dir.path().join("b");
+        let mut visited = Vec::new();
+        archive
+            .for_each_with_body(Some(&prefix), &all, |entry, _| {
+                visited.push(entry.name);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(visited, ["b"]);
+        assert_eq!(archive.count(Some(&prefix), &all).await.unwrap(), 1);
+        let future = EntryFilter {
+            since: Some(now + chrono::Duration::days(1)),
+            ..Default::default()
+        };
+        archive
+            .for_each_with_body(None, &future, |_, _| unreachable!("no archive is newer"))
+            .await
+            .unwrap();
+        assert_eq!(archive.count(None, &future).await.unwrap(), 0);
+
+        let mut count = 0;
+        let error = archive
+            .for_each_with_body(None, &all, |_, _| {
+                count += 1;
+                anyhow::bail!("stop")
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "stop");
+        assert_eq!(count, 1);
+
+        archive.delete("b").await.unwrap();
+        assert!(archive.get("b").await.unwrap().is_none());
+        assert!(archive.tags("b").await.unwrap().is_empty());
+        assert!(archive.delete("b").await.is_err());
     }
 
     #[tokio::test]
-    async fn find_by_pathするとpathに一致するアーカイブの一覧が取得できる() {
+    async fn for_each_body_likeは本文がパターンに一致するアーカイブだけを渡す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        let api = tmp_dir.path().join("api").join(".env");
+        let web = tmp_dir.path().join("web").join(".env");
+        let now = Utc::now();
+        for (path, name, body, seconds) in [
+            (&api, "api-old", "A=1\nKEY=sk_live_old\n", 0),
+            (&api, "api-new", "A=1\nKEY=rotated\n", 1),
+            (&web, "web", "# sk_live\nKEY=sk_live_web\nB=skxlive\n", 0),
+            (&web, "web-x", "B=skxlive\n", -1),
+        ] {
+            archive
+                .push_body(
+                    path,
+                    body,
+                    now + chrono::Duration::seconds(seconds),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let names = |like: Option<&'static str>, latest| {
+            let archive = &archive;
+            async move {
+                let mut names = Vec::new();
+                archive
+                    .for_each_body_like(like, latest, &EntryFilter::default(), |entry, _| {
+                        names.push(entry.name);
+                        Ok(())
+                    })
+                    .await
+                    .unwrap();
+                names
+            }
+        };
+
+        // _ は任意の 1 文字ではなく文字そのものとして照合する
+        assert_eq!(names(Some("%sk\\_live%"), false).await, ["web", "api-old"]);
+        assert_eq!(names(Some("%sk\\_live%"), true).await, ["web"]);
+        assert_eq!(names(None, true).await, ["api-new", "web"]);
+        assert_eq!(names(None, false).await.len(), 4);
+
+        // 隔離されているアーカイブは渡さない
+        archive.set_quarantined("api-old", true).await.unwrap();
+        assert_eq!(names(Some("%sk\\_live%"), false).await, ["web"]);
+        assert_eq!(names(None, false).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn searchするとkeywordに一致するアーカイブが取得できる() {
         let tmp_dir = tempfile::tempdir().unwrap();
         let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
 
         let env_files = [
             (tmp_dir.path().join(".env"), "FOO=FIRST"),
@@ -488,96 +4140,365 @@ mod tests {
         let now = Utc::now();
         for (n, (env_file_path, _)) in env_files.iter().enumerate() {
             archive
-                .push(env_file_path, now, n.to_string().as_str())
+                .push(env_file_path, now, n.to_string().as_str(), None)
                 .await
                 .unwrap();
         }
 
         let archives = archive
-            .find_by_path(&tmp_dir.path().join(".env"))
+            .search("test_", &EntryFilter::default())
             .await
             .unwrap();
-        assert_eq!(archives.len(), 1);
-        for (i, archive) in archives.iter().enumerate() {
-            assert_eq!(archive.name, i.to_string());
-            assert_eq!(archive.path, env_files[i].0.to_string_lossy());
-            assert_eq!(archive.created_at, now);
-        }
+        assert_eq!(archives.len(), 2);
 
         let archives = archive
-            .find_by_path(&tmp_dir.path().join("test_a").join(".env"))
+            .search("test_a", &EntryFilter::default())
             .await
             .unwrap();
         assert_eq!(archives.len(), 1);
         assert_eq!(archives[0].name, "1");
         assert_eq!(archives[0].path, env_files[1].0.to_string_lossy());
         assert_eq!(archives[0].created_at, now);
+
+        let regex = regex::Regex::new(r"/test_(a|b/internal)/\.env$").unwrap();
+        let archives = archive
+            .search_regex(
+                &[regex],
+                &[],
+                false,
+                &EntryFilter::default(),
+                &Page::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            archives
+                .iter()
+                .map(|entry| entry.name.as_str())
+                .collect::<Vec<_>>(),
+            ["2", "1"]
+        );
     }
 
     #[tokio::test]
-    async fn getするとnameに一致するアーカイブが取得できる() {
+    async fn search_latestはkeywordに一致するパスごとに最新のアーカイブだけを取得する() {
         let tmp_dir = tempfile::tempdir().unwrap();
-        let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        let pushes = [
+            ("/srv/web/.env", "web-1", 3),
+            ("/srv/api/.env", "api-1", 3),
+            ("/srv/api/.env", "api-2", 2),
+            ("/srv/api/.env", "api-3", 1),
+            ("/srv/api-backup/.env", "backup-1", 1),
+        ];
+        for (path, name, hours_ago) in pushes {
+            archive
+                .push_body(
+                    Path::new(path),
+                    &format!("NAME={}\n", name),
+                    now - chrono::Duration::hours(hours_ago),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        // 隔離されている最新のアーカイブは除き、その前のアーカイブを最新とする
+        archive.set_quarantined("api-3", true).await.unwrap();
+        let filter = EntryFilter::default();
+        let names = |archives: Vec<ArchiveEntry>| {
+            archives
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
 
-        let env_files = [
-            (tmp_dir.path().join(".env"), "FOO=FIRST"),
-            (tmp_dir.path().join("test_a").join(".env"), "FOO=SECOND"),
-            (
-                tmp_dir.path().join("test_b").join("internal").join(".env"),
-                "FOO=THIRD",
+        assert_eq!(
+            names(
+                archive
+                    .search_latest(&["api"], &[], &filter, &Page::default())
+                    .await
+                    .unwrap()
             ),
-        ];
-        create_dot_env_file(&env_files).await;
+            ["backup-1", "api-2"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search_latest(&["api"], &["backup"], &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["api-2"]
+        );
+        let regex = regex::Regex::new(r"^/srv/(api|web)/").unwrap();
+        assert_eq!(
+            names(
+                archive
+                    .search_regex(&[regex], &[], true, &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["api-2", "web-1"]
+        );
+    }
 
+    #[tokio::test]
+    async fn search_keywordsはすべてのkeywordを含みexcludesを含まないパスに一致する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
         let now = Utc::now();
-        for (n, (env_file_path, _)) in env_files.iter().enumerate() {
+        let paths = [
+            "/srv/api/staging/.env",
+            "/srv/api/staging-backup/.env",
+            "/srv/api/production/.env",
+            "/srv/web/staging/.env",
+        ];
+        for (n, path) in paths.iter().enumerate() {
             archive
-                .push(env_file_path, now, n.to_string().as_str())
+                .push_body(Path::new(path), "FOO=1\n", now, &n.to_string(), None)
                 .await
                 .unwrap();
         }
+        let filter = EntryFilter::default();
+        let names = |archives: Vec<ArchiveEntry>| {
+            archives
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
 
-        let (entry, body) = archive.get("1").await.unwrap().unwrap();
-        assert_eq!(entry.name, "1");
-        assert_eq!(entry.path, env_files[1].0.to_string_lossy());
-        assert_eq!(entry.created_at, now);
-        assert_eq!(body, env_files[1].1);
+        assert_eq!(
+            names(
+                archive
+                    .search_keywords(&["api", "staging"], &[], &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["1", "0"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search_keywords(&["api", "staging"], &["backup"], &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["0"]
+        );
+        // LIKE の特殊文字を含む keyword もそのまま部分一致させる
+        assert!(archive
+            .search_keywords(&["%"], &[], &filter, &Page::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            names(
+                archive
+                    .search_ignore_case(
+                        &["API", "Staging"],
+                        &["BACKUP"],
+                        false,
+                        &filter,
+                        &Page::default()
+                    )
+                    .await
+                    .unwrap()
+            ),
+            ["0"]
+        );
     }
 
     #[tokio::test]
-    async fn searchするとkeywordに一致するアーカイブが取得できる() {
+    async fn ignore_caseの場合だけ大文字を含むパスが小文字のkeywordに一致する() {
         let tmp_dir = tempfile::tempdir().unwrap();
-        let database_path = tmp_dir.path().join("test.db");
-        let archive = Archive::new(database_path.clone());
-        archive.initialize().await.unwrap();
+        let archive =
+            Archive::open(tmp_dir.path().join("test.db"), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        let paths = ["/Users/Me/Projects/API/.env", "/users/me/projects/web/.env"];
+        for (n, path) in paths.iter().enumerate() {
+            archive
+                .push_body(Path::new(path), "FOO=1\n", now, &n.to_string(), None)
+                .await
+                .unwrap();
+        }
+        let filter = EntryFilter::default();
+        let names = |archives: Vec<ArchiveEntry>| {
+            archives
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
 
-        let env_files = [
-            (tmp_dir.path().join(".env"), "FOO=FIRST"),
-            (tmp_dir.path().join("test_a").join(".env"), "FOO=SECOND"),
-            (
-                tmp_dir.path().join("test_b").join("internal").join(".env"),
-                "FOO=THIRD",
+        assert!(archive
+            .search("projects/api", &filter)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            names(
+                archive
+                    .search_ignore_case(&["projects/api"], &[], false, &filter, &Page::default())
+                    .await
+                    .unwrap()
             ),
-        ];
-        create_dot_env_file(&env_files).await;
+            ["0"]
+        );
+        assert_eq!(
+            names(archive.search("projects", &filter).await.unwrap()),
+            ["1"]
+        );
+        assert_eq!(
+            names(
+                archive
+                    .search_ignore_case(&["projects"], &[], false, &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["1", "0"]
+        );
+
+        let dir = Path::new("/users/me/projects/api");
+        assert!(archive
+            .list_in_path(dir, &filter, &Page::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            names(
+                archive
+                    .list_in_path_ignore_case(dir, &filter, &Page::default())
+                    .await
+                    .unwrap()
+            ),
+            ["0"]
+        );
+    }
 
+    #[tokio::test]
+    async fn move_to_coldした本文はブロブから読み出しrestore_from_coldでデータベースに戻す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
         let now = Utc::now();
-        for (n, (env_file_path, _)) in env_files.iter().enumerate() {
+        let env_file_path = tmp_dir.path().join(".env");
+        // old-1 と old-2 は同じ本文のため 1 つのブロブを共有する
+        for (name, body, days_ago) in [
+            ("old-1", "FOO=old", 400),
+            ("old-2", "FOO=old", 300),
+            ("new", "FOO=new", 1),
+        ] {
             archive
-                .push(env_file_path, now, n.to_string().as_str())
+                .push_body(
+                    &env_file_path,
+                    body,
+                    now - chrono::Duration::days(days_ago),
+                    name,
+                    None,
+                )
                 .await
                 .unwrap();
         }
+        let cold_dir = tmp_dir.path().join("cold");
+        fs::create_dir(&cold_dir).unwrap();
 
-        let archives = archive.search("test_").await.unwrap();
-        assert_eq!(archives.len(), 2);
+        let moved = archive
+            .move_to_cold(now - chrono::Duration::days(365 / 2), &cold_dir)
+            .await
+            .unwrap();
+        assert_eq!(moved, ["old-2", "old-1"]);
+        assert_eq!(fs::read_dir(&cold_dir).unwrap().count(), 1);
+        assert_eq!(archive.get("old-1").await.unwrap().unwrap().1, "FOO=old");
+        // 既に移した本文は移さない
+        let moved = archive.move_to_cold(now, &cold_dir).await.unwrap();
+        assert_eq!(moved, ["new"]);
+        let history = archive
+            .history(&env_file_path, None, &EntryFilter::default())
+            .await
+            .unwrap();
+        assert!(history.iter().all(|(_, bytes)| *bytes == 7));
 
-        let archives = archive.search("test_a").await.unwrap();
-        assert_eq!(archives.len(), 1);
-        assert_eq!(archives[0].name, "1");
-        assert_eq!(archives[0].path, env_files[1].0.to_string_lossy());
-        assert_eq!(archives[0].created_at, now);
+        // マウントが外れた状態では本文を読み出せないが、本文を使わない操作はできる
+        let unmounted = tmp_dir.path().join("unmounted");
+        fs::rename(&cold_dir, &unmounted).unwrap();
+        let error = archive.get("old-1").await.unwrap_err();
+        assert!(crate::cold::is_unavailable(&error));
+        assert!(error.to_string().starts_with("cold body unavailable"));
+        assert!(crate::cold::is_unavailable(
+            &archive.restore_from_cold().await.unwrap_err()
+        ));
+        assert_eq!(archive.entry("old-1").await.unwrap().unwrap().name, "old-1");
+        let mut unavailable = Vec::new();
+        archive
+            .for_each_with_body(None, &EntryFilter::default(), |entry, body| {
+                assert!(body.is_err());
+                unavailable.push(entry.name);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(unavailable.len(), 3);
+        assert_eq!(
+            archive
+                .list_all(&EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+        archive.rename("old-1", "oldest").await.unwrap();
+        assert_eq!(
+            archive
+                .summary(1, &EntryFilter::default())
+                .await
+                .unwrap()
+                .bytes,
+            21
+        );
+
+        fs::rename(&unmounted, &cold_dir).unwrap();
+        let mut restored = archive.restore_from_cold().await.unwrap();
+        restored.sort();
+        assert_eq!(restored, ["new", "old-2", "oldest"]);
+        assert_eq!(archive.get("oldest").await.unwrap().unwrap().1, "FOO=old");
+        assert_eq!(archive.get("new").await.unwrap().unwrap().1, "FOO=new");
+        // 戻したブロブは削除する
+        assert_eq!(fs::read_dir(&cold_dir).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn list_pathsするとパターンに一致するパスをページごとに取得できる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database_path = tmp_dir.path().join("test.db");
+        let archive = Archive::open(database_path.clone(), OpenOptions::default()).unwrap();
+        let now = Utc::now();
+        for (i, dir) in ["api", "api", "web", "w_b"].into_iter().enumerate() {
+            archive
+                .push_body(
+                    &Path::new("/work").join(dir).join(".env"),
+                    "FOO=1",
+                    now - chrono::Duration::minutes(i as i64),
+                    &i.to_string(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let all = EntryFilter::default();
+        assert_eq!(
+            archive.list_paths("%", 0, 10, &all).await.unwrap(),
+            ["/work/api/.env", "/work/w_b/.env", "/work/web/.env"]
+        );
+        assert_eq!(
+            archive.list_paths("%", 1, 1, &all).await.unwrap(),
+            ["/work/w_b/.env"]
+        );
+        assert_eq!(
+            archive.list_paths("%w\\_b%", 0, 10, &all).await.unwrap(),
+            ["/work/w_b/.env"]
+        );
     }
 }
@@ -0,0 +1,927 @@
+use crate::archive::ArchiveEntry;
+use crate::dotenv::{self, Item};
+use crate::progress;
+use chrono_tz::Tz;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+/// 一度に読み込むパスの件数
+pub const PATHS_PAGE: usize = 200;
+/// 一度に読み込むバージョンの件数
+pub const VERSIONS_PAGE: usize = 100;
+/// 読み込んだ一覧の末尾からこの件数以内まで選択が進んだら続きを読み込む
+const PREFETCH: usize = 20;
+/// PageUp, PageDown で移動する件数
+const PAGE_STEP: usize = 10;
+
+/// 選択を移動する一覧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Paths,
+    Versions,
+}
+
+/// キー入力の受け付け方
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    /// パスの絞り込みを入力している
+    Filter,
+    /// 選択しているアーカイブに付けるタグを入力している
+    Tag(String),
+    /// 操作を実行してよいかを確認している
+    Confirm(Effect),
+}
+
+/// プレビューに表示する内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preview {
+    Empty,
+    /// 値を伏せた本文
+    Body(String),
+    /// ディスク上のファイルとのキーの差分 空の場合は差分がない
+    Diff(Vec<String>),
+    Error(String),
+}
+
+/// update に渡すメッセージ
+#[derive(Debug)]
+pub enum Msg {
+    Key(KeyEvent),
+    /// Effect::LoadPaths で読み込んだパス
+    Paths {
+        offset: usize,
+        limit: usize,
+        paths: Vec<String>,
+    },
+    /// Effect::LoadVersions で読み込んだバージョンと本文のバイト数
+    Versions {
+        path: String,
+        versions: Vec<(ArchiveEntry, u64)>,
+    },
+    /// Effect::LoadBody で読み込んだ本文
+    Body {
+        name: String,
+        body: Result<String, String>,
+    },
+    /// Effect::Diff で求めた差分
+    Diff {
+        name: String,
+        lines: Result<Vec<String>, String>,
+    },
+    /// 復元、タグ付け、削除が終わった
+    /// 結果を表示して一覧を読み込み直す
+    Done(String),
+    /// 副作用の実行に失敗した
+    Failed(String),
+}
+
+/// update が実行を求める副作用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// pattern は Archive::list_paths に渡す LIKE のパターン
+    LoadPaths {
+        pattern: String,
+        offset: usize,
+        limit: usize,
+    },
+    LoadVersions {
+        path: String,
+        limit: usize,
+    },
+    LoadBody {
+        name: String,
+    },
+    /// アーカイブとアーカイブ元のファイルの差分を求める
+    Diff {
+        name: String,
+    },
+    /// アーカイブ元のパスに復元する
+    Recover {
+        name: String,
+    },
+    AddTag {
+        name: String,
+        tag: String,
+    },
+    Delete {
+        name: String,
+    },
+    Quit,
+}
+
+/// browse の画面の状態
+///
+/// 状態は update だけが変更し、データベースやファイルへのアクセスは update が返す Effect を
+/// 呼び出し側が実行して Msg として戻す
+#[derive(Debug)]
+pub struct State {
+    /// パスの絞り込み 文字がこの順に含まれるパスに限る
+    pub filter: String,
+    pub mode: Mode,
+    pub focus: Pane,
+    pub paths: Vec<String>,
+    /// パスをすべて読み込んだ
+    paths_exhausted: bool,
+    /// パスを読み込んでいる
+    loading_paths: bool,
+    pub path_index: usize,
+    pub versions: Vec<(ArchiveEntry, u64)>,
+    /// バージョンを読み込む件数
+    versions_limit: usize,
+    pub version_index: usize,
+    pub preview: Preview,
+    pub status: Option<String>,
+    pub timezone: Tz,
+    /// パスを伏せて表示する
+    pub private: bool,
+}
+
+impl State {
+    /// 最初に実行する副作用と共に状態を作る
+    pub fn new(timezone: Tz, private: bool) -> (Self, Vec<Effect>) {
+        let mut state = Self {
+            filter: String::new(),
+            mode: Mode::Normal,
+            focus: Pane::Paths,
+            paths: Vec::new(),
+            paths_exhausted: false,
+            loading_paths: false,
+            path_index: 0,
+            versions: Vec::new(),
+            versions_limit: VERSIONS_PAGE,
+            version_index: 0,
+            preview: Preview::Empty,
+            status: None,
+            timezone,
+            private,
+        };
+        let effects = vec![state.load_paths(0, PATHS_PAGE)];
+        (state, effects)
+    }
+
+    pub fn selected_path(&self) -> Option<&str> {
+        self.paths.get(self.path_index).map(String::as_str)
+    }
+
+    pub fn selected_entry(&self) -> Option<&ArchiveEntry> {
+        self.versions
+            .get(self.version_index)
+            .map(|(entry, _)| entry)
+    }
+
+    fn load_paths(&mut self, offset: usize, limit: usize) -> Effect {
+        self.loading_paths = true;
+        Effect::LoadPaths {
+            pattern: fuzzy_pattern(&self.filter),
+            offset,
+            limit,
+        }
+    }
+
+    /// 選択しているパスのバージョンを読み込み直す
+    fn select_path(&mut self) -> Vec<Effect> {
+        self.versions.clear();
+        self.versions_limit = VERSIONS_PAGE;
+        self.version_index = 0;
+        self.preview = Preview::Empty;
+        match self.selected_path() {
+            Some(path) => vec![Effect::LoadVersions {
+                path: path.to_string(),
+                limit: self.versions_limit,
+            }],
+            None => Vec::new(),
+        }
+    }
+
+    fn select_version(&mut self) -> Vec<Effect> {
+        match self.selected_entry() {
+            Some(entry) => vec![Effect::LoadBody {
+                name: entry.name.clone(),
+            }],
+            None => {
+                self.preview = Preview::Empty;
+                Vec::new()
+            }
+        }
+    }
+
+    /// 絞り込みが変わったのでパスを最初から読み込み直す
+    fn refilter(&mut self) -> Vec<Effect> {
+        self.path_index = 0;
+        vec![self.load_paths(0, PATHS_PAGE)]
+    }
+
+    fn move_selection(&mut self, delta: isize) -> Vec<Effect> {
+        match self.focus {
+            Pane::Paths => {
+                let Some(index) = moved(self.path_index, delta, self.paths.len()) else {
+                    return Vec::new();
+                };
+                self.path_index = index;
+                let mut effects = self.select_path();
+                if !self.paths_exhausted
+                    && !self.loading_paths
+                    && index + PREFETCH >= self.paths.len()
+                {
+                    effects.push(self.load_paths(self.paths.len(), PATHS_PAGE));
+                }
+                effects
+            }
+            Pane::Versions => {
+                let Some(index) = moved(self.version_index, delta, self.versions.len()) else {
+                    return Vec::new();
+                };
+                self.version_index = index;
+                let mut effects = self.select_version();
+                if self.versions.len() == self.versions_limit
+                    && index + PREFETCH >= self.versions.len()
+                {
+                    self.versions_limit += VERSIONS_PAGE;
+                    if let Some(path) = self.selected_path() {
+                        effects.push(Effect::LoadVersions {
+                            path: path.to_string(),
+                            limit: self.versions_limit,
+                        });
+                    }
+                }
+                effects
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Vec<Effect> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return vec![Effect::Quit];
+        }
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.handle_normal_key(key),
+            Mode::Filter => match key.code {
+                KeyCode::Char(c) => {
+                    self.mode = Mode::Filter;
+                    self.filter.push(c);
+                    self.refilter()
+                }
+                KeyCode::Backspace => {
+                    self.mode = Mode::Filter;
+                    if self.filter.pop().is_some() {
+                        self.refilter()
+                    } else {
+                        Vec::new()
+                    }
+                }
+                KeyCode::Esc if !self.filter.is_empty() => {
+                    self.filter.clear();
+                    self.refilter()
+                }
+                KeyCode::Enter | KeyCode::Esc => Vec::new(),
+                _ => {
+                    self.mode = Mode::Filter;
+                    Vec::new()
+                }
+            },
+            Mode::Tag(mut input) => match key.code {
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    self.mode = Mode::Tag(input);
+                    Vec::new()
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.mode = Mode::Tag(input);
+                    Vec::new()
+                }
+                KeyCode::Enter => match (self.selected_entry(), input.trim()) {
+                    (Some(entry), tag) if !tag.is_empty() => vec![Effect::AddTag {
+                        name: entry.name.clone(),
+                        tag: tag.to_string(),
+                    }],
+                    _ => Vec::new(),
+                },
+                KeyCode::Esc => Vec::new(),
+                _ => {
+                    self.mode = Mode::Tag(input);
+                    Vec::new()
+                }
+            },
+            Mode::Confirm(effect) => match key.code {
+                KeyCode::Char('y') => vec![effect],
+                _ => {
+                    self.status = Some("cancelled".to_string());
+                    Vec::new()
+                }
+            },
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent) -> Vec<Effect> {
+        let selected = self.selected_entry().map(|entry| entry.name.clone());
+        match (key.code, selected) {
+            (KeyCode::Char('q') | KeyCode::Esc, _) => vec![Effect::Quit],
+            (KeyCode::Down | KeyCode::Char('j'), _) => self.move_selection(1),
+            (KeyCode::Up | KeyCode::Char('k'), _) => self.move_selection(-1),
+            (KeyCode::PageDown, _) => self.move_selection(PAGE_STEP as isize),
+            (KeyCode::PageUp, _) => self.move_selection(-(PAGE_STEP as isize)),
+            (KeyCode::Left | KeyCode::Char('h'), _) => {
+                self.focus = Pane::Paths;
+                Vec::new()
+            }
+            (KeyCode::Right | KeyCode::Char('l'), _) => {
+                self.focus = Pane::Versions;
+                Vec::new()
+            }
+            (KeyCode::Tab, _) => {
+                self.focus = match self.focus {
+                    Pane::Paths => Pane::Versions,
+                    Pane::Versions => Pane::Paths,
+                };
+                Vec::new()
+            }
+            (KeyCode::Char('/'), _) => {
+                self.mode = Mode::Filter;
+                Vec::new()
+            }
+            (KeyCode::Enter, Some(_)) => self.select_version(),
+            (KeyCode::Char('d'), Some(name)) => vec![Effect::Diff { name }],
+            (KeyCode::Char('r'), Some(name)) => {
+                self.mode = Mode::Confirm(Effect::Recover { name });
+                Vec::new()
+            }
+            (KeyCode::Char('t'), Some(_)) => {
+                self.mode = Mode::Tag(String::new());
+                Vec::new()
+            }
+            (KeyCode::Char('D') | KeyCode::Delete, Some(name)) => {
+                self.mode = Mode::Confirm(Effect::Delete { name });
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// メッセージを受け取って状態を更新し、実行する副作用を返す
+/// 入出力を行わないため、キー操作をそのまま検証できる
+pub fn update(state: &mut State, msg: Msg) -> Vec<Effect> {
+    match msg {
+        Msg::Key(key) => state.handle_key(key),
+        Msg::Paths {
+            offset,
+            limit,
+            paths,
+        } => {
+            state.loading_paths = false;
+            state.paths_exhausted = paths.len() < limit;
+            if offset > 0 {
+                state.paths.extend(paths);
+                return Vec::new();
+            }
+            state.paths = paths;
+            state.path_index = state.path_index.min(state.paths.len().saturating_sub(1));
+            state.select_path()
+        }
+        Msg::Versions { path, versions } => {
+            if state.selected_path() != Some(path.as_str()) {
+                return Vec::new();
+            }
+            let selected = state.selected_entry().map(|entry| entry.name.clone());
+            state.versions = versions;
+            state.version_index = state
+                .version_index
+                .min(state.versions.len().saturating_sub(1));
+            // 続きを読み込んだだけで選択が変わっていなければ本文を読み込み直さない
+            if selected.is_some()
+                && state.selected_entry().map(|entry| &entry.name) == selected.as_ref()
+            {
+                return Vec::new();
+            }
+            state.select_version()
+        }
+        Msg::Body { name, body } => {
+            if state.selected_entry().map(|entry| entry.name.as_str()) == Some(name.as_str()) {
+                state.preview = match body {
+                    Ok(body) => Preview::Body(sanitize(&crate::redact::redact_body(&body))),
+                    Err(message) => Preview::Error(message),
+                };
+            }
+            Vec::new()
+        }
+        Msg::Diff { name, lines } => {
+            if state.selected_entry().map(|entry| entry.name.as_str()) == Some(name.as_str()) {
+                state.preview = match lines {
+                    Ok(lines) => Preview::Diff(lines.iter().map(|line| sanitize(line)).collect()),
+                    Err(message) => Preview::Error(message),
+                };
+            }
+            Vec::new()
+        }
+        Msg::Done(message) => {
+            state.status = Some(message);
+            // 削除や復元のバックアップでパスやバージョンが変わるため、読み込んだ範囲を読み込み直す
+            let limit = state.paths.len().max(PATHS_PAGE);
+            vec![state.load_paths(0, limit)]
+        }
+        Msg::Failed(message) => {
+            state.loading_paths = false;
+            state.status = Some(sanitize(&message));
+            Vec::new()
+        }
+    }
+}
+
+/// index を delta だけ動かした位置 len 件の範囲に収め、動かなければ None を返す
+fn moved(index: usize, delta: isize, len: usize) -> Option<usize> {
+    let moved = index
+        .saturating_add_signed(delta)
+        .min(len.saturating_sub(1));
+    (len > 0 && moved != index).then_some(moved)
+}
+
+/// 絞り込みの文字がこの順に含まれるパスに一致する LIKE のパターン
+pub fn fuzzy_pattern(filter: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in filter.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+        pattern.push('%');
+    }
+    pattern
+}
+
+/// アーカイブとディスク上のファイルのキーの差分
+/// 値は表示せず、アーカイブにだけあるキー、ファイルにだけあるキー、値が異なるキーを示す
+pub fn diff_keys(archived: &str, disk: &str) -> Vec<String> {
+    let archived = assignments(archived);
+    let disk = assignments(disk);
+    let mut lines = Vec::new();
+    for (key, value) in &archived {
+        match disk.iter().find(|(k, _)| k == key) {
+            None => lines.push(format!("- {} (only in archive)", key)),
+            Some((_, disk_value)) if disk_value != value => {
+                lines.push(format!("~ {} (value differs)", key))
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, _) in &disk {
+        if !archived.iter().any(|(k, _)| k == key) {
+            lines.push(format!("+ {} (only on disk)", key));
+        }
+    }
+    lines
+}
+
+/// キーと値の一覧 同じキーが複数回現れる場合は最後の値を使う
+fn assignments(body: &str) -> Vec<(String, String)> {
+    let mut values: Vec<(String, String)> = Vec::new();
+    for item in dotenv::parse(body) {
+        if let Item::Assignment { key, value, .. } = item {
+            values.retain(|(k, _)| *k != key);
+            values.push((key, value.trim().to_string()));
+        }
+    }
+    values
+}
+
+/// 端末の表示を乱さないよう、改行以外の制御文字を置き換える
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| if c != '\n' && c.is_control() { '?' } else { c })
+        .collect()
+}
+
+/// recover の進捗をステータス行の文言にまとめる
+#[derive(Debug, Default)]
+pub struct StatusProgress {
+    pub messages: Vec<String>,
+}
+
+impl progress::ProgressSink for StatusProgress {
+    fn emit(&mut self, event: progress::Event) {
+        let message = match event {
+            progress::Event::RecoverUnchanged { target_path } => {
+                format!("skipped {}: same checksum", target_path.display())
+            }
+            progress::Event::RecoverBackedUp {
+                name: Some(name), ..
+            } => format!("backed up as {}", name),
+            progress::Event::RecoverWritten {
+                target_path, name, ..
+            } => format!("recovered {} from {}", target_path.display(), name),
+            _ => return,
+        };
+        self.messages.push(message);
+    }
+}
+
+/// 画面を描く
+pub fn render(frame: &mut Frame, state: &State) {
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [paths_area, versions_area, preview_area] = Layout::horizontal([
+        Constraint::Percentage(35),
+        Constraint::Percentage(30),
+        Constraint::Percentage(35),
+    ])
+    .areas(body);
+
+    let filter = match state.mode {
+        Mode::Filter => format!("filter: {}▏", state.filter),
+        _ if state.filter.is_empty() => "filter: (press / to filter)".to_string(),
+        _ => format!("filter: {}", state.filter),
+    };
+    frame.render_widget(Paragraph::new(filter), header);
+
+    let highlight = Style::default().add_modifier(Modifier::REVERSED);
+    let pane = |title: &'static str, pane: Pane| {
+        let block = Block::default().borders(Borders::ALL).title(title);
+        if state.focus == pane {
+            block.border_style(Style::default().add_modifier(Modifier::BOLD))
+        } else {
+            block
+        }
+    };
+
+    let paths = state.paths.iter().map(|path| {
+        let path = if state.private {
+            crate::redact::redact_path(path)
+        } else {
+            path.clone()
+        };
+        ListItem::new(sanitize(&path))
+    });
+    let mut paths_state = ListState::default().with_selected(Some(state.path_index));
+    frame.render_stateful_widget(
+        List::new(paths)
+            .block(pane("paths", Pane::Paths))
+            .highlight_style(highlight),
+        paths_area,
+        &mut paths_state,
+    );
+
+    let versions = state.versions.iter().map(|(entry, bytes)| {
+        let mut line = format!(
+            "{} {} {}B",
+            entry
+                .created_at
+                .with_timezone(&state.timezone)
+                .format("%Y-%m-%d %H:%M"),
+            entry.name,
+            bytes
+        );
+        if entry.quarantined {
+            line.push(' ');
+            line.push_str(crate::output::QUARANTINED_MARK);
+        }
+        ListItem::new(line)
+    });
+    let mut versions_state = ListState::default().with_selected(Some(state.version_index));
+    frame.render_stateful_widget(
+        List::new(versions)
+            .block(pane("versions", Pane::Versions))
+            .highlight_style(highlight),
+        versions_area,
+        &mut versions_state,
+    );
+
+    let (title, lines): (&str, Vec<Line>) = match &state.preview {
+        Preview::Empty => ("preview", Vec::new()),
+        Preview::Body(body) => ("preview (masked)", body.lines().map(Line::from).collect()),
+        Preview::Diff(lines) if lines.is_empty() => (
+            "diff with disk",
+            vec![Line::from("same keys and values as the file on disk")],
+        ),
+        Preview::Diff(lines) => (
+            "diff with disk",
+            lines.iter().map(|line| Line::from(line.as_str())).collect(),
+        ),
+        Preview::Error(message) => ("preview", vec![Line::from(message.as_str())]),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        preview_area,
+    );
+
+    let footer_text = match &state.mode {
+        Mode::Confirm(Effect::Recover { name }) => {
+            format!("recover {} to its original path? (y/N)", name)
+        }
+        Mode::Confirm(Effect::Delete { name }) => format!("delete {}? (y/N)", name),
+        Mode::Confirm(_) => "continue? (y/N)".to_string(),
+        Mode::Tag(input) => format!("tag: {}▏", input),
+        Mode::Filter => "type to filter, enter: done, esc: clear".to_string(),
+        Mode::Normal => match &state.status {
+            Some(status) => status.clone(),
+            None => "q: quit  /: filter  tab: switch  d: diff  r: recover  t: tag  D: delete"
+                .to_string(),
+        },
+    };
+    frame.render_widget(Paragraph::new(footer_text), footer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn key(code: KeyCode) -> Msg {
+        Msg::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn entry(name: &str, path: &str) -> (ArchiveEntry, u64) {
+        let entry = ArchiveEntry {
+            name: name.to_string(),
+            path: path.to_string(),
+            created_at: Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+            checksum: String::new(),
+            message: None,
+            user: None,
+            quarantined: false,
+            size: 5,
+        };
+        (entry, 5)
+    }
+
+    /// パス a, b を読み込み、a のバージョン a-2, a-1 を読み込んだ状態
+    fn loaded() -> State {
+        let (mut state, _) = State::new(Tz::UTC, false);
+        update(
+            &mut state,
+            Msg::Paths {
+                offset: 0,
+                limit: PATHS_PAGE,
+                paths: vec!["/a/.env".to_string(), "/b/.env".to_string()],
+            },
+        );
+        update(
+            &mut state,
+            Msg::Versions {
+                path: "/a/.env".to_string(),
+                versions: vec![entry("a-2", "/a/.env"), entry("a-1", "/a/.env")],
+            },
+        );
+        state
+    }
+
+    #[test]
+    fn パスを読み込むと先頭のパスのバージョンと本文を読み込む() {
+        let (mut state, effects) = State::new(Tz::UTC, false);
+        assert_eq!(
+            effects,
+            vec![Effect::LoadPaths {
+                pattern: "%".to_string(),
+                offset: 0,
+                limit: PATHS_PAGE,
+            }]
+        );
+        let effects = update(
+            &mut state,
+            Msg::Paths {
+                offset: 0,
+                limit: PATHS_PAGE,
+                paths: vec!["/a/.env".to_string()],
+            },
+        );
+        assert_eq!(
+            effects,
+            vec![Effect::LoadVersions {
+                path: "/a/.env".to_string(),
+                limit: VERSIONS_PAGE,
+            }]
+        );
+        let effects = update(
+            &mut state,
+            Msg::Versions {
+                path: "/a/.env".to_string(),
+                versions: vec![entry("a-1", "/a/.env")],
+            },
+        );
+        assert_eq!(
+            effects,
+            vec![Effect::LoadBody {
+                name: "a-1".to_string(),
+            }]
+        );
+        update(
+            &mut state,
+            Msg::Body {
+                name: "a-1".to_string(),
+                body: Ok("SECRET=value\n".to_string()),
+            },
+        );
+        assert_eq!(
+            state.preview,
+            Preview::Body("SECRET=********\n".to_string())
+        );
+    }
+
+    #[test]
+    fn 上下のキーで選択を移動し選択したものを読み込む() {
+        let mut state = loaded();
+        assert_eq!(
+            update(&mut state, key(KeyCode::Down)),
+            vec![Effect::LoadVersions {
+                path: "/b/.env".to_string(),
+                limit: VERSIONS_PAGE,
+            }]
+        );
+        assert_eq!(state.path_index, 1);
+        assert!(state.versions.is_empty());
+        // 末尾より先には動かない
+        assert!(update(&mut state, key(KeyCode::Down)).is_empty());
+        assert_eq!(state.path_index, 1);
+        // 読み込みが終わる前に選択が変わった場合の結果は捨てる
+        update(
+            &mut state,
+            Msg::Versions {
+                path: "/a/.env".to_string(),
+                versions: vec![entry("a-1", "/a/.env")],
+            },
+        );
+        assert!(state.versions.is_empty());
+
+        let mut state = loaded();
+        update(&mut state, key(KeyCode::Tab));
+        assert_eq!(state.focus, Pane::Versions);
+        assert_eq!(
+            update(&mut state, key(KeyCode::Char('j'))),
+            vec![Effect::LoadBody {
+                name: "a-1".to_string(),
+            }]
+        );
+        assert_eq!(state.selected_entry().unwrap().name, "a-1");
+    }
+
+    #[test]
+    fn 絞り込みの入力でパスを最初から読み込み直す() {
+        let mut state = loaded();
+        state.path_index = 1;
+        update(&mut state, key(KeyCode::Char('/')));
+        assert_eq!(state.mode, Mode::Filter);
+        // 絞り込み中の q は終了ではなく入力になる
+        assert_eq!(
+            update(&mut state, key(KeyCode::Char('q'))),
+            vec![Effect::LoadPaths {
+                pattern: "%q%".to_string(),
+                offset: 0,
+                limit: PATHS_PAGE,
+            }]
+        );
+        assert_eq!(state.path_index, 0);
+        update(&mut state, key(KeyCode::Char('_')));
+        assert_eq!(fuzzy_pattern(&state.filter), "%q%\\_%");
+        update(&mut state, key(KeyCode::Enter));
+        assert_eq!(state.mode, Mode::Normal);
+        assert_eq!(state.filter, "q_");
+        assert_eq!(
+            update(&mut state, key(KeyCode::Char('q'))),
+            vec![Effect::Quit]
+        );
+    }
+
+    #[test]
+    fn 一覧の末尾に近づくと続きのパスを読み込む() {
+        let (mut state, _) = State::new(Tz::UTC, false);
+        let paths = (0..PATHS_PAGE).map(|i| format!("/{:03}/.env", i)).collect();
+        update(
+            &mut state,
+            Msg::Paths {
+                offset: 0,
+                limit: PATHS_PAGE,
+                paths,
+            },
+        );
+        state.path_index = PATHS_PAGE - PREFETCH - 1;
+        let effects = update(&mut state, key(KeyCode::Down));
+        assert_eq!(effects.len(), 2);
+        assert_eq!(
+            effects[1],
+            Effect::LoadPaths {
+                pattern: "%".to_string(),
+                offset: PATHS_PAGE,
+                limit: PATHS_PAGE,
+            }
+        );
+        // 読み込み中は重ねて読み込まない
+        assert_eq!(update(&mut state, key(KeyCode::Down)).len(), 1);
+        update(
+            &mut state,
+            Msg::Paths {
+                offset: PATHS_PAGE,
+                limit: PATHS_PAGE,
+                paths: vec!["/zzz/.env".to_string()],
+            },
+        );
+        assert_eq!(state.paths.len(), PATHS_PAGE + 1);
+        // すべて読み込んだ後は読み込まない
+        assert_eq!(update(&mut state, key(KeyCode::Down)).len(), 1);
+    }
+
+    #[test]
+    fn 復元と削除は確認してから実行する() {
+        let mut state = loaded();
+        assert!(update(&mut state, key(KeyCode::Char('r'))).is_empty());
+        assert_eq!(
+            update(&mut state, key(KeyCode::Char('y'))),
+            vec![Effect::Recover {
+                name: "a-2".to_string(),
+            }]
+        );
+        assert_eq!(state.mode, Mode::Normal);
+
+        update(&mut state, key(KeyCode::Char('D')));
+        assert!(update(&mut state, key(KeyCode::Char('n'))).is_empty());
+        assert_eq!(state.status.as_deref(), Some("cancelled"));
+
+        update(&mut state, key(KeyCode::Char('t')));
+        for c in "prod".chars() {
+            update(&mut state, key(KeyCode::Char(c)));
+        }
+        assert_eq!(
+            update(&mut state, key(KeyCode::Enter)),
+            vec![Effect::AddTag {
+                name: "a-2".to_string(),
+                tag: "prod".to_string(),
+            }]
+        );
+        assert_eq!(
+            update(&mut state, Msg::Done("tagged".to_string())),
+            vec![Effect::LoadPaths {
+                pattern: "%".to_string(),
+                offset: 0,
+                limit: PATHS_PAGE,
+            }]
+        );
+    }
+
+    #[test]
+    fn 読み込めない本文や制御文字はプレビューでエラーや記号として扱う() {
+        let mut state = loaded();
+        update(
+            &mut state,
+            Msg::Body {
+                name: "a-2".to_string(),
+                body: Err("Invalid UTF-8".to_string()),
+            },
+        );
+        assert_eq!(state.preview, Preview::Error("Invalid UTF-8".to_string()));
+        update(
+            &mut state,
+            Msg::Body {
+                name: "a-2".to_string(),
+                body: Ok("# \u{1b}[2J\n".to_string()),
+            },
+        );
+        assert_eq!(state.preview, Preview::Body("# ?[2J\n".to_string()));
+    }
+
+    #[test]
+    fn diff_keysは値を表示せずにキーの差分を示す() {
+        assert_eq!(
+            diff_keys("A=1\nB=2\nC=3\n", "A=1\nB=changed\nD=4\n"),
+            vec![
+                "~ B (value differs)".to_string(),
+                "- C (only in archive)".to_string(),
+                "+ D (only on disk)".to_string(),
+            ]
+        );
+        assert!(diff_keys("A=1\n", "# comment\nA=1").is_empty());
+    }
+
+    #[test]
+    fn 端末の大きさにかかわらず描画できる() {
+        let mut state = loaded();
+        update(
+            &mut state,
+            Msg::Body {
+                name: "a-2".to_string(),
+                body: Ok("API_KEY=secret\n".to_string()),
+            },
+        );
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| render(frame, &state)).unwrap();
+        let screen = format!("{:?}", terminal.backend().buffer());
+        assert!(screen.contains("/a/.env"));
+        assert!(screen.contains("a-2"));
+        assert!(screen.contains("API_KEY=********"));
+        assert!(!screen.contains("secret"));
+
+        // 端末の大きさが変わっても、極端に小さくなっても描画できる
+        for (width, height) in [(40, 10), (3, 2), (1, 1)] {
+            terminal.backend_mut().resize(width, height);
+            terminal.draw(|frame| render(frame, &state)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// コールドストレージに移した本文を読み出せない
+/// ディレクトリのマウントが外れている場合などに発生する
+#[derive(Debug)]
+pub struct ColdBodyUnavailable {
+    /// アーカイブの名前
+    pub name: String,
+    /// 読み出そうとしたブロブのパス
+    pub path: PathBuf,
+    pub source: std::io::Error,
+}
+
+impl std::fmt::Display for ColdBodyUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cold body unavailable: archive {} is stored in {}",
+            self.name,
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for ColdBodyUnavailable {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// error がコールドストレージの本文を読み出せなかったことによるものかどうか
+pub fn is_unavailable(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<ColdBodyUnavailable>().is_some()
+}
+
+/// 本文のブロブのパス
+/// ブロブは本文のチェックサムを名前にするため、同じ内容の本文は 1 つのブロブを共有する
+pub fn blob_path(dir: &Path, checksum: &str) -> PathBuf {
+    dir.join(checksum)
+}
+
+/// 本文をブロブとして dir に書き込む
+/// 同じチェックサムのブロブが既にあれば書き込まない
+pub fn write(dir: &Path, checksum: &str, body: &str) -> anyhow::Result<()> {
+    let path = blob_path(dir, checksum);
+    if path.exists() {
+        return Ok(());
+    }
+    // 書き込みの途中で中断されても不完全なブロブが残らないよう、一時ファイルから置き換える
+    let tmp_path = dir.join(format!(".{}.tmp", checksum));
+    crate::helper::create_private_file(&tmp_path)?.write_all(body.as_bytes())?;
+    std::fs::rename(&tmp_path, &path)?;
+    Ok(())
+}
+
+/// name のアーカイブの本文を dir のブロブから読み出す
+pub fn read(dir: &Path, name: &str, checksum: &str) -> Result<String, ColdBodyUnavailable> {
+    let path = blob_path(dir, checksum);
+    std::fs::read_to_string(&path).map_err(|source| ColdBodyUnavailable {
+        name: name.to_string(),
+        path,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 書き込んだブロブを読み出せなければcold_body_unavailableになる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path().join("cold");
+        std::fs::create_dir(&dir).unwrap();
+        write(&dir, "abc", "FOO=1\n").unwrap();
+        // 既にあるブロブは上書きしない
+        write(&dir, "abc", "FOO=2\n").unwrap();
+        assert_eq!(read(&dir, "x", "abc").unwrap(), "FOO=1\n");
+
+        std::fs::rename(&dir, tmp_dir.path().join("unmounted")).unwrap();
+        let error = anyhow::Error::from(read(&dir, "x", "abc").unwrap_err());
+        assert!(is_unavailable(&error.context("Failed to show archive")));
+    }
+}
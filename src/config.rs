@@ -0,0 +1,556 @@
+use crate::output::EntryFormat;
+use crate::{policy, Visibility};
+use anyhow::Context as _;
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// $XDG_CONFIG_HOME からの設定ファイルの相対パス
+const CONFIG_FILE: &str = "env-archive/config.toml";
+/// $XDG_DATA_HOME からの既定のデータベースの相対パス
+const DATA_FILE: &str = "env-archive/archive.db";
+/// ホームディレクトリからの以前の既定のデータベースの相対パス
+const LEGACY_DATABASE: &str = ".env_archive";
+
+/// 省略したオプションの既定値を書いた設定ファイル
+///
+/// 値はコマンドラインのオプション、環境変数、設定ファイル、組み込みの既定値の順に優先する
+///
+/// ```toml
+/// local = true
+/// database = "~/Dropbox/env_archive"
+/// timezone = "Asia/Tokyo"
+/// default_format = "json"
+/// private = true
+///
+/// [crawl]
+/// excludes = ["dist", "tmp"]
+/// patterns = [".env", "**/secrets.env"]
+///
+/// [visibility]
+/// default = "all"
+///
+/// [policy]
+/// enforce_on_push = "block"
+///
+/// [[policies]]
+/// name = "api"
+/// paths = ["**/api/.env*"]
+/// require = ["SENTRY_DSN"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    local: bool,
+    database: Option<PathBuf>,
+    timezone: Option<String>,
+    default_format: Option<String>,
+    #[serde(default)]
+    private: bool,
+    #[serde(default)]
+    crawl: CrawlConfig,
+    #[serde(default)]
+    visibility: VisibilityConfig,
+    #[serde(default)]
+    policy: policy::Settings,
+    #[serde(default)]
+    policies: Vec<policy::PolicyConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VisibilityConfig {
+    default: Option<String>,
+}
+
+/// 読み込んで検証した設定
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    /// --local を指定しなくてもリポジトリごとのデータベースを探す
+    pub local: bool,
+    /// --database の既定値
+    pub database: Option<PathBuf>,
+    /// --timezone の既定値
+    pub timezone: Option<Tz>,
+    /// 一覧や検索の --format の既定値
+    pub default_format: Option<EntryFormat>,
+    /// --private を指定しなくても伏せて表示する
+    pub private: bool,
+    pub crawl: CrawlConfig,
+    /// --visibility の既定値
+    pub visibility: Option<Visibility>,
+    /// --policies を指定しなかった場合に使うポリシー
+    pub policies: policy::Policies,
+}
+
+/// crawl の既定値
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CrawlConfig {
+    /// --exclude の既定値
+    #[serde(default)]
+    pub excludes: Vec<String>,
+    /// --pattern の既定値
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+/// 設定ファイルのパス
+/// XDG_CONFIG_HOME が空または相対パスの場合は XDG Base Directory の仕様に従って無視し、~/.config を使う
+pub fn path(xdg_config_home: Option<&OsStr>, home: Option<&Path>) -> Option<PathBuf> {
+    Some(xdg_dir(xdg_config_home, home, ".config")?.join(CONFIG_FILE))
+}
+
+/// XDG Base Directory の環境変数のディレクトリ
+/// 値が空または相対パスの場合は無視し、ホームディレクトリの fallback を使う
+fn xdg_dir(value: Option<&OsStr>, home: Option<&Path>, fallback: &str) -> Option<PathBuf> {
+    value
+        .map(Path::new)
+        .filter(|dir| dir.is_absolute())
+        .map(Path::to_path_buf)
+        .or_else(|| home.map(|home| home.join(fallback)))
+}
+
+/// --database や設定ファイルで指定しなかった場合のデータベースの場所
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultDatabase {
+    /// $XDG_DATA_HOME/env-archive/archive.db
+    /// XDG_DATA_HOME が空または相対パスの場合は ~/.local/share/env-archive/archive.db
+    pub data: PathBuf,
+    /// 以前の既定の場所 ~/.env_archive
+    pub legacy: PathBuf,
+}
+
+impl DefaultDatabase {
+    pub fn new(xdg_data_home: Option<&OsStr>, home: &Path) -> Self {
+        Self {
+            data: xdg_dir(xdg_data_home, Some(home), ".local/share")
+                .unwrap_or_else(|| home.join(".local/share"))
+                .join(DATA_FILE),
+            legacy: home.join(LEGACY_DATABASE),
+        }
+    }
+
+    /// 新しい場所にデータベースがなく以前の場所にある場合は、migrate-db で移すまで以前の場所を使う
+    pub fn uses_legacy(&self) -> bool {
+        !self.data.exists() && self.legacy.is_file()
+    }
+
+    /// 使うデータベースのパス
+    pub fn path(&self) -> &Path {
+        if self.uses_legacy() {
+            &self.legacy
+        } else {
+            &self.data
+        }
+    }
+}
+
+impl Config {
+    /// 設定ファイルを読み込む ファイルがない場合は何も設定していないものとする
+    pub fn load(path: &Path, home: Option<&Path>) -> anyhow::Result<Self> {
+        let toml = match std::fs::read_to_string(path) {
+            Ok(toml) => toml,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let base = path.parent().unwrap_or(Path::new("/"));
+        Self::parse(&toml, base, home)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// TOML の設定を読み込んで検証する
+    /// database の相対パスは base (設定ファイルのあるディレクトリ) を基準にし、`~/` はホームディレクトリにする
+    pub fn parse(toml: &str, base: &Path, home: Option<&Path>) -> anyhow::Result<Self> {
+        let file: File = toml::from_str(toml)?;
+        let database = file
+            .database
+            .map(|database| match database.strip_prefix("~") {
+                Ok(rest) => home
+                    .map(|home| home.join(rest))
+                    .ok_or_else(|| anyhow::anyhow!("failed to get home directory for database")),
+                Err(_) => Ok(crate::helper::normalize_path(&database, base)),
+            })
+            .transpose()?;
+        let timezone = file
+            .timezone
+            .map(|name| crate::helper::parse_timezone(&name))
+            .transpose()?;
+        let default_format = file
+            .default_format
+            .map(|name| {
+                <EntryFormat as clap::ValueEnum>::from_str(&name, false).map_err(|_| {
+                    anyhow::anyhow!(
+                        "invalid default_format {:?}. use one of text, json, jsonl, csv, tsv",
+                        name
+                    )
+                })
+            })
+            .transpose()?;
+        let visibility = file
+            .visibility
+            .default
+            .map(|name| {
+                <Visibility as clap::ValueEnum>::from_str(&name, false).map_err(|_| {
+                    anyhow::anyhow!("invalid visibility.default {:?}. use own or all", name)
+                })
+            })
+            .transpose()?;
+        let policies = policy::Policies::new(file.policy, file.policies)?;
+        Ok(Self {
+            local: file.local,
+            database,
+            timezone,
+            default_format,
+            private: file.private,
+            crawl: file.crawl,
+            visibility,
+            policies,
+        })
+    }
+
+    /// データベースのパスを --database または ENV_ARCHIVE_DATABASE、設定ファイルの順に決める
+    /// どちらもなければ None を返し、DefaultDatabase を使う
+    pub fn database(&self, arg: Option<String>) -> Option<PathBuf> {
+        arg.map(PathBuf::from).or_else(|| self.database.clone())
+    }
+
+    /// 日時の表示に使うタイムゾーンを --timezone、ENV_ARCHIVE_TZ、設定ファイル、システムのタイムゾーン、UTC の順に決める
+    /// --timezone と ENV_ARCHIVE_TZ の名前が不正な場合はエラーにするが、
+    /// システムのタイムゾーンは chrono-tz が知らない名前であっても UTC にする
+    pub fn timezone(
+        &self,
+        flag: Option<&str>,
+        env: Option<&str>,
+        detected: Option<&str>,
+    ) -> anyhow::Result<Tz> {
+        if let Some(name) = flag.or(env.filter(|name| !name.is_empty())) {
+            return crate::helper::parse_timezone(name);
+        }
+        Ok(self
+            .timezone
+            .or_else(|| detected.and_then(|name| name.parse().ok()))
+            .unwrap_or(Tz::UTC))
+    }
+
+    /// 一覧や検索の出力形式を --format、設定ファイル、text の順に決める
+    pub fn format(&self, flag: Option<EntryFormat>) -> EntryFormat {
+        flag.or(self.default_format).unwrap_or_default()
+    }
+
+    /// --private を指定するか設定ファイルで private = true にしていれば伏せて表示する
+    /// 設定ファイルで有効にした場合は、コマンドラインで無効にする手段はない
+    pub fn private(&self, flag: bool) -> bool {
+        flag || self.private
+    }
+
+    /// 一覧や検索で既定で表示するアーカイブを --visibility または ENV_ARCHIVE_VISIBILITY、設定ファイル、own の順に決める
+    pub fn visibility(&self, flag: Option<Visibility>) -> Visibility {
+        flag.or(self.visibility).unwrap_or_default()
+    }
+
+    /// ポリシーを --policies または ENV_ARCHIVE_POLICIES のファイル、設定ファイルの順に決める
+    /// load はファイルのパスからポリシーを読み込む
+    pub fn policies(
+        &self,
+        flag: Option<&str>,
+        load: impl FnOnce(&str) -> anyhow::Result<policy::Policies>,
+    ) -> anyhow::Result<policy::Policies> {
+        match flag {
+            Some(path) => load(path),
+            None => Ok(self.policies.clone()),
+        }
+    }
+
+    /// crawl で除外するグロブ --exclude を指定した場合は設定ファイルの excludes を使わない
+    pub fn crawl_excludes(&self, flag: Vec<String>) -> Vec<String> {
+        if flag.is_empty() {
+            self.crawl.excludes.clone()
+        } else {
+            flag
+        }
+    }
+
+    /// crawl で探すファイルのグロブ --pattern を指定した場合は設定ファイルの patterns を使わない
+    pub fn crawl_patterns(&self, flag: Vec<String>) -> Vec<String> {
+        if flag.is_empty() {
+            self.crawl.patterns.clone()
+        } else {
+            flag
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::parse(
+            r#"
+            local = true
+            database = "~/sync/env_archive"
+            timezone = "Asia/Tokyo"
+            default_format = "json"
+            private = true
+
+            [crawl]
+            excludes = ["dist"]
+            patterns = ["**/secrets.env"]
+
+            [visibility]
+            default = "all"
+
+            [policy]
+            enforce_on_push = "block"
+
+            [[policies]]
+            name = "api"
+            paths = ["**/api/.env"]
+            require = ["SENTRY_DSN"]
+            "#,
+            Path::new("/home/me/.config/env-archive"),
+            Some(Path::new("/home/me")),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn 設定ファイルを読み込み検証する() {
+        assert_eq!(
+            config(),
+            Config {
+                local: true,
+                database: Some(PathBuf::from("/home/me/sync/env_archive")),
+                timezone: Some(chrono_tz::Asia::Tokyo),
+                default_format: Some(EntryFormat::Json),
+                private: true,
+                crawl: CrawlConfig {
+                    excludes: vec!["dist".to_string()],
+                    patterns: vec!["**/secrets.env".to_string()],
+                },
+                visibility: Some(Visibility::All),
+                policies: policy::Policies::parse(
+                    r#"
+                    [policy]
+                    enforce_on_push = "block"
+
+                    [[policies]]
+                    name = "api"
+                    paths = ["**/api/.env"]
+                    require = ["SENTRY_DSN"]
+                    "#,
+                )
+                .unwrap(),
+            }
+        );
+
+        let parse = |toml: &str| Config::parse(toml, Path::new("/etc/env-archive"), None);
+        assert_eq!(
+            parse(r#"database = "../archive.db""#).unwrap().database,
+            Some(PathBuf::from("/etc/archive.db"))
+        );
+        assert_eq!(parse("").unwrap(), Config::default());
+        for toml in [
+            r#"timezone = "Mars/Olympus""#,
+            r#"default_format = "print0""#,
+            r#"database = "~/archive.db""#,
+            r#"databse = "/tmp/archive.db""#,
+            "[crawl]\nexclude = [\"dist\"]",
+            "[visibility]\ndefault = \"everyone\"",
+            "[policy]\nenforce_on_push = \"deny\"",
+            "[[policies]]\npaths = [\"[\"]",
+        ] {
+            assert!(parse(toml).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn 設定ファイルがなければ既定値を使う() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("env-archive/config.toml");
+        assert_eq!(Config::load(&path, None).unwrap(), Config::default());
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "default_format = \"yaml\"").unwrap();
+        let error = format!("{:#}", Config::load(&path, None).unwrap_err());
+        assert!(error.starts_with("Failed to parse"), "{}", error);
+        assert!(error.contains("invalid default_format"), "{}", error);
+    }
+
+    #[test]
+    fn pathはxdg_config_homeを優先し相対パスなら無視する() {
+        let home = Some(Path::new("/home/me"));
+        assert_eq!(
+            path(Some(OsStr::new("/xdg")), home),
+            Some(PathBuf::from("/xdg/env-archive/config.toml"))
+        );
+        for xdg in [None, Some(OsStr::new("")), Some(OsStr::new("xdg"))] {
+            assert_eq!(
+                path(xdg, home),
+                Some(PathBuf::from("/home/me/.config/env-archive/config.toml"))
+            );
+        }
+        assert_eq!(path(None, None), None);
+    }
+
+    #[test]
+    fn databaseはオプション_設定ファイルの順に決める() {
+        let config = config();
+        assert_eq!(
+            config.database(Some("/tmp/flag.db".to_string())),
+            Some(PathBuf::from("/tmp/flag.db"))
+        );
+        assert_eq!(
+            config.database(None),
+            Some(PathBuf::from("/home/me/sync/env_archive"))
+        );
+        assert_eq!(Config::default().database(None), None);
+    }
+
+    #[test]
+    fn 既定のデータベースは新しい場所になく以前の場所にある場合だけ以前の場所を使う() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let home = tmp_dir.path();
+        let default = DefaultDatabase::new(None, home);
+        assert_eq!(
+            default.data,
+            home.join(".local/share/env-archive/archive.db")
+        );
+        assert_eq!(default.legacy, home.join(".env_archive"));
+        for xdg in ["", "data"] {
+            assert_eq!(DefaultDatabase::new(Some(OsStr::new(xdg)), home), default);
+        }
+        assert_eq!(
+            DefaultDatabase::new(Some(OsStr::new("/xdg")), home).data,
+            PathBuf::from("/xdg/env-archive/archive.db")
+        );
+
+        // 新しくインストールした場合
+        assert!(!default.uses_legacy());
+        assert_eq!(default.path(), default.data);
+
+        // 以前の場所にだけデータベースがある場合
+        std::fs::write(&default.legacy, "").unwrap();
+        assert!(default.uses_legacy());
+        assert_eq!(default.path(), default.legacy);
+
+        // 両方にある場合は新しい場所を使う
+        std::fs::create_dir_all(default.data.parent().unwrap()).unwrap();
+        std::fs::write(&default.data, "").unwrap();
+        assert!(!default.uses_legacy());
+        assert_eq!(default.path(), default.data);
+    }
+
+    #[test]
+    fn timezoneはフラグ_環境変数_設定ファイル_システム_utcの順に決める() {
+        let flag = Some("Europe/Berlin");
+        let detected = Some("America/New_York");
+        let configured = config();
+        let none = Config::default();
+
+        // 環境変数がある場合
+        let env = Some("Australia/Sydney");
+        for config in [&configured, &none] {
+            assert_eq!(
+                config.timezone(flag, env, detected).unwrap(),
+                chrono_tz::Europe::Berlin
+            );
+            assert_eq!(
+                config.timezone(None, env, detected).unwrap(),
+                chrono_tz::Australia::Sydney
+            );
+            assert!(config
+                .timezone(None, Some("Mars/Olympus"), detected)
+                .is_err());
+            assert!(config
+                .timezone(Some("Mars/Olympus"), None, detected)
+                .is_err());
+        }
+
+        // 環境変数がない、または空の場合
+        for env in [None, Some("")] {
+            assert_eq!(
+                configured.timezone(flag, env, detected).unwrap(),
+                chrono_tz::Europe::Berlin
+            );
+            assert_eq!(
+                configured.timezone(None, env, detected).unwrap(),
+                chrono_tz::Asia::Tokyo
+            );
+            assert_eq!(
+                none.timezone(None, env, detected).unwrap(),
+                chrono_tz::America::New_York
+            );
+            assert_eq!(
+                none.timezone(None, env, Some("Mars/Olympus")).unwrap(),
+                chrono_tz::Tz::UTC
+            );
+            assert_eq!(none.timezone(None, env, None).unwrap(), chrono_tz::Tz::UTC);
+        }
+    }
+
+    #[test]
+    fn formatとcrawlの既定値はオプションを指定しなかった場合に使う() {
+        let configured = config();
+        let none = Config::default();
+        assert_eq!(configured.format(Some(EntryFormat::Csv)), EntryFormat::Csv);
+        assert_eq!(configured.format(None), EntryFormat::Json);
+        assert_eq!(none.format(None), EntryFormat::Text);
+
+        let flag = vec!["build".to_string()];
+        assert_eq!(configured.crawl_excludes(flag.clone()), flag);
+        assert_eq!(configured.crawl_excludes(vec![]), ["dist"]);
+        assert!(none.crawl_excludes(vec![]).is_empty());
+        assert_eq!(configured.crawl_patterns(flag.clone()), flag);
+        assert_eq!(configured.crawl_patterns(vec![]), ["**/secrets.env"]);
+        assert!(none.crawl_patterns(vec![]).is_empty());
+    }
+
+    #[test]
+    fn visibilityとpoliciesはフラグまたは環境変数_設定ファイル_既定値の順に決める() {
+        let configured = config();
+        let none = Config::default();
+        // ENV_ARCHIVE_VISIBILITY と ENV_ARCHIVE_POLICIES は clap がフラグと同じ値にする
+        assert_eq!(
+            configured.visibility(Some(Visibility::Own)),
+            Visibility::Own
+        );
+        assert_eq!(configured.visibility(None), Visibility::All);
+        assert_eq!(none.visibility(Some(Visibility::All)), Visibility::All);
+        assert_eq!(none.visibility(None), Visibility::Own);
+
+        let from_flag = policy::Policies::parse("[policy]\nenforce_on_push = \"warn\"").unwrap();
+        let load = |path: &str| {
+            assert_eq!(path, "/tmp/policies.toml");
+            Ok(from_flag.clone())
+        };
+        assert_eq!(
+            configured
+                .policies(Some("/tmp/policies.toml"), load)
+                .unwrap(),
+            from_flag
+        );
+        let policies = configured
+            .policies(None, |_| unreachable!("--policies is not given"))
+            .unwrap();
+        assert_eq!(policies.enforce_on_push, policy::Enforcement::Block);
+        assert!(!policies.is_empty());
+        let policies = none
+            .policies(None, |_| unreachable!("--policies is not given"))
+            .unwrap();
+        assert_eq!(policies, policy::Policies::default());
+    }
+
+    #[test]
+    fn privateはフラグと設定ファイルのどちらかで有効になる() {
+        let configured = config();
+        let none = Config::default();
+        assert!(configured.private(true));
+        assert!(configured.private(false));
+        assert!(none.private(true));
+        assert!(!none.private(false));
+    }
+}
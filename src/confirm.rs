@@ -0,0 +1,72 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+/// 操作を続けてよいかを確認する
+pub trait Confirm {
+    /// message を表示して確認し、了承された場合に true を返す
+    fn confirm(&mut self, message: &str) -> anyhow::Result<bool>;
+}
+
+/// 確認せずに常に了承する (--yes)
+pub struct AssumeYes;
+
+impl Confirm for AssumeYes {
+    fn confirm(&mut self, _message: &str) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+}
+
+/// reader から y/N の回答を読み取って確認する
+pub struct PromptConfirm<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: BufRead, W: Write> PromptConfirm<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self { reader, writer }
+    }
+}
+
+impl<R: BufRead, W: Write> Confirm for PromptConfirm<R, W> {
+    fn confirm(&mut self, message: &str) -> anyhow::Result<bool> {
+        write!(self.writer, "{} [y/N] ", message)?;
+        self.writer.flush()?;
+        let mut answer = String::new();
+        self.reader.read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+}
+
+/// 端末で y/N の回答を求めて確認する
+/// 標準入力が端末でない場合は回答を待たずにエラーにする
+pub struct TerminalConfirm;
+
+impl Confirm for TerminalConfirm {
+    fn confirm(&mut self, message: &str) -> anyhow::Result<bool> {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!("stdin is not a terminal. pass --yes to proceed without confirmation");
+        }
+        PromptConfirm::new(std::io::stdin().lock(), std::io::stderr()).confirm(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yと回答した場合のみ了承される() {
+        for (answer, expected) in [
+            ("y\n", true),
+            ("Yes\n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("", false),
+        ] {
+            let mut output = Vec::new();
+            let mut confirm = PromptConfirm::new(answer.as_bytes(), &mut output);
+            assert_eq!(confirm.confirm("overwrite?").unwrap(), expected);
+            assert_eq!(String::from_utf8(output).unwrap(), "overwrite? [y/N] ");
+        }
+    }
+}
@@ -0,0 +1,379 @@
+/// .env ファイルを構成する要素
+///
+/// 元の内容を改行も含めてそのまま保持しており、各要素の text を連結すると元の内容に戻る
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// 空行、コメント、代入として解釈できない行
+    Other(String),
+    /// `KEY=VALUE` の代入
+    /// 複数行にわたるクォートされた値は 1 つの要素になる
+    Assignment {
+        key: String,
+        /// 行頭から `=` までの部分 (`export ` や `=` の前後の空白を含む)
+        prefix: String,
+        /// `=` より後ろの部分 (改行を含む)
+        value: String,
+    },
+}
+
+impl Item {
+    pub fn text(&self) -> String {
+        match self {
+            Item::Other(text) => text.clone(),
+            Item::Assignment { prefix, value, .. } => format!("{}{}", prefix, value),
+        }
+    }
+}
+
+/// .env ファイルの内容を要素に分ける
+pub fn parse(body: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut lines = body.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let Some((key, prefix_len)) = split_assignment(line) else {
+            items.push(Item::Other(line.to_string()));
+            continue;
+        };
+        let mut value = line[prefix_len..].to_string();
+        let trimmed = value.trim_start();
+        if let Some(quote) = trimmed.chars().next().filter(|c| *c == '"' || *c == '\'') {
+            let opened = value.len() - trimmed.len() + 1;
+            while !is_closed(&value[opened..], quote) {
+                match lines.next() {
+                    Some(next) => value.push_str(next),
+                    None => break,
+                }
+            }
+        }
+        items.push(Item::Assignment {
+            key,
+            prefix: line[..prefix_len].to_string(),
+            value,
+        });
+    }
+    items
+}
+
+/// 各キーの値を、キーが最初に現れた順に返す
+/// 同じキーが複数回現れる場合は、読み込まれたときに有効になる最後の値を使う
+pub fn values(body: &str) -> Vec<(String, String)> {
+    let mut values: Vec<(String, String)> = Vec::new();
+    for item in parse(body) {
+        let Item::Assignment { key, value, .. } = item else {
+            continue;
+        };
+        let value = unquote(&value);
+        match values.iter_mut().find(|(k, _)| *k == key) {
+            Some(defined) => defined.1 = value,
+            None => values.push((key, value)),
+        }
+    }
+    values
+}
+
+/// values を `export KEY='value'` の行にしたシェルのスクリプトを作る
+/// シェルの変数名として使えないキーは含めず、2 つ目の値として返す
+pub fn export_script(values: &[(String, String)]) -> (String, Vec<String>) {
+    let mut script = String::new();
+    let mut skipped = Vec::new();
+    for (key, value) in values {
+        if !is_shell_identifier(key) {
+            skipped.push(key.clone());
+            continue;
+        }
+        script.push_str(&format!("export {}={}\n", key, shell_quote(value)));
+    }
+    (script, skipped)
+}
+
+/// シングルクォートで囲む 値の中のシングルクォートは `'\''` に置き換える
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn is_shell_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `=` より後ろの部分から、前後の空白と値を囲むクォートを取り除く
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// 代入の行であれば、キーと `=` までのバイト数を返す
+fn split_assignment(line: &str) -> Option<(String, usize)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('#') {
+        return None;
+    }
+    let declaration = trimmed
+        .strip_prefix("export ")
+        .map(str::trim_start)
+        .unwrap_or(trimmed);
+    let (key, _) = declaration.split_once('=')?;
+    let key = key.trim();
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if !valid {
+        return None;
+    }
+    Some((key.to_string(), line.find('=')? + 1))
+}
+
+/// 開きクォートより後ろの文字列に、閉じクォートが含まれるかどうか
+/// ダブルクォートの中ではバックスラッシュでエスケープされたクォートを無視する
+fn is_closed(after_open: &str, quote: char) -> bool {
+    let mut escaped = false;
+    for c in after_open.chars() {
+        if escaped {
+            escaped = false;
+        } else if quote == '"' && c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return true;
+        }
+    }
+    false
+}
+
+/// merge_keys の結果
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Merge {
+    pub body: String,
+    /// 値を置き換えたキー
+    pub updated: Vec<String>,
+    /// 末尾に追加したキー
+    pub appended: Vec<String>,
+    /// 値が同じだったため変更しなかったキー
+    pub unchanged: Vec<String>,
+}
+
+/// current のうち patterns に一致するキーの値だけを source の値に置き換える
+///
+/// 一致しない行、コメント、行の順序はそのまま残し、current にないキーは末尾に追加する。
+/// patterns にはグロブ (`DB_*` など) を使える。
+/// 同じキーが複数回現れる場合は、読み込まれたときに有効になる最後のものを対象にする。
+pub fn merge_keys(current: &str, source: &str, patterns: &[String]) -> anyhow::Result<Merge> {
+    let globs = patterns
+        .iter()
+        .map(|pattern| {
+            globmatch::Builder::new(pattern)
+                .build_glob()
+                .map_err(anyhow::Error::msg)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut selected: Vec<(String, String)> = Vec::new();
+    for item in parse(source) {
+        let Item::Assignment { key, value, .. } = item else {
+            continue;
+        };
+        if !globs.iter().any(|glob| glob.is_match(&key)) {
+            continue;
+        }
+        match selected.iter_mut().find(|(k, _)| *k == key) {
+            Some(selected) => selected.1 = value,
+            None => selected.push((key, value)),
+        }
+    }
+    for (pattern, glob) in patterns.iter().zip(&globs) {
+        if !selected.iter().any(|(key, _)| glob.is_match(key)) {
+            anyhow::bail!("no key matching {} in the archive", pattern);
+        }
+    }
+
+    let mut items = parse(current);
+    let mut merge = Merge::default();
+    for (key, value) in selected {
+        let last = items
+            .iter()
+            .rposition(|item| matches!(item, Item::Assignment { key: k, .. } if *k == key));
+        match last {
+            Some(i) => {
+                let Item::Assignment { value: current, .. } = &mut items[i] else {
+                    unreachable!();
+                };
+                if current.trim() == value.trim() {
+                    merge.unchanged.push(key);
+                    continue;
+                }
+                let newline = current.ends_with('\n');
+                *current = value.trim_end_matches('\n').to_string();
+                if newline {
+                    current.push('\n');
+                }
+                merge.updated.push(key);
+            }
+            None => {
+                if let Some(last) = items.last_mut() {
+                    if !last.text().ends_with('\n') {
+                        match last {
+                            Item::Other(text) => text.push('\n'),
+                            Item::Assignment { value, .. } => value.push('\n'),
+                        }
+                    }
+                }
+                let mut value = value.trim_end_matches('\n').to_string();
+                value.push('\n');
+                items.push(Item::Assignment {
+                    prefix: format!("{}=", key),
+                    key: key.clone(),
+                    value,
+                });
+                merge.appended.push(key);
+            }
+        }
+    }
+    merge.body = items.iter().map(Item::text).collect();
+    Ok(merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn parseした要素を連結すると元の内容に戻る() {
+        let body = "# comment\nexport FOO=bar\n\nBAZ = \"quoted # not comment\"\nMULTI=\"line1\nline2\"\nESCAPED=\"say \\\"hi\\\"\"\nSINGLE='a\nb'\nnot an assignment\nLAST=x";
+        let items = parse(body);
+        assert_eq!(items.iter().map(Item::text).collect::<String>(), body);
+
+        let keys = items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Assignment { key, .. } => Some(key.as_str()),
+                Item::Other(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["FOO", "BAZ", "MULTI", "ESCAPED", "SINGLE", "LAST"]);
+    }
+
+    #[test]
+    fn valuesはクォートとexportを取り除き最後の値を使う() {
+        let body = "# FOO=comment\nexport FOO=first\nURL = \"https://example.com\"\nMULTI='a\nb'\nFOO=second\n";
+        assert_eq!(
+            values(body),
+            [
+                ("FOO".to_string(), "second".to_string()),
+                ("URL".to_string(), "https://example.com".to_string()),
+                ("MULTI".to_string(), "a\nb".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn export_scriptをシェルで評価すると元の値になる() {
+        let body = "# comment\n\nQUOTE=\"it's \\\"quoted\\\"\"\nMULTI='line1\nline2'\nDOLLAR=$HOME `id` \\n\napp.name=x\nEMPTY=\n";
+        let (script, skipped) = export_script(&values(body));
+        assert_eq!(skipped, ["app.name"]);
+        assert!(!script.contains("comment"));
+
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                "{}printf '%s|%s|%s|%s' \"$QUOTE\" \"$MULTI\" \"$DOLLAR\" \"$EMPTY\"",
+                script
+            ))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "it's \\\"quoted\\\"|line1\nline2|$HOME `id` \\n|"
+        );
+    }
+
+    #[test]
+    fn merge_keysは指定したキーの値だけを置き換えて他の行を残す() {
+        let current =
+            "# database\nexport DATABASE_URL=postgres://broken\nAPI_KEY=current\n\n# end\n";
+        let source = "DATABASE_URL=postgres://archived\nAPI_KEY=archived\n";
+        let merge = merge_keys(current, source, &keys(&["DATABASE_URL"])).unwrap();
+        assert_eq!(
+            merge.body,
+            "# database\nexport DATABASE_URL=postgres://archived\nAPI_KEY=current\n\n# end\n"
+        );
+        assert_eq!(merge.updated, ["DATABASE_URL"]);
+        assert!(merge.appended.is_empty());
+        assert!(merge.unchanged.is_empty());
+    }
+
+    #[test]
+    fn merge_keysは存在しないキーを末尾に追加する() {
+        let merge = merge_keys("FOO=1", "FOO=1\nBAR=2\n", &keys(&["FOO", "BAR"])).unwrap();
+        assert_eq!(merge.body, "FOO=1\nBAR=2\n");
+        assert_eq!(merge.unchanged, ["FOO"]);
+        assert_eq!(merge.appended, ["BAR"]);
+
+        let merge = merge_keys("", "BAR=2", &keys(&["BAR"])).unwrap();
+        assert_eq!(merge.body, "BAR=2\n");
+    }
+
+    #[test]
+    fn merge_keysはクォートされた複数行の値を置き換える() {
+        let current = "KEY=\"old\nvalue\"\nOTHER='keep # this'\n";
+        let source = "KEY=\"new\nmulti\nline\"\nOTHER=changed\n";
+        let merge = merge_keys(current, source, &keys(&["KEY"])).unwrap();
+        assert_eq!(
+            merge.body,
+            "KEY=\"new\nmulti\nline\"\nOTHER='keep # this'\n"
+        );
+
+        let source = "KEY=\"contains \\\" quote\"\n";
+        let merge = merge_keys(current, source, &keys(&["KEY"])).unwrap();
+        assert_eq!(
+            merge.body,
+            "KEY=\"contains \\\" quote\"\nOTHER='keep # this'\n"
+        );
+    }
+
+    #[test]
+    fn merge_keysは重複したキーのうち最後のものを使う() {
+        let current = "FOO=first\nBAR=x\nFOO=second\n";
+        let source = "FOO=archived-1\nFOO=archived-2\n";
+        let merge = merge_keys(current, source, &keys(&["FOO"])).unwrap();
+        assert_eq!(merge.body, "FOO=first\nBAR=x\nFOO=archived-2\n");
+        assert_eq!(merge.updated, ["FOO"]);
+    }
+
+    #[test]
+    fn merge_keysはグロブに一致するキーをすべて対象にする() {
+        let current = "DB_HOST=old\nDB_PASS=old\nAPP=old\n";
+        let source = "DB_HOST=new\nDB_PASS=old\nDB_PORT=5432\nAPP=new\n";
+        let merge = merge_keys(current, source, &keys(&["DB_*"])).unwrap();
+        assert_eq!(
+            merge.body,
+            "DB_HOST=new\nDB_PASS=old\nAPP=old\nDB_PORT=5432\n"
+        );
+        assert_eq!(merge.updated, ["DB_HOST"]);
+        assert_eq!(merge.unchanged, ["DB_PASS"]);
+        assert_eq!(merge.appended, ["DB_PORT"]);
+    }
+
+    #[test]
+    fn merge_keysはアーカイブに一致するキーがないパターンをエラーにする() {
+        let error = merge_keys("FOO=1\n", "FOO=2\n", &keys(&["FOO", "MISSING_*"]))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "no key matching MISSING_* in the archive");
+    }
+}
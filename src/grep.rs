@@ -0,0 +1,149 @@
+use std::ops::Range;
+
+/// 一致した箇所を強調する ANSI エスケープシーケンス
+const HIGHLIGHT_START: &str = "\x1b[1;31m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
+/// grep で本文から探すパターン
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// 文字列をそのまま探す
+    Plain(String),
+    /// 正規表現に一致する箇所を探す
+    Regex(regex::Regex),
+}
+
+/// 本文のうちパターンに一致した行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch {
+    /// 1 から始まる行番号
+    pub number: usize,
+    pub line: String,
+    /// 行のうち一致した箇所のバイト範囲
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, regex: bool) -> anyhow::Result<Self> {
+        if pattern.is_empty() {
+            anyhow::bail!("pattern must not be empty");
+        }
+        if !regex {
+            return Ok(Pattern::Plain(pattern.to_string()));
+        }
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| anyhow::anyhow!("invalid regex {:?}: {}", pattern, e))?;
+        Ok(Pattern::Regex(regex))
+    }
+
+    /// データベースで本文を絞り込むための LIKE のパターン
+    /// 正規表現はデータベースでは評価できないため None を返し、すべての本文を照合する
+    pub fn like(&self) -> Option<String> {
+        match self {
+            Pattern::Plain(pattern) => {
+                let escaped = pattern
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                Some(format!("%{}%", escaped))
+            }
+            Pattern::Regex(_) => None,
+        }
+    }
+
+    /// body のうちパターンに一致する行を返す
+    pub fn matches(&self, body: &str) -> Vec<LineMatch> {
+        body.lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let ranges = self.find(line)?;
+                Some(LineMatch {
+                    number: i + 1,
+                    line: line.to_string(),
+                    ranges,
+                })
+            })
+            .collect()
+    }
+
+    /// line のうち一致した箇所の範囲を返す 一致しなければ None を返す
+    /// 正規表現が空の文字列に一致した箇所は強調しないため範囲に含めない
+    fn find(&self, line: &str) -> Option<Vec<Range<usize>>> {
+        match self {
+            Pattern::Plain(pattern) => {
+                let ranges = line
+                    .match_indices(pattern.as_str())
+                    .map(|(start, matched)| start..start + matched.len())
+                    .collect::<Vec<_>>();
+                (!ranges.is_empty()).then_some(ranges)
+            }
+            Pattern::Regex(regex) => regex.is_match(line).then(|| {
+                regex
+                    .find_iter(line)
+                    .map(|m| m.range())
+                    .filter(|range| !range.is_empty())
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// 一致した箇所を強調した行
+pub fn highlight(line: &LineMatch) -> String {
+    let mut highlighted = String::new();
+    let mut end = 0;
+    for range in &line.ranges {
+        highlighted.push_str(&line.line[end..range.start]);
+        highlighted.push_str(HIGHLIGHT_START);
+        highlighted.push_str(&line.line[range.clone()]);
+        highlighted.push_str(HIGHLIGHT_END);
+        end = range.end;
+    }
+    highlighted.push_str(&line.line[end..]);
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 複数行の本文から一致する行をすべて返す() {
+        let body =
+            "# stripe_live keys\nSTRIPE_KEY=sk_live_1\nOTHER=x\nBACKUP=sk_live_2 sk_live_3\n";
+        let pattern = Pattern::new("sk_live", false).unwrap();
+        let matches = pattern.matches(body);
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| (
+                    m.number,
+                    m.ranges
+                        .iter()
+                        .map(|r| (r.start, r.end))
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            [(2, vec![(11, 18)]), (4, vec![(7, 14), (17, 24)])]
+        );
+        assert_eq!(
+            highlight(&matches[1]),
+            "BACKUP=\x1b[1;31msk_live\x1b[0m_2 \x1b[1;31msk_live\x1b[0m_3"
+        );
+        assert_eq!(pattern.like().as_deref(), Some("%sk\\_live%"));
+
+        let pattern = Pattern::new("^[A-Z_]+=sk_", true).unwrap();
+        let matches = pattern.matches(body);
+        assert_eq!(matches.iter().map(|m| m.number).collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(pattern.like(), None);
+    }
+
+    #[test]
+    fn 空のパターンと不正な正規表現はエラーになる() {
+        assert!(Pattern::new("", false).is_err());
+        assert!(Pattern::new("(", true)
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid regex"));
+    }
+}
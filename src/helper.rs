@@ -1,18 +1,171 @@
-use std::path::{Path, PathBuf};
+use anyhow::Context as _;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::path::{Component, Path, PathBuf};
 
-pub fn search_env_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
-    let files = globmatch::Builder::new("**/{.env,.env.*}")
-        .build(dir)
-        .expect("Failed to build globmatch")
+/// 巡回中に読み取れずスキップしたパス
+#[derive(Debug)]
+pub struct SkippedPath {
+    pub path: PathBuf,
+    pub kind: Option<std::io::ErrorKind>,
+    pub message: String,
+}
+
+impl SkippedPath {
+    pub fn is_permission_denied(&self) -> bool {
+        self.kind == Some(std::io::ErrorKind::PermissionDenied)
+    }
+}
+
+/// search_env_files の結果
+/// 読み取れなかったディレクトリは巡回を止めずに skipped に記録する
+#[derive(Debug, Default)]
+pub struct EnvFileSearch {
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<SkippedPath>,
+}
+
+/// search_env_files の巡回のしかた
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// 巡回しないディレクトリやファイルのグロブ
+    /// パスの要素、または巡回を始めたディレクトリからの相対パスに一致するものを除外する
+    pub excludes: Vec<String>,
+    /// .gitignore で無視されるディレクトリを巡回しない
+    /// .env ファイル自体は .gitignore に書かれていることが多いため、ファイルには適用しない
+    pub respect_gitignore: bool,
+    /// 巡回を始めたディレクトリから何階層下までを巡回するか
+    /// 直下のファイルは 1 階層目とし、None の場合は制限しない
+    pub max_depth: Option<usize>,
+    /// 探すファイルのグロブ 空でなければ DEFAULT_PATTERN の代わりに使う
+    /// 巡回を始めたディレクトリからの相対パスに一致するファイルを探す
+    pub patterns: Vec<String>,
+    /// patterns または DEFAULT_PATTERN に加えて探すファイルのグロブ
+    pub add_patterns: Vec<String>,
+    /// DEFAULT_EXCLUDES のディレクトリも巡回する
+    pub no_default_excludes: bool,
+    /// シンボリックリンクのディレクトリも巡回する
+    /// リンクが循環している場合はそのディレクトリを skipped に記録して巡回を続ける
+    /// シンボリックリンクのファイルは follow_symlinks にかかわらず、リンク自体のパスで探す
+    pub follow_symlinks: bool,
+}
+
+/// 既定で巡回しないディレクトリの名前
+/// node_modules は no_default_excludes にかかわらず巡回しない
+pub const DEFAULT_EXCLUDES: [&str; 6] =
+    [".git", "target", ".venv", "venv", "vendor", "__pycache__"];
+
+/// 既定で探すファイルのグロブ
+pub const DEFAULT_PATTERN: &str = "**/{.env,.env.*}";
+
+/// dir 配下の .env, .env.* ファイルをパスの順に探す
+/// node_modules と DEFAULT_EXCLUDES、options.excludes に一致するものは巡回しない
+/// グロブはすべて巡回を始める前に検証する
+pub fn search_env_files(dir: &Path, options: &SearchOptions) -> anyhow::Result<EnvFileSearch> {
+    let patterns = if options.patterns.is_empty() {
+        vec![DEFAULT_PATTERN.to_string()]
+    } else {
+        options.patterns.clone()
+    };
+    let patterns = patterns
         .into_iter()
-        .filter_entry(|entry| {
-            entry
-                .components()
-                .all(|component| component.as_os_str() != "node_modules")
-        })
-        .flatten()
+        .chain(options.add_patterns.iter().cloned())
         .collect::<Vec<_>>();
-    Ok(files)
+    let globs = patterns
+        .iter()
+        .map(|pattern| {
+            globmatch::Builder::new(pattern)
+                .build_glob()
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("invalid pattern {:?}", pattern))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let excludes = options
+        .excludes
+        .iter()
+        .map(|pattern| {
+            globmatch::Builder::new(pattern)
+                .build_glob()
+                .map(|glob| glob.matcher)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("invalid exclude pattern {:?}", pattern))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    // オーバーライドは .gitignore より優先されるため、探すファイルは無視されない
+    // オーバーライドに一致しないディレクトリには .gitignore が適用される
+    let mut overrides = ignore::overrides::OverrideBuilder::new(dir);
+    for pattern in &patterns {
+        overrides
+            .add(pattern)
+            .with_context(|| format!("invalid pattern {:?}", pattern))?;
+    }
+
+    let root = dir.to_path_buf();
+    let default_excludes: &[&str] = if options.no_default_excludes {
+        &[]
+    } else {
+        &DEFAULT_EXCLUDES
+    };
+    let walker = ignore::WalkBuilder::new(dir)
+        .standard_filters(false)
+        .git_ignore(options.respect_gitignore)
+        .git_exclude(options.respect_gitignore)
+        .git_global(options.respect_gitignore)
+        .parents(options.respect_gitignore)
+        .require_git(false)
+        .max_depth(options.max_depth)
+        .follow_links(options.follow_symlinks)
+        .overrides(overrides.build()?)
+        .filter_entry(move |entry| {
+            let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+            let excluded = relative.components().any(|component| {
+                let component = Path::new(component.as_os_str());
+                component == Path::new("node_modules")
+                    || default_excludes
+                        .iter()
+                        .any(|name| component == Path::new(name))
+                    || excludes.iter().any(|glob| glob.is_match(component))
+            }) || excludes.iter().any(|glob| glob.is_match(relative));
+            entry.depth() == 0 || !excluded
+        })
+        .build();
+
+    let mut search = EnvFileSearch::default();
+    for entry in walker {
+        match entry {
+            Ok(entry) => {
+                let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+                // .env.d のようなディレクトリはグロブに一致しても探さない
+                let is_dir = entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_dir());
+                if !is_dir && globs.iter().any(|glob| glob.is_match(relative)) {
+                    search.files.push(entry.into_path());
+                }
+            }
+            Err(error) => search.skipped.push(SkippedPath {
+                path: walk_error_path(&error).unwrap_or(dir).to_path_buf(),
+                kind: error.io_error().map(|error| error.kind()),
+                message: error.to_string(),
+            }),
+        }
+    }
+    // 巡回の順序はファイルシステムによって異なるため、パスの要素ごとに辞書順に並べる
+    search.files.sort();
+    search.skipped.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(search)
+}
+
+/// 巡回中のエラーが発生したパス
+fn walk_error_path(error: &ignore::Error) -> Option<&Path> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            walk_error_path(err)
+        }
+        ignore::Error::Loop { child, .. } => Some(child),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
@@ -24,8 +177,9 @@ mod tests_search_env_files {
         let tmp_dir = tempfile::tempdir().unwrap();
         std::fs::File::create(tmp_dir.path().join(".env")).unwrap();
         std::fs::File::create(tmp_dir.path().join(".env.local")).unwrap();
-        let files = search_env_files(tmp_dir.path()).unwrap();
-        assert_eq!(files.len(), 2);
+        let search = search_env_files(tmp_dir.path(), &SearchOptions::default()).unwrap();
+        assert_eq!(search.files.len(), 2);
+        assert!(search.skipped.is_empty());
     }
 
     #[test]
@@ -35,7 +189,850 @@ mod tests_search_env_files {
         let env_file = node_modules_dir.join(".env");
         std::fs::create_dir(node_modules_dir).unwrap();
         std::fs::File::create(env_file).unwrap();
-        let files = search_env_files(tmp_dir.path()).unwrap();
-        assert_eq!(files.len(), 0);
+        let search = search_env_files(tmp_dir.path(), &SearchOptions::default()).unwrap();
+        assert_eq!(search.files.len(), 0);
+    }
+
+    #[test]
+    fn excludesのグロブに一致するディレクトリとファイルが除外される() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        for dir in ["vendor", "dist/app", "clients/acme", "api"] {
+            let dir = tmp_dir.path().join(dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::File::create(dir.join(".env")).unwrap();
+        }
+        std::fs::File::create(tmp_dir.path().join("api/.env.secret")).unwrap();
+        let options = SearchOptions {
+            excludes: vec![
+                "vendor".to_string(),
+                "dist".to_string(),
+                "clients/*".to_string(),
+                "*.secret".to_string(),
+            ],
+            ..Default::default()
+        };
+        let search = search_env_files(tmp_dir.path(), &options).unwrap();
+        assert_eq!(search.files, vec![tmp_dir.path().join("api/.env")]);
+
+        let options = SearchOptions {
+            excludes: vec!["a/**/[".to_string()],
+            ..Default::default()
+        };
+        assert!(search_env_files(tmp_dir.path(), &options).is_err());
+    }
+
+    #[test]
+    fn patternsで探すファイルを置き換えadd_patternsで追加する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        std::fs::create_dir(root.join("app")).unwrap();
+        for file in [".env", "app/secrets.env", "app/.envrc", "app/config.toml"] {
+            std::fs::File::create(root.join(file)).unwrap();
+        }
+        let search = |patterns: &[&str], add_patterns: &[&str]| {
+            let options = SearchOptions {
+                patterns: patterns.iter().map(|p| p.to_string()).collect(),
+                add_patterns: add_patterns.iter().map(|p| p.to_string()).collect(),
+                ..Default::default()
+            };
+            let mut files = search_env_files(root, &options).unwrap().files;
+            files.sort();
+            files
+        };
+
+        assert_eq!(
+            search(&["**/secrets.env", "**/.envrc"], &[]),
+            vec![root.join("app/.envrc"), root.join("app/secrets.env")]
+        );
+        assert_eq!(
+            search(&[], &["**/secrets.env"]),
+            vec![root.join(".env"), root.join("app/secrets.env")]
+        );
+
+        // 巡回を始める前に不正なグロブをエラーにする
+        let options = SearchOptions {
+            add_patterns: vec!["**/[".to_string()],
+            ..Default::default()
+        };
+        let error = search_env_files(&root.join("missing"), &options).unwrap_err();
+        assert!(error.to_string().starts_with("invalid pattern \"**/[\""));
+    }
+
+    #[test]
+    fn 作成した順序にかかわらずパスの順に返す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        let files = [
+            "web/.env",
+            ".env.local",
+            "api/v2/.env",
+            "api.old/.env",
+            ".env",
+            "api/.env",
+            "Web/.env",
+        ];
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::File::create(path).unwrap();
+        }
+
+        let found = search_env_files(root, &SearchOptions::default())
+            .unwrap()
+            .files;
+        let expected = [
+            ".env",
+            ".env.local",
+            "Web/.env",
+            "api/.env",
+            "api/v2/.env",
+            "api.old/.env",
+            "web/.env",
+        ]
+        .map(|file| root.join(file));
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn 既定で除外するディレクトリ配下のファイルは探さない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        std::fs::File::create(root.join(".env")).unwrap();
+        for name in DEFAULT_EXCLUDES {
+            let dir = root.join("app").join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::File::create(dir.join(".env")).unwrap();
+
+            let files = search_env_files(root, &SearchOptions::default())
+                .unwrap()
+                .files;
+            assert_eq!(files, vec![root.join(".env")], "{} is not excluded", name);
+        }
+
+        let options = SearchOptions {
+            no_default_excludes: true,
+            ..Default::default()
+        };
+        let files = search_env_files(root, &options).unwrap().files;
+        assert_eq!(files.len(), DEFAULT_EXCLUDES.len() + 1);
+    }
+
+    #[test]
+    fn follow_symlinksの場合だけシンボリックリンクのディレクトリを巡回する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let shared = tmp_dir.path().join("shared");
+        let root = tmp_dir.path().join("project");
+        std::fs::create_dir_all(shared.join("config")).unwrap();
+        std::fs::create_dir(&root).unwrap();
+        std::fs::File::create(shared.join("config/.env")).unwrap();
+        std::os::unix::fs::symlink(shared.join("config"), root.join("config")).unwrap();
+        // 循環するリンク
+        std::os::unix::fs::symlink(&root, root.join("loop")).unwrap();
+
+        let search = search_env_files(&root, &SearchOptions::default()).unwrap();
+        assert!(search.files.is_empty());
+        assert!(search.skipped.is_empty());
+
+        let options = SearchOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let search = search_env_files(&root, &options).unwrap();
+        assert_eq!(search.files, vec![root.join("config/.env")]);
+        assert_eq!(search.skipped.len(), 1);
+        assert_eq!(search.skipped[0].path, root.join("loop"));
+    }
+
+    #[test]
+    fn max_depthより深い階層のファイルは探さない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        let deep_dir = root.join("project/go/pkg/mod");
+        std::fs::create_dir_all(&deep_dir).unwrap();
+        std::fs::File::create(deep_dir.join(".env")).unwrap();
+        std::fs::File::create(root.join("project/.env")).unwrap();
+
+        let options = |max_depth| SearchOptions {
+            max_depth,
+            ..Default::default()
+        };
+        let search = search_env_files(root, &options(Some(2))).unwrap();
+        assert_eq!(search.files, vec![root.join("project/.env")]);
+        assert!(search_env_files(root, &options(Some(1)))
+            .unwrap()
+            .files
+            .is_empty());
+        let search = search_env_files(root, &options(None)).unwrap();
+        assert_eq!(search.files.len(), 2);
+    }
+
+    #[test]
+    fn respect_gitignoreではgitignoreに一致するディレクトリだけを巡回しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        for dir in ["build", "app/tmp", "app/src"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::File::create(root.join(dir).join(".env")).unwrap();
+        }
+        std::fs::File::create(root.join(".env")).unwrap();
+        std::fs::File::create(root.join(".env.local")).unwrap();
+        // .env ファイル自体が無視されていても拾う
+        std::fs::write(root.join(".gitignore"), "build/\n.env\n.env.*\n").unwrap();
+        // サブディレクトリの .gitignore も適用する
+        std::fs::write(root.join("app/.gitignore"), "tmp\n").unwrap();
+
+        let options = SearchOptions {
+            respect_gitignore: true,
+            ..Default::default()
+        };
+        let mut files = search_env_files(root, &options).unwrap().files;
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                root.join(".env"),
+                root.join(".env.local"),
+                root.join("app/src/.env"),
+            ]
+        );
+
+        let search = search_env_files(root, &SearchOptions::default()).unwrap();
+        assert_eq!(search.files.len(), 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn 読み取れないディレクトリはスキップして巡回を続ける() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        std::fs::File::create(tmp_dir.path().join(".env")).unwrap();
+        let locked_dir = tmp_dir.path().join("locked");
+        std::fs::create_dir(&locked_dir).unwrap();
+        std::fs::File::create(locked_dir.join(".env")).unwrap();
+        std::fs::set_permissions(&locked_dir, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // root 権限では権限に関係なく読めてしまうため検証できない
+        let readable = std::fs::read_dir(&locked_dir).is_ok();
+
+        let search = search_env_files(tmp_dir.path(), &SearchOptions::default()).unwrap();
+        std::fs::set_permissions(&locked_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        if readable {
+            return;
+        }
+        assert_eq!(search.files, vec![tmp_dir.path().join(".env")]);
+        assert_eq!(search.skipped.len(), 1);
+        assert_eq!(search.skipped[0].path, locked_dir);
+        assert!(search.skipped[0].is_permission_denied());
+    }
+}
+
+/// old から new への変更で追加された行数と削除された行数を数える
+/// 行の並び替えは変更として扱わない
+pub fn count_changed_lines(old: &str, new: &str) -> (usize, usize) {
+    let mut remaining = std::collections::HashMap::new();
+    for line in old.lines() {
+        *remaining.entry(line).or_insert(0usize) += 1;
+    }
+    let mut added = 0;
+    for line in new.lines() {
+        match remaining.get_mut(line) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => added += 1,
+        }
+    }
+    let removed = remaining.values().sum();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests_count_changed_lines {
+    use super::*;
+
+    #[test]
+    fn 追加と削除の行数を数える() {
+        assert_eq!(count_changed_lines("A=1\nB=2\n", "A=1\nB=2\n"), (0, 0));
+        assert_eq!(count_changed_lines("A=1\nB=2\n", "A=1\nB=3\nC=4\n"), (2, 1));
+        assert_eq!(count_changed_lines("A=1\nA=1\n", "A=1\n"), (0, 1));
+    }
+}
+
+/// `Europe/Berlin` のような IANA のタイムゾーン名を解釈する
+pub fn parse_timezone(name: &str) -> anyhow::Result<Tz> {
+    name.parse::<Tz>().map_err(|_| {
+        anyhow::anyhow!(
+            "invalid timezone {:?}. use an IANA timezone name (e.g. UTC, Asia/Tokyo, Europe/Berlin, America/New_York)",
+            name
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests_parse_timezone {
+    use super::*;
+
+    #[test]
+    fn ianaのタイムゾーン名を解釈し不正な名前は例を示してエラーにする() {
+        assert_eq!(parse_timezone("Europe/Berlin").unwrap(), Tz::Europe__Berlin);
+        assert_eq!(parse_timezone("UTC").unwrap(), Tz::UTC);
+        for invalid in ["", "Europe/Atlantis", "JST+9"] {
+            let error = parse_timezone(invalid).unwrap_err().to_string();
+            assert!(error.contains("e.g. UTC, Asia/Tokyo"), "{}", error);
+        }
+    }
+}
+
+/// RFC3339 または `YYYY-MM-DD` 形式の日時を解釈する
+/// `YYYY-MM-DD` の場合は timezone におけるその日の 0 時として扱う
+pub fn parse_timestamp(value: &str, timezone: &Tz) -> anyhow::Result<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        anyhow::anyhow!(
+            "invalid timestamp {:?}. use RFC3339 (e.g. 2024-05-01T12:00:00+09:00) or YYYY-MM-DD",
+            value
+        )
+    })?;
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is always valid");
+    let timestamp = timezone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist in {}", value, timezone))?;
+    Ok(timestamp.with_timezone(&Utc))
+}
+
+/// 期間の終わりとして日時を解釈する
+/// `YYYY-MM-DD` の場合はその日全体を含むよう、timezone における翌日の 0 時の直前として扱う
+pub fn parse_end_timestamp(value: &str, timezone: &Tz) -> anyhow::Result<DateTime<Utc>> {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => {
+            let next_day = date
+                .succ_opt()
+                .ok_or_else(|| anyhow::anyhow!("invalid timestamp {:?}", value))?;
+            let next_day = parse_timestamp(&next_day.format("%Y-%m-%d").to_string(), timezone)?;
+            Ok(next_day - chrono::Duration::nanoseconds(1))
+        }
+        Err(_) => parse_timestamp(value, timezone),
+    }
+}
+
+#[cfg(test)]
+mod tests_parse_timestamp {
+    use super::*;
+
+    #[test]
+    fn rfc3339形式の日時を解釈する() {
+        let timestamp = parse_timestamp("2024-05-01T12:00:00+09:00", &Tz::UTC).unwrap();
+        assert_eq!(
+            timestamp,
+            Utc.with_ymd_and_hms(2024, 5, 1, 3, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn 日付のみの場合はタイムゾーンにおける0時として解釈する() {
+        let timestamp = parse_timestamp("2024-05-01", &chrono_tz::Asia::Tokyo).unwrap();
+        assert_eq!(
+            timestamp,
+            Utc.with_ymd_and_hms(2024, 4, 30, 15, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn 解釈できない場合はエラーになる() {
+        assert!(parse_timestamp("2024/05/01", &Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn 期間の終わりの日付はその日の終わりとして解釈する() {
+        let timestamp = parse_end_timestamp("2024-05-01", &chrono_tz::Asia::Tokyo).unwrap();
+        assert_eq!(
+            timestamp,
+            Utc.with_ymd_and_hms(2024, 5, 1, 15, 0, 0).unwrap() - chrono::Duration::nanoseconds(1)
+        );
+        let timestamp = parse_end_timestamp("2024-05-01T12:00:00+09:00", &Tz::UTC).unwrap();
+        assert_eq!(
+            timestamp,
+            Utc.with_ymd_and_hms(2024, 5, 1, 3, 0, 0).unwrap()
+        );
+    }
+}
+
+/// `90d` や `1y` のような期間を解釈する
+/// 単位は h (時間), d (日), w (週), m (30 日), y (365 日)
+pub fn parse_age(value: &str) -> anyhow::Result<chrono::Duration> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "invalid age {:?}. use a number followed by h, d, w, m or y (e.g. 90d, 1y)",
+            value
+        )
+    };
+    let split = value.len().checked_sub(1).ok_or_else(invalid)?;
+    let (number, unit) = value.split_at_checked(split).ok_or_else(invalid)?;
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let hours = match unit {
+        "h" => 1,
+        "d" => 24,
+        "w" => 24 * 7,
+        "m" => 24 * 30,
+        "y" => 24 * 365,
+        _ => return Err(invalid()),
+    };
+    number
+        .checked_mul(hours)
+        // chrono::Duration はミリ秒で表せる範囲を超えると panic する
+        .filter(|hours| (0..=i64::MAX / 3_600_000).contains(hours))
+        .map(chrono::Duration::hours)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests_parse_age {
+    use super::*;
+
+    #[test]
+    fn 数値と単位から期間を解釈する() {
+        assert_eq!(parse_age("36h").unwrap(), chrono::Duration::hours(36));
+        assert_eq!(parse_age("90d").unwrap(), chrono::Duration::days(90));
+        assert_eq!(parse_age("2w").unwrap(), chrono::Duration::days(14));
+        assert_eq!(parse_age("1y").unwrap(), chrono::Duration::days(365));
+        for invalid in ["", "y", "1", "1.5d", "-1d", "1x", "1あ", "9999999999999y"] {
+            assert!(parse_age(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
+
+/// now から見た at の時間を `3 days ago` のように表示する
+/// parse_age と同じく 1 か月を 30 日、1 年を 365 日として数え、1 分未満は `just now` にする
+pub fn relative_time(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+    let seconds = now.signed_duration_since(at).num_seconds();
+    if seconds < 0 {
+        // 別のホストで登録したアーカイブは時計のずれで未来の日時になることがある
+        return "in the future".to_string();
+    }
+    let (count, unit) = match seconds {
+        s if s < MINUTE => return "just now".to_string(),
+        s if s < HOUR => (s / MINUTE, "minute"),
+        s if s < DAY => (s / HOUR, "hour"),
+        s if s < 30 * DAY => (s / DAY, "day"),
+        s if s < 365 * DAY => (s / (30 * DAY), "month"),
+        s => (s / (365 * DAY), "year"),
+    };
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+#[cfg(test)]
+mod tests_relative_time {
+    use super::*;
+
+    #[test]
+    fn 経過した時間を最も大きい単位で表示する() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let ago = |duration: chrono::Duration| relative_time(now - duration, now);
+        assert_eq!(ago(chrono::Duration::seconds(59)), "just now");
+        assert_eq!(ago(chrono::Duration::minutes(1)), "1 minute ago");
+        assert_eq!(ago(chrono::Duration::minutes(59)), "59 minutes ago");
+        assert_eq!(ago(chrono::Duration::hours(2)), "2 hours ago");
+        assert_eq!(ago(chrono::Duration::days(3)), "3 days ago");
+        assert_eq!(ago(chrono::Duration::days(29)), "29 days ago");
+        assert_eq!(ago(chrono::Duration::days(240)), "8 months ago");
+        assert_eq!(ago(chrono::Duration::days(365)), "1 year ago");
+        assert_eq!(ago(chrono::Duration::days(800)), "2 years ago");
+        assert_eq!(ago(chrono::Duration::seconds(-5)), "in the future");
+    }
+}
+
+/// path を cwd を基準に絶対パスにし、`.` と `..` を取り除く
+/// ファイルシステムにはアクセスしないため、存在しないパスにも使える
+/// シンボリックリンクは解決しないので、`..` はリンク先ではなく字句上の親ディレクトリになる
+pub fn normalize_path(path: &Path, cwd: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in cwd.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// 既に存在するはずの path を正規化する
+/// 存在しない場合は、どの要素が存在しないのかを示すエラーを返す
+pub fn canonicalize_existing(path: &Path, cwd: &Path) -> anyhow::Result<PathBuf> {
+    let error = match std::fs::canonicalize(cwd.join(path)) {
+        Ok(canonical) => return Ok(canonical),
+        Err(error) => error,
+    };
+    let mut current = PathBuf::new();
+    for component in normalize_path(path, cwd).components() {
+        current.push(component);
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.is_symlink() && std::fs::metadata(&current).is_err() => {
+                anyhow::bail!(
+                    "{} does not exist: {} is a dangling symlink",
+                    path.display(),
+                    current.display()
+                )
+            }
+            Ok(_) => {}
+            Err(_) => anyhow::bail!(
+                "{} does not exist: {} is missing",
+                path.display(),
+                current.display()
+            ),
+        }
+    }
+    Err(error).with_context(|| format!("Failed to resolve {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests_normalize_path {
+    use super::*;
+
+    #[test]
+    fn カレントディレクトリを基準にドットとドットドットを取り除く() {
+        let cwd = Path::new("/home/user/work");
+        assert_eq!(
+            normalize_path(Path::new("../project/./api/.env"), cwd),
+            Path::new("/home/user/project/api/.env")
+        );
+        assert_eq!(normalize_path(Path::new("."), cwd), cwd);
+        assert_eq!(
+            normalize_path(Path::new("/srv/app/../web"), cwd),
+            Path::new("/srv/web")
+        );
+        assert_eq!(
+            normalize_path(Path::new("../../../../.."), cwd),
+            Path::new("/")
+        );
+    }
+
+    #[test]
+    fn 存在しないパスもファイルシステムにアクセスせずに正規化する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            normalize_path(Path::new("missing/../not-cloned/.env"), tmp_dir.path()),
+            tmp_dir.path().join("not-cloned/.env")
+        );
+    }
+
+    #[test]
+    fn canonicalize_existingは存在しない最初の要素を示す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cwd = std::fs::canonicalize(tmp_dir.path()).unwrap();
+        std::fs::create_dir(cwd.join("project")).unwrap();
+
+        assert_eq!(
+            canonicalize_existing(Path::new("project/../project"), &cwd).unwrap(),
+            cwd.join("project")
+        );
+        let error = canonicalize_existing(Path::new("project/api/.env"), &cwd)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            format!(
+                "project/api/.env does not exist: {} is missing",
+                cwd.join("project/api").display()
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn canonicalize_existingはリンク切れのシンボリックリンクを示す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cwd = std::fs::canonicalize(tmp_dir.path()).unwrap();
+        std::os::unix::fs::symlink(cwd.join("gone"), cwd.join(".env")).unwrap();
+
+        let error = canonicalize_existing(Path::new(".env"), &cwd)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            format!(
+                ".env does not exist: {} is a dangling symlink",
+                cwd.join(".env").display()
+            )
+        );
+    }
+}
+
+/// コマンドを実行している OS のユーザー名を環境変数から取得する
+/// 取得できない場合は None を返す
+pub fn current_user() -> Option<String> {
+    ["USER", "LOGNAME", "USERNAME"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|user| !user.is_empty())
+}
+
+/// 登録しようとしたパスが通常のファイルではない
+/// ディレクトリや FIFO などを読み込もうとすると失敗したり止まったりするため、読み込む前に確かめる
+#[derive(Debug)]
+pub struct NotRegularFile {
+    pub path: PathBuf,
+}
+
+impl std::fmt::Display for NotRegularFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' is not a regular file", self.path.display())
+    }
+}
+
+impl std::error::Error for NotRegularFile {}
+
+/// path が通常のファイル (へのシンボリックリンク) であることを確かめる
+pub fn ensure_regular_file(path: &Path) -> anyhow::Result<()> {
+    let metadata =
+        std::fs::metadata(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(NotRegularFile {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// `1MiB` や `512KiB` のような大きさをバイト数として解釈する
+/// 単位は B, KiB, MiB, GiB で、単位を省略した場合はバイト数とする
+pub fn parse_size(value: &str) -> anyhow::Result<u64> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "invalid size {:?}. use a number optionally followed by B, KiB, MiB or GiB (e.g. 1MiB)",
+            value
+        )
+    };
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let multiplier = match unit {
+        "" | "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(multiplier).ok_or_else(invalid)
+}
+
+/// バイト数を parse_size と同じ単位で `1.2 KiB` のように表示する
+/// 1 KiB 未満はバイト数をそのまま表示する
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1 << 10 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// path のファイルの大きさが max_size バイトを超えていれば、その大きさを返す
+/// ファイルは読まずにメタデータから大きさを調べる
+pub fn oversized(path: &Path, max_size: u64) -> std::io::Result<Option<u64>> {
+    let size = std::fs::metadata(path)?.len();
+    Ok((size > max_size).then_some(size))
+}
+
+#[cfg(test)]
+mod tests_parse_size {
+    use super::*;
+
+    #[test]
+    fn 数値と単位から大きさを解釈する() {
+        assert_eq!(parse_size("1MiB").unwrap(), 1024 * 1024);
+        assert_eq!(parse_size("512KiB").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("100B").unwrap(), 100);
+        assert_eq!(parse_size("100").unwrap(), 100);
+        for invalid in [
+            "",
+            "MiB",
+            "1.5MiB",
+            "-1",
+            "1MB",
+            "1 MiB",
+            "99999999999999999999GiB",
+        ] {
+            assert!(parse_size(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn 大きさを単位を付けて表示する() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1229), "1.2 KiB");
+        assert_eq!(format_size(4 * 1024 * 1024), "4.0 MiB");
+        assert_eq!(format_size(3 << 40), "3072.0 GiB");
+    }
+
+    #[test]
+    fn 通常のファイルでなければnot_regular_fileになる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let file = tmp_dir.path().join(".env");
+        std::fs::write(&file, "FOO=1").unwrap();
+        ensure_regular_file(&file).unwrap();
+
+        let error = ensure_regular_file(tmp_dir.path()).unwrap_err();
+        assert!(error.is::<NotRegularFile>());
+        assert_eq!(
+            error.to_string(),
+            format!("'{}' is not a regular file", tmp_dir.path().display())
+        );
+
+        let fifo = tmp_dir.path().join(".env.fifo");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(ensure_regular_file(&fifo)
+            .unwrap_err()
+            .is::<NotRegularFile>());
+    }
+
+    #[test]
+    fn 上限を超えるファイルだけ大きさを返す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join(".env");
+        std::fs::write(&path, vec![b'x'; 1025]).unwrap();
+        assert_eq!(oversized(&path, 1024).unwrap(), Some(1025));
+        assert_eq!(oversized(&path, 1025).unwrap(), None);
+        assert!(oversized(&tmp_dir.path().join("missing"), 1024).is_err());
+    }
+}
+
+/// value がグロブの特殊文字を含むかどうか
+pub fn has_glob_meta(value: &str) -> bool {
+    value.contains(['*', '?', '[', '{'])
+}
+
+/// cwd を基準にグロブ pattern に一致するファイルをパスの順に返す
+/// グロブを含まない先頭のディレクトリから探し、`**` を含まなければそれより深い階層は探さない
+pub fn expand_glob(pattern: &str, cwd: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut root = cwd.to_path_buf();
+    let mut rest = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        if rest.as_os_str().is_empty() && !has_glob_meta(&component.as_os_str().to_string_lossy()) {
+            root.push(component);
+        } else {
+            rest.push(component);
+        }
+    }
+    let rest = rest.to_string_lossy();
+    let glob = globmatch::Builder::new(&rest)
+        .build_glob()
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("invalid pattern {:?}", pattern))?;
+    let max_depth = (!rest.contains("**")).then(|| Path::new(rest.as_ref()).components().count());
+
+    let mut files = Vec::new();
+    let walker = ignore::WalkBuilder::new(&root)
+        .standard_filters(false)
+        .max_depth(max_depth)
+        .build();
+    for entry in walker {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir())
+            && glob.is_match(relative)
+        {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests_expand_glob {
+    use super::*;
+
+    #[test]
+    fn グロブに一致するファイルをパスの順に返す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cwd = tmp_dir.path();
+        std::fs::create_dir_all(cwd.join("api/.env.d")).unwrap();
+        for file in [".env.local", ".env", "api/.env", "README.md"] {
+            std::fs::File::create(cwd.join(file)).unwrap();
+        }
+
+        assert_eq!(
+            expand_glob(".env*", cwd).unwrap(),
+            vec![cwd.join(".env"), cwd.join(".env.local")]
+        );
+        assert_eq!(
+            expand_glob("api/.env*", cwd).unwrap(),
+            vec![cwd.join("api/.env")]
+        );
+        assert_eq!(
+            expand_glob("**/.env", cwd).unwrap(),
+            vec![cwd.join(".env"), cwd.join("api/.env")]
+        );
+        assert!(expand_glob("*.toml", cwd).unwrap().is_empty());
+        assert!(expand_glob("[", cwd).is_err());
+        assert!(!has_glob_meta(".env.local"));
+    }
+}
+
+/// .env ファイルの内容を書き込むファイルのパーミッション
+/// 秘密の値を含むため、tar に書き出すファイルや編集用の一時ファイルと同じく所有者だけが読み書きできるようにする
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// path に所有者だけが読み書きできるファイルを作成し、既にあれば空にして開く
+/// パーミッションは作成時に決めるため、書き込んだ内容が他のユーザーから読める瞬間はない
+pub fn create_private_file(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, PRIVATE_FILE_MODE);
+    options.open(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests_create_private_file {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn 所有者だけが読み書きできるファイルを作成する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("secret");
+        create_private_file(&path)
+            .unwrap()
+            .write_all(b"FOO=BAR")
+            .unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, PRIVATE_FILE_MODE);
+
+        // 既にあるファイルは空にしてから書き込む
+        create_private_file(&path).unwrap().write_all(b"A").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "A");
     }
 }
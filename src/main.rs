@@ -4,10 +4,29 @@
 // タグ付けされた .env ファイルは一意に識別できるため、同じファイルを複数回アーカイブしても問題ありません。
 
 mod archive;
+mod browse;
+mod cold;
+mod config;
+mod confirm;
 mod digest;
+mod dotenv;
+mod grep;
 mod helper;
+mod manifest;
+mod output;
+mod password_manager;
+mod pick;
+mod policy;
+mod progress;
+mod redact;
+mod stats;
+mod tarball;
+mod workspace;
 
+use anyhow::Context as _;
 use clap::{Parser, Subcommand};
+use futures::StreamExt as _;
+use std::io::IsTerminal as _;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Parser)]
@@ -22,11 +41,182 @@ struct Args {
     #[clap(subcommand)]
     subcommand: SubCommands,
     /// アーカイブデータベースファイルのパス
-    /// デフォルトは $HOME/.env_archive です
+    /// 省略した場合は設定ファイルの database、$XDG_DATA_HOME/env-archive/archive.db の順に使う
+    /// ($XDG_DATA_HOME がなければ ~/.local/share/env-archive/archive.db)
     #[clap(short, long, env = "ENV_ARCHIVE_DATABASE")]
     database: Option<String>,
+    /// カレントディレクトリから親ディレクトリへたどって見つけた .env_archive を使う
+    /// 見つからなければ --local を指定しない場合と同じデータベースを使う
+    /// init では git リポジトリのルート、または --at のディレクトリに .env_archive を作成する
+    #[clap(long, global = true)]
+    local: bool,
+    /// 端末の幅にかかわらず一覧を 1 レコード 1 行で表示する
+    #[clap(long, global = true)]
+    wide: bool,
+    /// 一覧の日時を経過時間 (3 days ago) ではなく日時で表示する
+    #[clap(long = "absolute-time", global = true)]
+    absolute_time: bool,
+    /// すべての日時を --timezone などの設定にかかわらず UTC の RFC3339 (2024-01-02T03:04:05Z) で表示する
+    #[clap(long, global = true)]
+    utc: bool,
+    /// 画面共有時などのために、パスの大部分、メッセージ、.env ファイルの値を伏せて表示する
+    #[clap(long, global = true)]
+    private: bool,
+    /// データベース操作のタイムアウト秒数
+    /// 超過した場合は操作を中断して終了コード 4 で終了する
+    #[clap(long = "db-timeout", global = true, default_value = "30")]
+    db_timeout: u64,
+    /// 一覧や検索で --user, --all-users を省略したときに表示するアーカイブ
+    /// own は自分が登録したアーカイブだけ、all はすべてのユーザーのアーカイブを表示する
+    /// 省略した場合は設定ファイルの visibility.default、own の順に使う
+    #[clap(long, global = true, value_enum, env = "ENV_ARCHIVE_VISIBILITY")]
+    visibility: Option<Visibility>,
+    /// lint と push で適用するポリシーを書いた TOML ファイルのパス
+    /// 省略した場合は設定ファイルの [policy] と [[policies]] を使う
+    #[clap(long, global = true, env = "ENV_ARCHIVE_POLICIES")]
+    policies: Option<String>,
+    /// 日時の表示と、--since などの YYYY-MM-DD の解釈に使うタイムゾーン (例: Europe/Berlin)
+    /// 省略した場合は環境変数 ENV_ARCHIVE_TZ、設定ファイルの timezone、システムのタイムゾーン、UTC の順に使う
+    #[clap(long, global = true)]
+    timezone: Option<String>,
 }
 
+/// --timezone を省略した場合に使うタイムゾーンの環境変数
+const TIMEZONE_ENV: &str = "ENV_ARCHIVE_TZ";
+
+/// 一覧や検索で既定で表示するアーカイブ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+enum Visibility {
+    /// 自分が登録したアーカイブだけ
+    #[default]
+    Own,
+    /// すべてのユーザーのアーカイブ
+    All,
+}
+
+/// show で値を伏せるかどうか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Redact {
+    /// 標準出力が端末の場合だけ伏せる
+    Auto,
+    /// 常に伏せる
+    Always,
+    /// 伏せない
+    Never,
+}
+
+impl Redact {
+    /// 値を伏せるかどうか
+    /// explicit は show --key, --export で値を使うために表示する場合
+    fn applies(self, is_terminal: bool, explicit: bool) -> bool {
+        match self {
+            Redact::Auto => is_terminal && !explicit,
+            Redact::Always => true,
+            Redact::Never => false,
+        }
+    }
+}
+
+/// アーカイブを登録したユーザーによる絞り込み
+/// 登録したユーザーが記録されていない古いアーカイブは常に表示する
+#[derive(Debug, Clone, Default, clap::Args)]
+struct UserArgs {
+    /// 指定したユーザーが登録したアーカイブに限る
+    #[clap(long, conflicts_with = "all_users")]
+    user: Option<String>,
+    /// すべてのユーザーのアーカイブを表示する
+    #[clap(long = "all-users")]
+    all_users: bool,
+}
+
+/// アーカイブを登録した日時による絞り込み
+/// 日時は RFC3339 または `YYYY-MM-DD` 形式で、日付のみの場合は表示するタイムゾーンで解釈する
+#[derive(Debug, Clone, Default, clap::Args)]
+struct DateRangeArgs {
+    /// 指定した日時以降に登録したアーカイブに限る
+    #[clap(long)]
+    since: Option<String>,
+    /// 指定した日時以前に登録したアーカイブに限る 日付のみの場合はその日全体を含む
+    #[clap(long)]
+    until: Option<String>,
+}
+
+/// 一覧のうち表示する範囲
+#[derive(Debug, Clone, Default, clap::Args)]
+struct PageArgs {
+    /// 表示する件数の上限
+    #[clap(long)]
+    limit: Option<std::num::NonZeroUsize>,
+    /// 先頭から指定した件数を飛ばして表示する
+    #[clap(long, default_value_t = 0)]
+    offset: usize,
+    /// 並べる列 (指定しない場合は作成日時の新しい順、list-all ではパスの昇順で同じパスは新しい順)
+    #[clap(long, value_enum)]
+    sort: Option<archive::SortKey>,
+    /// --sort の列の降順に並べる
+    #[clap(long)]
+    desc: bool,
+}
+
+impl PageArgs {
+    fn page(&self) -> archive::Page {
+        self.page_or(archive::Sort::default())
+    }
+
+    /// --sort を指定しなかった場合は default の順に並べる
+    fn page_or(&self, default: archive::Sort) -> archive::Page {
+        let sort = match self.sort {
+            Some(key) => archive::Sort {
+                key,
+                desc: self.desc,
+            },
+            None => default,
+        };
+        archive::Page {
+            limit: self.limit,
+            offset: self.offset,
+            sort,
+        }
+    }
+}
+
+/// 一覧の出力形式
+#[derive(Debug, Clone, Default, clap::Args)]
+struct ListFormatArgs {
+    /// 出力形式 省略した場合は設定ファイルの default_format、text の順に使う
+    #[clap(long, value_enum)]
+    format: Option<output::EntryFormat>,
+    /// 名前、パス、登録日時をタブで区切り、1 件ごとに NUL で区切って出力する (fzf --read0 や xargs -0 向け)
+    #[clap(short = '0', long, conflicts_with = "format")]
+    print0: bool,
+    /// --print0 で名前だけを出力する (xargs -0 向け)
+    #[clap(long = "names-only", requires = "print0")]
+    names_only: bool,
+}
+
+impl ListFormatArgs {
+    fn format(&self, config: &config::Config) -> output::EntryFormat {
+        if self.names_only {
+            output::EntryFormat::Print0Names
+        } else if self.print0 {
+            output::EntryFormat::Print0
+        } else {
+            config.format(self.format)
+        }
+    }
+}
+
+/// データベースがない、または初期化されていない場合の終了コード
+const EXIT_NOT_INITIALIZED: i32 = 3;
+/// データベース操作がタイムアウトした場合の終了コード
+const EXIT_DB_TIMEOUT: i32 = 4;
+/// コールドストレージに移した本文を読み出せなかった場合の終了コード
+const EXIT_COLD_BODY_UNAVAILABLE: i32 = 5;
+/// push で通常のファイルではないパスを指定した場合の終了コード
+const EXIT_NOT_REGULAR_FILE: i32 = 6;
+/// search で一致するアーカイブがなかった場合の終了コード
+const EXIT_NO_MATCHES: i32 = 1;
+
 #[derive(Debug, Subcommand)]
 enum SubCommands {
     /// アーカイブを初期化する
@@ -35,15 +225,54 @@ enum SubCommands {
         /// アーカイブを強制的に初期化する
         #[clap(long, default_value = "false")]
         clean: bool,
+        /// --local で .env_archive を作成するディレクトリ 省略した場合は git リポジトリのルートに作成する
+        #[clap(long, requires = "local")]
+        at: Option<String>,
     },
     /// アーカイブに .env ファイルを登録する
     Push {
-        /// アーカイブに登録する .env ファイルのパス
+        /// アーカイブに登録する .env ファイルのパス 複数指定できる
+        /// `-` を指定すると標準入力から読み込み、--path のパスに登録する
         #[clap(default_value = ".env")]
-        file: String,
-        /// 登録名
+        files: Vec<String>,
+        /// 標準入力から読み込んだ内容を登録するアーカイブ元のパス
+        #[clap(long)]
+        path: Option<String>,
+        /// 登録名 ファイルを 1 つだけ指定した場合に使える
         #[clap(short, long)]
         name: Option<String>,
+        /// 登録名を `<prefix>-<ファイル名>` にする
+        #[clap(long = "name-prefix", conflicts_with = "name")]
+        name_prefix: Option<String>,
+        /// 登録名が既に使われている場合に `-2`, `-3`, ... と連番を付けて登録する
+        #[clap(long = "rename-on-conflict")]
+        rename_on_conflict: bool,
+        /// 登録名が既に使われている場合にそのアーカイブの内容を置き換える タグは残す
+        #[clap(long, conflicts_with = "rename_on_conflict")]
+        overwrite: bool,
+        /// アーカイブの説明
+        #[clap(short, long)]
+        message: Option<String>,
+        /// 登録するファイルの大きさの上限 単位は B, KiB, MiB, GiB
+        #[clap(long = "max-size", default_value = "1MiB")]
+        max_size: String,
+        /// --max-size を超える大きさのファイルも登録する
+        #[clap(long)]
+        force: bool,
+        /// 最新のアーカイブと同じ内容なら登録しない
+        #[clap(long = "skip-if-same")]
+        skip_if_same: bool,
+        /// 結果を 1 ファイルごとに 1 行の JSON で出力する
+        /// 後続の処理で --rename-on-conflict などで決まった登録名を使う場合向け
+        #[clap(long)]
+        json: bool,
+        /// 空のファイルも登録する
+        #[clap(long = "allow-empty")]
+        allow_empty: bool,
+        /// 登録日時 RFC3339 または YYYY-MM-DD 形式 過去のバックアップを取り込むときに使う
+        /// 省略した場合は現在日時 未来の日時は指定できない
+        #[clap(long)]
+        at: Option<String>,
     },
     /// ディレクトリを再帰的に巡回して .env, .env.* ファイルを探し、アーカイブに登録する
     #[clap(arg_required_else_help = false)]
@@ -53,240 +282,7398 @@ enum SubCommands {
         dir: String,
         #[clap(long = "dry-run")]
         dry_run: bool,
+        /// 権限がなく読み取れないディレクトリがあった場合に終了コード 1 で終了する
+        #[clap(long = "strict-permissions")]
+        strict_permissions: bool,
+        /// 巡回しないディレクトリやファイルのグロブ 複数回指定できる
+        /// パスの要素、または --dir からの相対パスに一致するものを除外する
+        /// node_modules と .git, target, .venv, venv, vendor, __pycache__ は指定しなくても除外する
+        /// 省略した場合は設定ファイルの crawl.excludes を使う
+        #[clap(long)]
+        exclude: Vec<String>,
+        /// .git, target, .venv, venv, vendor, __pycache__ も巡回する
+        /// node_modules は常に除外する
+        #[clap(long = "no-default-excludes")]
+        no_default_excludes: bool,
+        /// シンボリックリンクのディレクトリも巡回する 循環するリンクはスキップする
+        /// シンボリックリンクの .env ファイルは指定しなくても、リンク先の内容をリンク自体のパスで登録する
+        #[clap(long = "follow-symlinks")]
+        follow_symlinks: bool,
+        /// .gitignore で無視されるディレクトリを巡回しない
+        /// .env, .env.* ファイル自体は .gitignore で無視されていても登録する
+        #[clap(long = "respect-gitignore")]
+        respect_gitignore: bool,
+        /// --dir から何階層下までを巡回するか --dir 直下のファイルは 1 階層目とする
+        /// 省略した場合は制限しない
+        #[clap(long = "max-depth")]
+        max_depth: Option<usize>,
+        /// 探すファイルのグロブ 複数回指定でき、既定の .env, .env.* の代わりに使う
+        /// --dir からの相対パスに一致するファイルを探す (例: '**/secrets.env')
+        /// 省略した場合は設定ファイルの crawl.patterns を使う
+        #[clap(long)]
+        pattern: Vec<String>,
+        /// .env, .env.* または --pattern に加えて探すファイルのグロブ 複数回指定できる
+        #[clap(long = "add-pattern")]
+        add_pattern: Vec<String>,
+        /// 並行して読み込むファイルの数 アーカイブへの登録は 1 件ずつ行う
+        #[clap(short, long, default_value_t = DEFAULT_CRAWL_JOBS)]
+        jobs: std::num::NonZeroUsize,
+        /// 登録するファイルの大きさの上限 単位は B, KiB, MiB, GiB
+        /// 上限を超えるファイルは読み込まずにスキップする
+        #[clap(long = "max-size", default_value = "1MiB")]
+        max_size: String,
+        /// 空のファイルも登録する 指定しなければスキップする
+        #[clap(long = "include-empty")]
+        include_empty: bool,
     },
     /// アーカイブに登録されている .env ファイルをパス名の部分一致で検索する
+    /// 大文字と小文字は区別する 一致するものがなければ終了コード 1 で終了する
     Search {
         /// アーカイブに登録されている .env ファイルパスの一部
+        /// 複数指定した場合はすべてを含むパスに限る
+        #[clap(required = true, value_name = "KEYWORD")]
+        keywords: Vec<String>,
+        /// 指定した文字列を含むパスを除く 複数回指定できる
+        #[clap(long = "not", value_name = "KEYWORD")]
+        excludes: Vec<String>,
+        /// KEYWORD と --not をファイルパスに一致する正規表現として扱う (例: '/apps/(api|worker)/\.env$')
+        #[clap(long)]
+        regex: bool,
+        /// パス名の大文字と小文字を区別せずに検索する
+        #[clap(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+        /// パスごとに最新のアーカイブだけを表示する 隔離されているアーカイブは除く
+        #[clap(long)]
+        latest: bool,
+        /// 指定したタグが付いたアーカイブに限る
+        #[clap(long)]
+        tag: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+        #[clap(flatten)]
+        dates: DateRangeArgs,
+        #[clap(flatten)]
+        page: PageArgs,
+        #[clap(flatten)]
+        format: ListFormatArgs,
+    },
+    /// アーカイブの本文を検索し、一致する行をアーカイブの名前とパス、登録日時と共に表示する
+    /// 隔離されているアーカイブは検索しない
+    Grep {
+        /// 本文から探す文字列 大文字と小文字を区別する
+        #[clap(required = true)]
+        pattern: String,
+        /// pattern を正規表現として扱う
+        #[clap(long)]
+        regex: bool,
+        /// パスごとに最新のアーカイブだけを検索する
+        #[clap(long)]
+        latest: bool,
+        /// 指定したタグが付いたアーカイブに限る
+        #[clap(long)]
+        tag: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// 指定したキーを定義しているアーカイブを、アーカイブの名前とパス、登録日時と共に表示する
+    /// 隔離されているアーカイブは探さない
+    WhichKey {
+        /// 探すキーの名前 大文字と小文字を区別する
+        #[clap(required = true)]
+        key: String,
+        /// key で始まるキーをすべて探す
+        #[clap(long)]
+        prefix: bool,
+        /// パスごとに最新のアーカイブだけを探す
+        #[clap(long)]
+        latest: bool,
+        /// キーの値も表示する
+        #[clap(long = "show-values")]
+        show_values: bool,
+        /// 指定したタグが付いたアーカイブに限る
+        #[clap(long)]
+        tag: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// 値に指定した文字列を含むキーがあるアーカイブを、アーカイブの名前とパス、登録日時と共に表示する
+    /// 値は --show-values を指定しなければ表示しない 隔離されているアーカイブは探さない
+    WhichValue {
+        /// 値から探す文字列 大文字と小文字を区別する
         #[clap(required = true)]
-        keyword: String,
+        value: String,
+        /// パスごとに最新のアーカイブだけを探す
+        #[clap(long)]
+        latest: bool,
+        /// キーの値も表示する
+        #[clap(long = "show-values")]
+        show_values: bool,
+        /// 指定したタグが付いたアーカイブに限る
+        #[clap(long)]
+        tag: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
     },
     /// カレントディレクトリ、または指定したパス配下に一致するアーカイブの一覧を表示する
+    ///
+    /// 既定ではパスごとに最新のアーカイブだけを、そのパスのアーカイブの件数 (versions) と共に表示する
     List {
         #[clap(short, long, default_value = ".")]
         dir: String,
+        /// パスの大文字と小文字を区別せずに一致させる
+        #[clap(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+        /// パスごとにまとめず、すべてのアーカイブを表示する
+        #[clap(long = "all-versions")]
+        all_versions: bool,
+        /// パスごとの最新のアーカイブをディレクトリの木構造で表示する
+        #[clap(long, conflicts_with_all = ["all_versions", "format", "print0"])]
+        tree: bool,
+        /// 指定したタグが付いたアーカイブに限る
+        #[clap(long)]
+        tag: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+        #[clap(flatten)]
+        dates: DateRangeArgs,
+        #[clap(flatten)]
+        page: PageArgs,
+        #[clap(flatten)]
+        format: ListFormatArgs,
+    },
+    /// アーカイブに登録されている .env ファイルの一覧をパスの順に、同じパスは新しい順に表示する
+    /// データベースの VACUUM や import の後も同じ順に並ぶ
+    ListAll {
+        /// 指定したタグが付いたアーカイブに限る
+        #[clap(long)]
+        tag: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+        #[clap(flatten)]
+        dates: DateRangeArgs,
+        #[clap(flatten)]
+        page: PageArgs,
+        #[clap(flatten)]
+        format: ListFormatArgs,
+    },
+    /// パスごとに最新のアーカイブだけを一覧表示する
+    Latest {
+        /// 指定したパス配下に一致するアーカイブに限る
+        #[clap(short, long)]
+        dir: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+        /// 出力形式 省略した場合は設定ファイルの default_format、text の順に使う
+        #[clap(long, value_enum)]
+        format: Option<output::EntryFormat>,
+    },
+    /// パスとバージョンを一覧し、値を伏せた本文を確かめながら復元などを行う画面を開く
+    Browse {
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// アーカイブを絞り込みながら選ぶ画面を開き、選んだアーカイブの名前を表示する
+    ///
+    /// `recover $(dot-env-archive pick api)` のように、名前を引数に取るコマンドと組み合わせて使う
+    Pick {
+        /// ファイルパスに部分一致するアーカイブに限る
+        keyword: Option<String>,
+        /// パスごとにまとめず、すべてのアーカイブを選択肢にする
+        #[clap(long = "all-versions")]
+        all_versions: bool,
+        /// 指定したタグが付いたアーカイブに限る
+        #[clap(long)]
+        tag: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// 指定したファイルのアーカイブ履歴を新しい順に表示する
+    History {
+        /// 対象の .env ファイルのパス
+        #[clap(required = true)]
+        path: String,
+        /// 表示する件数の上限
+        #[clap(short, long)]
+        limit: Option<usize>,
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// アーカイブのタグを操作する
+    Tag {
+        #[clap(subcommand)]
+        command: TagCommands,
+    },
+    /// アーカイブに説明を設定する
+    Annotate {
+        /// アーカイブの名前
+        #[clap(required = true)]
+        name: String,
+        /// アーカイブの説明
+        #[clap(short, long, required_unless_present = "clear")]
+        message: Option<String>,
+        /// 説明を消す
+        #[clap(long, conflicts_with = "message")]
+        clear: bool,
+    },
+    /// アーカイブの本文を $EDITOR (未設定の場合は vi) で編集し、変更があれば同じパスの新しいアーカイブとして登録する
+    Edit {
+        /// 編集するアーカイブの名前
+        #[clap(required = true)]
+        name: String,
+        /// 新しいアーカイブの登録名 省略時は ULID
+        #[clap(short = 'n', long = "name")]
+        new_name: Option<String>,
+        /// 新しいアーカイブの説明
+        #[clap(short, long)]
+        message: Option<String>,
+        /// 他のユーザーが登録したアーカイブも編集できるようにする
+        #[clap(long = "all-users")]
+        all_users: bool,
+    },
+    /// アーカイブの名前を変更する
+    Rename {
+        /// 変更前の名前
+        #[clap(required = true)]
+        old_name: String,
+        /// 変更後の名前
+        #[clap(required = true)]
+        new_name: String,
     },
-    /// アーカイブに登録されている .env ファイルの一覧を表示する
-    ListAll,
     /// アーカイブに登録されている .env ファイルを表示する
     Show {
+        /// アーカイブに登録されている .env ファイルの名前
+        #[clap(required_unless_present = "path", conflicts_with = "path")]
+        name: Option<String>,
+        /// アーカイブ元のパス このパスの最新のアーカイブを表示する
+        /// アーカイブの名前と登録日時は標準エラー出力に表示する
+        #[clap(long)]
+        path: Option<String>,
+        /// --path で指定したパスのアーカイブのうち、新しい方から何番目を表示するか
+        /// 省略した場合は最新のアーカイブを表示する
+        #[clap(long, requires = "path", conflicts_with = "name")]
+        nth: Option<std::num::NonZeroUsize>,
+        /// 他のユーザーが登録したアーカイブも表示できるようにする
+        #[clap(long = "all-users")]
+        all_users: bool,
+        /// 隔離されているアーカイブも表示できるようにする
+        #[clap(long = "include-quarantined")]
+        include_quarantined: bool,
+        /// 本文の代わりに表示する変数のキー
+        /// 複数回指定すると `KEY=value` の形式で指定した順に表示する
+        #[clap(long = "key")]
+        keys: Vec<String>,
+        /// 本文の代わりに `export KEY='value'` の形式で表示する
+        /// `eval "$(env-archive show NAME --export)"` で変数を読み込める
+        /// シェルの変数名として使えないキーは警告を表示してスキップする
+        #[clap(long, conflicts_with = "keys")]
+        export: bool,
+        /// 値を `********` に伏せて表示するかどうか 値を省略すると always
+        /// auto は標準出力が端末の場合に伏せる ただし --key, --export を指定した場合は伏せない
+        #[clap(
+            long,
+            value_enum,
+            env = "ENV_ARCHIVE_REDACT",
+            default_value = "auto",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "always"
+        )]
+        redact: Redact,
+        /// 値を伏せずに表示する --redact より優先する
+        #[clap(long = "show-values")]
+        show_values: bool,
+    },
+    /// アーカイブに登録されている .env ファイルを、アーカイブ元と同じファイル名でディレクトリに書き出す
+    /// 書き出したファイルの絶対パスを表示する
+    Extract {
+        /// アーカイブに登録されている .env ファイルの名前
+        #[clap(required = true)]
+        name: String,
+        /// 書き出し先のディレクトリ
+        #[clap(default_value = ".")]
+        dest_dir: String,
+        /// 書き出し先に同じ名前のファイルがある場合に上書きする
+        #[clap(short, long)]
+        force: bool,
+        /// 他のユーザーが登録したアーカイブも書き出せるようにする
+        #[clap(long = "all-users")]
+        all_users: bool,
+        /// 隔離されているアーカイブも書き出せるようにする
+        #[clap(long = "include-quarantined")]
+        include_quarantined: bool,
+    },
+    /// アーカイブの本文がチェックサムと一致するかを検証する
+    /// 一致しない場合はアーカイブを隔離し、終了コード 1 で終了する
+    Verify {
+        /// アーカイブに登録されている .env ファイルの名前
+        #[clap(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+        /// すべてのアーカイブを検証し、一致しないアーカイブを一覧にする
+        #[clap(long)]
+        all: bool,
+        /// 隔離されているアーカイブが一致した場合に隔離を解除する
+        #[clap(long)]
+        repair: bool,
+        /// --all で一致しなかったアーカイブを、確認した上で隔離する代わりに削除する
+        #[clap(long = "delete-corrupt", requires = "all")]
+        delete_corrupt: bool,
+        /// --delete-corrupt で削除する前に確認しない
+        #[clap(short, long, requires = "delete_corrupt")]
+        yes: bool,
+        /// --all で検証するアーカイブを、パスがこのディレクトリまたは文字列で始まるものに限る
+        #[clap(long, requires = "all")]
+        path: Option<String>,
+        /// --all で検証するアーカイブを、指定した日時以降に登録したものに限る
+        /// 日時は RFC3339 または YYYY-MM-DD 形式で、日付のみの場合は表示するタイムゾーンで解釈する
+        #[clap(long, requires = "all")]
+        since: Option<String>,
+        /// --all で並行してチェックサムを計算するスレッドの数
+        #[clap(short, long, default_value_t = DEFAULT_VERIFY_JOBS)]
+        jobs: std::num::NonZeroUsize,
+        /// --all の結果を JSON で出力する 途中経過と見つかったアーカイブの行は標準エラー出力に表示する
+        #[clap(long, requires = "all")]
+        json: bool,
+    },
+    /// アーカイブの隔離を検証せずに解除する
+    Unquarantine {
         /// アーカイブに登録されている .env ファイルの名前
         #[clap(required = true)]
         name: String,
+        /// チェックサムと一致しない本文を使えるようにすることを了承する
+        #[clap(long, required = true)]
+        force: bool,
+    },
+    /// 最新のアーカイブが --policies のポリシーを満たしているかを確かめる
+    /// 違反があれば終了コード 1 で終了する
+    #[clap(arg_required_else_help = false)]
+    Lint {
+        /// 指定した名前のアーカイブを確かめる
+        #[clap(long, conflicts_with_all = ["path", "dir"])]
+        name: Option<String>,
+        /// 指定したパスの最新のアーカイブを確かめる
+        #[clap(long, conflicts_with = "dir")]
+        path: Option<String>,
+        /// 指定したディレクトリ配下のパスごとに最新のアーカイブを確かめる
+        /// いずれも省略した場合はすべてのパスの最新のアーカイブを確かめる
+        #[clap(long)]
+        dir: Option<String>,
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// export で書き出したファイル、またはパスワードマネージャーに保存されている
+    /// .env ファイルの内容をアーカイブに登録する
+    Import {
+        /// export で書き出したファイルのパス
+        /// 名前、パス、作成日時、チェックサムを変えずに登録する
+        #[clap(conflicts_with_all = ["from_op", "from_bw"])]
+        file: Option<String>,
+        /// export のファイルのアーカイブと同じ名前のアーカイブがある場合に置き換える
+        /// 省略時は登録しない
+        #[clap(long, requires = "file")]
+        overwrite: bool,
+        /// 1Password のアイテム名または ID
+        #[clap(
+            long = "from-op",
+            conflicts_with = "from_bw",
+            required_unless_present_any = ["from_bw", "file"]
+        )]
+        from_op: Option<String>,
+        /// Bitwarden のアイテム名または ID
+        #[clap(long = "from-bw")]
+        from_bw: Option<String>,
+        /// .env ファイルの内容が入っているフィールド
+        /// 省略時はセキュアノート (ノート) を使う
+        #[clap(long)]
+        field: Option<String>,
+        /// アーカイブ元のパスとして記録するパス
+        #[clap(long, required_unless_present = "file")]
+        path: Option<String>,
+        /// 登録名
+        #[clap(short, long)]
+        name: Option<String>,
+        /// 1Password CLI のパス
+        #[clap(long = "op-bin", env = "ENV_ARCHIVE_OP_BIN", default_value = "op")]
+        op_bin: PathBuf,
+        /// Bitwarden CLI のパス
+        #[clap(long = "bw-bin", env = "ENV_ARCHIVE_BW_BIN", default_value = "bw")]
+        bw_bin: PathBuf,
+    },
+    /// アーカイブの内容を本文と共に JSON で、または .env ファイルのまま tar で書き出す
+    Export {
+        /// 書き出すファイルのパス `-` を指定すると標準出力に書き出す
+        #[clap(short, long, required_unless_present = "tar", conflicts_with = "tar")]
+        output: Option<String>,
+        /// ファイルパスに指定した文字列を含むアーカイブに限る
+        #[clap(long, conflicts_with = "tar")]
+        filter: Option<String>,
+        /// JSON の代わりに、--dir 配下のパスごとに最新のアーカイブを
+        /// .env ファイルとして gzip 圧縮の tar に書き出す
+        #[clap(long)]
+        tar: Option<String>,
+        /// --tar に書き出すアーカイブのパスの基準となるディレクトリ
+        /// tar の中ではこのディレクトリからの相対パスに置く
+        #[clap(long, default_value = ".")]
+        dir: String,
+        /// 隔離されているアーカイブも書き出す
+        /// --tar では常に隔離されていない最新のアーカイブを書き出す
+        #[clap(long = "include-quarantined", conflicts_with = "tar")]
+        include_quarantined: bool,
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// マニフェストに書かれたプロジェクトを clone し、.env ファイルをアーカイブから復元する
+    /// 既に存在するファイルは上書きしない
+    Bootstrap {
+        /// ワークスペースのマニフェスト (TOML) のパス
+        #[clap(long, required = true)]
+        manifest: String,
+        /// Git のパス
+        #[clap(long = "git-bin", env = "ENV_ARCHIVE_GIT_BIN", default_value = "git")]
+        git_bin: PathBuf,
+    },
+    /// アーカイブの統計情報を表示する
+    Stats {
+        /// 登録件数とバイト数の推移を集計単位ごとに表示する
+        #[clap(long)]
+        growth: bool,
+        /// --growth の集計単位
+        #[clap(long, value_enum, default_value = "month")]
+        bucket: stats::Bucket,
+        /// --growth の登録件数を棒グラフで表示する
+        #[clap(long)]
+        chart: bool,
+        /// 出力形式
+        #[clap(long, value_enum, default_value = "table")]
+        format: stats::StatsFormat,
+        /// JSON で出力する (--format json と同じ)
+        #[clap(long, conflicts_with = "format")]
+        json: bool,
+        #[clap(flatten)]
+        users: UserArgs,
+    },
+    /// 古いアーカイブの本文をデータベースの外のディレクトリに移す
+    /// 移した本文は show, recover, verify などで透過的に読み出す
+    Tier {
+        /// この期間より前に作成されたアーカイブの本文を移す (例: 90d, 1y)
+        #[clap(
+            long = "older-than",
+            required_unless_present = "restore",
+            conflicts_with = "restore"
+        )]
+        older_than: Option<String>,
+        /// 本文を移す既存のディレクトリ
+        #[clap(long, required_unless_present = "restore", conflicts_with = "restore")]
+        to: Option<String>,
+        /// 移した本文をすべてデータベースに戻す
+        #[clap(long)]
+        restore: bool,
     },
     /// アーカイブに登録されている .env ファイルを復元する
     Recover {
         /// アーカイブに登録されている .env ファイルの名前
+        /// `/` を含み、その名前のアーカイブがない場合はアーカイブ元のパスとして扱う
+        /// --interactive の場合は選択肢を絞り込むファイルパスのキーワード
+        #[clap(
+            required_unless_present_any = ["path", "interactive"],
+            conflicts_with = "path"
+        )]
+        name: Option<String>,
+        /// 復元するアーカイブを pick と同じ画面で選び、復元先と差分を示して確認してから書き込む
+        #[clap(long, conflicts_with_all = ["path", "yes"])]
+        interactive: bool,
+        /// アーカイブ元のパス このパスの最新のアーカイブを復元する
+        #[clap(long)]
+        path: Option<String>,
+        /// --path で指定したパスのアーカイブのうち、この日時より前で最新のものを復元する
+        /// RFC3339 または YYYY-MM-DD 形式で指定する
+        #[clap(long, requires = "path")]
+        before: Option<String>,
+        /// 復元先のファイルパス
+        /// 省略時はカレントディレクトリにアーカイブ元と同じファイル名で復元する
+        /// `-` を指定すると標準出力に書き出す
+        #[clap(short, long, conflicts_with = "in_place")]
+        output: Option<String>,
+        /// アーカイブ元のパスに復元する
+        #[clap(long = "in-place")]
+        in_place: bool,
+        /// --in-place で復元先のディレクトリが存在しない場合に作成する
+        #[clap(long = "create-dirs", requires = "in_place")]
+        create_dirs: bool,
+        /// 実際には書き込まず、行われる操作を表示する
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+        /// 上書きする前の復元先ファイルをアーカイブにバックアップしない
+        #[clap(long = "no-backup")]
+        no_backup: bool,
+        /// 既存のファイルを上書きする前に確認しない
+        #[clap(short, long)]
+        yes: bool,
+        /// 指定したキーの値だけを復元先のファイルに反映する
+        /// 他の行はそのまま残し、復元先にないキーは末尾に追加する `DB_*` のようなグロブも使える
+        #[clap(long = "only-keys", num_args = 1.., value_delimiter = ',')]
+        only_keys: Vec<String>,
+        /// 他のユーザーが登録したアーカイブも復元できるようにする
+        #[clap(long = "all-users")]
+        all_users: bool,
+        /// 隔離されているアーカイブも復元できるようにする
+        #[clap(long = "include-quarantined")]
+        include_quarantined: bool,
+    },
+    /// シェルの補完スクリプトから呼び出し、名前が prefix で始まるアーカイブの名前とパスをタブで区切って表示する
+    #[clap(name = "__complete-names", hide = true)]
+    CompleteNames { prefix: Option<String> },
+    /// 以前の既定の場所 ~/.env_archive のデータベースを新しい既定の場所に移す
+    /// 移した後は ~/.env_archive.moved に移し先を書いたメモを残す
+    #[clap(name = "migrate-db")]
+    MigrateDb,
+    /// 設定ファイルを扱う
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    /// 読み込む設定ファイルのパスを表示する ファイルがなくても表示する
+    Path,
+}
+
+#[derive(Debug, Subcommand)]
+enum TagCommands {
+    /// アーカイブにタグを付ける
+    Add {
+        /// アーカイブの名前
+        #[clap(required = true)]
+        name: String,
+        /// 付けるタグ
+        #[clap(required = true)]
+        tags: Vec<String>,
+    },
+    /// アーカイブからタグを外す
+    Rm {
+        /// アーカイブの名前
         #[clap(required = true)]
         name: String,
+        /// 外すタグ
+        #[clap(required = true)]
+        tag: String,
     },
 }
 
 #[derive(Debug, Clone)]
 struct Context {
     database: PathBuf,
+    /// 起動した日時 表示や期間の計算に使う
+    /// 同じプロセスで複数回登録しても (path, created_at) が重複しないよう、
+    /// 登録するアーカイブの日時には登録する時点の日時を使う
     now: chrono::DateTime<chrono::Utc>,
     timezone: chrono_tz::Tz,
+    /// 一覧表示に使う端末幅 None の場合は幅を考慮しない
+    width: Option<usize>,
+    /// 一覧の日時を now からの経過時間ではなく timezone の日時で表示する
+    absolute_time: bool,
+    /// すべての日時を UTC の RFC3339 で表示する (--utc)
+    utc: bool,
+    /// パスの大部分と .env ファイルの値を伏せて表示する
+    private: bool,
+    /// データベース操作のタイムアウト
+    db_timeout: std::time::Duration,
+    /// コマンドを実行している OS のユーザー
+    /// 登録するアーカイブに記録し、一覧を自分のアーカイブに絞り込むときに使う
+    user: Option<String>,
+    /// --user, --all-users を省略したときに表示するアーカイブ
+    visibility: Visibility,
+    /// lint と push で適用するポリシー
+    policies: policy::Policies,
+}
+
+impl Context {
+    /// 初期化済みのアーカイブを開く
+    /// データベースがない、またはテーブルが作成されていない場合は NotInitialized を返す
+    fn archive(&self) -> anyhow::Result<archive::Archive> {
+        if !archive::Archive::is_initialized(&self.database).context("Failed to open archive")? {
+            return Err(NotInitialized(self.database.clone()).into());
+        }
+        self.open_archive(archive::OpenOptions {
+            create_if_missing: false,
+            ..Default::default()
+        })
+    }
+
+    /// アーカイブを開き、データベースがなければ作成して初期化する
+    /// init を省略して push, crawl を実行できるようにするためのもので、読み取るだけのコマンドでは archive を使う
+    fn archive_or_initialize(&self) -> anyhow::Result<archive::Archive> {
+        let missing = !self.database.exists();
+        let archive = self.open_archive(archive::OpenOptions::default())?;
+        if missing {
+            eprintln!("initialized new archive at {}", self.database.display());
+        }
+        Ok(archive)
+    }
+
+    fn open_archive(&self, options: archive::OpenOptions) -> anyhow::Result<archive::Archive> {
+        let options = archive::OpenOptions {
+            busy_timeout: self.db_timeout,
+            ..options
+        };
+        let archive = archive::Archive::open(self.database.to_path_buf(), options)
+            .context("Failed to open archive")?;
+        Ok(archive
+            .with_timeout(self.db_timeout)
+            .with_user(self.user.clone()))
+    }
+
+    /// 一覧の絞り込み条件を作る
+    /// ユーザーの指定がなければ visibility に従い、own の場合は自分のアーカイブに限る
+    fn filter(&self, tag: Option<String>, users: &UserArgs) -> archive::EntryFilter {
+        let user = match (&users.user, users.all_users, self.visibility) {
+            (Some(user), _, _) => Some(user.clone()),
+            (None, true, _) | (None, false, Visibility::All) => None,
+            (None, false, Visibility::Own) => self.user.clone(),
+        };
+        archive::EntryFilter {
+            tag,
+            user,
+            ..Default::default()
+        }
+    }
+
+    /// filter と同じ条件を、さらに dates の期間に登録したアーカイブに限る
+    fn dated_filter(
+        &self,
+        tag: Option<String>,
+        users: &UserArgs,
+        dates: &DateRangeArgs,
+    ) -> anyhow::Result<archive::EntryFilter> {
+        let since = dates
+            .since
+            .as_deref()
+            .map(|since| helper::parse_timestamp(since, &self.timezone))
+            .transpose()?;
+        let until = dates
+            .until
+            .as_deref()
+            .map(|until| helper::parse_end_timestamp(until, &self.timezone))
+            .transpose()?;
+        if let (Some(since), Some(until)) = (since, until) {
+            if since > until {
+                anyhow::bail!(
+                    "--since {} is after --until {}",
+                    dates.since.as_deref().unwrap_or_default(),
+                    dates.until.as_deref().unwrap_or_default()
+                );
+            }
+        }
+        Ok(archive::EntryFilter {
+            since,
+            until,
+            ..self.filter(tag, users)
+        })
+    }
+
+    /// 他のユーザーが登録したアーカイブを all_users なしで扱おうとしていればエラーにする
+    fn ensure_visible(&self, entry: &archive::ArchiveEntry, all_users: bool) -> anyhow::Result<()> {
+        match &entry.user {
+            Some(owner) if !all_users && Some(owner) != self.user.as_ref() => anyhow::bail!(
+                "archive {} was pushed by user {}. use --all-users to access it",
+                entry.name,
+                owner
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// 一覧に表示する日時の形式
+    fn time_format(&self) -> output::TimeFormat {
+        if self.absolute_time || self.utc {
+            self.timestamp_format()
+        } else {
+            output::TimeFormat::Relative(self.now)
+        }
+    }
+
+    /// 一覧以外の show の見出しやメッセージに表示する日時の形式 経過時間にはしない
+    fn timestamp_format(&self) -> output::TimeFormat {
+        if self.utc {
+            output::TimeFormat::Utc
+        } else {
+            output::TimeFormat::Absolute(self.timezone)
+        }
+    }
+
+    /// 一覧以外に表示する日時
+    fn timestamp(&self, at: chrono::DateTime<chrono::Utc>) -> String {
+        self.timestamp_format().format(at)
+    }
+
+    /// 進捗を標準出力に表示する ProgressSink
+    fn progress(&self) -> progress::PrintProgress {
+        progress::PrintProgress::new(self.private, self.timestamp_format())
+    }
+
+    /// 隔離されているアーカイブを include_quarantined なしで扱おうとしていればエラーにする
+    fn ensure_not_quarantined(
+        &self,
+        entry: &archive::ArchiveEntry,
+        include_quarantined: bool,
+    ) -> anyhow::Result<()> {
+        if entry.quarantined && !include_quarantined {
+            anyhow::bail!(
+                "archive {} is quarantined because its body does not match the checksum. use --include-quarantined to access it",
+                entry.name
+            );
+        }
+        Ok(())
+    }
+}
+
+/// リポジトリごとのデータベースのファイル名
+const LOCAL_DATABASE: &str = ".env_archive";
+
+/// start から親ディレクトリへたどって、最初に見つかった .env_archive のパスを返す
+/// ホームディレクトリの .env_archive は以前の既定のデータベースなので探さない
+fn find_local_database(start: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .filter(|dir| Some(*dir) != home)
+        .map(|dir| dir.join(LOCAL_DATABASE))
+        .find(|path| path.is_file())
+}
+
+/// init --local で作成するデータベースのパス
+/// at を指定した場合はそのディレクトリ、省略した場合は cwd を含む git リポジトリのルートに作成する
+fn local_init_database(at: Option<&str>, cwd: &Path) -> anyhow::Result<PathBuf> {
+    let dir = match at {
+        Some(at) => helper::canonicalize_existing(Path::new(at), cwd)?,
+        None => cwd
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no git repository found from {}. use --at to choose the directory",
+                    cwd.display()
+                )
+            })?
+            .to_path_buf(),
+    };
+    if !dir.is_dir() {
+        anyhow::bail!("{} is not a directory", dir.display());
+    }
+    Ok(dir.join(LOCAL_DATABASE))
+}
+
+/// 以前の既定のデータベースを使っている案内を一度表示したことを示すファイルの名前
+/// 新しい既定のデータベースと同じディレクトリに作成する
+const LEGACY_HINT_MARKER: &str = "legacy-hint-shown";
+
+/// 以前の既定の場所のデータベースを使っている場合に、migrate-db で移すよう案内する
+/// 案内は一度だけ表示し、既に表示している場合や以前の場所を使っていない場合は None を返す
+fn legacy_database_hint(default: &config::DefaultDatabase) -> Option<String> {
+    let marker = default.data.with_file_name(LEGACY_HINT_MARKER);
+    if !default.uses_legacy() || marker.exists() {
+        return None;
+    }
+    // 印を残せなかった場合は次も案内する
+    if let Some(dir) = marker.parent() {
+        let _ = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&marker, ""));
+    }
+    Some(format!(
+        "hint: using the database at the legacy location {}. run `{} migrate-db` to move it to {}",
+        default.legacy.display(),
+        env!("CARGO_PKG_NAME"),
+        default.data.display()
+    ))
+}
+
+/// SQLite がデータベースと同じ場所に作成するファイルの接尾辞
+const SQLITE_SIDECARS: [&str; 3] = ["-wal", "-shm", "-journal"];
+
+/// 以前の既定の場所のデータベースを新しい既定の場所に移し、以前の場所に移し先を書いたメモを残す
+fn migrate_db(default: &config::DefaultDatabase) -> anyhow::Result<()> {
+    if !default.legacy.is_file() {
+        anyhow::bail!("no database to migrate at {}", default.legacy.display());
+    }
+    if default.data.exists() {
+        anyhow::bail!(
+            "{} already exists. remove it or merge {} with import",
+            default.data.display(),
+            default.legacy.display()
+        );
+    }
+    if let Some(dir) = default.data.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    for suffix in std::iter::once("").chain(SQLITE_SIDECARS) {
+        let mut from = default.legacy.as_os_str().to_owned();
+        from.push(suffix);
+        let mut to = default.data.as_os_str().to_owned();
+        to.push(suffix);
+        let (from, to) = (Path::new(&from), Path::new(&to));
+        if from.exists() {
+            // 別のファイルシステムには移動できないため、コピーしてから削除する
+            std::fs::rename(from, to)
+                .or_else(|_| std::fs::copy(from, to).and_then(|_| std::fs::remove_file(from)))
+                .with_context(|| format!("Failed to move {}", from.display()))?;
+        }
+    }
+    let mut note = default.legacy.as_os_str().to_owned();
+    note.push(".moved");
+    std::fs::write(
+        &note,
+        format!(
+            "the archive database was moved to {} by `{} migrate-db` at {}\n",
+            default.data.display(),
+            env!("CARGO_PKG_NAME"),
+            output::format_rfc3339(chrono::Utc::now())
+        ),
+    )
+    .with_context(|| format!("Failed to write {}", Path::new(&note).display()))?;
+    println!(
+        "[MOVED] {} -> {}",
+        default.legacy.display(),
+        default.data.display()
+    );
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let database: PathBuf = args.database.map(PathBuf::from).unwrap_or_else(|| {
-        dirs::home_dir()
-            .expect("Failed to get home directory")
-            .join(".env_archive")
+    let home = dirs::home_dir();
+    let config_path = config::path(
+        std::env::var_os("XDG_CONFIG_HOME").as_deref(),
+        home.as_deref(),
+    );
+    let config = match &config_path {
+        Some(path) => config::Config::load(path, home.as_deref()),
+        None => Ok(config::Config::default()),
+    };
+    let default_database = home.as_deref().map(|home| {
+        config::DefaultDatabase::new(std::env::var_os("XDG_DATA_HOME").as_deref(), home)
     });
+    // 補完中のシェルにエラーを表示しないよう、設定ファイルの誤りやタイムゾーン、ポリシーの設定を検証する前に処理する
+    if let SubCommands::CompleteNames { prefix } = &args.subcommand {
+        let config = config.unwrap_or_default();
+        let local_database = std::env::current_dir()
+            .ok()
+            .filter(|_| args.database.is_none() && (args.local || config.local))
+            .and_then(|cwd| find_local_database(&cwd, home.as_deref()));
+        let database = local_database
+            .or_else(|| config.database(args.database))
+            .or_else(|| default_database.map(|default| default.path().to_path_buf()));
+        if let Some(database) = database {
+            complete_names(&database, prefix.as_deref().unwrap_or(""), args.private).await;
+        }
+        return Ok(());
+    }
+    // 設定ファイルの誤りを調べるときにも使えるよう、設定ファイルを検証する前に処理する
+    if let SubCommands::Config {
+        command: ConfigCommands::Path,
+    } = &args.subcommand
+    {
+        let path = config_path.context("Failed to get config directory")?;
+        println!("{}", path.display());
+        return Ok(());
+    }
+    let config = config?;
+    // 以前の場所のデータベースを使っている案内を表示しないよう、データベースを決める前に処理する
+    if let SubCommands::MigrateDb = &args.subcommand {
+        return migrate_db(&default_database.context("Failed to get home directory")?);
+    }
+    let local_database = if args.database.is_some() {
+        None
+    } else if let SubCommands::Init { at, .. } = &args.subcommand {
+        if args.local {
+            let cwd = std::env::current_dir()?;
+            Some(local_init_database(at.as_deref(), &cwd)?)
+        } else {
+            None
+        }
+    } else if args.local || config.local {
+        find_local_database(&std::env::current_dir()?, home.as_deref())
+    } else {
+        None
+    };
+    let database = match local_database.or_else(|| config.database(args.database)) {
+        Some(database) => database,
+        None => {
+            let default = default_database.context("Failed to get home directory")?;
+            if let Some(hint) = legacy_database_hint(&default) {
+                eprintln!("{}", hint);
+            }
+            default.path().to_path_buf()
+        }
+    };
+
+    // --utc の場合はタイムゾーンの設定を解釈しない
+    let timezone = if args.utc {
+        chrono_tz::Tz::UTC
+    } else {
+        config.timezone(
+            args.timezone.as_deref(),
+            std::env::var(TIMEZONE_ENV).ok().as_deref(),
+            iana_time_zone::get_timezone().ok().as_deref(),
+        )?
+    };
 
     let now = chrono::Utc::now();
     let context = Context {
         database,
         now,
-        timezone: chrono_tz::Asia::Tokyo,
+        timezone,
+        width: if args.wide {
+            None
+        } else {
+            output::terminal_width()
+        },
+        absolute_time: args.absolute_time,
+        utc: args.utc,
+        private: config.private(args.private),
+        db_timeout: std::time::Duration::from_secs(args.db_timeout),
+        user: helper::current_user(),
+        visibility: config.visibility(args.visibility),
+        policies: config.policies(args.policies.as_deref(), |path| {
+            load_policies(&existing_path(path)?)
+        })?,
     };
 
-    match args.subcommand {
-        SubCommands::Crawl { dir, dry_run } => {
-            crawl(&context, &std::fs::canonicalize(Path::new(&dir))?, dry_run).await;
+    let result = match args.subcommand {
+        SubCommands::Crawl {
+            dir,
+            dry_run,
+            strict_permissions,
+            exclude,
+            no_default_excludes,
+            follow_symlinks,
+            respect_gitignore,
+            max_depth,
+            pattern,
+            add_pattern,
+            jobs,
+            max_size,
+            include_empty,
+        } => {
+            let options = CrawlOptions {
+                dry_run,
+                strict_permissions,
+                jobs,
+                max_size: helper::parse_size(&max_size)?,
+                include_empty,
+                search: helper::SearchOptions {
+                    excludes: config.crawl_excludes(exclude),
+                    respect_gitignore,
+                    max_depth,
+                    patterns: config.crawl_patterns(pattern),
+                    add_patterns: add_pattern,
+                    no_default_excludes,
+                    follow_symlinks,
+                },
+            };
+            let mut progress = context.progress();
+            crawl(&context, &existing_path(&dir)?, &options, &mut progress).await
+        }
+        SubCommands::Init { clean, .. } => init(&context, clean).await,
+        SubCommands::Push {
+            files,
+            path,
+            name,
+            name_prefix,
+            rename_on_conflict,
+            overwrite,
+            message,
+            max_size,
+            force,
+            skip_if_same,
+            allow_empty,
+            at,
+            json,
+        } => {
+            let options = PushOptions {
+                naming: PushNaming::new(name, name_prefix),
+                rename_on_conflict,
+                overwrite,
+                skip_if_same,
+                json,
+                allow_empty,
+                at: at
+                    .map(|at| helper::parse_timestamp(&at, &context.timezone))
+                    .transpose()?,
+                message,
+                max_size: (!force)
+                    .then(|| helper::parse_size(&max_size))
+                    .transpose()?,
+            };
+            push_command(&context, &files, path.as_deref(), &options).await
+        }
+        SubCommands::List {
+            dir,
+            ignore_case,
+            all_versions,
+            tree,
+            tag,
+            users,
+            dates,
+            page,
+            format,
+        } => {
+            let filter = context.dated_filter(tag, &users, &dates)?;
+            let view = if tree {
+                ListView::Tree
+            } else if all_versions {
+                ListView::AllVersions
+            } else {
+                ListView::Latest
+            };
+            list(
+                &context,
+                &lookup_path(&dir),
+                ignore_case,
+                view,
+                &filter,
+                &page.page(),
+                format.format(&config),
+            )
+            .await
         }
-        SubCommands::Init { clean } => {
-            init(&context, clean).await;
+        SubCommands::ListAll {
+            tag,
+            users,
+            dates,
+            page,
+            format,
+        } => {
+            let filter = context.dated_filter(tag, &users, &dates)?;
+            let page = page.page_or(archive::Sort::PATH);
+            list_all(&context, &filter, &page, format.format(&config)).await
         }
-        SubCommands::Push { file, name } => {
-            push(&context, &std::fs::canonicalize(Path::new(&file))?, name).await;
+        SubCommands::Latest { dir, users, format } => {
+            let dir = dir.map(|dir| lookup_path(&dir));
+            latest(
+                &context,
+                dir.as_deref(),
+                &context.filter(None, &users),
+                config.format(format),
+            )
+            .await
         }
-        SubCommands::List { dir } => {
-            list(&context, &std::fs::canonicalize(Path::new(&dir))?).await;
+        SubCommands::Browse { users } => browse(&context, &context.filter(None, &users)).await,
+        SubCommands::Pick {
+            keyword,
+            all_versions,
+            tag,
+            users,
+        } => {
+            let filter = context.filter(tag, &users);
+            pick(&context, keyword.as_deref(), all_versions, &filter).await
         }
-        SubCommands::ListAll => {
-            list_all(&context).await;
+        SubCommands::History { path, limit, users } => {
+            history(
+                &context,
+                &lookup_path(&path),
+                limit,
+                &context.filter(None, &users),
+            )
+            .await
         }
-        SubCommands::Show { name } => {
-            show(&context, &name).await;
+        SubCommands::Tag { command } => match command {
+            TagCommands::Add { name, tags } => add_tags(&context, &name, &tags).await,
+            TagCommands::Rm { name, tag } => remove_tag(&context, &name, &tag).await,
+        },
+        SubCommands::Annotate { name, message, .. } => {
+            annotate(&context, &name, message.as_deref()).await
         }
-        SubCommands::Search { keyword } => {
-            search(&context, keyword).await;
+        SubCommands::Edit {
+            name,
+            new_name,
+            message,
+            all_users,
+        } => {
+            let editor = std::env::var("EDITOR").unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+            edit(
+                &context,
+                &name,
+                new_name,
+                message.as_deref(),
+                all_users,
+                &editor,
+            )
+            .await
         }
-        SubCommands::Recover { name } => {
-            recover(&context, &name).await;
+        SubCommands::Rename { old_name, new_name } => rename(&context, &old_name, &new_name).await,
+        SubCommands::Show {
+            name,
+            path,
+            nth,
+            all_users,
+            include_quarantined,
+            keys,
+            export,
+            redact,
+            show_values,
+        } => {
+            let format = if export {
+                ShowFormat::Export
+            } else if !keys.is_empty() {
+                ShowFormat::Keys(keys)
+            } else {
+                ShowFormat::Body
+            };
+            let redact = !show_values
+                && redact.applies(std::io::stdout().is_terminal(), format != ShowFormat::Body);
+            let name = match (name, &path) {
+                (_, Some(path)) => {
+                    resolve_show_name(
+                        &context,
+                        path,
+                        nth.unwrap_or(std::num::NonZeroUsize::MIN),
+                        all_users,
+                        include_quarantined,
+                    )
+                    .await?
+                }
+                (Some(name), None) => name,
+                (None, None) => anyhow::bail!("name or --path is required"),
+            };
+            let options = ShowOptions {
+                all_users,
+                include_quarantined,
+                format,
+                redact,
+                header: path.is_some(),
+            };
+            show(&context, &name, &options).await
         }
-    }
+        SubCommands::Verify {
+            name,
+            all,
+            repair,
+            delete_corrupt,
+            yes,
+            path,
+            since,
+            jobs,
+            json,
+        } => match name {
+            Some(name) if !all => verify(&context, &name, repair).await,
+            _ => {
+                let options = VerifyAllOptions {
+                    repair,
+                    delete_corrupt,
+                    prefix: path.as_deref().map(absolute_path),
+                    since: since
+                        .as_deref()
+                        .map(|since| helper::parse_timestamp(since, &context.timezone))
+                        .transpose()?,
+                    jobs,
+                    json,
+                };
+                let mut progress = context.progress().with_results_on_stderr(json);
+                if yes {
+                    verify_all(&context, &options, &mut confirm::AssumeYes, &mut progress).await
+                } else {
+                    verify_all(
+                        &context,
+                        &options,
+                        &mut confirm::TerminalConfirm,
+                        &mut progress,
+                    )
+                    .await
+                }
+            }
+        },
+        SubCommands::Unquarantine { name, force: _ } => unquarantine(&context, &name).await,
+        SubCommands::Lint {
+            name,
+            path,
+            dir,
+            users,
+        } => {
+            let target = match (name, path, dir) {
+                (Some(name), _, _) => LintTarget::Name(name),
+                (None, Some(path), _) => LintTarget::Path(lookup_path(&path)),
+                (None, None, dir) => LintTarget::Dir(dir.map(|dir| lookup_path(&dir))),
+            };
+            lint(&context, &target, &context.filter(None, &users)).await
+        }
+        SubCommands::Extract {
+            name,
+            dest_dir,
+            force,
+            all_users,
+            include_quarantined,
+        } => {
+            extract(
+                &context,
+                &name,
+                &existing_path(&dest_dir)?,
+                force,
+                all_users,
+                include_quarantined,
+            )
+            .await
+        }
+        SubCommands::Search {
+            keywords,
+            excludes,
+            regex,
+            ignore_case,
+            latest,
+            tag,
+            users,
+            dates,
+            page,
+            format,
+        } => {
+            let filter = context.dated_filter(tag, &users, &dates)?;
+            // 正規表現はデータベースに問い合わせる前に検証する
+            let query = if regex {
+                PathQuery::Regex {
+                    keywords: path_regexes(&keywords, ignore_case)?,
+                    excludes: path_regexes(&excludes, ignore_case)?,
+                }
+            } else {
+                PathQuery::Plain {
+                    keywords,
+                    excludes,
+                    ignore_case,
+                }
+            };
+            search(
+                &context,
+                &query,
+                latest,
+                &filter,
+                &page.page(),
+                format.format(&config),
+            )
+            .await
+        }
+        SubCommands::Grep {
+            pattern,
+            regex,
+            latest,
+            tag,
+            users,
+        } => {
+            let pattern = grep::Pattern::new(&pattern, regex)?;
+            grep(&context, &pattern, latest, &context.filter(tag, &users)).await
+        }
+        SubCommands::WhichKey {
+            key,
+            prefix,
+            latest,
+            show_values,
+            tag,
+            users,
+        } => {
+            let query = VariableQuery::Key { key, prefix };
+            find_variables(
+                &context,
+                &query,
+                latest,
+                show_values,
+                &context.filter(tag, &users),
+            )
+            .await
+        }
+        SubCommands::WhichValue {
+            value,
+            latest,
+            show_values,
+            tag,
+            users,
+        } => {
+            let query = VariableQuery::Value(value);
+            find_variables(
+                &context,
+                &query,
+                latest,
+                show_values,
+                &context.filter(tag, &users),
+            )
+            .await
+        }
+        SubCommands::Import {
+            file: Some(file),
+            overwrite,
+            ..
+        } => import_archive(&context, Path::new(&file), overwrite).await,
+        SubCommands::Import {
+            from_op,
+            from_bw,
+            field,
+            path,
+            name,
+            op_bin,
+            bw_bin,
+            ..
+        } => {
+            let (manager, item): (Box<dyn password_manager::PasswordManager>, _) =
+                match (from_op, from_bw) {
+                    (Some(item), _) => (
+                        Box::new(password_manager::OnePassword { program: op_bin }),
+                        item,
+                    ),
+                    (None, Some(item)) => (
+                        Box::new(password_manager::Bitwarden { program: bw_bin }),
+                        item,
+                    ),
+                    (None, None) => anyhow::bail!("--from-op or --from-bw is required"),
+                };
+            import_secret(
+                &context,
+                manager.as_ref(),
+                &item,
+                field.as_deref(),
+                &absolute_path(&path.context("--path is required")?),
+                name,
+            )
+            .await
+        }
+        SubCommands::Export {
+            output,
+            filter,
+            tar,
+            dir,
+            include_quarantined,
+            users,
+        } => {
+            let entry_filter = context.filter(None, &users);
+            match (output, tar) {
+                (_, Some(tar)) => {
+                    let dir = lookup_path(&dir);
+                    export_tar(&context, Path::new(&tar), &dir, &entry_filter).await
+                }
+                (Some(output), None) => {
+                    export(
+                        &context,
+                        &output,
+                        filter.as_deref(),
+                        &entry_filter,
+                        include_quarantined,
+                    )
+                    .await
+                }
+                (None, None) => anyhow::bail!("--output or --tar is required"),
+            }
+        }
+        SubCommands::Bootstrap { manifest, git_bin } => {
+            bootstrap(&context, &existing_path(&manifest)?, &git_bin).await
+        }
+        SubCommands::Stats {
+            growth,
+            bucket,
+            chart,
+            format,
+            json,
+            users,
+        } => {
+            let format = if json {
+                stats::StatsFormat::Json
+            } else {
+                format
+            };
+            let filter = context.filter(None, &users);
+            show_stats(&context, &filter, growth, bucket, chart, format).await
+        }
+        SubCommands::Tier {
+            older_than: Some(older_than),
+            to: Some(to),
+            restore: false,
+        } => {
+            let cutoff = context.now - helper::parse_age(&older_than)?;
+            tier(&context, cutoff, &existing_path(&to)?).await
+        }
+        SubCommands::Tier { .. } => restore_tier(&context).await,
+        SubCommands::Recover {
+            name,
+            path,
+            before,
+            output,
+            in_place,
+            create_dirs,
+            dry_run,
+            no_backup,
+            yes,
+            only_keys,
+            all_users,
+            include_quarantined,
+            interactive,
+        } => {
+            let options = RecoverOptions {
+                output,
+                in_place,
+                create_dirs,
+                dry_run,
+                no_backup,
+                only_keys,
+                all_users,
+                include_quarantined,
+                confirm_always: interactive,
+            };
+            let before = before
+                .map(|before| helper::parse_timestamp(&before, &context.timezone))
+                .transpose()?;
+            let filter = context.filter(
+                None,
+                &UserArgs {
+                    user: None,
+                    all_users,
+                },
+            );
+            let name = if interactive {
+                pick_name(&context, name.as_deref(), false, &filter).await?
+            } else {
+                resolve_recover_name(&context, name.as_deref(), path.as_deref(), before, &filter)
+                    .await?
+            };
+            let mut progress = context.progress();
+            if yes {
+                recover(
+                    &context,
+                    &name,
+                    &options,
+                    &mut confirm::AssumeYes,
+                    &mut progress,
+                )
+                .await
+            } else {
+                recover(
+                    &context,
+                    &name,
+                    &options,
+                    &mut confirm::TerminalConfirm,
+                    &mut progress,
+                )
+                .await
+            }
+        }
+        SubCommands::CompleteNames { .. } | SubCommands::Config { .. } | SubCommands::MigrateDb => {
+            unreachable!("the subcommand is handled before building the context")
+        }
+    };
 
-    Ok(())
+    if let Err(e) = &result {
+        if let Some(e) = e.downcast_ref::<NotInitialized>() {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_NOT_INITIALIZED);
+        }
+        if archive::is_timeout(e) {
+            eprintln!(
+                "Error: database operation timed out (path {})",
+                context.database.display()
+            );
+            std::process::exit(EXIT_DB_TIMEOUT);
+        }
+        if cold::is_unavailable(e) {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(EXIT_COLD_BODY_UNAVAILABLE);
+        }
+        if e.is::<helper::NotRegularFile>() {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(EXIT_NOT_REGULAR_FILE);
+        }
+        if e.is::<NoMatches>() {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_NO_MATCHES);
+        }
+    }
+    result
 }
 
-async fn init(context: &Context, clean: bool) {
+async fn init(context: &Context, clean: bool) -> anyhow::Result<()> {
     if clean && context.database.exists() {
-        std::fs::remove_file(&context.database).expect("Failed to remove archive");
+        std::fs::remove_file(&context.database).context("Failed to remove archive")?;
     }
-    let archive = archive::Archive::new(context.database.to_path_buf());
-    archive
-        .initialize()
-        .await
-        .expect("Failed to initialize archive");
+    context
+        .open_archive(archive::OpenOptions::default())
+        .context("Failed to initialize archive")?;
+    Ok(())
 }
 
-async fn push(context: &Context, env_file_path: &Path, name: Option<String>) {
-    let archive = archive::Archive::new(context.database.to_path_buf());
-    archive
-        .push(
-            env_file_path,
-            context.now,
-            name.unwrap_or_else(|| {
-                let ulid = ulid::Ulid::new();
-                ulid.to_string()
-            })
-            .as_str(),
-        )
-        .await
-        .expect("Failed to push archive");
+/// push でファイルの代わりに標準入力から読み込むことを示す引数
+const STDIN_FILE: &str = "-";
+
+/// push の登録のしかた
+#[derive(Debug, Default)]
+struct PushOptions {
+    naming: PushNaming,
+    /// 登録名が既に使われている場合に連番を付けて登録する
+    rename_on_conflict: bool,
+    /// 登録名が既に使われている場合にそのアーカイブの内容を置き換える
+    overwrite: bool,
+    /// 最新のアーカイブと同じ内容なら登録しない
+    skip_if_same: bool,
+    /// 結果を JSON で出力する
+    json: bool,
+    /// 空の内容も登録する
+    allow_empty: bool,
+    /// 登録日時 None の場合は登録した時点の日時
+    at: Option<chrono::DateTime<chrono::Utc>>,
+    message: Option<String>,
+    /// これを超える大きさの内容は登録しない None の場合は制限しない
+    max_size: Option<u64>,
 }
 
-async fn list_all(context: &Context) {
-    // think 現状はすべてのタイムスタンプを出力しているが、最新のアーカイブのみを表示するコマンドとして
-    // 過去のアーカイブを列挙するコマンドを別に切り出したほうが使いやすくなる
-    let archive = archive::Archive::new(context.database.to_path_buf());
-    let archives = archive.list_all().await.expect("Failed to list archive");
-    for archive in archives {
-        println!(
-            "{} {:?} {}",
-            archive.name,
-            archive.path,
-            archive.created_at.with_timezone(&context.timezone)
-        );
-    }
+/// push で登録名を決める方法
+#[derive(Debug, Clone, Default)]
+enum PushNaming {
+    /// ULID を生成する
+    #[default]
+    Generated,
+    /// 指定した名前にする
+    Name(String),
+    /// `<prefix>-<ファイル名>` にする
+    Prefix(String),
 }
 
-async fn list(context: &Context, path: &Path) {
-    // think 現状はすべてのタイムスタンプを出力しているが、最新のアーカイブのみを表示するコマンドとして
-    // 過去のアーカイブを列挙するコマンドを別に切り出したほうが使いやすくなる
-    let archive = archive::Archive::new(context.database.to_path_buf());
-    let archives = archive
-        .list_in_path(path)
-        .await
-        .expect("Failed to list archive");
-    for archive in archives {
-        println!(
-            "{} {:?} {}",
-            archive.name,
-            archive.path,
-            archive.created_at.with_timezone(&context.timezone)
-        );
+impl PushNaming {
+    fn new(name: Option<String>, name_prefix: Option<String>) -> Self {
+        match (name, name_prefix) {
+            (Some(name), _) => Self::Name(name),
+            (None, Some(prefix)) => Self::Prefix(prefix),
+            (None, None) => Self::Generated,
+        }
     }
-}
 
-async fn show(context: &Context, name: &str) {
-    let archive = archive::Archive::new(context.database.to_path_buf());
-    let (_, body) = archive
-        .get(name)
-        .await
-        .expect("Failed to show archive")
-        .expect("Archive not found");
-    println!("{}", body);
+    /// path を登録するときの名前
+    fn name_for(&self, path: &Path) -> String {
+        match self {
+            Self::Generated => ulid::Ulid::new().to_string(),
+            Self::Name(name) => name.clone(),
+            Self::Prefix(prefix) => format!(
+                "{}-{}",
+                prefix,
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }
+    }
 }
 
-async fn recover(context: &Context, name: &str) {
-    let archive = archive::Archive::new(context.database.to_path_buf());
-    let (entry, body) = archive
-        .get(name)
-        .await
-        .expect("Failed to show archive")
-        .expect("Archive not found");
-    let target_filename = Path::new(&entry.path)
-        .file_name()
-        .expect("Failed to get file name")
-        .to_string_lossy()
-        .to_string();
-    let target_path = Path::new(&target_filename);
-    println!(
-        "archive_path: {}\ntarget_path: {:?}",
-        entry.path, target_path
-    );
-
-    if target_path.exists() {
-        if archive
-            .check_is_same_by_name(name, target_path)
-            .await
-            .expect("Failed to check body")
-        {
-            println!("[SKIP] same checksum. {}", target_path.display());
-            return;
+/// push の引数を検証し、ファイルまたは標準入力の内容を登録する
+/// files が `-` の場合は標準入力から読み込み、path をアーカイブ元のパスにする
+async fn push_command(
+    context: &Context,
+    files: &[String],
+    path: Option<&str>,
+    options: &PushOptions,
+) -> anyhow::Result<()> {
+    if files.iter().any(|file| file == STDIN_FILE) {
+        if files.len() > 1 {
+            anyhow::bail!("- cannot be combined with other files");
         }
-        let ulid = ulid::Ulid::new();
-        let backup_name = format!("backup.{}", ulid.to_string());
-        archive
-            .push(target_path, context.now, &backup_name)
-            .await
-            .expect("Failed to push archive for backup");
-        println!(
-            "[BACKUP] {} with name {}",
-            target_path.display(),
-            backup_name
+        let Some(path) = path else {
+            anyhow::bail!("--path is required when reading from stdin (-)");
+        };
+        let body = read_body(std::io::stdin().lock(), "stdin", options.max_size)?;
+        return push_body(context, &absolute_path(path), &body, options).await;
+    }
+    if path.is_some() {
+        anyhow::bail!("--path can only be used when reading from stdin (-)");
+    }
+    // シェルが展開しなかったグロブは、一致するファイルに展開する
+    let cwd = std::env::current_dir()?;
+    let mut paths = Vec::new();
+    for file in files {
+        if !helper::has_glob_meta(file) {
+            paths.push(PathBuf::from(file));
+            continue;
+        }
+        let matched = helper::expand_glob(file, &cwd)?;
+        if matched.is_empty() {
+            anyhow::bail!("no files match {}", file);
+        }
+        paths.extend(matched);
+    }
+    if matches!(options.naming, PushNaming::Name(_)) && paths.len() > 1 {
+        anyhow::bail!(
+            "--name can only be used with a single file. use --name-prefix for multiple files"
         );
     }
-
-    std::fs::write(target_path, body).expect("Failed to write file");
-    println!("[RECOVERED] {} from {}", target_path.display(), name);
+    push_files(context, &paths, options).await
 }
 
-async fn crawl(context: &Context, dir: &Path, dry_run: bool) {
-    let files = helper::search_env_files(dir).expect("Failed to search env files");
-
-    let archive = archive::Archive::new(context.database.to_path_buf());
+/// files を順に push する
+/// 登録できないファイルがあっても残りのファイルは登録し、最後にエラーにする
+async fn push_files(
+    context: &Context,
+    files: &[PathBuf],
+    options: &PushOptions,
+) -> anyhow::Result<()> {
+    let cwd = std::env::current_dir()?;
+    let mut errors = Vec::new();
     for file in files {
-        let name = ulid::Ulid::new().to_string();
-        if archive
-            .check_is_same_as_latest(&file)
-            .await
-            .expect("Failed to check body")
-        {
-            println!("[SKIP] {}", file.display());
-            continue;
+        let result = match helper::canonicalize_existing(file, &cwd) {
+            Ok(path) => push(context, &path, options).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            // ファイルが 1 つの場合は終了コードを決められるようエラーをそのまま返す
+            if files.len() == 1 {
+                return Err(e);
+            }
+            eprintln!("[ERROR] {}: {:#}", file.display(), e);
+            errors.push(e);
         }
-        if dry_run {
-            println!("[PUSH DRY RUN] {}", file.display());
-            continue;
+    }
+    if !errors.is_empty() {
+        anyhow::bail!("failed to push {} of {} file(s)", errors.len(), files.len());
+    }
+    Ok(())
+}
+
+async fn push(
+    context: &Context,
+    env_file_path: &Path,
+    options: &PushOptions,
+) -> anyhow::Result<()> {
+    helper::ensure_regular_file(env_file_path)?;
+    if let Some(max_size) = options.max_size {
+        if let Some(size) =
+            helper::oversized(env_file_path, max_size).context("Failed to read env file")?
+        {
+            anyhow::bail!(
+                "{} is too large ({} bytes, limit {} bytes). pass --force to push it anyway",
+                env_file_path.display(),
+                size,
+                max_size
+            );
         }
-        archive
-            .push(&file, context.now, &name)
-            .await
-            .expect("Failed to push archive");
-        println!("[PUSHED] {}", file.display());
     }
+    let body = std::fs::read_to_string(env_file_path).context("Failed to read env file")?;
+    push_body(context, env_file_path, &body, options).await
 }
 
-async fn search(context: &Context, keyword: String) {
-    let archive = archive::Archive::new(context.database.to_path_buf());
-    let archives = archive
-        .search(&keyword)
-        .await
-        .expect("Failed to search archive");
-    for archive in archives {
-        println!(
-            "{} {:?} {}",
-            archive.name,
-            archive.path,
-            archive.created_at.with_timezone(&context.timezone)
+/// body を path の内容として登録する
+async fn push_body(
+    context: &Context,
+    path: &Path,
+    body: &str,
+    options: &PushOptions,
+) -> anyhow::Result<()> {
+    if body.is_empty() && !options.allow_empty {
+        eprintln!("warning: {} is empty", path.display());
+        anyhow::bail!(
+            "refused to push empty {}. pass --allow-empty to push it anyway",
+            path.display()
+        );
+    }
+    let archive = context.archive_or_initialize()?;
+    if options.skip_if_same
+        && archive
+            .latest_checksum(path)
+            .context("Failed to check body")?
+            == Some(digest::bytes_checksum(body.as_bytes()))
+    {
+        println!("{}", push_result(options.json, path, None));
+        return Ok(());
+    }
+    if context.policies.enforce_on_push != policy::Enforcement::Off {
+        enforce_policies(context, path, body)?;
+    }
+    let created_at = match options.at {
+        Some(at) if at > chrono::Utc::now() => {
+            anyhow::bail!("--at {} is in the future", context.timestamp(at))
+        }
+        Some(at) => at,
+        None => chrono::Utc::now(),
+    };
+    let name = options.naming.name_for(path);
+    let message = options.message.as_deref();
+    let name = if options.rename_on_conflict {
+        archive
+            .push_body_with_rename(path, body, created_at, &name, message)
+            .await
+            .context("Failed to push archive")?
+    } else if options.overwrite {
+        archive
+            .push_body_overwrite(path, body, created_at, &name, message)
+            .await
+            .context("Failed to push archive")?;
+        name
+    } else {
+        match archive
+            .push_body(path, body, created_at, &name, message)
+            .await
+        {
+            Err(e) if e.is::<archive::NameAlreadyExists>() => {
+                let existing = archive.entry(&name).await.ok().flatten();
+                let Some(existing) = existing else {
+                    return Err(e.context("Failed to push archive"));
+                };
+                anyhow::bail!(
+                    "archive name {} already exists (path {}, created at {}). use --overwrite to replace it or choose another name",
+                    name,
+                    existing.path,
+                    context.timestamp(existing.created_at)
+                )
+            }
+            result => result.context("Failed to push archive")?,
+        }
+        name
+    };
+    println!("{}", push_result(options.json, path, Some(&name)));
+    Ok(())
+}
+
+/// push の結果の 1 行
+/// name は登録した名前で、最新のアーカイブと同じ内容のため登録しなかった場合は None
+fn push_result(json: bool, path: &Path, name: Option<&str>) -> String {
+    match (json, name) {
+        (true, _) => serde_json::json!({
+            "path": path.to_string_lossy(),
+            "name": name,
+            "status": if name.is_some() { "pushed" } else { "unchanged" },
+        })
+        .to_string(),
+        (false, Some(name)) => format!("[PUSHED] {} with name {}", path.display(), name),
+        (false, None) => format!("[SKIP] unchanged. {}", path.display()),
+    }
+}
+
+/// reader から内容を読み込む
+/// max_size を超える場合は、超えた分を読まずにエラーにする
+fn read_body(
+    mut reader: impl std::io::Read,
+    source: &str,
+    max_size: Option<u64>,
+) -> anyhow::Result<String> {
+    use std::io::Read as _;
+
+    let mut body = String::new();
+    match max_size {
+        Some(max_size) => {
+            reader
+                .take(max_size + 1)
+                .read_to_string(&mut body)
+                .with_context(|| format!("Failed to read {}", source))?;
+            if body.len() as u64 > max_size {
+                anyhow::bail!(
+                    "{} is too large (limit {} bytes). pass --force to push it anyway",
+                    source,
+                    max_size
+                );
+            }
+        }
+        None => {
+            reader
+                .read_to_string(&mut body)
+                .with_context(|| format!("Failed to read {}", source))?;
+        }
+    }
+    Ok(body)
+}
+
+/// push する .env ファイルの内容にポリシーを適用する
+/// warn の場合は違反を警告として表示し、block の場合は違反があればエラーにする
+fn enforce_policies(context: &Context, path: &Path, body: &str) -> anyhow::Result<()> {
+    let violations = context.policies.check(path, body);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    match context.policies.enforce_on_push {
+        policy::Enforcement::Off => Ok(()),
+        policy::Enforcement::Warn => {
+            for violation in &violations {
+                eprintln!("warning: {}", violation);
+            }
+            Ok(())
+        }
+        policy::Enforcement::Block => {
+            for violation in &violations {
+                println!("[VIOLATION] {}", violation);
+            }
+            anyhow::bail!(
+                "push of {} was blocked by {} policy violation(s)",
+                path.display(),
+                violations.len()
+            )
+        }
+    }
+}
+
+/// crawl で登録しようとしているファイルをポリシーで検査し、違反を progress に伝える
+/// enforce_on_push が block で違反がある場合は true を返す
+fn blocked_by_policies(
+    context: &Context,
+    path: &Path,
+    body: &str,
+    progress: &mut dyn progress::ProgressSink,
+) -> bool {
+    if context.policies.enforce_on_push == policy::Enforcement::Off {
+        return false;
+    }
+    let violations = context.policies.check(path, body);
+    if violations.is_empty() {
+        return false;
+    }
+    let blocked = context.policies.enforce_on_push == policy::Enforcement::Block;
+    progress.emit(progress::Event::CrawlPolicyViolated {
+        path: path.to_path_buf(),
+        violations: violations.iter().map(ToString::to_string).collect(),
+        blocked,
+    });
+    blocked
+}
+
+/// 名前が prefix で始まるアーカイブの名前とパスをタブで区切って 1 行ずつ表示する
+/// 補完中のシェルにエラーを表示しないよう、データベースがない場合などは何も表示せずに終える
+async fn complete_names(database: &Path, prefix: &str, private: bool) {
+    let options = archive::OpenOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let Ok(archive) = archive::Archive::open(database.to_path_buf(), options) else {
+        return;
+    };
+    let Ok(archives) = archive.find_by_name_prefix(prefix).await else {
+        return;
+    };
+    for entry in archives {
+        let path = if private {
+            redact::redact_path(&entry.path)
+        } else {
+            entry.path
+        };
+        println!("{}\t{}", entry.name, path);
+    }
+}
+
+async fn list_all(
+    context: &Context,
+    filter: &archive::EntryFilter,
+    page: &archive::Page,
+    format: output::EntryFormat,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let archives = archive
+        .list_all_paged(filter, page)
+        .await
+        .context("Failed to list archive")?;
+    print_entries(context, archives, format)
+}
+
+/// list の表示方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListView {
+    /// パスごとに最新のアーカイブだけを表示する
+    Latest,
+    /// すべてのアーカイブを表示する (--all-versions)
+    AllVersions,
+    /// パスごとに最新のアーカイブをディレクトリの木構造で表示する (--tree)
+    Tree,
+}
+
+async fn list(
+    context: &Context,
+    path: &Path,
+    ignore_case: bool,
+    view: ListView,
+    filter: &archive::EntryFilter,
+    page: &archive::Page,
+    format: output::EntryFormat,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    if view != ListView::AllVersions {
+        let archives = if ignore_case {
+            archive
+                .list_latest_in_path_ignore_case(path, filter, page)
+                .await
+        } else {
+            archive.list_latest_in_path(path, filter, page).await
+        }
+        .context("Failed to list archive")?;
+        let archives = if context.private {
+            archives
+                .into_iter()
+                .map(|latest| archive::LatestEntry {
+                    entry: redact::redact_entry(latest.entry),
+                    ..latest
+                })
+                .collect()
+        } else {
+            archives
+        };
+        if view == ListView::Tree {
+            // プライベートモードでは一覧のパスと同じく起点のパスも伏せる
+            let root = if context.private {
+                println!("{}", redact::PRIVATE_MODE_HEADER);
+                PathBuf::from(redact::redact_path(&path.to_string_lossy()))
+            } else {
+                path.to_path_buf()
+            };
+            print!(
+                "{}",
+                output::render_tree(&root, &archives, &context.time_format())
+            );
+            return Ok(());
+        }
+        return print_listing(context, output::Listing::Latest(&archives), format);
+    }
+    let archives = if ignore_case {
+        archive.list_in_path_ignore_case(path, filter, page).await
+    } else {
+        archive.list_in_path(path, filter, page).await
+    }
+    .context("Failed to list archive")?;
+    print_entries(context, archives, format)
+}
+
+async fn latest(
+    context: &Context,
+    dir: Option<&Path>,
+    filter: &archive::EntryFilter,
+    format: output::EntryFormat,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let archives = archive
+        .list_latest_per_path(dir, filter)
+        .await
+        .context("Failed to list archive")?;
+    print_entries(context, archives, format)
+}
+
+/// アーカイブの一覧を表示する
+/// プライベートモードの場合は見出しを付けて各項目を伏せる
+/// JSON で出力する場合、--private の注意書きは出力を解釈できるよう標準エラー出力に表示する
+fn print_entries(
+    context: &Context,
+    archives: Vec<archive::ArchiveEntry>,
+    format: output::EntryFormat,
+) -> anyhow::Result<()> {
+    let archives = if context.private {
+        archives.into_iter().map(redact::redact_entry).collect()
+    } else {
+        archives
+    };
+    print_listing(context, output::Listing::Entries(&archives), format)
+}
+
+/// 一覧を format の形式で表示する
+/// プライベートモードの場合は見出しを付ける 一覧の項目は呼び出し元で伏せておく
+fn print_listing(
+    context: &Context,
+    listing: output::Listing,
+    format: output::EntryFormat,
+) -> anyhow::Result<()> {
+    if context.private {
+        match format {
+            output::EntryFormat::Text => println!("{}", redact::PRIVATE_MODE_HEADER),
+            _ => eprintln!("{}", redact::PRIVATE_MODE_HEADER),
+        }
+    }
+    print!(
+        "{}",
+        output::render(listing, format, &context.time_format(), context.width)?
+    );
+    Ok(())
+}
+
+/// 全画面でアーカイブを閲覧する
+/// 画面の状態は browse::update が管理し、ここでは求められた副作用を実行して結果を返す
+async fn browse(context: &Context, filter: &archive::EntryFilter) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let (mut state, effects) = browse::State::new(context.timezone, context.private);
+    let mut terminal = ratatui::init();
+    let result = run_browser(
+        context,
+        &archive,
+        filter,
+        &mut terminal,
+        &mut state,
+        effects,
+    )
+    .await;
+    ratatui::restore();
+    result
+}
+
+/// 選択肢を絞り込みながらアーカイブを選び、名前を標準出力に表示する
+async fn pick(
+    context: &Context,
+    keyword: Option<&str>,
+    all_versions: bool,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<()> {
+    let name = pick_name(context, keyword, all_versions, filter).await?;
+    println!("{}", name);
+    Ok(())
+}
+
+/// ファイルパスに keyword を含むアーカイブを選択肢にして選ばせ、選んだアーカイブの名前を返す
+/// all_versions でなければパスごとに最新のアーカイブだけを選択肢にする
+/// 標準出力はコマンド置換で受け取られるため、画面は標準エラー出力に描く
+async fn pick_name(
+    context: &Context,
+    keyword: Option<&str>,
+    all_versions: bool,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<String> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        anyhow::bail!(
+            "pick needs an interactive terminal. use search --print0 with an external picker instead"
+        );
+    }
+    let archive = context.archive()?;
+    let keywords = keyword.into_iter().collect::<Vec<_>>();
+    let page = archive::Page::default();
+    let archives = if all_versions {
+        archive
+            .search_keywords(&keywords, &[], filter, &page)
+            .await?
+    } else {
+        archive.search_latest(&keywords, &[], filter, &page).await?
+    };
+    if archives.is_empty() {
+        anyhow::bail!("no archives found");
+    }
+    let candidates = pick_candidates(archives, &context.time_format(), context.private);
+    let mut state = pick::State::new(candidates);
+    match run_picker(&mut state)? {
+        pick::Outcome::Selected(name) => Ok(name),
+        pick::Outcome::Cancelled => anyhow::bail!("no archive selected"),
+    }
+}
+
+/// アーカイブを名前、パス、日時を並べた選択肢にする
+/// private の場合はパスを伏せ、端末の表示を乱さないよう制御文字は置き換える
+fn pick_candidates(
+    archives: Vec<archive::ArchiveEntry>,
+    time: &output::TimeFormat,
+    private: bool,
+) -> Vec<pick::Candidate> {
+    archives
+        .into_iter()
+        .map(|entry| {
+            let path = if private {
+                redact::redact_path(&entry.path)
+            } else {
+                entry.path
+            };
+            let label = format!(
+                "{}  {}  {}",
+                entry.name,
+                path,
+                time.format(entry.created_at)
+            );
+            pick::Candidate {
+                label: label
+                    .chars()
+                    .map(|c| if c.is_control() { '?' } else { c })
+                    .collect(),
+                name: entry.name,
+            }
+        })
+        .collect()
+}
+
+/// pick の画面を標準エラー出力に描き、選択が終わるまでキー入力を処理する
+fn run_picker(state: &mut pick::State) -> anyhow::Result<pick::Outcome> {
+    use ratatui::crossterm::event::{self, Event, KeyEventKind};
+    use ratatui::crossterm::{execute, terminal};
+
+    terminal::enable_raw_mode()?;
+    execute!(std::io::stderr(), terminal::EnterAlternateScreen)?;
+    let result = (|| {
+        let backend = ratatui::backend::CrosstermBackend::new(std::io::stderr());
+        let mut terminal = ratatui::Terminal::new(backend)?;
+        loop {
+            terminal.draw(|frame| pick::render(frame, state))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    if let Some(outcome) = pick::update(state, key) {
+                        return Ok(outcome);
+                    }
+                }
+            }
+        }
+    })();
+    execute!(std::io::stderr(), terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+async fn run_browser(
+    context: &Context,
+    archive: &archive::Archive,
+    filter: &archive::EntryFilter,
+    terminal: &mut ratatui::DefaultTerminal,
+    state: &mut browse::State,
+    effects: Vec<browse::Effect>,
+) -> anyhow::Result<()> {
+    use ratatui::crossterm::event::{self, Event, KeyEventKind};
+
+    let mut queue = std::collections::VecDeque::from(effects);
+    loop {
+        while let Some(effect) = queue.pop_front() {
+            if effect == browse::Effect::Quit {
+                return Ok(());
+            }
+            let msg = browse_effect(context, archive, filter, effect).await;
+            queue.extend(browse::update(state, msg));
+        }
+        terminal.draw(|frame| browse::render(frame, state))?;
+        // 端末の大きさが変わった場合などは描き直すだけにする
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                queue.extend(browse::update(state, browse::Msg::Key(key)));
+            }
+        }
+    }
+}
+
+/// browse の副作用を実行し、結果を browse::update に渡すメッセージにする
+async fn browse_effect(
+    context: &Context,
+    archive: &archive::Archive,
+    filter: &archive::EntryFilter,
+    effect: browse::Effect,
+) -> browse::Msg {
+    let result = match effect {
+        browse::Effect::LoadPaths {
+            pattern,
+            offset,
+            limit,
+        } => archive
+            .list_paths(&pattern, offset, limit, filter)
+            .await
+            .map(|paths| browse::Msg::Paths {
+                offset,
+                limit,
+                paths,
+            }),
+        browse::Effect::LoadVersions { path, limit } => archive
+            .history(Path::new(&path), Some(limit), filter)
+            .await
+            .map(|versions| browse::Msg::Versions { path, versions }),
+        browse::Effect::LoadBody { name } => {
+            let body = match archive.get(&name).await {
+                Ok(Some((_, body))) => Ok(body),
+                Ok(None) => Err(format!("archive {} not found", name)),
+                Err(e) => Err(format!("{:#}", e)),
+            };
+            Ok(browse::Msg::Body { name, body })
+        }
+        browse::Effect::Diff { name } => {
+            let lines = match archive.get(&name).await {
+                Ok(Some((entry, body))) => std::fs::read_to_string(&entry.path)
+                    .map(|disk| browse::diff_keys(&body, &disk))
+                    .map_err(|e| format!("failed to read {}: {}", entry.path, e)),
+                Ok(None) => Err(format!("archive {} not found", name)),
+                Err(e) => Err(format!("{:#}", e)),
+            };
+            Ok(browse::Msg::Diff { name, lines })
+        }
+        browse::Effect::Recover { name } => {
+            // 一覧には絞り込み条件に一致するアーカイブだけを表示しているため、登録したユーザーは問わない
+            let options = RecoverOptions {
+                in_place: true,
+                all_users: true,
+                ..Default::default()
+            };
+            let mut progress = browse::StatusProgress::default();
+            recover(
+                context,
+                &name,
+                &options,
+                &mut confirm::AssumeYes,
+                &mut progress,
+            )
+            .await
+            .map(|()| browse::Msg::Done(progress.messages.join(", ")))
+        }
+        browse::Effect::AddTag { name, tag } => archive
+            .add_tags(&name, std::slice::from_ref(&tag))
+            .await
+            .map(|()| browse::Msg::Done(format!("tagged {} with {}", name, tag))),
+        browse::Effect::Delete { name } => archive
+            .delete(&name)
+            .await
+            .map(|()| browse::Msg::Done(format!("deleted {}", name))),
+        browse::Effect::Quit => unreachable!("Quit is handled by run_browser"),
+    };
+    result.unwrap_or_else(|e| browse::Msg::Failed(format!("{:#}", e)))
+}
+
+/// history で表示するチェックサムの桁数
+const CHECKSUM_PREFIX_LEN: usize = 12;
+
+async fn history(
+    context: &Context,
+    path: &Path,
+    limit: Option<usize>,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let history = archive
+        .history(path, limit, filter)
+        .await
+        .context("Failed to get history")?;
+    if history.is_empty() {
+        anyhow::bail!(
+            "{} has never been archived. push it first with `dot-env-archive push {}`",
+            path.display(),
+            path.display()
+        );
+    }
+    if context.private {
+        println!("{}", redact::PRIVATE_MODE_HEADER);
+    }
+    for (entry, size) in history {
+        let checksum = entry
+            .checksum
+            .get(..CHECKSUM_PREFIX_LEN)
+            .unwrap_or(&entry.checksum);
+        print!(
+            "{} {} {} {} bytes",
+            entry.name,
+            context.timestamp(entry.created_at),
+            checksum,
+            size
+        );
+        if entry.quarantined {
+            print!(" {}", output::QUARANTINED_MARK);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+async fn add_tags(context: &Context, name: &str, tags: &[String]) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    archive
+        .add_tags(name, tags)
+        .await
+        .context("Failed to tag archive")?;
+    let tags = archive.tags(name).await.context("Failed to get tags")?;
+    println!("[TAGGED] {}: {}", name, tags.join(", "));
+    Ok(())
+}
+
+async fn remove_tag(context: &Context, name: &str, tag: &str) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    archive
+        .remove_tag(name, tag)
+        .await
+        .context("Failed to untag archive")?;
+    println!("[UNTAGGED] {}: {}", name, tag);
+    Ok(())
+}
+
+async fn annotate(context: &Context, name: &str, message: Option<&str>) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let entry = archive
+        .annotate(name, message)
+        .await
+        .context("Failed to annotate archive")?;
+    print_entries(context, vec![entry], output::EntryFormat::Text)
+}
+
+/// $EDITOR が設定されていない場合に使うエディタ
+const DEFAULT_EDITOR: &str = "vi";
+
+/// name のアーカイブの本文を editor で編集し、変更があれば同じパスの新しいアーカイブとして登録する
+async fn edit(
+    context: &Context,
+    name: &str,
+    new_name: Option<String>,
+    message: Option<&str>,
+    all_users: bool,
+    editor: &str,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let (entry, body) = archive
+        .get(name)
+        .await
+        .context("Failed to show archive")?
+        .context("Archive not found")?;
+    context.ensure_visible(&entry, all_users)?;
+    context.ensure_not_quarantined(&entry, false)?;
+
+    let edited = edit_body(editor, &body)?;
+    if edited == body {
+        println!("no changes, nothing archived");
+        return Ok(());
+    }
+    let new_name = new_name.unwrap_or_else(|| ulid::Ulid::new().to_string());
+    let path = Path::new(&entry.path);
+    if context.policies.enforce_on_push != policy::Enforcement::Off {
+        enforce_policies(context, path, &edited)?;
+    }
+    archive
+        .push_body(path, &edited, chrono::Utc::now(), &new_name, message)
+        .await
+        .context("Failed to push archive")?;
+    println!("[PUSHED] {} with name {}", path.display(), new_name);
+    Ok(())
+}
+
+/// body を一時ファイルに書き出して editor で編集させ、エディタを閉じた後の内容を返す
+/// editor は空白で区切ってコマンドと引数にする (`code --wait` など)
+/// 一時ファイルは所有者だけが読み書きできる権限で作り、エディタが失敗した場合も削除する
+fn edit_body(editor: &str, body: &str) -> anyhow::Result<String> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("EDITOR is empty")?;
+    let file = tempfile::Builder::new()
+        .prefix("env-archive-")
+        .suffix(".env")
+        .tempfile()
+        .context("Failed to create temporary file")?;
+    std::fs::write(file.path(), body).context("Failed to write temporary file")?;
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .with_context(|| format!("Failed to run editor {}", program))?;
+    if !status.success() {
+        anyhow::bail!(
+            "editor {} exited with {}. nothing archived",
+            program,
+            status
+        );
+    }
+    std::fs::read_to_string(file.path()).context("Failed to read temporary file")
+}
+
+async fn rename(context: &Context, old_name: &str, new_name: &str) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let entry = archive
+        .rename(old_name, new_name)
+        .await
+        .context("Failed to rename archive")?;
+    print_entries(context, vec![entry], output::EntryFormat::Text)
+}
+
+/// show の表示のしかた
+#[derive(Debug)]
+struct ShowOptions {
+    all_users: bool,
+    include_quarantined: bool,
+    format: ShowFormat,
+    /// 値を伏せる
+    redact: bool,
+    /// アーカイブの名前と登録日時を表示する
+    header: bool,
+}
+
+async fn show(context: &Context, name: &str, options: &ShowOptions) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let (entry, body) = archive
+        .get(name)
+        .await
+        .context("Failed to show archive")?
+        .context("Archive not found")?;
+    context.ensure_visible(&entry, options.all_users)?;
+    context.ensure_not_quarantined(&entry, options.include_quarantined)?;
+    let entry = if context.private {
+        redact::redact_entry(entry)
+    } else {
+        entry
+    };
+    // 標準出力はファイルにリダイレクトして復元に使われるため、見出しと説明は標準エラー出力に表示する
+    if options.header {
+        eprintln!("# {} {}", entry.name, context.timestamp(entry.created_at));
+    }
+    if let Some(message) = &entry.message {
+        eprintln!("# {}", message);
+    }
+    // --private では常に値を伏せる
+    let redact = options.redact || context.private;
+    let format = &options.format;
+    let lines = match format {
+        ShowFormat::Keys(keys) => Some(key_lines(&body, keys, redact)?),
+        _ => None,
+    };
+    if context.private {
+        println!("{}", redact::PRIVATE_MODE_HEADER);
+    } else if redact {
+        eprintln!("# values are redacted. pass --show-values to show them");
+    }
+    if let Some(lines) = lines {
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    if *format == ShowFormat::Export {
+        let mut values = dotenv::values(&body);
+        if redact {
+            for (_, value) in &mut values {
+                *value = redact::MASK.to_string();
+            }
+        }
+        let (script, skipped) = dotenv::export_script(&values);
+        for key in skipped {
+            eprintln!(
+                "warning: skipped {} because it is not a valid shell variable name",
+                key
+            );
+        }
+        print!("{}", script);
+        return Ok(());
+    }
+    if redact {
+        println!("{}", redact::redact_body(&body));
+    } else {
+        println!("{}", body);
+    }
+    Ok(())
+}
+
+/// show で表示する内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ShowFormat {
+    /// 本文をそのまま表示する
+    Body,
+    /// 指定したキーの値だけを表示する (--key)
+    Keys(Vec<String>),
+    /// `export KEY='value'` の形式で表示する (--export)
+    Export,
+}
+
+/// show --path で表示するアーカイブの名前を決める
+/// path のアーカイブのうち、新しい方から nth 番目のものの名前を返す
+/// include_quarantined でなければ隔離されているアーカイブは数えない
+async fn resolve_show_name(
+    context: &Context,
+    path: &str,
+    nth: std::num::NonZeroUsize,
+    all_users: bool,
+    include_quarantined: bool,
+) -> anyhow::Result<String> {
+    let archive = context.archive()?;
+    let path = absolute_path(path);
+    let filter = context.filter(
+        None,
+        &UserArgs {
+            user: None,
+            all_users,
+        },
+    );
+    let archives = archive
+        .find_by_path(&path, &filter)
+        .await
+        .context("Failed to find archive")?
+        .into_iter()
+        .filter(|entry| include_quarantined || !entry.quarantined)
+        .collect::<Vec<_>>();
+    if archives.is_empty() {
+        anyhow::bail!(no_archive_message(&archive, &path, &filter).await?);
+    }
+    let count = archives.len();
+    archives
+        .into_iter()
+        .nth(nth.get() - 1)
+        .map(|entry| entry.name)
+        .with_context(|| {
+            format!(
+                "path {} has only {} archive(s), but --nth {} was given",
+                path.display(),
+                count,
+                nth
+            )
+        })
+}
+
+/// show --key で表示する行
+/// キーが 1 つの場合は値だけを、複数の場合は `KEY=value` を keys の順に返す
+/// redact の場合は値を伏せる
+fn key_lines(body: &str, keys: &[String], redact: bool) -> anyhow::Result<Vec<String>> {
+    let values = dotenv::values(body);
+    keys.iter()
+        .map(|key| {
+            let Some((_, value)) = values.iter().find(|(k, _)| k == key) else {
+                let available = values.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+                anyhow::bail!(
+                    "key {} is not defined in the archive. available keys: {}",
+                    key,
+                    if available.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        available.join(", ")
+                    }
+                );
+            };
+            let value = if redact { redact::MASK } else { value.as_str() };
+            Ok(if keys.len() == 1 {
+                value.to_string()
+            } else {
+                format!("{}={}", key, value)
+            })
+        })
+        .collect()
+}
+
+/// name のアーカイブを dest_dir にアーカイブ元と同じファイル名で書き出す
+/// force でなければ既存のファイルは上書きしない
+async fn extract(
+    context: &Context,
+    name: &str,
+    dest_dir: &Path,
+    force: bool,
+    all_users: bool,
+    include_quarantined: bool,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let (entry, body) = archive
+        .get(name)
+        .await
+        .context("Failed to show archive")?
+        .context("Archive not found")?;
+    context.ensure_visible(&entry, all_users)?;
+    context.ensure_not_quarantined(&entry, include_quarantined)?;
+
+    let file_name = Path::new(&entry.path)
+        .file_name()
+        .with_context(|| format!("archive {} has no file name in its path", name))?;
+    let target_path = dest_dir.join(file_name);
+    // 存在を確認してから書き込むと間に作成されたファイルを上書きしてしまうため、作成と同時に確認する
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(&target_path);
+    let mut file = match file {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => anyhow::bail!(
+            "{} already exists. use --force to overwrite it",
+            target_path.display()
+        ),
+        file => file.context("Failed to write file")?,
+    };
+    std::io::Write::write_all(&mut file, body.as_bytes()).context("Failed to write file")?;
+    println!("{}", target_path.display());
+    Ok(())
+}
+
+/// 登録されている本文から計算したチェックサムが、登録時のチェックサムと一致するかを確かめる
+/// 一致しない場合はアーカイブを隔離する
+/// repair の場合は、隔離されているアーカイブが一致すれば隔離を解除する
+async fn verify(context: &Context, name: &str, repair: bool) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let (entry, body) = archive
+        .get(name)
+        .await
+        .context("Failed to show archive")?
+        .context("Archive not found")?;
+    let checksum = digest::bytes_checksum(body.as_bytes());
+    if checksum != entry.checksum {
+        if !entry.quarantined {
+            archive
+                .set_quarantined(name, true)
+                .await
+                .context("Failed to quarantine archive")?;
+        }
+        println!(
+            "[CORRUPT] {} expected checksum {} but the body has {}",
+            name, entry.checksum, checksum
+        );
+        anyhow::bail!("archive {} is corrupt and has been quarantined", name);
+    }
+    match (entry.quarantined, repair) {
+        (false, _) => println!("[OK] {}", name),
+        (true, false) => println!("[OK] {} is quarantined. use --repair to release it", name),
+        (true, true) => {
+            archive
+                .set_quarantined(name, false)
+                .await
+                .context("Failed to release archive")?;
+            println!("[RELEASED] {}", name);
+        }
+    }
+    Ok(())
+}
+
+/// verify --all で途中経過を通知する間隔 (件数)
+const VERIFY_PROGRESS_INTERVAL: u64 = 1000;
+
+/// verify --all が並行してチェックサムを計算するスレッドの数の既定値
+const DEFAULT_VERIFY_JOBS: std::num::NonZeroUsize = std::num::NonZeroUsize::new(4).unwrap();
+
+/// verify --all で 1 つのスレッドにまとめて渡す本文の数
+/// 読み出した本文はこの数にスレッドの数をかけた件数ずつしかメモリに持たない
+const VERIFY_BATCH_PER_JOB: usize = 64;
+
+/// verify --all のオプション
+#[derive(Debug)]
+struct VerifyAllOptions {
+    /// 隔離されているアーカイブが一致した場合に隔離を解除する
+    repair: bool,
+    /// 一致しなかったアーカイブを隔離する代わりに削除する
+    delete_corrupt: bool,
+    /// パスがこれで始まるアーカイブに限る
+    prefix: Option<PathBuf>,
+    /// この日時以降に登録したアーカイブに限る
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    jobs: std::num::NonZeroUsize,
+    /// 結果を JSON で出力する
+    json: bool,
+}
+
+impl Default for VerifyAllOptions {
+    fn default() -> Self {
+        Self {
+            repair: false,
+            delete_corrupt: false,
+            prefix: None,
+            since: None,
+            jobs: DEFAULT_VERIFY_JOBS,
+            json: false,
+        }
+    }
+}
+
+/// verify --all --json で出力する結果
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize)]
+struct VerifyReport {
+    checked: u64,
+    /// 本文がチェックサムと一致しなかったアーカイブ
+    corrupt: Vec<archive::ArchiveEntry>,
+    /// コールドストレージの本文を読み出せず検証できなかったアーカイブ
+    unavailable: Vec<archive::ArchiveEntry>,
+    /// 一致したため隔離を解除したアーカイブの名前
+    released: Vec<String>,
+    /// 了承を得て削除したアーカイブの名前
+    deleted: Vec<String>,
+}
+
+/// 本文と、読み出せなかった場合のエラー
+type VerifyBatch = Vec<(
+    archive::ArchiveEntry,
+    Result<String, cold::ColdBodyUnavailable>,
+)>;
+
+/// batch の本文がそれぞれチェックサムと一致するかを jobs 個のスレッドに分けて確かめる
+/// 本文を読み出せなかったものは None にする
+fn checksums_match(batch: &VerifyBatch, jobs: std::num::NonZeroUsize) -> Vec<Option<bool>> {
+    let matches = |(entry, body): &(archive::ArchiveEntry, Result<String, _>)| {
+        body.as_ref()
+            .ok()
+            .map(|body| digest::bytes_checksum(body.as_bytes()) == entry.checksum)
+    };
+    if jobs.get() == 1 {
+        return batch.iter().map(matches).collect();
+    }
+    let chunk_size = batch.len().div_ceil(jobs.get()).max(1);
+    std::thread::scope(|scope| {
+        let handles = batch
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(matches).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// verify --all の途中経過
+struct VerifyRun<'a> {
+    total: u64,
+    report: VerifyReport,
+    unavailable: UnavailableBodies,
+    progress: &'a mut dyn progress::ProgressSink,
+}
+
+impl VerifyRun<'_> {
+    /// batch を検証し、結果を読み出した順に通知する
+    /// 通知する順序と内容はスレッドの数にかかわらず同じになる
+    fn check(&mut self, batch: VerifyBatch, options: &VerifyAllOptions) {
+        let matches = checksums_match(&batch, options.jobs);
+        for ((entry, body), matched) in batch.into_iter().zip(matches) {
+            self.report.checked += 1;
+            match (body, matched) {
+                (Err(error), _) => {
+                    self.progress.emit(progress::Event::VerifyUnavailable {
+                        name: entry.name.clone(),
+                        path: entry.path.clone(),
+                        created_at: entry.created_at,
+                    });
+                    self.unavailable.push(error);
+                    self.report.unavailable.push(entry);
+                }
+                (Ok(_), Some(false)) => {
+                    self.progress.emit(progress::Event::VerifyMismatch {
+                        name: entry.name.clone(),
+                        path: entry.path.clone(),
+                        created_at: entry.created_at,
+                    });
+                    self.report.corrupt.push(entry);
+                }
+                (Ok(_), _) => {
+                    if options.repair && entry.quarantined {
+                        self.report.released.push(entry.name);
+                    }
+                }
+            }
+            if self.report.checked.is_multiple_of(VERIFY_PROGRESS_INTERVAL) {
+                self.progress.emit(progress::Event::VerifyProgress {
+                    checked: self.report.checked,
+                    total: self.total,
+                });
+            }
+        }
+    }
+}
+
+/// アーカイブについて、本文から計算したチェックサムが登録時のものと一致するかを確かめる
+/// 本文は読み出しながら options.jobs 個のスレッドでチェックサムを計算し、全件をメモリに読み込まない
+/// 一致しないアーカイブがあれば隔離 (delete_corrupt の場合は了承を得て削除) し、エラーを返す
+async fn verify_all(
+    context: &Context,
+    options: &VerifyAllOptions,
+    confirm: &mut dyn confirm::Confirm,
+    progress: &mut dyn progress::ProgressSink,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let prefix = options.prefix.as_deref();
+    let filter = archive::EntryFilter {
+        since: options.since,
+        ..Default::default()
+    };
+    let total = archive
+        .count(prefix, &filter)
+        .await
+        .context("Failed to verify archive")?;
+    progress.emit(progress::Event::VerifyStarted { total });
+    let mut run = VerifyRun {
+        total,
+        report: VerifyReport::default(),
+        unavailable: UnavailableBodies::default(),
+        progress,
+    };
+    let batch_size = options.jobs.get() * VERIFY_BATCH_PER_JOB;
+    let mut batch = Vec::with_capacity(batch_size);
+    archive
+        .for_each_with_body(prefix, &filter, |entry, body| {
+            batch.push((entry, body));
+            if batch.len() == batch_size {
+                run.check(std::mem::take(&mut batch), options);
+            }
+            Ok(())
+        })
+        .await
+        .context("Failed to verify archive")?;
+    run.check(batch, options);
+    let VerifyRun {
+        mut report,
+        unavailable,
+        progress,
+        ..
+    } = run;
+    progress.emit(progress::Event::VerifyFinished {
+        checked: report.checked,
+        corrupt: report.corrupt.len() as u64,
+        unavailable: unavailable.count,
+    });
+
+    for name in &report.released {
+        archive
+            .set_quarantined(name, false)
+            .await
+            .context("Failed to release archive")?;
+        if !options.json {
+            println!("[RELEASED] {}", name);
+        }
+    }
+    let delete = !report.corrupt.is_empty()
+        && options.delete_corrupt
+        && confirm.confirm(&format!(
+            "delete {} corrupt archive(s)?",
+            report.corrupt.len()
+        ))?;
+    if delete {
+        for entry in &report.corrupt {
+            archive
+                .delete(&entry.name)
+                .await
+                .context("Failed to delete archive")?;
+            if !options.json {
+                println!("[DELETED] {}", entry.name);
+            }
+            report.deleted.push(entry.name.clone());
+        }
+    } else {
+        for entry in report.corrupt.iter().filter(|entry| !entry.quarantined) {
+            archive
+                .set_quarantined(&entry.name, true)
+                .await
+                .context("Failed to quarantine archive")?;
+        }
+    }
+    let corrupt = report.corrupt.len();
+    if options.json {
+        if context.private {
+            report.corrupt = report
+                .corrupt
+                .into_iter()
+                .map(redact::redact_entry)
+                .collect();
+            report.unavailable = report
+                .unavailable
+                .into_iter()
+                .map(redact::redact_entry)
+                .collect();
+        }
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    if corrupt == 0 {
+        return unavailable.into_result();
+    }
+    if delete {
+        anyhow::bail!("found {} corrupt archive(s) and deleted them", corrupt);
+    }
+    anyhow::bail!("found {} corrupt archive(s) and quarantined them", corrupt)
+}
+
+/// チェックサムを検証せずにアーカイブの隔離を解除する
+async fn unquarantine(context: &Context, name: &str) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let entry = archive
+        .entry(name)
+        .await
+        .context("Failed to show archive")?
+        .context("Archive not found")?;
+    if !entry.quarantined {
+        anyhow::bail!("archive {} is not quarantined", name);
+    }
+    archive
+        .set_quarantined(name, false)
+        .await
+        .context("Failed to release archive")?;
+    println!("[RELEASED] {}", name);
+    Ok(())
+}
+
+/// ポリシーを適用するファイルを読み込む
+fn load_policies(path: &Path) -> anyhow::Result<policy::Policies> {
+    let toml = std::fs::read_to_string(path).context("Failed to read policies")?;
+    policy::Policies::parse(&toml).context("Failed to parse policies")
+}
+
+/// lint で確かめるアーカイブ
+#[derive(Debug)]
+enum LintTarget {
+    Name(String),
+    Path(PathBuf),
+    /// None の場合はすべてのパス
+    Dir(Option<PathBuf>),
+}
+
+async fn lint(
+    context: &Context,
+    target: &LintTarget,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<()> {
+    if context.policies.is_empty() {
+        anyhow::bail!(
+            "no policies configured. pass --policies <PATH> or add [[policies]] to the config file"
+        );
+    }
+    let archive = context.archive()?;
+    let entries = match target {
+        LintTarget::Name(name) => {
+            let entry = archive
+                .entry(name)
+                .await
+                .context("Failed to show archive")?
+                .context("Archive not found")?;
+            vec![entry]
+        }
+        LintTarget::Path(path) => {
+            let entry = archive
+                .find_by_path(path, filter)
+                .await
+                .context("Failed to find archive")?
+                .into_iter()
+                .find(|entry| !entry.quarantined)
+                .with_context(|| format!("no archive found for {}", path.display()))?;
+            vec![entry]
+        }
+        LintTarget::Dir(dir) => archive
+            .list_latest_per_path(dir.as_deref(), filter)
+            .await
+            .context("Failed to list archive")?,
+    };
+
+    let mut count = 0;
+    for entry in &entries {
+        let (_, body) = archive
+            .get(&entry.name)
+            .await
+            .context("Failed to show archive")?
+            .context("Archive not found")?;
+        let path = if context.private {
+            redact::redact_path(&entry.path)
+        } else {
+            entry.path.clone()
+        };
+        for violation in context.policies.check(Path::new(&entry.path), &body) {
+            println!("[VIOLATION] {} ({}) {}", path, entry.name, violation);
+            count += 1;
+        }
+    }
+    println!("checked: {}, violations: {}", entries.len(), count);
+    if count > 0 {
+        anyhow::bail!("found {} policy violation(s)", count);
+    }
+    Ok(())
+}
+
+/// パスワードマネージャーのアイテムを取り出し、アイテムの ID をメッセージに記録して登録する
+async fn import_secret(
+    context: &Context,
+    manager: &dyn password_manager::PasswordManager,
+    item: &str,
+    field: Option<&str>,
+    path: &Path,
+    name: Option<String>,
+) -> anyhow::Result<()> {
+    let secret = manager
+        .fetch(item, field)
+        .context("Failed to fetch item from password manager")?;
+    let options = PushOptions {
+        naming: PushNaming::new(name, None),
+        message: Some(format!(
+            "imported from {} item {}",
+            manager.name(),
+            secret.id
+        )),
+        ..Default::default()
+    };
+    push_body(context, path, &secret.body, &options).await
+}
+
+/// export で書き出したファイルのアーカイブを、名前や作成日時を変えずに登録する
+///
+/// 本文とチェックサムが一致しないアーカイブは登録せず、最後にその名前を並べてエラーにする
+async fn import_archive(context: &Context, file: &Path, overwrite: bool) -> anyhow::Result<()> {
+    let json = std::fs::read_to_string(file).context("Failed to read import file")?;
+    let document = manifest::parse(&json).context("Failed to parse import file")?;
+    let archive = context.archive()?;
+
+    let (mut imported, mut skipped) = (0, 0);
+    let mut failed = Vec::new();
+    for exported in &document.entries {
+        let name = &exported.entry.name;
+        // 隔離されているアーカイブは本文がチェックサムと一致しないまま書き出されるため、隔離したまま取り込む
+        if !exported.entry.quarantined
+            && digest::bytes_checksum(exported.body.as_bytes()) != exported.entry.checksum
+        {
+            println!("[FAILED] {} checksum does not match the body", name);
+            failed.push(name.as_str());
+            continue;
+        }
+        let restored = archive
+            .restore(&exported.entry, &exported.body, &exported.tags, overwrite)
+            .await;
+        match restored {
+            Ok(archive::Restored::Inserted) => {
+                println!("[IMPORTED] {}", name);
+                imported += 1;
+            }
+            Ok(archive::Restored::Replaced) => {
+                println!("[REPLACED] {}", name);
+                imported += 1;
+            }
+            Ok(archive::Restored::Skipped) => {
+                println!(
+                    "[SKIP] {} already exists. use --overwrite to replace it",
+                    name
+                );
+                skipped += 1;
+            }
+            Err(e) => {
+                println!("[FAILED] {} {:#}", name, e);
+                failed.push(name.as_str());
+            }
+        }
+    }
+    println!(
+        "imported: {}, skipped: {}, failed: {}",
+        imported,
+        skipped,
+        failed.len()
+    );
+    if !failed.is_empty() {
+        anyhow::bail!("failed to import {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+async fn export(
+    context: &Context,
+    output: &str,
+    keyword: Option<&str>,
+    filter: &archive::EntryFilter,
+    include_quarantined: bool,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let mut archives = archive
+        .list_with_body(keyword, filter)
+        .await
+        .context("Failed to list archive")?;
+    if !include_quarantined {
+        archives.retain(|(entry, _)| {
+            if entry.quarantined {
+                eprintln!(
+                    "warning: skipped quarantined archive {}. use --include-quarantined to export it",
+                    entry.name
+                );
+            }
+            !entry.quarantined
+        });
+    }
+    let tags = archive.tags_by_name().await.context("Failed to get tags")?;
+    let document = manifest::Document::new(archives, tags);
+    if output == "-" {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &document)
+            .context("Failed to write export")?;
+        println!();
+        return Ok(());
+    }
+    let file =
+        helper::create_private_file(Path::new(output)).context("Failed to create export file")?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), &document)
+        .context("Failed to write export")?;
+    println!(
+        "[EXPORTED] {} entries to {}",
+        document.entries.len(),
+        output
+    );
+    Ok(())
+}
+
+/// dir 配下のパスごとに最新のアーカイブを tar に書き出す
+async fn export_tar(
+    context: &Context,
+    tar: &Path,
+    dir: &Path,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let mut archives = Vec::new();
+    for entry in archive
+        .list_latest_per_path(Some(dir), filter)
+        .await
+        .context("Failed to list archive")?
+    {
+        let (entry, body) = archive
+            .get(&entry.name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("archive {} not found", entry.name))?;
+        archives.push((entry, body));
+    }
+    let file = helper::create_private_file(tar).context("Failed to create tar file")?;
+    let files = tarball::write(std::io::BufWriter::new(file), dir, &archives)
+        .context("Failed to write tar file")?;
+    for file in &files {
+        println!("[EXPORTED] {} ({})", file.path, file.name);
+    }
+    println!("[EXPORTED] {} files to {}", files.len(), tar.display());
+    Ok(())
+}
+
+/// bootstrap でプロジェクトごとに数える件数
+#[derive(Debug, Default)]
+struct BootstrapSummary {
+    restored: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+/// マニフェストのプロジェクトを順に準備する
+/// 失敗したプロジェクトがあっても残りのプロジェクトを続け、最後にまとめてエラーにする
+async fn bootstrap(context: &Context, manifest_path: &Path, git_bin: &Path) -> anyhow::Result<()> {
+    let toml = std::fs::read_to_string(manifest_path).context("Failed to read manifest")?;
+    let base = manifest_path.parent().unwrap_or(Path::new("/"));
+    let manifest = workspace::Manifest::parse(&toml, base, dirs::home_dir().as_deref())
+        .context("Failed to parse manifest")?;
+    let archive = context.archive()?;
+
+    let mut failed = Vec::new();
+    for project in &manifest.projects {
+        println!("[PROJECT] {}", project.dir.display());
+        match bootstrap_project(context, &archive, project, git_bin).await {
+            Ok(summary) => {
+                println!(
+                    "restored: {}, skipped: {}, failed: {}",
+                    summary.restored, summary.skipped, summary.failed
+                );
+                if summary.failed > 0 {
+                    failed.push(project.dir.display().to_string());
+                }
+            }
+            Err(e) => {
+                println!("[FAILED] {} {:#}", project.dir.display(), e);
+                failed.push(project.dir.display().to_string());
+            }
+        }
+    }
+    println!(
+        "projects: {}, succeeded: {}, failed: {}",
+        manifest.projects.len(),
+        manifest.projects.len() - failed.len(),
+        failed.len()
+    );
+    if !failed.is_empty() {
+        anyhow::bail!("failed to bootstrap {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// プロジェクトのディレクトリがなければ clone し、.env ファイルを復元する
+async fn bootstrap_project(
+    context: &Context,
+    archive: &archive::Archive,
+    project: &workspace::Project,
+    git_bin: &Path,
+) -> anyhow::Result<BootstrapSummary> {
+    match &project.git {
+        Some(_) if project.dir.exists() => {
+            println!("[SKIP CLONE] {} already exists", project.dir.display())
+        }
+        Some(url) => {
+            // マニフェストの URL が `-` で始まっても git のオプションとして解釈されないよう `--` で区切る
+            let status = std::process::Command::new(git_bin)
+                .arg("clone")
+                .arg("--")
+                .arg(url)
+                .arg(&project.dir)
+                .status()
+                .with_context(|| format!("Failed to run {}", git_bin.display()))?;
+            if !status.success() {
+                anyhow::bail!("git clone {} failed with {}", url, status);
+            }
+            println!("[CLONED] {}", url);
+        }
+        None if !project.dir.exists() => anyhow::bail!(
+            "{} does not exist. add git to the project to clone it",
+            project.dir.display()
+        ),
+        None => {}
+    }
+
+    let filter = context.filter(None, &UserArgs::default());
+    let mut summary = BootstrapSummary::default();
+    // 復元先のパスと、復元するアーカイブの名前 (見つからなければ None)
+    let mut targets = Vec::new();
+    if project.files.is_empty() {
+        for entry in archive
+            .list_latest_per_path(Some(&project.dir), &filter)
+            .await?
+        {
+            if Path::new(&entry.path).starts_with(&project.dir) {
+                targets.push((PathBuf::from(entry.path), Some(entry.name)));
+            }
+        }
+    }
+    for file in &project.files {
+        let target_path = project.dir.join(file.path());
+        let name = match file {
+            workspace::FileSpec::Path(_) => archive
+                .find_by_path(&target_path, &filter)
+                .await?
+                .into_iter()
+                .next()
+                .map(|entry| entry.name),
+            workspace::FileSpec::Named { name, .. } => Some(name.clone()),
+        };
+        targets.push((target_path, name));
+    }
+
+    for (target_path, name) in targets {
+        let Some(name) = name else {
+            println!("[NOT FOUND] {}", target_path.display());
+            summary.failed += 1;
+            continue;
+        };
+        if target_path.exists() {
+            println!("[SKIP] {} already exists", target_path.display());
+            summary.skipped += 1;
+            continue;
+        }
+        match restore_file(context, archive, &name, &target_path).await {
+            Ok(()) => {
+                println!("[RECOVERED] {} from {}", target_path.display(), name);
+                summary.restored += 1;
+            }
+            Err(e) => {
+                println!("[FAILED] {} {:#}", target_path.display(), e);
+                summary.failed += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// name のアーカイブを target_path に書き出す 親ディレクトリがなければ作成する
+async fn restore_file(
+    context: &Context,
+    archive: &archive::Archive,
+    name: &str,
+    target_path: &Path,
+) -> anyhow::Result<()> {
+    let (entry, body) = archive
+        .get(name)
+        .await?
+        .with_context(|| format!("archive {} not found", name))?;
+    context.ensure_visible(&entry, false)?;
+    if let Some(parent) = target_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(target_path, body)?;
+    Ok(())
+}
+
+async fn show_stats(
+    context: &Context,
+    filter: &archive::EntryFilter,
+    growth: bool,
+    bucket: stats::Bucket,
+    chart: bool,
+    format: stats::StatsFormat,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    if !growth {
+        let mut summary = archive
+            .summary(stats::TOP_PATHS, filter)
+            .await
+            .context("Failed to aggregate archive")?;
+        if context.private {
+            for top_path in &mut summary.top_paths {
+                top_path.path = redact::redact_path(&top_path.path);
+            }
+        }
+        let database_bytes = std::fs::metadata(&context.database)
+            .with_context(|| format!("Failed to read {}", context.database.display()))?
+            .len();
+        let overview = stats::Overview {
+            summary,
+            database_bytes,
+        };
+        match format {
+            stats::StatsFormat::Table => {
+                if context.private {
+                    println!("{}", redact::PRIVATE_MODE_HEADER);
+                }
+                let time = context.timestamp_format();
+                print!("{}", stats::render_overview(&overview, &time))
+            }
+            stats::StatsFormat::Csv => anyhow::bail!("--format csv requires --growth"),
+            stats::StatsFormat::Json => {
+                if context.private {
+                    eprintln!("{}", redact::PRIVATE_MODE_HEADER);
+                }
+                println!("{}", serde_json::to_string_pretty(&overview)?)
+            }
+        }
+        return Ok(());
+    }
+
+    let slots = archive
+        .growth_slots(filter)
+        .await
+        .context("Failed to aggregate archive")?;
+    let rows = stats::bucket_growth(&slots, bucket, &context.timezone);
+    match format {
+        stats::StatsFormat::Table if chart => print!("{}", stats::render_chart(&rows)),
+        stats::StatsFormat::Table => print!("{}", stats::render_table(&rows)),
+        stats::StatsFormat::Csv => print!("{}", stats::render_csv(&rows)),
+        stats::StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+    }
+    Ok(())
+}
+
+/// cutoff より前に作成されたアーカイブの本文を dir に移す
+async fn tier(
+    context: &Context,
+    cutoff: chrono::DateTime<chrono::Utc>,
+    dir: &Path,
+) -> anyhow::Result<()> {
+    if !dir.is_dir() {
+        anyhow::bail!("{} is not a directory", dir.display());
+    }
+    let archive = context.archive()?;
+    let names = archive
+        .move_to_cold(cutoff, dir)
+        .await
+        .context("Failed to move bodies to cold storage")?;
+    println!("moved {} bodies to {}", names.len(), dir.display());
+    Ok(())
+}
+
+/// コールドストレージに移した本文をデータベースに戻す
+async fn restore_tier(context: &Context) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let names = archive
+        .restore_from_cold()
+        .await
+        .context("Failed to restore bodies from cold storage")?;
+    println!("restored {} bodies", names.len());
+    Ok(())
+}
+
+/// パスを絶対パスにする
+/// ファイルが既に存在しない場合もあるため、正規化できなければ字句的に正規化する
+fn absolute_path(path: &str) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| {
+        std::env::current_dir()
+            .map(|cwd| helper::normalize_path(Path::new(path), &cwd))
+            .unwrap_or_else(|_| PathBuf::from(path))
+    })
+}
+
+/// 読み取りだけのコマンドで指定されたパスを絶対パスにする
+/// 手元に存在しないパスでもアーカイブは検索できるため、その旨を標準エラー出力に表示して続ける
+fn lookup_path(path: &str) -> PathBuf {
+    let resolved = absolute_path(path);
+    if !resolved.exists() {
+        eprintln!("note: {} does not exist locally", resolved.display());
+    }
+    resolved
+}
+
+/// 書き込むコマンドで指定された、既に存在するはずのパスを正規化する
+fn existing_path(path: &str) -> anyhow::Result<PathBuf> {
+    helper::canonicalize_existing(Path::new(path), &std::env::current_dir()?)
+}
+
+/// recover で復元するアーカイブの名前を決める
+///
+/// path が指定された場合、または name が `/` を含みその名前のアーカイブがない場合は、
+/// パスに一致するアーカイブのうち filter に一致する最新のものの名前を返す
+/// before が指定された場合は、その日時より前で最新のアーカイブの名前を返す
+async fn resolve_recover_name(
+    context: &Context,
+    name: Option<&str>,
+    path: Option<&str>,
+    before: Option<chrono::DateTime<chrono::Utc>>,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<String> {
+    let archive = context.archive()?;
+    let path = match (name, path) {
+        (_, Some(path)) => path,
+        (Some(name), None) if name.contains('/') => {
+            if archive.entry(name).await?.is_some() {
+                return Ok(name.to_string());
+            }
+            name
+        }
+        (Some(name), None) => return Ok(name.to_string()),
+        (None, None) => anyhow::bail!("name or --path is required"),
+    };
+
+    let path = absolute_path(path);
+    if let Some(before) = before {
+        return resolve_recover_name_before(context, &archive, &path, before, filter).await;
+    }
+    let archives = archive
+        .find_by_path(&path, filter)
+        .await
+        .context("Failed to find archive")?;
+    // 隔離されているアーカイブは飛ばし、その前のアーカイブを最新とする
+    let found = !archives.is_empty();
+    for entry in archives {
+        if !entry.quarantined {
+            return Ok(entry.name);
+        }
+        eprintln!(
+            "warning: skipped quarantined archive {} created at {}",
+            entry.name,
+            context.timestamp(entry.created_at)
+        );
+    }
+    if found {
+        anyhow::bail!(
+            "all archives for path {} are quarantined. recover one by name with --include-quarantined",
+            path.display()
+        );
+    }
+
+    anyhow::bail!(no_archive_message(&archive, &path, filter).await?)
+}
+
+/// path のアーカイブのうち before より前で最新のものの名前を返す
+/// 見つからない場合は、そのパスで利用できるアーカイブの日時を一覧にしたエラーを返す
+async fn resolve_recover_name_before(
+    context: &Context,
+    archive: &archive::Archive,
+    path: &Path,
+    before: chrono::DateTime<chrono::Utc>,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<String> {
+    if let Some(entry) = archive
+        .find_by_path_before(path, before, filter)
+        .await
+        .context("Failed to find archive")?
+    {
+        return Ok(entry.name);
+    }
+
+    let mut message = format!(
+        "no archive found for path {} before {}",
+        path.display(),
+        context.timestamp(before)
+    );
+    let archives = archive
+        .find_by_path(path, filter)
+        .await
+        .context("Failed to find archive")?;
+    if !archives.is_empty() {
+        message.push_str("\navailable:");
+        for entry in archives {
+            message.push_str(&format!(
+                "\n  {} {}",
+                context.timestamp(entry.created_at),
+                entry.name
+            ));
+        }
+    }
+    anyhow::bail!(message)
+}
+
+/// path のアーカイブが見つからない場合のエラーメッセージ
+/// 近いパスがあれば候補として添える
+async fn no_archive_message(
+    archive: &archive::Archive,
+    path: &Path,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<String> {
+    let mut message = format!("no archive found for path {}", path.display());
+    let suggestions = similar_paths(archive, path, filter).await?;
+    if !suggestions.is_empty() {
+        message.push_str("\ndid you mean:");
+        for suggestion in suggestions {
+            message.push_str(&format!("\n  {}", suggestion));
+        }
+    }
+    Ok(message)
+}
+
+/// 候補として提示するパスの最大数
+const MAX_SUGGESTIONS: usize = 5;
+
+/// path に近いアーカイブ元のパスを探す
+/// 親ディレクトリ名とファイル名、ファイル名の順に大文字と小文字を区別せず部分一致で検索する
+/// 候補は最後に登録した日時の新しい順に、同じパスは 1 度だけ並べる
+async fn similar_paths(
+    archive: &archive::Archive,
+    path: &Path,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<Vec<String>> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut keywords = Vec::new();
+    if let Some(parent) = path.parent().and_then(|parent| parent.file_name()) {
+        keywords.push(format!("{}/{}", parent.to_string_lossy(), file_name));
+    }
+    keywords.push(file_name);
+
+    for keyword in keywords {
+        let mut seen = std::collections::HashSet::new();
+        let mut paths = archive
+            .search_ignore_case(&[&keyword], &[], false, filter, &archive::Page::default())
+            .await?
+            .into_iter()
+            .map(|entry| entry.path)
+            .filter(|path| seen.insert(path.clone()))
+            .collect::<Vec<_>>();
+        if !paths.is_empty() {
+            paths.truncate(MAX_SUGGESTIONS);
+            return Ok(paths);
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// recover の書き込み先
+#[derive(Debug, PartialEq, Eq)]
+enum RecoverTarget {
+    /// 標準出力に書き出す
+    Stdout,
+    /// ファイルに書き出す
+    /// create_dirs が true の場合は存在しない親ディレクトリを作成する
+    File { path: PathBuf, create_dirs: bool },
+}
+
+/// recover のオプションから書き込み先を決める
+fn recover_target(
+    entry_path: &str,
+    output: Option<&str>,
+    in_place: bool,
+    create_dirs: bool,
+    cwd: &Path,
+) -> RecoverTarget {
+    match output {
+        Some("-") => RecoverTarget::Stdout,
+        Some(output) => RecoverTarget::File {
+            path: cwd.join(output),
+            create_dirs: true,
+        },
+        None if in_place => RecoverTarget::File {
+            path: PathBuf::from(entry_path),
+            create_dirs,
+        },
+        None => RecoverTarget::File {
+            path: cwd.join(
+                Path::new(entry_path)
+                    .file_name()
+                    .expect("Failed to get file name"),
+            ),
+            create_dirs: false,
+        },
+    }
+}
+
+/// recover のオプション
+#[derive(Debug, Default)]
+struct RecoverOptions {
+    /// 復元先のファイルパス `-` の場合は標準出力
+    output: Option<String>,
+    /// アーカイブ元のパスに復元する
+    in_place: bool,
+    /// in_place で復元先のディレクトリが存在しない場合に作成する
+    create_dirs: bool,
+    /// 書き込みやバックアップを行わず、行われる操作を表示するだけにする
+    dry_run: bool,
+    /// 上書きする前の復元先ファイルをアーカイブにバックアップしない
+    no_backup: bool,
+    /// 空でなければ、これらのキーの値だけを復元先のファイルに反映する
+    only_keys: Vec<String>,
+    /// 他のユーザーが登録したアーカイブも復元できるようにする
+    all_users: bool,
+    /// 隔離されているアーカイブも復元できるようにする
+    include_quarantined: bool,
+    /// 復元先が存在しない場合も書き込む前に確認する (--interactive)
+    confirm_always: bool,
+}
+
+/// recover で書き込む前に確認する文言
+/// current は復元先の現在の内容で、復元先が存在しない場合は None
+fn recover_prompt(
+    target_path: &Path,
+    name: &str,
+    created_at: &str,
+    current: Option<&str>,
+    body: &str,
+) -> String {
+    match current {
+        Some(current) => {
+            let (added, removed) = helper::count_changed_lines(current, body);
+            format!(
+                "overwrite {} with {} created at {} (+{} -{} lines)?",
+                target_path.display(),
+                name,
+                created_at,
+                added,
+                removed
+            )
+        }
+        None => format!(
+            "create {} from {} created at {} ({} lines)?",
+            target_path.display(),
+            name,
+            created_at,
+            body.lines().count()
+        ),
+    }
+}
+
+/// recover で上書きする前のファイルをバックアップするときの登録名
+/// `backup.<先頭のドットを除いたファイル名>.<日時>.<ULID>` の形式にする
+fn backup_name(
+    target_path: &Path,
+    now: chrono::DateTime<chrono::Utc>,
+    timezone: &chrono_tz::Tz,
+    ulid: ulid::Ulid,
+) -> String {
+    let basename = target_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!(
+        "backup.{}.{}.{}",
+        basename.trim_start_matches('.'),
+        now.with_timezone(timezone).format("%Y%m%dT%H%M"),
+        ulid
+    )
+}
+
+async fn recover(
+    context: &Context,
+    name: &str,
+    options: &RecoverOptions,
+    confirm: &mut dyn confirm::Confirm,
+    progress: &mut dyn progress::ProgressSink,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let (entry, body) = archive
+        .get(name)
+        .await
+        .context("Failed to show archive")?
+        .context("Archive not found")?;
+    context.ensure_visible(&entry, options.all_users)?;
+    context.ensure_not_quarantined(&entry, options.include_quarantined)?;
+
+    let cwd = std::env::current_dir()?;
+    let target = recover_target(
+        &entry.path,
+        options.output.as_deref(),
+        options.in_place,
+        options.create_dirs,
+        &cwd,
+    );
+    let (target_path, create_dirs) = match target {
+        RecoverTarget::Stdout if options.dry_run => {
+            println!("[RECOVER DRY RUN] stdout from {}", name);
+            return Ok(());
+        }
+        RecoverTarget::Stdout if !options.only_keys.is_empty() => {
+            let merge = dotenv::merge_keys("", &body, &options.only_keys)?;
+            print!("{}", merge.body);
+            return Ok(());
+        }
+        RecoverTarget::Stdout => {
+            print!("{}", body);
+            return Ok(());
+        }
+        RecoverTarget::File { path, create_dirs } => (path, create_dirs),
+    };
+    let target_path = target_path.as_path();
+    progress.emit(progress::Event::RecoverPlanned {
+        name: name.to_string(),
+        archive_path: entry.path.clone(),
+        target_path: target_path.to_path_buf(),
+        target_exists: target_path.exists(),
+        dry_run: options.dry_run,
+    });
+
+    if let Some(parent) = target_path.parent() {
+        if !parent.exists() {
+            if !create_dirs {
+                anyhow::bail!(
+                    "directory {} does not exist. pass --create-dirs to create it",
+                    parent.display()
+                );
+            }
+            if !options.dry_run {
+                std::fs::create_dir_all(parent).context("Failed to create directory")?;
+            }
+            progress.emit(progress::Event::RecoverDirectoryCreated {
+                path: parent.to_path_buf(),
+                dry_run: options.dry_run,
+            });
+        }
+    }
+
+    // --only-keys の場合は、復元先の内容に指定したキーの値だけを反映したものを書き込む
+    let merge = if options.only_keys.is_empty() {
+        None
+    } else {
+        let current = if target_path.exists() {
+            std::fs::read_to_string(target_path).context("Failed to read file")?
+        } else {
+            String::new()
+        };
+        Some(dotenv::merge_keys(&current, &body, &options.only_keys)?)
+    };
+    let body = merge.as_ref().map_or(body, |merge| merge.body.clone());
+
+    if target_path.exists() {
+        // 比較と差分の表示には同じ時点で読み込んだ内容を使う
+        let current = std::fs::read(target_path).context("Failed to read file")?;
+        let same = match &merge {
+            Some(merge) => merge.updated.is_empty() && merge.appended.is_empty(),
+            None => archive
+                .check_is_same_by_name(name, &current)
+                .await
+                .context("Failed to check body")?,
+        };
+        if same {
+            progress.emit(progress::Event::RecoverUnchanged {
+                target_path: target_path.to_path_buf(),
+            });
+            if let Some(merge) = merge {
+                emit_key_changes(progress, merge);
+            }
+            return Ok(());
+        }
+        if !options.dry_run {
+            let current = String::from_utf8(current).context("Failed to read file")?;
+            let message = recover_prompt(
+                target_path,
+                name,
+                &context.timestamp(entry.created_at),
+                Some(&current),
+                &body,
+            );
+            if !confirm.confirm(&message)? {
+                progress.emit(progress::Event::RecoverAborted {
+                    target_path: target_path.to_path_buf(),
+                });
+                return Ok(());
+            }
+        }
+        let backed_up_at = chrono::Utc::now();
+        let backup_name = backup_name(
+            target_path,
+            backed_up_at,
+            &context.timezone,
+            ulid::Ulid::new(),
+        );
+        if !options.no_backup && !options.dry_run {
+            archive
+                .push(target_path, backed_up_at, &backup_name, None)
+                .await
+                .context("Failed to push archive for backup")?;
+        }
+        progress.emit(progress::Event::RecoverBackedUp {
+            target_path: target_path.to_path_buf(),
+            name: (!options.no_backup).then_some(backup_name),
+            dry_run: options.dry_run,
+        });
+    } else if options.confirm_always && !options.dry_run {
+        let message = recover_prompt(
+            target_path,
+            name,
+            &context.timestamp(entry.created_at),
+            None,
+            &body,
+        );
+        if !confirm.confirm(&message)? {
+            progress.emit(progress::Event::RecoverAborted {
+                target_path: target_path.to_path_buf(),
+            });
+            return Ok(());
+        }
+    }
+
+    let written_path = if options.dry_run {
+        target_path.to_path_buf()
+    } else {
+        std::fs::write(target_path, body).context("Failed to write file")?;
+        std::fs::canonicalize(target_path).context("Failed to resolve path")?
+    };
+    progress.emit(progress::Event::RecoverWritten {
+        target_path: written_path,
+        name: name.to_string(),
+        dry_run: options.dry_run,
+    });
+    if let Some(merge) = merge {
+        emit_key_changes(progress, merge);
+    }
+    Ok(())
+}
+
+fn emit_key_changes(progress: &mut dyn progress::ProgressSink, merge: dotenv::Merge) {
+    progress.emit(progress::Event::RecoverKeysMerged {
+        updated: merge.updated,
+        appended: merge.appended,
+        unchanged: merge.unchanged,
+    });
+}
+
+/// crawl が登録するファイルの大きさの上限の既定値 (1 MiB)
+const DEFAULT_MAX_SIZE: u64 = 1 << 20;
+
+/// crawl が並行して読み込むファイルの数の既定値
+const DEFAULT_CRAWL_JOBS: std::num::NonZeroUsize = std::num::NonZeroUsize::new(8).unwrap();
+
+#[derive(Debug)]
+struct CrawlOptions {
+    dry_run: bool,
+    strict_permissions: bool,
+    jobs: std::num::NonZeroUsize,
+    /// これを超える大きさのファイルは読み込まない
+    max_size: u64,
+    /// 空のファイルも登録する
+    include_empty: bool,
+    search: helper::SearchOptions,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            strict_permissions: false,
+            jobs: DEFAULT_CRAWL_JOBS,
+            max_size: DEFAULT_MAX_SIZE,
+            include_empty: false,
+            search: helper::SearchOptions::default(),
+        }
+    }
+}
+
+async fn crawl(
+    context: &Context,
+    dir: &Path,
+    options: &CrawlOptions,
+    progress: &mut dyn progress::ProgressSink,
+) -> anyhow::Result<()> {
+    let search =
+        helper::search_env_files(dir, &options.search).context("Failed to search env files")?;
+
+    // --dry-run ではデータベースを作成しない
+    let archive = if options.dry_run {
+        context.archive()?
+    } else {
+        context.archive_or_initialize()?
+    };
+    let files = search.files.len();
+    // アーカイブのデータベース自体が .env.* に一致しても登録しない
+    let database = std::fs::canonicalize(&context.database).context("Failed to open archive")?;
+    let (database_files, env_files): (Vec<_>, Vec<_>) = search
+        .files
+        .into_iter()
+        .partition(|file| is_database_file(&database, file));
+    for file in database_files {
+        progress.emit(progress::Event::CrawlFileResult {
+            path: file,
+            status: progress::CrawlStatus::ArchiveDatabase,
+            name: None,
+        });
+    }
+    // 読み込みとチェックサムの計算は並行して行い、アーカイブとの比較と登録はパスの順に 1 件ずつ行う
+    let mut bodies = futures::stream::iter(env_files)
+        .map(|file| async move {
+            let body = read_crawled_file(&file, options.max_size).await;
+            (file, body)
+        })
+        .buffered(options.jobs.get());
+    // 登録するファイルは最後に 1 つのトランザクションでまとめて登録する
+    let mut requests = Vec::new();
+    let mut unchanged = 0;
+    let mut would_push = 0;
+    let mut too_large = 0;
+    let mut empty = 0;
+    let mut failed = 0;
+    let mut blocked = 0;
+    while let Some((file, body)) = bodies.next().await {
+        progress.emit(progress::Event::CrawlFileStarted { path: file.clone() });
+        // 読み込めないファイルがあっても他のファイルの登録は続ける
+        let (body, checksum) = match body {
+            Ok(Some(body)) => body,
+            Ok(None) => {
+                too_large += 1;
+                progress.emit(progress::Event::CrawlFileResult {
+                    path: file,
+                    status: progress::CrawlStatus::TooLarge,
+                    name: None,
+                });
+                continue;
+            }
+            Err(e) => {
+                failed += 1;
+                progress.emit(progress::Event::CrawlFileFailed {
+                    path: file,
+                    message: format!("{:#}", e),
+                });
+                continue;
+            }
+        };
+        let status = if body.is_empty() && !options.include_empty {
+            empty += 1;
+            progress::CrawlStatus::Empty
+        } else if archive
+            .latest_checksum(&file)
+            .context("Failed to check body")?
+            .is_some_and(|latest| latest == checksum)
+        {
+            unchanged += 1;
+            progress::CrawlStatus::Unchanged
+        } else if blocked_by_policies(context, &file, &body, progress) {
+            blocked += 1;
+            continue;
+        } else if options.dry_run {
+            would_push += 1;
+            progress::CrawlStatus::DryRun
+        } else {
+            requests.push(archive::PushRequest {
+                path: file,
+                body,
+                checksum,
+                name: ulid::Ulid::new().to_string(),
+                created_at: chrono::Utc::now(),
+            });
+            continue;
+        };
+        progress.emit(progress::Event::CrawlFileResult {
+            path: file,
+            status,
+            name: None,
+        });
+    }
+    let pushed = requests
+        .iter()
+        .map(|request| (request.path.clone(), request.name.clone()))
+        .collect::<Vec<_>>();
+    archive
+        .push_many(requests)
+        .context("Failed to push archive")?;
+    let pushed_count = pushed.len() + would_push;
+    for (path, name) in pushed {
+        progress.emit(progress::Event::CrawlFileResult {
+            path,
+            status: progress::CrawlStatus::Pushed,
+            name: Some(name),
+        });
+    }
+
+    for skipped in &search.skipped {
+        progress.emit(progress::Event::CrawlPathSkipped {
+            path: skipped.path.clone(),
+            permission_denied: skipped.is_permission_denied(),
+            message: skipped.message.clone(),
+        });
+    }
+    progress.emit(progress::Event::CrawlFinished {
+        files,
+        pushed: pushed_count,
+        unchanged,
+        too_large,
+        empty,
+        failed,
+        dry_run: options.dry_run,
+        permission_denied: permission_denied_count(&search.skipped),
+    });
+    if failed > 0 {
+        anyhow::bail!("failed to read {} file(s)", failed);
+    }
+    if blocked > 0 {
+        anyhow::bail!(
+            "push of {} file(s) was blocked by policy violations",
+            blocked
+        );
+    }
+    if let Some(summary) = permission_summary(&search.skipped) {
+        if options.strict_permissions {
+            anyhow::bail!("{} (--strict-permissions)", summary);
+        }
+    }
+    Ok(())
+}
+
+/// file がアーカイブのデータベース、またはその WAL などの一時ファイルかどうか
+/// database は正規化したデータベースのパス
+fn is_database_file(database: &Path, file: &Path) -> bool {
+    let Ok(file) = std::fs::canonicalize(file) else {
+        return false;
+    };
+    file == database
+        || SQLITE_SIDECARS.iter().any(|suffix| {
+            let mut sidecar = database.as_os_str().to_owned();
+            sidecar.push(suffix);
+            file == Path::new(&sidecar)
+        })
+}
+
+/// crawl で見つけたファイルを読み込み、チェックサムを計算する
+/// max_size を超える大きさのファイルは読み込まずに None を返す
+/// 通常のファイルではない場合はエラーにする
+async fn read_crawled_file(path: &Path, max_size: u64) -> anyhow::Result<Option<(String, String)>> {
+    helper::ensure_regular_file(path)?;
+    if helper::oversized(path, max_size)?.is_some() {
+        return Ok(None);
+    }
+    let body = tokio::fs::read_to_string(path).await?;
+    let checksum = digest::bytes_checksum(body.as_bytes());
+    Ok(Some((body, checksum)))
+}
+
+fn permission_denied_count(skipped: &[helper::SkippedPath]) -> usize {
+    skipped.iter().filter(|s| s.is_permission_denied()).count()
+}
+
+/// 権限がなくスキップしたディレクトリの件数を要約する
+/// スキップしたものがなければ None を返す
+fn permission_summary(skipped: &[helper::SkippedPath]) -> Option<String> {
+    progress::permission_summary(permission_denied_count(skipped))
+}
+
+/// search でファイルパスを絞り込む条件
+/// keywords のすべてに一致し、excludes のいずれにも一致しないパスに限る
+#[derive(Debug)]
+enum PathQuery {
+    /// 部分一致で探す
+    /// ignore_case の場合は大文字と小文字を区別しない
+    Plain {
+        keywords: Vec<String>,
+        excludes: Vec<String>,
+        ignore_case: bool,
+    },
+    /// 正規表現で探す
+    Regex {
+        keywords: Vec<regex::Regex>,
+        excludes: Vec<regex::Regex>,
+    },
+}
+
+/// patterns をファイルパスに一致させる正規表現として解釈する
+fn path_regexes(patterns: &[String], ignore_case: bool) -> anyhow::Result<Vec<regex::Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            regex::RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|e| anyhow::anyhow!("invalid regex {:?}: {}", pattern, e))
+        })
+        .collect()
+}
+
+/// データベースがない、または初期化されていない
+#[derive(Debug)]
+struct NotInitialized(PathBuf);
+
+impl std::fmt::Display for NotInitialized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "archive database not initialized at {}; run `env-archive init`",
+            self.0.display()
+        )
+    }
+}
+
+impl std::error::Error for NotInitialized {}
+
+/// コールドストレージの本文を読み出せなかったアーカイブを数え、残りを処理した後でまとめてエラーにする
+#[derive(Debug, Default)]
+struct UnavailableBodies {
+    count: u64,
+    first: Option<cold::ColdBodyUnavailable>,
+}
+
+impl UnavailableBodies {
+    fn push(&mut self, error: cold::ColdBodyUnavailable) {
+        self.count += 1;
+        self.first.get_or_insert(error);
+    }
+
+    /// 読み出せなかったアーカイブがあれば、終了コード 5 で終了するエラーを返す
+    fn into_result(self) -> anyhow::Result<()> {
+        match self.first {
+            None => Ok(()),
+            Some(error) => Err(anyhow::Error::new(error).context(format!(
+                "{} archive(s) in cold storage could not be read",
+                self.count
+            ))),
+        }
+    }
+}
+
+/// search で一致するアーカイブがなかった
+#[derive(Debug)]
+struct NoMatches;
+
+impl std::fmt::Display for NoMatches {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no matches")
+    }
+}
+
+impl std::error::Error for NoMatches {}
+
+/// query に一致するアーカイブを表示する
+/// latest の場合はパスごとに最新のアーカイブだけを表示する
+/// page の順に並べたうち範囲内のものを表示し、表示するものがなければ NoMatches を返す
+async fn search(
+    context: &Context,
+    query: &PathQuery,
+    latest: bool,
+    filter: &archive::EntryFilter,
+    page: &archive::Page,
+    format: output::EntryFormat,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    fn words(words: &[String]) -> Vec<&str> {
+        words.iter().map(String::as_str).collect()
+    }
+    let archives = match query {
+        PathQuery::Plain {
+            keywords,
+            excludes,
+            ignore_case: true,
+        } => {
+            archive
+                .search_ignore_case(&words(keywords), &words(excludes), latest, filter, page)
+                .await
+        }
+        PathQuery::Plain {
+            keywords, excludes, ..
+        } if latest => {
+            archive
+                .search_latest(&words(keywords), &words(excludes), filter, page)
+                .await
+        }
+        PathQuery::Plain {
+            keywords, excludes, ..
+        } => {
+            archive
+                .search_keywords(&words(keywords), &words(excludes), filter, page)
+                .await
+        }
+        PathQuery::Regex { keywords, excludes } => {
+            archive
+                .search_regex(keywords, excludes, latest, filter, page)
+                .await
+        }
+    }
+    .context("Failed to search archive")?;
+    if archives.is_empty() {
+        return Err(NoMatches.into());
+    }
+    print_entries(context, archives, format)
+}
+
+/// 本文が pattern に一致するアーカイブと、一致した行を表示する
+/// 端末に表示する場合は一致した箇所を強調する
+async fn grep(
+    context: &Context,
+    pattern: &grep::Pattern,
+    latest: bool,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<()> {
+    let archive = context.archive()?;
+    let color = !context.private && std::io::stdout().is_terminal();
+    if context.private {
+        println!("{}", redact::PRIVATE_MODE_HEADER);
+    }
+    let mut unavailable = UnavailableBodies::default();
+    archive
+        .for_each_body_like(pattern.like().as_deref(), latest, filter, |entry, body| {
+            let body = match body {
+                Ok(body) => body,
+                Err(error) => {
+                    eprintln!("warning: {}", error);
+                    unavailable.push(error);
+                    return Ok(());
+                }
+            };
+            let matches = pattern.matches(&body);
+            if matches.is_empty() {
+                return Ok(());
+            }
+            let path = if context.private {
+                redact::redact_path(&entry.path)
+            } else {
+                entry.path
+            };
+            println!(
+                "{} {} {}",
+                entry.name,
+                path,
+                context.timestamp(entry.created_at)
+            );
+            for line_match in matches {
+                let line = if context.private {
+                    redact::redact_body(&line_match.line)
+                } else if color {
+                    grep::highlight(&line_match)
+                } else {
+                    line_match.line
+                };
+                println!("  {}: {}", line_match.number, line);
+            }
+            Ok(())
+        })
+        .await
+        .context("Failed to search archive")?;
+    unavailable.into_result()
+}
+
+/// which-key, which-value で探す変数
+#[derive(Debug)]
+enum VariableQuery {
+    /// キーの名前が一致する変数
+    /// prefix の場合は key で始まるキーをすべて探す
+    Key { key: String, prefix: bool },
+    /// 値に文字列を含む変数
+    Value(String),
+}
+
+impl VariableQuery {
+    /// キーの名前、または値から探す文字列
+    fn needle(&self) -> &str {
+        match self {
+            VariableQuery::Key { key, .. } => key,
+            VariableQuery::Value(value) => value,
+        }
+    }
+
+    /// body で定義されている変数のうち、探しているものをキーと値の組で返す
+    fn find(&self, body: &str) -> Vec<(String, String)> {
+        dotenv::values(body)
+            .into_iter()
+            .filter(|(key, value)| match self {
+                VariableQuery::Key { key: query, prefix } => {
+                    if *prefix {
+                        key.starts_with(query.as_str())
+                    } else {
+                        key == query
+                    }
+                }
+                VariableQuery::Value(query) => value.contains(query.as_str()),
+            })
+            .collect()
+    }
+}
+
+/// query の変数を定義しているアーカイブと、その変数のキーを表示する
+/// show_values の場合はキーの値も表示する
+/// 最後に見つかったアーカイブとパスの件数を表示する
+async fn find_variables(
+    context: &Context,
+    query: &VariableQuery,
+    latest: bool,
+    show_values: bool,
+    filter: &archive::EntryFilter,
+) -> anyhow::Result<()> {
+    if query.needle().is_empty() {
+        anyhow::bail!("search string must not be empty");
+    }
+    let archive = context.archive()?;
+    if context.private {
+        println!("{}", redact::PRIVATE_MODE_HEADER);
+    }
+    // 探す文字列を含まない本文はデータベースで除いてから解析する
+    let like = grep::Pattern::Plain(query.needle().to_string()).like();
+    let mut archives = 0;
+    let mut paths = std::collections::HashSet::new();
+    let mut unavailable = UnavailableBodies::default();
+    archive
+        .for_each_body_like(like.as_deref(), latest, filter, |entry, body| {
+            let body = match body {
+                Ok(body) => body,
+                Err(error) => {
+                    eprintln!("warning: {}", error);
+                    unavailable.push(error);
+                    return Ok(());
+                }
+            };
+            let found = query.find(&body);
+            if found.is_empty() {
+                return Ok(());
+            }
+            archives += 1;
+            paths.insert(entry.path.clone());
+            let path = if context.private {
+                redact::redact_path(&entry.path)
+            } else {
+                entry.path
+            };
+            println!(
+                "{} {} {}",
+                entry.name,
+                path,
+                context.timestamp(entry.created_at)
+            );
+            for (key, value) in found {
+                match (show_values, context.private) {
+                    (false, _) => println!("  {}", key),
+                    (true, true) => println!("  {}={}", key, redact::MASK),
+                    (true, false) => println!("  {}={}", key, value),
+                }
+            }
+            Ok(())
+        })
+        .await
+        .context("Failed to search archive")?;
+    println!("found {} archive(s) in {} path(s)", archives, paths.len());
+    unavailable.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_context(database: PathBuf) -> Context {
+        Context {
+            database,
+            now: chrono::Utc::now(),
+            timezone: chrono_tz::Asia::Tokyo,
+            width: None,
+            absolute_time: false,
+            utc: false,
+            private: false,
+            db_timeout: std::time::Duration::from_secs(30),
+            user: Some("alice".to_string()),
+            visibility: Visibility::Own,
+            policies: policy::Policies::default(),
+        }
+    }
+
+    #[test]
+    fn recover_target_アーカイブ元がカレントディレクトリの場合はin_placeと同じ書き込み先になる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let entry_path = tmp_dir.path().join(".env");
+        let entry_path = entry_path.to_string_lossy();
+
+        let default_target = recover_target(&entry_path, None, false, false, tmp_dir.path());
+        let in_place_target = recover_target(&entry_path, None, true, false, tmp_dir.path());
+        assert_eq!(default_target, in_place_target);
+        assert_eq!(
+            in_place_target,
+            RecoverTarget::File {
+                path: tmp_dir.path().join(".env"),
+                create_dirs: false,
+            }
+        );
+    }
+
+    #[test]
+    fn recover_target_in_placeはカレントディレクトリにかかわらずアーカイブ元を書き込み先にする() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let entry_path = tmp_dir.path().join("project").join(".env");
+
+        let target = recover_target(
+            &entry_path.to_string_lossy(),
+            None,
+            true,
+            true,
+            &tmp_dir.path().join("elsewhere"),
+        );
+        assert_eq!(
+            target,
+            RecoverTarget::File {
+                path: entry_path,
+                create_dirs: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_in_placeで復元先のディレクトリが存在しない場合はcreate_dirsがなければ失敗する()
+    {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let project_dir = tmp_dir.path().join("project");
+        let env_file_path = project_dir.join(".env");
+        std::fs::create_dir(&project_dir).unwrap();
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        archive
+            .push(&env_file_path, context.now, "test-name", None)
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&project_dir).unwrap();
+
+        let options = RecoverOptions {
+            in_place: true,
+            ..Default::default()
+        };
+        let result = recover(
+            &context,
+            "test-name",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(!project_dir.exists());
+
+        let options = RecoverOptions {
+            in_place: true,
+            create_dirs: true,
+            ..Default::default()
+        };
+        recover(
+            &context,
+            "test-name",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&env_file_path).unwrap(), "FOO=BAR");
+    }
+
+    #[tokio::test]
+    async fn recover_dry_runではファイルもアーカイブも変更されない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        archive
+            .push(&env_file_path, context.now, "test-name", None)
+            .await
+            .unwrap();
+        std::fs::write(&env_file_path, "FOO=CHANGED").unwrap();
+        let modified = std::fs::metadata(&env_file_path)
+            .unwrap()
+            .modified()
+            .unwrap();
+
+        let options = RecoverOptions {
+            in_place: true,
+            dry_run: true,
+            ..Default::default()
+        };
+        recover(
+            &context,
+            "test-name",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::metadata(&env_file_path)
+                .unwrap()
+                .modified()
+                .unwrap(),
+            modified
+        );
+        assert_eq!(
+            std::fs::read_to_string(&env_file_path).unwrap(),
+            "FOO=CHANGED"
+        );
+        assert_eq!(
+            archive
+                .list_all(&archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn backup_nameはファイル名と日時を含む() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2024, 6, 1, 3, 3, 0).unwrap();
+        let ulid = ulid::Ulid::from_string("01HZ0000000000000000000000").unwrap();
+        assert_eq!(
+            backup_name(
+                Path::new("/project/.env"),
+                now,
+                &chrono_tz::Asia::Tokyo,
+                ulid
+            ),
+            "backup.env.20240601T1203.01HZ0000000000000000000000"
+        );
+        assert_eq!(
+            backup_name(Path::new(".env.local"), now, &chrono_tz::UTC, ulid),
+            "backup.env.local.20240601T0303.01HZ0000000000000000000000"
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_no_backupではバックアップが登録されない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        archive
+            .push(
+                &env_file_path,
+                context.now - chrono::Duration::minutes(1),
+                "test-name",
+                None,
+            )
+            .await
+            .unwrap();
+        std::fs::write(&env_file_path, "FOO=CHANGED").unwrap();
+
+        let options = RecoverOptions {
+            in_place: true,
+            no_backup: true,
+            ..Default::default()
+        };
+        recover(
+            &context,
+            "test-name",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&env_file_path).unwrap(), "FOO=BAR");
+        assert_eq!(
+            archive
+                .list_all(&archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        std::fs::write(&env_file_path, "FOO=CHANGED").unwrap();
+        let options = RecoverOptions {
+            in_place: true,
+            ..Default::default()
+        };
+        recover(
+            &context,
+            "test-name",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+
+        let archives = archive
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 2);
+        assert!(archives[0].name.starts_with("backup.env."));
+    }
+
+    #[tokio::test]
+    async fn recoverで上書きを了承しなかった場合はファイルもアーカイブも変更されない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        archive
+            .push(&env_file_path, context.now, "test-name", None)
+            .await
+            .unwrap();
+        std::fs::write(&env_file_path, "FOO=CHANGED").unwrap();
+
+        let options = RecoverOptions {
+            in_place: true,
+            ..Default::default()
+        };
+        let mut prompt = Vec::new();
+        let mut confirm = confirm::PromptConfirm::new("n\n".as_bytes(), &mut prompt);
+        recover(
+            &context,
+            "test-name",
+            &options,
+            &mut confirm,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+
+        let prompt = String::from_utf8(prompt).unwrap();
+        assert!(prompt.contains(&env_file_path.display().to_string()));
+        assert!(prompt.contains("(+1 -1 lines)"));
+        assert_eq!(
+            std::fs::read_to_string(&env_file_path).unwrap(),
+            "FOO=CHANGED"
+        );
+        assert_eq!(
+            archive
+                .list_all(&archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn recoverのconfirm_alwaysでは復元先がなくても書き込む前に確認する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=BAR\nBAZ=1\n").unwrap();
+        archive
+            .push(&env_file_path, context.now, "test-name", None)
+            .await
+            .unwrap();
+        std::fs::remove_file(&env_file_path).unwrap();
+
+        let options = RecoverOptions {
+            in_place: true,
+            confirm_always: true,
+            ..Default::default()
+        };
+        for (answer, written) in [("n\n", false), ("y\n", true)] {
+            let mut prompt = Vec::new();
+            let mut confirm = confirm::PromptConfirm::new(answer.as_bytes(), &mut prompt);
+            recover(
+                &context,
+                "test-name",
+                &options,
+                &mut confirm,
+                &mut progress::PrintProgress::default(),
+            )
+            .await
+            .unwrap();
+            let prompt = String::from_utf8(prompt).unwrap();
+            assert!(prompt.starts_with(&format!(
+                "create {} from test-name",
+                env_file_path.display()
+            )));
+            assert!(prompt.contains("(2 lines)"));
+            assert_eq!(env_file_path.exists(), written);
+        }
+    }
+
+    /// 編集した一時ファイルのパスと権限を dir に記録し、script を実行するエディタ
+    fn script_editor(dir: &Path, script: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let editor = dir.join("editor.sh");
+        std::fs::write(
+            &editor,
+            format!(
+                "#!/bin/sh\necho \"$1\" > {dir}/edited_path\nstat -c %a \"$1\" > {dir}/mode\n{script}\n",
+                dir = dir.display(),
+                script = script
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+        editor.display().to_string()
+    }
+
+    #[tokio::test]
+    async fn editは変更した本文を同じパスの新しいアーカイブとして登録する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        archive
+            .push_body(
+                Path::new("/srv/api/.env"),
+                "FOO=1\nBAR=2\n",
+                context.now,
+                "original",
+                None,
+            )
+            .await
+            .unwrap();
+
+        let editor = script_editor(tmp_dir.path(), "sed -i s/FOO=1/FOO=9/ \"$1\"");
+        edit(
+            &context,
+            "original",
+            Some("edited".to_string()),
+            Some("bump FOO"),
+            false,
+            &editor,
+        )
+        .await
+        .unwrap();
+        let (entry, body) = archive.get("edited").await.unwrap().unwrap();
+        assert_eq!(
+            (entry.path.as_str(), body.as_str(), entry.message.as_deref()),
+            ("/srv/api/.env", "FOO=9\nBAR=2\n", Some("bump FOO"))
+        );
+        let mode = std::fs::read_to_string(tmp_dir.path().join("mode")).unwrap();
+        assert_eq!(mode.trim(), "600");
+
+        // 変更しなかった場合は登録しない
+        let editor = script_editor(tmp_dir.path(), "true");
+        edit(&context, "original", None, None, false, &editor)
+            .await
+            .unwrap();
+        assert_eq!(
+            archive
+                .list_all(&archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn edit_bodyはエディタが失敗しても一時ファイルを削除する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let editor = script_editor(tmp_dir.path(), "exit 3");
+        let error = edit_body(&editor, "FOO=1\n").unwrap_err();
+        assert!(error.to_string().contains("nothing archived"));
+
+        let edited_path = std::fs::read_to_string(tmp_dir.path().join("edited_path")).unwrap();
+        assert!(!Path::new(edited_path.trim()).exists());
+        assert!(edit_body("", "FOO=1\n").is_err());
+    }
+
+    #[test]
+    fn recover_promptは復元先の有無で文言を変える() {
+        let path = Path::new("/srv/api/.env");
+        assert_eq!(
+            recover_prompt(
+                path,
+                "n1",
+                "2024-01-02T03:04:05Z",
+                Some("A=1\n"),
+                "A=2\nB=3\n"
+            ),
+            "overwrite /srv/api/.env with n1 created at 2024-01-02T03:04:05Z (+2 -1 lines)?"
+        );
+        assert_eq!(
+            recover_prompt(path, "n1", "2024-01-02T03:04:05Z", None, "A=2\nB=3\n"),
+            "create /srv/api/.env from n1 created at 2024-01-02T03:04:05Z (2 lines)?"
+        );
+    }
+
+    #[test]
+    fn pick_candidatesはパスを伏せて制御文字を置き換える() {
+        let entry = |name: &str, path: &str| archive::ArchiveEntry {
+            name: name.to_string(),
+            path: path.to_string(),
+            created_at: chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2024, 1, 2, 3, 4, 5)
+                .unwrap(),
+            checksum: String::new(),
+            message: None,
+            user: None,
+            quarantined: false,
+            size: 0,
+        };
+        let archives = vec![entry("a", "/srv/api/.env"), entry("b", "/srv/we\x1bb/.env")];
+        let candidates = pick_candidates(archives.clone(), &output::TimeFormat::Utc, false);
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|c| (c.name.as_str(), c.label.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("a", "a  /srv/api/.env  2024-01-02T03:04:05Z"),
+                ("b", "b  /srv/we?b/.env  2024-01-02T03:04:05Z")
+            ]
+        );
+        let private = pick_candidates(archives, &output::TimeFormat::Utc, true);
+        assert!(!private[0].label.contains("/srv/api"));
+
+        let args =
+            Args::try_parse_from(["env-archive", "recover", "--interactive", "api"]).unwrap();
+        let SubCommands::Recover {
+            name, interactive, ..
+        } = args.subcommand
+        else {
+            panic!("unexpected subcommand");
+        };
+        assert_eq!((name.as_deref(), interactive), (Some("api"), true));
+        assert!(Args::try_parse_from(["env-archive", "recover", "--interactive"]).is_ok());
+        assert!(
+            Args::try_parse_from(["env-archive", "recover", "--interactive", "--yes"]).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_recover_nameはパスに一致する最新のアーカイブの名前を返す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = std::fs::canonicalize(tmp_dir.path())
+            .unwrap()
+            .join("project")
+            .join(".env");
+        std::fs::create_dir(env_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        archive
+            .push(
+                &env_file_path,
+                context.now - chrono::Duration::minutes(1),
+                "old",
+                None,
+            )
+            .await
+            .unwrap();
+        archive
+            .push(&env_file_path, context.now, "new", None)
+            .await
+            .unwrap();
+
+        let path = env_file_path.to_string_lossy();
+        let name = resolve_recover_name(
+            &context,
+            None,
+            Some(&path),
+            None,
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(name, "new");
+        let name = resolve_recover_name(
+            &context,
+            Some(&path),
+            None,
+            None,
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(name, "new");
+        let name = resolve_recover_name(
+            &context,
+            Some("old"),
+            None,
+            None,
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(name, "old");
+    }
+
+    #[tokio::test]
+    async fn resolve_recover_nameはパスに一致するアーカイブがない場合に近いパスを提示する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = tmp_dir.path().join("project").join(".env");
+        std::fs::create_dir(env_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        archive
+            .push(&env_file_path, context.now, "test-name", None)
+            .await
+            .unwrap();
+
+        let missing = tmp_dir.path().join("moved").join("project").join(".env");
+        let error = resolve_recover_name(
+            &context,
+            None,
+            Some(&missing.to_string_lossy()),
+            None,
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("no archive found"));
+        assert!(error.contains(&env_file_path.to_string_lossy().to_string()));
+    }
+
+    #[tokio::test]
+    async fn resolve_show_nameはパスのアーカイブのうち新しい方からnth番目の名前を返す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = tmp_dir.path().join("project").join(".env");
+        std::fs::create_dir(env_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        for (i, name) in ["oldest", "older", "newest"].iter().enumerate() {
+            archive
+                .push(
+                    &env_file_path,
+                    context.now + chrono::Duration::seconds(i as i64),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        archive.set_quarantined("older", true).await.unwrap();
+        let path = env_file_path.to_string_lossy();
+        let nth = |n| std::num::NonZeroUsize::new(n).unwrap();
+
+        assert_eq!(
+            resolve_show_name(&context, &path, nth(1), false, false)
+                .await
+                .unwrap(),
+            "newest"
+        );
+        // 隔離されているアーカイブは数えない
+        assert_eq!(
+            resolve_show_name(&context, &path, nth(2), false, false)
+                .await
+                .unwrap(),
+            "oldest"
+        );
+        assert_eq!(
+            resolve_show_name(&context, &path, nth(2), false, true)
+                .await
+                .unwrap(),
+            "older"
+        );
+        let error = resolve_show_name(&context, &path, nth(3), false, false)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.ends_with("has only 2 archive(s), but --nth 3 was given"));
+
+        let missing = tmp_dir.path().join("moved").join("project").join(".env");
+        let error = resolve_show_name(&context, &missing.to_string_lossy(), nth(1), false, false)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no archive found"));
+        assert!(error.contains(&format!("did you mean:\n  {}", path)));
+    }
+
+    #[tokio::test]
+    async fn similar_pathsは交互に登録したパスも1度ずつ新しい順に提示する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let archive = context.archive().unwrap();
+        let q = tmp_dir.path().join("q/.env");
+        let r = tmp_dir.path().join("r/.env");
+        for (i, path) in [&q, &r, &q, &r].into_iter().enumerate() {
+            archive
+                .push_body(
+                    path,
+                    &format!("A={}", i),
+                    context.now + chrono::Duration::minutes(i as i64),
+                    &format!("v{}", i),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let suggestions = similar_paths(
+            &archive,
+            &tmp_dir.path().join("zz/.env"),
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(suggestions, [r.to_string_lossy(), q.to_string_lossy()]);
+    }
+
+    #[tokio::test]
+    async fn resolve_recover_nameでbeforeより前のアーカイブがない場合は利用できる日時を提示する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = std::fs::canonicalize(tmp_dir.path()).unwrap().join(".env");
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        archive
+            .push(&env_file_path, context.now, "test-name", None)
+            .await
+            .unwrap();
+
+        let path = env_file_path.to_string_lossy();
+        let before = context.now - chrono::Duration::days(1);
+        let error = resolve_recover_name(
+            &context,
+            None,
+            Some(&path),
+            Some(before),
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("available:"));
+        assert!(error.contains("test-name"));
+
+        let before = context.now + chrono::Duration::days(1);
+        let name = resolve_recover_name(
+            &context,
+            None,
+            Some(&path),
+            Some(before),
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(name, "test-name");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn crawlは読み取れないディレクトリがあっても続行しstrict_permissionsではエラーになる() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let project_dir = tmp_dir.path().join("project");
+        let locked_dir = project_dir.join("locked");
+        std::fs::create_dir_all(&locked_dir).unwrap();
+        std::fs::write(project_dir.join(".env"), "FOO=BAR").unwrap();
+        std::fs::set_permissions(&locked_dir, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // root 権限では権限に関係なく読めてしまうため検証できない
+        if std::fs::read_dir(&locked_dir).is_ok() {
+            std::fs::set_permissions(&locked_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+
+        let lenient = crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut progress::PrintProgress::default(),
+        )
+        .await;
+        let strict = crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions {
+                strict_permissions: true,
+                ..Default::default()
+            },
+            &mut progress::PrintProgress::default(),
+        )
+        .await;
+        std::fs::set_permissions(&locked_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(lenient.is_ok());
+        assert_eq!(
+            archive
+                .list_all(&archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        let error = strict.unwrap_err().to_string();
+        assert!(error.contains("skipped 1 subtree(s) due to insufficient permissions"));
+    }
+
+    #[tokio::test]
+    async fn verifyはチェックサムが書き換えられたアーカイブを壊れているとする() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        archive
+            .push_body(
+                &tmp_dir.path().join(".env"),
+                "FOO=BAR\n",
+                context.now,
+                "intact",
+                None,
+            )
+            .await
+            .unwrap();
+        archive
+            .push_body(
+                &tmp_dir.path().join(".env.local"),
+                "FOO=BAZ\n",
+                context.now,
+                "tampered",
+                None,
+            )
+            .await
+            .unwrap();
+        rusqlite::Connection::open(&context.database)
+            .unwrap()
+            .execute(
+                "UPDATE archives SET checksum = ?1 WHERE name = 'tampered'",
+                [digest::bytes_checksum(b"FOO=QUX\n")],
+            )
+            .unwrap();
+
+        verify(&context, "intact", false).await.unwrap();
+        let error = verify(&context, "tampered", false)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "archive tampered is corrupt and has been quarantined"
+        );
+        assert!(verify(&context, "missing", false).await.is_err());
+        let (entry, _) = archive.get("tampered").await.unwrap().unwrap();
+        assert!(entry.quarantined);
+
+        // 壊れたままでは --repair でも隔離は解除されない
+        assert!(verify(&context, "tampered", true).await.is_err());
+        rusqlite::Connection::open(&context.database)
+            .unwrap()
+            .execute(
+                "UPDATE archives SET checksum = ?1 WHERE name = 'tampered'",
+                [digest::bytes_checksum(b"FOO=BAZ\n")],
+            )
+            .unwrap();
+        verify(&context, "tampered", false).await.unwrap();
+        let (entry, _) = archive.get("tampered").await.unwrap().unwrap();
+        assert!(entry.quarantined);
+        verify(&context, "tampered", true).await.unwrap();
+        let (entry, _) = archive.get("tampered").await.unwrap().unwrap();
+        assert!(!entry.quarantined);
+    }
+
+    #[tokio::test]
+    async fn 最新のアーカイブが隔離されている場合はその前のアーカイブを復元する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        let env_path = tmp_dir.path().join(".env");
+        for (i, name) in ["oldest", "second", "latest"].into_iter().enumerate() {
+            archive
+                .push_body(
+                    &env_path,
+                    &format!("VERSION={}", i),
+                    context.now - chrono::Duration::days(3 - i as i64),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        archive.set_quarantined("latest", true).await.unwrap();
+
+        let name = resolve_recover_name(
+            &context,
+            None,
+            Some(env_path.to_str().unwrap()),
+            None,
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(name, "second");
+
+        let target = tmp_dir.path().join("recovered.env");
+        let options = RecoverOptions {
+            output: Some(target.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let error = recover(
+            &context,
+            "latest",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("use --include-quarantined"));
+        assert!(!target.exists());
+
+        let options = RecoverOptions {
+            include_quarantined: true,
+            ..options
+        };
+        recover(
+            &context,
+            "latest",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "VERSION=2");
+    }
+
+    #[tokio::test]
+    async fn verify_allは壊れたアーカイブを一覧にし了承されれば削除する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        for name in ["a", "b", "c"] {
+            archive
+                .push_body(
+                    &tmp_dir.path().join(name).join(".env"),
+                    &format!("NAME={}", name),
+                    context.now,
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        rusqlite::Connection::open(&context.database)
+            .unwrap()
+            .execute("UPDATE archives SET body = 'NAME=x' WHERE name = 'b'", [])
+            .unwrap();
+
+        let mut progress = CollectProgress::default();
+        let error = verify_all(
+            &context,
+            &VerifyAllOptions::default(),
+            &mut confirm::AssumeYes,
+            &mut progress,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "found 1 corrupt archive(s) and quarantined them"
+        );
+        assert_eq!(
+            progress.0,
+            vec![
+                progress::Event::VerifyStarted { total: 3 },
+                progress::Event::VerifyMismatch {
+                    name: "b".to_string(),
+                    path: tmp_dir.path().join("b/.env").to_string_lossy().to_string(),
+                    created_at: context.now,
+                },
+                progress::Event::VerifyFinished {
+                    checked: 3,
+                    corrupt: 1,
+                    unavailable: 0,
+                },
+            ]
+        );
+        assert!(archive.get("b").await.unwrap().unwrap().0.quarantined);
+
+        // 削除を了承しなかった場合は隔離したまま残す
+        let options = VerifyAllOptions {
+            delete_corrupt: true,
+            ..Default::default()
+        };
+        let mut decline = confirm::PromptConfirm::new("n\n".as_bytes(), Vec::new());
+        assert!(verify_all(
+            &context,
+            &options,
+            &mut decline,
+            &mut progress::PrintProgress::default()
+        )
+        .await
+        .is_err());
+        assert!(archive.get("b").await.unwrap().is_some());
+
+        let error = verify_all(
+            &context,
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "found 1 corrupt archive(s) and deleted them"
+        );
+        assert!(archive.get("b").await.unwrap().is_none());
+        verify_all(
+            &context,
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[derive(Default)]
+    struct CollectProgress(Vec<progress::Event>);
+
+    impl progress::ProgressSink for CollectProgress {
+        fn emit(&mut self, event: progress::Event) {
+            self.0.push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn crawlの進捗イベントはアーカイブへの登録と一致する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        let unchanged = project_dir.join("api/.env");
+        let added = project_dir.join("web/.env");
+        std::fs::create_dir_all(unchanged.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(added.parent().unwrap()).unwrap();
+        std::fs::write(&unchanged, "API=1").unwrap();
+        std::fs::write(&added, "WEB=1").unwrap();
+        archive
+            .push(&unchanged, context.now, "api", None)
+            .await
+            .unwrap();
+
+        let mut progress = CollectProgress::default();
+        crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut progress,
+        )
+        .await
+        .unwrap();
+
+        let events = progress.0;
+        assert_eq!(events.len(), 5);
+        // 登録したファイルの結果はまとめて登録した後に通知するため、開始と結果は隣り合うとは限らない
+        let mut results = Vec::new();
+        for (i, event) in events[..4].iter().enumerate() {
+            match event {
+                progress::Event::CrawlFileStarted { .. } => {}
+                progress::Event::CrawlFileResult { path, status, name } => {
+                    let started = progress::Event::CrawlFileStarted { path: path.clone() };
+                    assert!(events[..i].contains(&started));
+                    results.push((path.clone(), *status, name.clone()));
+                }
+                _ => panic!("unexpected event {:?}", event),
+            }
+        }
+        assert_eq!(results.len(), 2);
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(results[0].0, unchanged);
+        assert_eq!(results[0].1, progress::CrawlStatus::Unchanged);
+        assert_eq!(results[0].2, None);
+        assert_eq!(results[1].0, added);
+        assert_eq!(results[1].1, progress::CrawlStatus::Pushed);
+        assert_eq!(
+            events[4],
+            progress::Event::CrawlFinished {
+                files: 2,
+                pushed: 1,
+                unchanged: 1,
+                too_large: 0,
+                empty: 0,
+                failed: 0,
+                dry_run: false,
+                permission_denied: 0,
+            }
+        );
+
+        let pushed = archive
+            .find_by_path(&added, &archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(Some(pushed[0].name.clone()), results[1].2);
+        assert_eq!(
+            archive
+                .history(&unchanged, None, &archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn crawlは並行して読み込んでもファイルごとに結果を1つだけ通知する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        for i in 0..20 {
+            let dir = project_dir.join(format!("app{}", i));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(".env"), format!("APP={}", i)).unwrap();
+        }
+        let options = CrawlOptions {
+            jobs: std::num::NonZeroUsize::new(3).unwrap(),
+            ..Default::default()
+        };
+
+        for expected in [
+            progress::CrawlStatus::Pushed,
+            progress::CrawlStatus::Unchanged,
+        ] {
+            let mut progress = CollectProgress::default();
+            crawl(&context, &project_dir, &options, &mut progress)
+                .await
+                .unwrap();
+            let results = progress
+                .0
+                .iter()
+                .filter_map(|event| match event {
+                    progress::Event::CrawlFileResult { path, status, .. } => Some((path, status)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(results.len(), 20);
+            assert!(results.iter().all(|(_, status)| **status == expected));
+            let paths = results
+                .iter()
+                .map(|(path, _)| path)
+                .collect::<std::collections::HashSet<_>>();
+            assert_eq!(paths.len(), 20);
+        }
+        let archives = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 20);
+    }
+
+    #[tokio::test]
+    async fn crawlは読み込めないファイルがあっても他のファイルを登録しエラーで終わる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        std::fs::create_dir(&project_dir).unwrap();
+        std::fs::write(project_dir.join(".env"), "FOO=1").unwrap();
+        std::fs::write(project_dir.join(".env.binary"), [0xff, 0xfe]).unwrap();
+
+        let dry_run = CrawlOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut progress = CollectProgress::default();
+        let error = crawl(&context, &project_dir, &dry_run, &mut progress)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "failed to read 1 file(s)");
+        let Some(progress::Event::CrawlFinished {
+            pushed,
+            unchanged,
+            too_large,
+            empty,
+            failed,
+            dry_run,
+            ..
+        }) = progress.0.last().cloned()
+        else {
+            panic!("unexpected events {:?}", progress.0);
+        };
+        assert_eq!(
+            progress::crawl_summary(dry_run, pushed, unchanged, too_large, empty, failed),
+            "would push 1, skipped 0 (unchanged), failed 1"
+        );
+
+        let mut progress = CollectProgress::default();
+        assert!(crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut progress
+        )
+        .await
+        .is_err());
+        assert!(progress.0.contains(&progress::Event::CrawlFileFailed {
+            path: project_dir.join(".env.binary"),
+            message: "stream did not contain valid UTF-8".to_string(),
+        }));
+        let archives = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].path, project_dir.join(".env").to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn crawlは権限のないファイルやリンク切れがあっても他のファイルを登録する() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        std::fs::create_dir(&project_dir).unwrap();
+        std::fs::write(project_dir.join(".env"), "FOO=1").unwrap();
+        std::os::unix::fs::symlink(project_dir.join("missing"), project_dir.join(".env.link"))
+            .unwrap();
+        let locked = project_dir.join(".env.locked");
+        std::fs::write(&locked, "SECRET=1").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // root 権限では権限に関係なく読めてしまうため、リンク切れだけが失敗する
+        let failures = if std::fs::read(&locked).is_ok() { 1 } else { 2 };
+
+        let mut progress = CollectProgress::default();
+        let error = crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut progress,
+        )
+        .await
+        .unwrap_err();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            format!("failed to read {} file(s)", failures)
+        );
+        assert!(progress.0.iter().any(|event| matches!(
+            event,
+            progress::Event::CrawlFileFailed { path, .. } if *path == project_dir.join(".env.link")
+        )));
+        let archives = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert!(archives
+            .iter()
+            .any(|entry| entry.path == project_dir.join(".env").to_string_lossy()));
+        assert_eq!(archives.len(), 3 - failures);
+    }
+
+    #[tokio::test]
+    async fn crawlはシンボリックリンクのファイルをリンク先の内容でリンクのパスに登録する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let shared = tmp_dir.path().join("shared");
+        let project_dir = tmp_dir.path().join("project");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::create_dir(&project_dir).unwrap();
+        std::fs::write(shared.join(".env"), "SHARED=1").unwrap();
+        std::os::unix::fs::symlink(shared.join(".env"), project_dir.join(".env")).unwrap();
+
+        crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut CollectProgress::default(),
+        )
+        .await
+        .unwrap();
+
+        let archive = context.archive().unwrap();
+        let archives = archive
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].path, project_dir.join(".env").to_string_lossy());
+        let (_, body) = archive.get(&archives[0].name).await.unwrap().unwrap();
+        assert_eq!(body, "SHARED=1");
+    }
+
+    #[tokio::test]
+    async fn 上限を超える大きさのファイルはcrawlではスキップしpushではforceがなければエラーにする()
+    {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        std::fs::create_dir(&project_dir).unwrap();
+        let large = project_dir.join(".env.backup");
+        std::fs::write(&large, "x".repeat(1025)).unwrap();
+        std::fs::write(project_dir.join(".env"), "FOO=1").unwrap();
+
+        let options = CrawlOptions {
+            max_size: 1024,
+            ..Default::default()
+        };
+        let mut progress = CollectProgress::default();
+        crawl(&context, &project_dir, &options, &mut progress)
+            .await
+            .unwrap();
+        assert!(progress.0.contains(&progress::Event::CrawlFileResult {
+            path: large.clone(),
+            status: progress::CrawlStatus::TooLarge,
+            name: None,
+        }));
+        assert_eq!(
+            progress::crawl_summary(false, 1, 0, 1, 0, 0),
+            "pushed 1, skipped 0 (unchanged), skipped 1 (too large), failed 0"
+        );
+
+        let limited = PushOptions {
+            max_size: Some(1024),
+            ..Default::default()
+        };
+        let error = push(&context, &large, &limited).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("is too large (1025 bytes, limit 1024 bytes)"));
+        push(&context, &large, &PushOptions::default())
+            .await
+            .unwrap();
+
+        let archive = context.archive().unwrap();
+        assert_eq!(
+            archive
+                .find_by_path(&large, &archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn 空のファイルはallow_emptyやinclude_emptyがなければ登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        std::fs::create_dir(&project_dir).unwrap();
+        let empty = project_dir.join(".env.local");
+        std::fs::write(&empty, "").unwrap();
+        std::fs::write(project_dir.join(".env"), "FOO=1").unwrap();
+
+        let mut progress = CollectProgress::default();
+        crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut progress,
+        )
+        .await
+        .unwrap();
+        assert!(progress.0.contains(&progress::Event::CrawlFileResult {
+            path: empty.clone(),
+            status: progress::CrawlStatus::Empty,
+            name: None,
+        }));
+        assert_eq!(
+            progress::crawl_summary(false, 1, 0, 0, 1, 0),
+            "pushed 1, skipped 0 (unchanged), skipped 1 (empty), failed 0"
+        );
+        let error = push(&context, &empty, &PushOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("pass --allow-empty"));
+        let archive = context.archive().unwrap();
+        assert!(archive
+            .find_by_path(&empty, &archive::EntryFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let include_empty = CrawlOptions {
+            include_empty: true,
+            ..Default::default()
+        };
+        let mut progress = CollectProgress::default();
+        crawl(&context, &project_dir, &include_empty, &mut progress)
+            .await
+            .unwrap();
+        assert!(progress.0.contains(&progress::Event::CrawlFileResult {
+            path: empty.clone(),
+            status: progress::CrawlStatus::Pushed,
+            name: archive
+                .find_by_path(&empty, &archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .first()
+                .map(|entry| entry.name.clone()),
+        }));
+        let allow_empty = PushOptions {
+            allow_empty: true,
+            ..Default::default()
+        };
+        push(&context, &empty, &allow_empty).await.unwrap();
+        assert_eq!(
+            archive
+                .find_by_path(&empty, &archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn crawlはアーカイブのデータベース自体を登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database = tmp_dir.path().join(".env.archive");
+        let context = test_context(database.clone());
+        init(&context, false).await.unwrap();
+        std::fs::write(tmp_dir.path().join(".env"), "FOO=1").unwrap();
+        std::fs::write(tmp_dir.path().join(".env.archive-wal"), "").unwrap();
+
+        let mut progress = CollectProgress::default();
+        crawl(
+            &context,
+            tmp_dir.path(),
+            &CrawlOptions::default(),
+            &mut progress,
+        )
+        .await
+        .unwrap();
+
+        assert!(progress.0.contains(&progress::Event::CrawlFileResult {
+            path: database,
+            status: progress::CrawlStatus::ArchiveDatabase,
+            name: None,
+        }));
+        let archives = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(
+            archives[0].path,
+            tmp_dir.path().join(".env").to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn permission_summaryは権限エラーの件数だけを数える() {
+        let skipped = vec![
+            helper::SkippedPath {
+                path: PathBuf::from("/a"),
+                kind: Some(std::io::ErrorKind::PermissionDenied),
+                message: "denied".to_string(),
+            },
+            helper::SkippedPath {
+                path: PathBuf::from("/b"),
+                kind: None,
+                message: "loop".to_string(),
+            },
+        ];
+        assert_eq!(
+            permission_summary(&skipped).unwrap(),
+            "skipped 1 subtree(s) due to insufficient permissions"
+        );
+        assert_eq!(permission_summary(&skipped[1..]), None);
+    }
+
+    #[tokio::test]
+    async fn crawlはexcludeに一致するディレクトリ配下のファイルを登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        let kept = project_dir.join("api/.env");
+        let excluded = project_dir.join("fixtures/lib/.env");
+        std::fs::create_dir_all(kept.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(excluded.parent().unwrap()).unwrap();
+        std::fs::write(&kept, "API=1").unwrap();
+        std::fs::write(&excluded, "LIB=1").unwrap();
+
+        let options = CrawlOptions {
+            search: helper::SearchOptions {
+                excludes: vec!["fixtures".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        crawl(
+            &context,
+            &project_dir,
+            &options,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+
+        let archives = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].path, kept.to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn 複数のファイルをpushすると登録できないファイルがあっても残りを登録する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let files = [".env", ".env.missing", ".env.local"].map(|file| tmp_dir.path().join(file));
+        std::fs::write(&files[0], "FOO=1").unwrap();
+        std::fs::write(&files[2], "FOO=2").unwrap();
+
+        let options = PushOptions {
+            naming: PushNaming::new(None, Some("myproj".to_string())),
+            ..Default::default()
+        };
+        let error = push_files(&context, &files, &options).await.unwrap_err();
+        assert_eq!(error.to_string(), "failed to push 1 of 3 file(s)");
+
+        let archive = context.archive().unwrap();
+        let mut names = archive
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["myproj-.env", "myproj-.env.local"]);
+    }
+
+    #[tokio::test]
+    async fn pushはグロブに一致するすべてのファイルを登録し一致しなければエラーにする() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let dir = tmp_dir.path().join("project");
+        std::fs::create_dir(&dir).unwrap();
+        for file in [".env", ".env.local", ".env.test"] {
+            std::fs::write(dir.join(file), file).unwrap();
+        }
+        let pattern = |glob: &str| vec![dir.join(glob).to_string_lossy().to_string()];
+        let options = PushOptions {
+            naming: PushNaming::new(None, Some("myproj".to_string())),
+            ..Default::default()
+        };
+
+        let error = push_command(&context, &pattern(".env.*.bak"), None, &options)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("no files match "));
+        push_command(&context, &pattern(".en[v]"), None, &options)
+            .await
+            .unwrap();
+        push_command(&context, &pattern(".env.*"), None, &options)
+            .await
+            .unwrap();
+        let named = PushOptions {
+            naming: PushNaming::Name("all".to_string()),
+            ..Default::default()
+        };
+        let error = push_command(&context, &pattern(".env*"), None, &named)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("--name can only be used"));
+
+        let mut names = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["myproj-.env", "myproj-.env.local", "myproj-.env.test"]
+        );
+    }
+
+    #[tokio::test]
+    async fn 標準入力から読み込んだ内容をpathのアーカイブとして登録する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let path = tmp_dir.path().join("srv/app/.env");
+
+        let body = read_body("FOO=1\n".as_bytes(), "stdin", Some(6)).unwrap();
+        let options = PushOptions {
+            naming: PushNaming::Name("prod".to_string()),
+            ..Default::default()
+        };
+        push_body(&context, &path, &body, &options).await.unwrap();
+
+        let (entry, body) = context
+            .archive()
+            .unwrap()
+            .get("prod")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.path, path.to_string_lossy());
+        assert_eq!(body, "FOO=1\n");
+        assert_eq!(entry.checksum, digest::bytes_checksum(b"FOO=1\n"));
+
+        let error = read_body("FOO=1\n".as_bytes(), "stdin", Some(5)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "stdin is too large (limit 5 bytes). pass --force to push it anyway"
+        );
+        let stdin = [STDIN_FILE.to_string()];
+        let error = push_command(&context, &stdin, None, &options)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "--path is required when reading from stdin (-)"
+        );
+    }
+
+    #[tokio::test]
+    async fn 登録名が既にある場合はエラーにしoverwriteなら内容を置き換える() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=1").unwrap();
+        let named = PushOptions {
+            naming: PushNaming::Name("prod".to_string()),
+            ..Default::default()
+        };
+        push(&context, &env_file_path, &named).await.unwrap();
+        let archive = context.archive().unwrap();
+        archive
+            .add_tags("prod", &["keep".to_string()])
+            .await
+            .unwrap();
+        let (existing, _) = archive.get("prod").await.unwrap().unwrap();
+
+        std::fs::write(&env_file_path, "FOO=2").unwrap();
+        let error = push(&context, &env_file_path, &named).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "archive name prod already exists (path {}, created at {}). use --overwrite to replace it or choose another name",
+                env_file_path.display(),
+                context.timestamp(existing.created_at)
+            )
+        );
+
+        let overwrite = PushOptions {
+            overwrite: true,
+            ..named
+        };
+        push(&context, &env_file_path, &overwrite).await.unwrap();
+        let (entry, body) = archive.get("prod").await.unwrap().unwrap();
+        assert_eq!(body, "FOO=2");
+        assert_eq!(entry.checksum, digest::bytes_checksum(b"FOO=2"));
+        assert!(entry.created_at > existing.created_at);
+        assert_eq!(
+            archive.tags("prod").await.unwrap(),
+            vec!["keep".to_string()]
+        );
+        assert_eq!(
+            archive
+                .list_all(&archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn 通常のファイルではないパスはpushもcrawlも登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let project_dir = tmp_dir.path().join("project");
+        std::fs::create_dir_all(project_dir.join(".env.d")).unwrap();
+        std::fs::write(project_dir.join(".env"), "FOO=1").unwrap();
+        let fifo = project_dir.join(".env.fifo");
+        assert!(std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap()
+            .success());
+
+        let error = push(
+            &context,
+            &project_dir.join(".env.d"),
+            &PushOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.is::<helper::NotRegularFile>());
+        let error = push(&context, &fifo, &PushOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("'{}' is not a regular file", fifo.display())
+        );
+
+        // ディレクトリは探さず、FIFO は読み込まずにエラーにする
+        let mut progress = CollectProgress::default();
+        let error = crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut progress,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "failed to read 1 file(s)");
+        assert!(progress.0.contains(&progress::Event::CrawlFileFailed {
+            path: fifo.clone(),
+            message: format!("'{}' is not a regular file", fifo.display()),
+        }));
+        let archives = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].path, project_dir.join(".env").to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn skip_if_sameの場合は最新のアーカイブと同じ内容を登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=1").unwrap();
+        let options = PushOptions {
+            skip_if_same: true,
+            ..Default::default()
+        };
+
+        let archive = context.archive().unwrap();
+        let filter = archive::EntryFilter::default();
+
+        push(&context, &env_file_path, &options).await.unwrap();
+        push(&context, &env_file_path, &options).await.unwrap();
+        let history = archive
+            .history(&env_file_path, None, &filter)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+
+        std::fs::write(&env_file_path, "FOO=2").unwrap();
+        push(&context, &env_file_path, &options).await.unwrap();
+        let history = archive
+            .history(&env_file_path, None, &filter)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn redactのautoは端末に全文を表示する場合だけ値を伏せる() {
+        assert!(Redact::Auto.applies(true, false));
+        assert!(!Redact::Auto.applies(false, false));
+        assert!(!Redact::Auto.applies(true, true));
+        assert!(Redact::Always.applies(false, true));
+        assert!(!Redact::Never.applies(true, false));
+
+        let args = Args::try_parse_from(["env-archive", "show", "--redact", "name"]).unwrap();
+        let SubCommands::Show { name, redact, .. } = args.subcommand else {
+            panic!("unexpected subcommand");
+        };
+        assert_eq!((name.as_deref(), redact), (Some("name"), Redact::Always));
+    }
+
+    #[tokio::test]
+    async fn complete_namesはデータベースがなければ作成せずに何も表示しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let database = tmp_dir.path().join("missing.db");
+        complete_names(&database, "", false).await;
+        assert!(!database.exists());
+
+        let args = Args::try_parse_from(["env-archive", "__complete-names", "prod"]).unwrap();
+        let SubCommands::CompleteNames { prefix } = args.subcommand else {
+            panic!("unexpected subcommand");
+        };
+        assert_eq!(prefix.as_deref(), Some("prod"));
+    }
+
+    #[test]
+    fn print0はformatと同時に指定できない() {
+        let args = Args::try_parse_from(["env-archive", "search", "-0", "api"]).unwrap();
+        let SubCommands::Search { format, .. } = args.subcommand else {
+            panic!("unexpected subcommand");
+        };
+        assert_eq!(
+            format.format(&config::Config::default()),
+            output::EntryFormat::Print0
+        );
+
+        for command in ["search", "list", "list-all"] {
+            let mut argv = vec!["env-archive", command, "--print0", "--format", "json"];
+            if command == "search" {
+                argv.push("api");
+            }
+            assert!(Args::try_parse_from(argv).is_err(), "{}", command);
+        }
+
+        let args = Args::try_parse_from(["env-archive", "list-all", "-0", "--names-only"]).unwrap();
+        let SubCommands::ListAll { format, .. } = args.subcommand else {
+            panic!("unexpected subcommand");
+        };
+        assert_eq!(
+            format.format(&config::Config::default()),
+            output::EntryFormat::Print0Names
+        );
+        // --names-only は --print0 と一緒に指定する
+        assert!(Args::try_parse_from(["env-archive", "list-all", "--names-only"]).is_err());
+    }
+
+    #[test]
+    fn variable_queryはキーの名前が完全に一致するか前方一致するものを返す() {
+        let body = "# SENTRY_DSN=commented\nSENTRY_DSN_BACKUP=b\nexport SENTRY_DSN=\"https://sentry\"\nMY_SENTRY_DSN=x\n";
+        let query = |prefix| VariableQuery::Key {
+            key: "SENTRY_DSN".to_string(),
+            prefix,
+        };
+        assert_eq!(
+            query(false).find(body),
+            [("SENTRY_DSN".to_string(), "https://sentry".to_string())]
+        );
+        assert_eq!(
+            query(true)
+                .find(body)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            ["SENTRY_DSN_BACKUP", "SENTRY_DSN"]
+        );
+        assert!(query(false).find("SENTRY=1\n").is_empty());
+    }
+
+    #[test]
+    fn variable_queryは値に文字列を含むキーをすべて返す() {
+        let body = "# OLD=sk_live_abc\nSTRIPE_KEY=\"sk_live_abc123\"\nBACKUP=prefix-sk_live_abc\nOTHER=sk_live_xyz\n";
+        let query = VariableQuery::Value("sk_live_abc".to_string());
+        assert_eq!(
+            query
+                .find(body)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            ["STRIPE_KEY", "BACKUP"]
+        );
+    }
+
+    #[test]
+    fn key_linesは指定したキーの値を指定した順に返す() {
+        let body = "export DATABASE_URL=\"postgres://db\"\nAPI_KEY='secret'\nDEBUG=1\n";
+        let keys = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            key_lines(body, &keys(&["DATABASE_URL"]), false).unwrap(),
+            ["postgres://db"]
+        );
+        assert_eq!(
+            key_lines(body, &keys(&["DEBUG", "API_KEY"]), false).unwrap(),
+            ["DEBUG=1", "API_KEY=secret"]
+        );
+        assert_eq!(
+            key_lines(body, &keys(&["API_KEY"]), true).unwrap(),
+            [redact::MASK]
+        );
+        assert_eq!(
+            key_lines(body, &keys(&["DEBUG", "MISSING"]), false)
+                .unwrap_err()
+                .to_string(),
+            "key MISSING is not defined in the archive. available keys: DATABASE_URL, API_KEY, DEBUG"
+        );
+    }
+
+    #[tokio::test]
+    async fn atを指定すると過去の日時で登録し最新のアーカイブは変わらない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=new").unwrap();
+        push(&context, &env_file_path, &PushOptions::default())
+            .await
+            .unwrap();
+
+        let at = |value: &str| PushOptions {
+            at: Some(helper::parse_timestamp(value, &context.timezone).unwrap()),
+            ..Default::default()
+        };
+        std::fs::write(&env_file_path, "FOO=old").unwrap();
+        push(&context, &env_file_path, &at("2020-01-01"))
+            .await
+            .unwrap();
+        std::fs::write(&env_file_path, "FOO=older").unwrap();
+        push(&context, &env_file_path, &at("2019-06-01T12:00:00+09:00"))
+            .await
+            .unwrap();
+
+        let archive = context.archive().unwrap();
+        let filter = archive::EntryFilter::default();
+        let history = archive
+            .history(&env_file_path, None, &filter)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 3);
+        // YYYY-MM-DD はタイムゾーン (Asia/Tokyo) におけるその日の 0 時
+        assert_eq!(
+            history[1].0.created_at.to_rfc3339(),
+            "2019-12-31T15:00:00+00:00"
+        );
+        assert_eq!(
+            history[2].0.created_at.to_rfc3339(),
+            "2019-06-01T03:00:00+00:00"
+        );
+        // 後から取り込んだ過去のアーカイブより、先に登録した最新のアーカイブを選ぶ
+        let latest = archive.list_latest_per_path(None, &filter).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].name, history[0].0.name);
+        assert_eq!(
+            archive.latest_checksum(&env_file_path).unwrap(),
+            Some(digest::bytes_checksum(b"FOO=new"))
+        );
+
+        let future = (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339();
+        let error = push(&context, &env_file_path, &at(&future))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().ends_with("is in the future"));
+        assert_eq!(
+            archive
+                .history(&env_file_path, None, &filter)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn 同じプロセスで同じパスを続けてpushしても両方登録する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+
+        std::fs::write(&env_file_path, "VERSION=1").unwrap();
+        push(&context, &env_file_path, &PushOptions::default())
+            .await
+            .unwrap();
+        std::fs::write(&env_file_path, "VERSION=2").unwrap();
+        push(&context, &env_file_path, &PushOptions::default())
+            .await
+            .unwrap();
+
+        let history = context
+            .archive()
+            .unwrap()
+            .history(&env_file_path, None, &archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_ne!(history[0].0.created_at, history[1].0.created_at);
+    }
+
+    #[tokio::test]
+    async fn enforce_on_pushがblockの場合はポリシーに違反するファイルを登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let mut context = test_context(tmp_dir.path().join("test.db"));
+        context.policies = policy::Policies::parse(
+            r#"
+            [policy]
+            enforce_on_push = "block"
+
+            [[policies]]
+            name = "api"
+            paths = ["**/api/.env"]
+            require = ["SENTRY_DSN"]
+            "#,
+        )
+        .unwrap();
+        init(&context, false).await.unwrap();
+
+        let api_dir = tmp_dir.path().join("api");
+        std::fs::create_dir(&api_dir).unwrap();
+        let env_file_path = api_dir.join(".env");
+        std::fs::write(
+            &env_file_path,
+            "DATABASE_URL=x
+",
+        )
+        .unwrap();
+        let error = push(&context, &env_file_path, &PushOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("blocked by 1 policy violation(s)"));
+        // ポリシーに一致しないパスは登録する
+        let web_env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(
+            &web_env_file_path,
+            "DATABASE_URL=x
+",
+        )
+        .unwrap();
+        push(&context, &web_env_file_path, &PushOptions::default())
+            .await
+            .unwrap();
+
+        std::fs::write(
+            &env_file_path,
+            "DATABASE_URL=x
+SENTRY_DSN=y
+",
+        )
+        .unwrap();
+        push(&context, &env_file_path, &PushOptions::default())
+            .await
+            .unwrap();
+
+        let archives = context
+            .archive()
+            .unwrap()
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 2);
+        assert!(archives
+            .iter()
+            .any(|entry| entry.path == env_file_path.to_string_lossy()));
+    }
+
+    #[tokio::test]
+    async fn enforce_on_pushがblockの場合はcrawlとeditでもポリシーに違反する内容を登録しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let mut context = test_context(tmp_dir.path().join("test.db"));
+        context.policies = policy::Policies::parse(
+            r#"
+            [policy]
+            enforce_on_push = "block"
+
+            [[policies]]
+            name = "api"
+            paths = ["**/api/.env"]
+            require = ["SENTRY_DSN"]
+            "#,
+        )
+        .unwrap();
+        init(&context, false).await.unwrap();
+
+        let project_dir = tmp_dir.path().join("project");
+        std::fs::create_dir_all(project_dir.join("api")).unwrap();
+        let api_env_file_path = project_dir.join("api/.env");
+        std::fs::write(&api_env_file_path, "DATABASE_URL=x\n").unwrap();
+        std::fs::write(project_dir.join(".env"), "DATABASE_URL=x\n").unwrap();
+
+        let mut progress = CollectProgress::default();
+        let error = crawl(
+            &context,
+            &project_dir,
+            &CrawlOptions::default(),
+            &mut progress,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("blocked by policy violations"));
+        assert!(progress.0.contains(&progress::Event::CrawlPolicyViolated {
+            path: api_env_file_path.clone(),
+            violations: vec!["api: SENTRY_DSN must be defined".to_string()],
+            blocked: true,
+        }));
+        // 違反しないファイルは登録する
+        let archive = context.archive().unwrap();
+        let archives = archive
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].path, project_dir.join(".env").to_string_lossy());
+
+        // edit で違反する内容に変えた場合も登録しない
+        archive
+            .push_body(
+                &api_env_file_path,
+                "DATABASE_URL=x\nSENTRY_DSN=y\n",
+                context.now,
+                "api",
+                None,
+            )
+            .await
+            .unwrap();
+        let editor = script_editor(tmp_dir.path(), "sed -i /SENTRY_DSN/d \"$1\"");
+        let error = edit(&context, "api", None, None, false, &editor)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("blocked by 1 policy violation(s)"));
+        assert_eq!(
+            archive
+                .list_all(&archive::EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_only_keysでは指定したキーだけを反映しバックアップを残す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "DATABASE_URL=archived\nAPI_KEY=archived\n").unwrap();
+        archive
+            .push(
+                &env_file_path,
+                context.now - chrono::Duration::minutes(1),
+                "test-name",
+                None,
+            )
+            .await
+            .unwrap();
+        std::fs::write(
+            &env_file_path,
+            "# local\nDATABASE_URL=broken\nAPI_KEY=local\n",
+        )
+        .unwrap();
+
+        let options = RecoverOptions {
+            in_place: true,
+            only_keys: vec!["DATABASE_URL".to_string()],
+            ..Default::default()
+        };
+        recover(
+            &context,
+            "test-name",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&env_file_path).unwrap(),
+            "# local\nDATABASE_URL=archived\nAPI_KEY=local\n"
+        );
+        let archives = archive
+            .list_all(&archive::EntryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(archives.len(), 2);
+        assert!(archives[0].name.starts_with("backup.env."));
+    }
+
+    #[tokio::test]
+    async fn import_archiveは既存の名前を飛ばしチェックサムが一致しないアーカイブをエラーにする() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=LOCAL").unwrap();
+        archive
+            .push(&env_file_path, context.now, "existing", None)
+            .await
+            .unwrap();
+
+        let entry = |name: &str, body: &str| manifest::ExportedEntry {
+            entry: archive::ArchiveEntry {
+                name: name.to_string(),
+                path: format!("/home/user/{}/.env", name),
+                created_at: context.now - chrono::Duration::days(1),
+                checksum: digest::bytes_checksum(body.as_bytes()),
+                message: None,
+                user: None,
+                quarantined: false,
+                size: body.len() as u64,
+            },
+            body: body.to_string(),
+            tags: vec!["imported".to_string()],
+        };
+        let mut tampered = entry("tampered", "FOO=ORIGINAL");
+        tampered.body = "FOO=TAMPERED".to_string();
+        let document = manifest::Document {
+            format_version: manifest::FORMAT_VERSION,
+            entries: vec![
+                entry("new", "FOO=NEW"),
+                entry("existing", "FOO=EXPORTED"),
+                tampered,
+            ],
+        };
+        let file = tmp_dir.path().join("archive.json");
+        std::fs::write(&file, serde_json::to_string(&document).unwrap()).unwrap();
+
+        let error = import_archive(&context, &file, false).await.unwrap_err();
+        assert_eq!(error.to_string(), "failed to import tampered");
+        assert_eq!(
+            archive.get("new").await.unwrap(),
+            Some((document.entries[0].entry.clone(), "FOO=NEW".to_string()))
+        );
+        assert_eq!(archive.tags("new").await.unwrap(), ["imported"]);
+        assert_eq!(
+            archive.get("existing").await.unwrap().unwrap().1,
+            "FOO=LOCAL"
+        );
+        assert!(archive.get("tampered").await.unwrap().is_none());
+
+        let error = import_archive(&context, &file, true).await.unwrap_err();
+        assert_eq!(error.to_string(), "failed to import tampered");
+        assert_eq!(
+            archive.get("existing").await.unwrap().unwrap().1,
+            "FOO=EXPORTED"
+        );
+    }
+
+    #[tokio::test]
+    async fn 隔離されているアーカイブは書き出して取り込んでも隔離したままになる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let archive = context.archive().unwrap();
+        archive
+            .push_body(
+                &tmp_dir.path().join(".env"),
+                "FOO=BAR",
+                context.now,
+                "corrupt",
+                None,
+            )
+            .await
+            .unwrap();
+        rusqlite::Connection::open(&context.database)
+            .unwrap()
+            .execute(
+                "UPDATE archives SET body = 'FOO=x' WHERE name = 'corrupt'",
+                [],
+            )
+            .unwrap();
+        archive.set_quarantined("corrupt", true).await.unwrap();
+        let file = tmp_dir.path().join("archive.json");
+        export(
+            &context,
+            &file.to_string_lossy(),
+            None,
+            &archive::EntryFilter::default(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        let imported = test_context(tmp_dir.path().join("imported.db"));
+        init(&imported, false).await.unwrap();
+        import_archive(&imported, &file, false).await.unwrap();
+        let (entry, body) = imported
+            .archive()
+            .unwrap()
+            .get("corrupt")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(entry.quarantined);
+        assert_eq!(entry.checksum, digest::bytes_checksum(b"FOO=BAR"));
+        assert_eq!(body, "FOO=x");
+    }
+
+    #[test]
+    fn filterはユーザーの指定がなければvisibilityに従って絞り込む() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let user = |users: &UserArgs, visibility| {
+            Context {
+                visibility,
+                ..context.clone()
+            }
+            .filter(None, users)
+            .user
+        };
+        let default = UserArgs::default();
+        let all_users = UserArgs {
+            all_users: true,
+            ..Default::default()
+        };
+        let bob = UserArgs {
+            user: Some("bob".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(user(&default, Visibility::Own).as_deref(), Some("alice"));
+        assert_eq!(user(&default, Visibility::All), None);
+        assert_eq!(user(&all_users, Visibility::Own), None);
+        assert_eq!(user(&bob, Visibility::Own).as_deref(), Some("bob"));
+        assert_eq!(user(&bob, Visibility::All).as_deref(), Some("bob"));
+    }
+
+    #[tokio::test]
+    async fn 他のユーザーのアーカイブはall_usersを指定しなければ復元できない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let env_file_path = tmp_dir.path().join(".env");
+        std::fs::write(&env_file_path, "FOO=BOB").unwrap();
+        archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+            .unwrap()
+            .with_user(Some("bob".to_string()))
+            .push(&env_file_path, context.now, "bob-env", None)
+            .await
+            .unwrap();
+
+        let error = resolve_recover_name(
+            &context,
+            None,
+            Some(&env_file_path.to_string_lossy()),
+            None,
+            &context.filter(None, &UserArgs::default()),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().starts_with("no archive found for path"));
+
+        let output = tmp_dir.path().join("recovered.env");
+        let mut options = RecoverOptions {
+            output: Some(output.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let error = recover(
+            &context,
+            "bob-env",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "archive bob-env was pushed by user bob. use --all-users to access it"
+        );
+        assert!(!output.exists());
+
+        options.all_users = true;
+        recover(
+            &context,
+            "bob-env",
+            &options,
+            &mut confirm::AssumeYes,
+            &mut progress::PrintProgress::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "FOO=BOB");
+    }
+
+    #[tokio::test]
+    async fn extractはアーカイブ元のファイル名で書き出しforceがなければ上書きしない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        let env_file_path = tmp_dir.path().join("project").join(".env.local");
+        std::fs::create_dir(env_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&env_file_path, "FOO=ARCHIVED").unwrap();
+        archive
+            .push(&env_file_path, context.now, "test-name", None)
+            .await
+            .unwrap();
+
+        let dest_dir = tmp_dir.path().join("scratch");
+        std::fs::create_dir(&dest_dir).unwrap();
+        extract(&context, "test-name", &dest_dir, false, false, false)
+            .await
+            .unwrap();
+        let extracted = dest_dir.join(".env.local");
+        assert_eq!(std::fs::read_to_string(&extracted).unwrap(), "FOO=ARCHIVED");
+
+        std::fs::write(&extracted, "FOO=LOCAL").unwrap();
+        let error = extract(&context, "test-name", &dest_dir, false, false, false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{} already exists. use --force to overwrite it",
+                extracted.display()
+            )
+        );
+        assert_eq!(std::fs::read_to_string(&extracted).unwrap(), "FOO=LOCAL");
+
+        extract(&context, "test-name", &dest_dir, true, false, false)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&extracted).unwrap(), "FOO=ARCHIVED");
+    }
+
+    #[tokio::test]
+    async fn bootstrapはプロジェクトをcloneして存在しない_envファイルだけを復元する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = std::fs::canonicalize(tmp_dir.path()).unwrap();
+        let context = test_context(root.join("test.db"));
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        let origin = root.join("origin");
+        let origin = origin.to_str().unwrap();
+        git(&["init", "-q", origin]);
+        std::fs::write(root.join("origin/README.md"), "# api").unwrap();
+        git(&["-C", origin, "add", "README.md"]);
+        git(&["-C", origin, "commit", "-q", "-m", "init"]);
+        let bare = root.join("origin.git");
+        git(&["clone", "-q", "--bare", origin, bare.to_str().unwrap()]);
+
+        let workspace = root.join("workspace");
+        let archive =
+            archive::Archive::open(context.database.clone(), archive::OpenOptions::default())
+                .unwrap();
+        for (path, body, name) in [
+            ("api/.env", "API=1", "api"),
+            ("api/web/.env.local", "WEB=1", "web-prod"),
+            ("tools/.env", "TOOLS=ARCHIVED", "tools"),
+            ("tools/sub/.env", "SUB=1", "tools-sub"),
+        ] {
+            archive
+                .push_body(&workspace.join(path), body, context.now, name, None)
+                .await
+                .unwrap();
+        }
+        std::fs::create_dir_all(workspace.join("tools")).unwrap();
+        std::fs::write(workspace.join("tools/.env"), "TOOLS=LOCAL").unwrap();
+
+        let manifest = root.join("workspaces.toml");
+        std::fs::write(
+            &manifest,
+            format!(
+                r#"
+                [[projects]]
+                dir = "workspace/api"
+                git = "{}"
+                files = [".env", {{ path = "web/.env.local", name = "web-prod" }}]
+
+                [[projects]]
+                dir = "workspace/missing"
+
+                [[projects]]
+                dir = "workspace/tools"
+                "#,
+                bare.display()
+            ),
+        )
+        .unwrap();
+
+        let error = bootstrap(&context, &manifest, Path::new("git"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "failed to bootstrap {}",
+                workspace.join("missing").display()
+            )
+        );
+        let read = |path: &str| std::fs::read_to_string(workspace.join(path)).unwrap();
+        assert_eq!(read("api/README.md"), "# api");
+        assert_eq!(read("api/.env"), "API=1");
+        assert_eq!(read("api/web/.env.local"), "WEB=1");
+        assert_eq!(read("tools/.env"), "TOOLS=LOCAL");
+        assert_eq!(read("tools/sub/.env"), "SUB=1");
+        assert!(!workspace.join("missing").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn bootstrapはオプションのようなurlをgitのオプションとして渡さない() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        let context = test_context(root.join("test.db"));
+        init(&context, false).await.unwrap();
+        // 受け取った引数を記録して失敗する git
+        let git = root.join("git.sh");
+        std::fs::write(
+            &git,
+            format!(
+                "#!/bin/sh\nprintf '%s\\n' \"$@\" > {}/args\nexit 1\n",
+                root.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&git, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let manifest = root.join("workspaces.toml");
+        std::fs::write(
+            &manifest,
+            "[[projects]]\ndir = \"api\"\ngit = \"--upload-pack=touch pwned\"\n",
+        )
+        .unwrap();
+
+        bootstrap(&context, &manifest, &git).await.unwrap_err();
+        assert_eq!(
+            std::fs::read_to_string(root.join("args")).unwrap(),
+            format!(
+                "clone\n--\n--upload-pack=touch pwned\n{}\n",
+                root.join("api").display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn search_除外した結果一致するアーカイブがなければno_matchesになる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        context
+            .archive()
+            .unwrap()
+            .push_body(
+                Path::new("/srv/api/staging-backup/.env"),
+                "FOO=1\n",
+                chrono::Utc::now(),
+                "backup",
+                None,
+            )
+            .await
+            .unwrap();
+        let filter = archive::EntryFilter::default();
+        let query = |excludes: &[&str]| PathQuery::Plain {
+            keywords: vec!["api".to_string(), "staging".to_string()],
+            excludes: excludes.iter().map(|word| word.to_string()).collect(),
+            ignore_case: false,
+        };
+
+        let page = archive::Page::default();
+        search(
+            &context,
+            &query(&[]),
+            false,
+            &filter,
+            &page,
+            output::EntryFormat::Text,
+        )
+        .await
+        .unwrap();
+        let error = search(
+            &context,
+            &query(&["backup"]),
+            false,
+            &filter,
+            &page,
+            output::EntryFormat::Text,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.is::<NoMatches>());
+        assert_eq!(error.to_string(), "no matches");
+    }
+
+    #[test]
+    fn find_local_databaseは親ディレクトリへたどって最も近いものを見つける() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let home = tmp_dir.path();
+        let repo = home.join("src/repo");
+        let nested = repo.join("packages/api");
+        std::fs::create_dir_all(nested.join("src")).unwrap();
+        std::fs::write(home.join(".env_archive"), "").unwrap();
+
+        // ホームディレクトリの .env_archive はリポジトリのデータベースとして扱わない
+        assert_eq!(find_local_database(&nested, Some(home)), None);
+        assert_eq!(
+            find_local_database(&nested, None),
+            Some(home.join(".env_archive"))
+        );
+
+        std::fs::write(repo.join(".env_archive"), "").unwrap();
+        assert_eq!(
+            find_local_database(&nested.join("src"), Some(home)),
+            Some(repo.join(".env_archive"))
+        );
+        std::fs::write(nested.join(".env_archive"), "").unwrap();
+        assert_eq!(
+            find_local_database(&nested.join("src"), Some(home)),
+            Some(nested.join(".env_archive"))
+        );
+
+        // .env_archive という名前のディレクトリはデータベースではない
+        std::fs::create_dir(nested.join("src/.env_archive")).unwrap();
+        assert_eq!(
+            find_local_database(&nested.join("src"), Some(home)),
+            Some(nested.join(".env_archive"))
+        );
+    }
+
+    #[test]
+    fn init_localはgitリポジトリのルートか指定したディレクトリに作成する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let tmp = std::fs::canonicalize(tmp_dir.path()).unwrap();
+        let repo = tmp.join("repo");
+        let nested = repo.join("packages/api");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let error = local_init_database(None, &nested).unwrap_err().to_string();
+        assert!(error.starts_with("no git repository found"), "{}", error);
+
+        std::fs::create_dir(repo.join(".git")).unwrap();
+        assert_eq!(
+            local_init_database(None, &nested).unwrap(),
+            repo.join(".env_archive")
+        );
+        assert_eq!(
+            local_init_database(Some(".."), &nested).unwrap(),
+            repo.join("packages/.env_archive")
+        );
+        assert!(local_init_database(Some("missing"), &nested).is_err());
+        std::fs::write(nested.join("file"), "").unwrap();
+        assert!(local_init_database(Some("file"), &nested).is_err());
+
+        let args = Args::try_parse_from(["env-archive", "init", "--local", "--at", "."]).unwrap();
+        assert!(args.local);
+        assert!(Args::try_parse_from(["env-archive", "init", "--at", "."]).is_err());
+    }
+
+    #[tokio::test]
+    async fn migrate_dbは以前の場所のデータベースを新しい場所に移しメモを残す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let home = tmp_dir.path();
+        let default = config::DefaultDatabase::new(None, home);
+
+        // 新しくインストールした場合は移すものがない
+        let error = migrate_db(&default).unwrap_err().to_string();
+        assert!(error.starts_with("no database to migrate"), "{}", error);
+        assert_eq!(legacy_database_hint(&default), None);
+
+        // 以前の場所にデータベースがある場合
+        let legacy = test_context(default.legacy.clone());
+        init(&legacy, false).await.unwrap();
+        legacy
+            .archive()
+            .unwrap()
+            .push_body(&home.join(".env"), "A=1\n", legacy.now, "legacy", None)
+            .await
+            .unwrap();
+        std::fs::write(home.join(".env_archive-wal"), "wal").unwrap();
+        assert_eq!(default.path(), default.legacy);
+
+        let hint = legacy_database_hint(&default).unwrap();
+        assert!(hint.contains("migrate-db"), "{}", hint);
+        assert_eq!(legacy_database_hint(&default), None);
+
+        migrate_db(&default).unwrap();
+        assert!(!default.legacy.exists());
+        assert!(!home.join(".env_archive-wal").exists());
+        assert_eq!(
+            std::fs::read_to_string(default.data.with_file_name("archive.db-wal")).unwrap(),
+            "wal"
+        );
+        let note = std::fs::read_to_string(home.join(".env_archive.moved")).unwrap();
+        assert!(
+            note.contains(&default.data.display().to_string()),
+            "{}",
+            note
+        );
+        assert_eq!(default.path(), default.data);
+        let archive = test_context(default.data.clone()).archive().unwrap();
+        assert!(archive.get("legacy").await.unwrap().is_some());
+
+        // 新しい場所に既にある場合は上書きしない
+        std::fs::write(&default.legacy, "").unwrap();
+        let error = migrate_db(&default).unwrap_err().to_string();
+        assert!(error.ends_with("with import"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn initはないディレクトリを作成し初期化前はinitを促す() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("var/lib/env-archive/team.db"));
+
+        let error = context.archive().err().unwrap();
+        assert!(error.is::<NotInitialized>());
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "archive database not initialized at {}; run `env-archive init`",
+                context.database.display()
+            )
+        );
+        assert!(!context.database.exists());
+
+        // テーブルのないファイルも初期化されていないものとして扱い、作成しない
+        std::fs::create_dir_all(context.database.parent().unwrap()).unwrap();
+        std::fs::write(&context.database, "").unwrap();
+        assert!(context.archive().err().unwrap().is::<NotInitialized>());
+        assert_eq!(std::fs::metadata(&context.database).unwrap().len(), 0);
+        std::fs::remove_file(&context.database).unwrap();
+
+        init(&context, false).await.unwrap();
+        assert!(context.database.is_file());
+        context.archive().unwrap();
+    }
+
+    #[tokio::test]
+    async fn pushとcrawlはデータベースがなければ初期化し読み取るだけのコマンドは作成しない() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let env_file_path = tmp_dir.path().join("project/.env");
+        std::fs::create_dir_all(env_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&env_file_path, "A=1\n").unwrap();
+
+        // 読み取るだけのコマンドと crawl --dry-run はデータベースを作成しない
+        let context = test_context(tmp_dir.path().join("data/archive.db"));
+        let filter = archive::EntryFilter::default();
+        let page = archive::Page::default();
+        let error = list_all(&context, &filter, &page, output::EntryFormat::Text)
+            .await
+            .unwrap_err();
+        assert!(error.is::<NotInitialized>());
+        let dry_run = CrawlOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut progress = CollectProgress::default();
+        assert!(crawl(&context, tmp_dir.path(), &dry_run, &mut progress)
+            .await
+            .is_err());
+        assert!(!context.database.exists());
+
+        push(&context, &env_file_path, &PushOptions::default())
+            .await
+            .unwrap();
+        let archive = context.archive().unwrap();
+        assert_eq!(archive.list_all(&filter).await.unwrap().len(), 1);
+
+        let context = test_context(tmp_dir.path().join("crawled/archive.db"));
+        let mut progress = CollectProgress::default();
+        crawl(
+            &context,
+            &tmp_dir.path().join("project"),
+            &CrawlOptions::default(),
+            &mut progress,
+        )
+        .await
+        .unwrap();
+        let archive = context.archive().unwrap();
+        assert_eq!(archive.list_all(&filter).await.unwrap().len(), 1);
+    }
+
+    struct FakePasswordManager(password_manager::SecretItem);
+
+    impl password_manager::PasswordManager for FakePasswordManager {
+        fn name(&self) -> &'static str {
+            "Fake"
+        }
+
+        fn fetch(
+            &self,
+            _item: &str,
+            _field: Option<&str>,
+        ) -> anyhow::Result<password_manager::SecretItem> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn import_secretはアイテムのidをメッセージに記録し正規化したパスで登録する() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("archive.db"));
+        let manager = FakePasswordManager(password_manager::SecretItem {
+            id: "item-123".to_string(),
+            body: "A=1\n".to_string(),
+        });
+        let path = tmp_dir.path().join("project/../app/.env");
+
+        import_secret(
+            &context,
+            &manager,
+            "my-item",
+            None,
+            &absolute_path(&path.to_string_lossy()),
+            Some("imported".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let entry = context
+            .archive()
+            .unwrap()
+            .entry("imported")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            entry.path,
+            tmp_dir.path().join("app/.env").to_string_lossy()
+        );
+        assert_eq!(
+            entry.message.as_deref(),
+            Some("imported from Fake item item-123")
+        );
+
+        // 空の内容は push と同じく登録しない
+        let empty = FakePasswordManager(password_manager::SecretItem {
+            id: "item-456".to_string(),
+            body: String::new(),
+        });
+        assert!(import_secret(&context, &empty, "empty", None, &path, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn コールドストレージがなくてもverify_allとgrepは残りのアーカイブを処理し終了コード5のエラーにする(
+    ) {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let archive = context.archive().unwrap();
+        let env_file_path = tmp_dir.path().join(".env");
+        for (name, days_ago) in [("old", 400), ("new", 1)] {
+            archive
+                .push_body(
+                    &env_file_path,
+                    &format!("NAME={}\n", name),
+                    context.now - chrono::Duration::days(days_ago),
+                    name,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let cold_dir = tmp_dir.path().join("cold");
+        std::fs::create_dir(&cold_dir).unwrap();
+        archive
+            .move_to_cold(context.now - chrono::Duration::days(365), &cold_dir)
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&cold_dir).unwrap();
+
+        let mut progress = CollectProgress::default();
+        let error = verify_all(
+            &context,
+            &VerifyAllOptions::default(),
+            &mut confirm::AssumeYes,
+            &mut progress,
+        )
+        .await
+        .unwrap_err();
+        assert!(cold::is_unavailable(&error));
+        assert!(error
+            .to_string()
+            .starts_with("1 archive(s) in cold storage could not be read"));
+        assert_eq!(
+            progress.0,
+            vec![
+                progress::Event::VerifyStarted { total: 2 },
+                progress::Event::VerifyUnavailable {
+                    name: "old".to_string(),
+                    path: env_file_path.to_string_lossy().to_string(),
+                    created_at: context.now - chrono::Duration::days(400),
+                },
+                progress::Event::VerifyFinished {
+                    checked: 2,
+                    corrupt: 0,
+                    unavailable: 1,
+                },
+            ]
+        );
+
+        let pattern = grep::Pattern::Plain("NAME".to_string());
+        let filter = archive::EntryFilter::default();
+        let error = grep(&context, &pattern, false, &filter).await.unwrap_err();
+        assert!(cold::is_unavailable(&error));
+        let query = VariableQuery::Key {
+            key: "NAME".to_string(),
+            prefix: false,
+        };
+        let error = find_variables(&context, &query, false, false, &filter)
+            .await
+            .unwrap_err();
+        assert!(cold::is_unavailable(&error));
+    }
+
+    #[test]
+    fn list_allはsortを省略するとパスの順に並べる() {
+        let args = Args::try_parse_from(["env-archive", "list-all"]).unwrap();
+        let SubCommands::ListAll { page, .. } = args.subcommand else {
+            panic!("unexpected subcommand");
+        };
+        assert_eq!(page.page_or(archive::Sort::PATH).sort, archive::Sort::PATH);
+        assert_eq!(page.page().sort, archive::Sort::default());
+
+        let args =
+            Args::try_parse_from(["env-archive", "list-all", "--sort", "created_at", "--desc"])
+                .unwrap();
+        let SubCommands::ListAll { page, .. } = args.subcommand else {
+            panic!("unexpected subcommand");
+        };
+        assert_eq!(
+            page.page_or(archive::Sort::PATH).sort,
+            archive::Sort::default()
+        );
+    }
+
+    #[test]
+    fn push_resultは登録した名前をテキストまたはjsonで示す() {
+        let path = Path::new("/srv/api/.env");
+        assert_eq!(
+            push_result(false, path, Some("deploy-2")),
+            "[PUSHED] /srv/api/.env with name deploy-2"
+        );
+        assert_eq!(
+            push_result(false, path, None),
+            "[SKIP] unchanged. /srv/api/.env"
+        );
+        let json = |name| {
+            serde_json::from_str::<serde_json::Value>(&push_result(true, path, name)).unwrap()
+        };
+        assert_eq!(
+            json(Some("deploy-2")),
+            serde_json::json!({"path": "/srv/api/.env", "name": "deploy-2", "status": "pushed"})
+        );
+        assert_eq!(
+            json(None),
+            serde_json::json!({"path": "/srv/api/.env", "name": null, "status": "unchanged"})
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn 書き出したファイルは所有者だけが読み書きできる() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let env_file_path = tmp_dir.path().join("project/.env");
+        std::fs::create_dir_all(env_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&env_file_path, "FOO=BAR").unwrap();
+        push(&context, &env_file_path, &PushOptions::default())
+            .await
+            .unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let json = tmp_dir.path().join("archive.json");
+        export(
+            &context,
+            &json.to_string_lossy(),
+            None,
+            &archive::EntryFilter::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(mode(&json), helper::PRIVATE_FILE_MODE);
+
+        let tar = tmp_dir.path().join("archive.tar.gz");
+        export_tar(
+            &context,
+            &tar,
+            tmp_dir.path(),
+            &archive::EntryFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(mode(&tar), helper::PRIVATE_FILE_MODE);
+    }
+
+    #[tokio::test]
+    async fn verify_allは並行して検証しても1件ずつ検証した場合と同じ結果になり範囲を絞り込める() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let context = test_context(tmp_dir.path().join("test.db"));
+        init(&context, false).await.unwrap();
+        let started = context.now - chrono::Duration::days(30);
+        let requests = (0..3000)
+            .map(|i| {
+                let body = format!("INDEX={}", i);
+                archive::PushRequest {
+                    path: tmp_dir
+                        .path()
+                        .join(["api", "web", "worker"][i % 3])
+                        .join(".env"),
+                    checksum: digest::bytes_checksum(body.as_bytes()),
+                    body,
+                    name: format!("entry-{}", i),
+                    created_at: started + chrono::Duration::minutes(i as i64 * 10),
+                }
+            })
+            .collect();
+        context.archive().unwrap().push_many(requests).unwrap();
+        rusqlite::Connection::open(&context.database)
+            .unwrap()
+            .execute("UPDATE archives SET body = 'X' WHERE rowid % 97 = 0", [])
+            .unwrap();
+
+        let run = |options: VerifyAllOptions| {
+            let context = context.clone();
+            async move {
+                let mut progress = CollectProgress::default();
+                let result =
+                    verify_all(&context, &options, &mut confirm::AssumeYes, &mut progress).await;
+                (result.unwrap_err().to_string(), progress.0)
+            }
+        };
+        let jobs = |jobs| VerifyAllOptions {
+            jobs: std::num::NonZeroUsize::new(jobs).unwrap(),
+            ..Default::default()
+        };
+        let (error, sequential) = run(jobs(1)).await;
+        assert_eq!(error, "found 30 corrupt archive(s) and quarantined them");
+        assert_eq!(
+            sequential[0],
+            progress::Event::VerifyStarted { total: 3000 }
+        );
+        assert_eq!(
+            sequential.last(),
+            Some(&progress::Event::VerifyFinished {
+                checked: 3000,
+                corrupt: 30,
+                unavailable: 0,
+            })
+        );
+        assert!(sequential.contains(&progress::Event::VerifyProgress {
+            checked: 2000,
+            total: 3000,
+        }));
+        let (_, parallel) = run(jobs(4)).await;
+        assert_eq!(parallel, sequential);
+
+        let finished = |events: &[progress::Event]| events.last().cloned().unwrap();
+        let (_, api) = run(VerifyAllOptions {
+            prefix: Some(tmp_dir.path().join("api")),
+            ..jobs(4)
+        })
+        .await;
+        assert_eq!(api[0], progress::Event::VerifyStarted { total: 1000 });
+        assert!(matches!(
+            finished(&api),
+            progress::Event::VerifyFinished { checked: 1000, .. }
+        ));
+        let (_, recent) = run(VerifyAllOptions {
+            since: Some(started + chrono::Duration::minutes(29_000)),
+            ..jobs(4)
+        })
+        .await;
+        assert!(matches!(
+            finished(&recent),
+            progress::Event::VerifyFinished {
+                checked: 100,
+                corrupt: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn verifyの進捗は残り時間の見込みと共に表示する() {
+        let eta = progress::estimate_remaining(std::time::Duration::from_secs(10), 1000, 4000);
+        assert_eq!(eta, Some(std::time::Duration::from_secs(30)));
+        assert_eq!(
+            progress::estimate_remaining(std::time::Duration::ZERO, 0, 4000),
+            None
+        );
+        assert_eq!(
+            progress::verify_progress(1000, 4000, eta, false),
+            "checked 1000/4000 entries ETA 30s"
+        );
+        assert_eq!(
+            progress::verify_progress(1000, 4000, None, true),
+            format!("[{}{}] 1000/4000", "#".repeat(7), ".".repeat(23))
+        );
+        assert_eq!(
+            progress::format_eta(std::time::Duration::from_secs(187)),
+            "3m07s"
+        );
+        assert_eq!(
+            progress::format_eta(std::time::Duration::from_secs(3900)),
+            "1h05m"
         );
     }
 }
@@ -0,0 +1,223 @@
+use crate::archive::ArchiveEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// エクスポートするドキュメントの形式のバージョン
+/// 形式を変更した場合は上げる
+///
+/// 1. アーカイブのメタデータと本文
+/// 2. タグを追加
+/// 3. 登録したユーザーを追加
+/// 4. 隔離されているかどうかを追加
+pub const FORMAT_VERSION: u32 = 4;
+
+/// export で出力するドキュメント
+///
+/// 古い形式のドキュメントも読み込めるよう、後から追加した項目には
+/// `#[serde(default)]` を付けて省略時の値を決めておく
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Document {
+    pub format_version: u32,
+    pub entries: Vec<ExportedEntry>,
+}
+
+/// アーカイブのメタデータと本文
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedEntry {
+    #[serde(flatten)]
+    pub entry: ArchiveEntry,
+    pub body: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Document {
+    /// tags はアーカイブの名前ごとのタグ
+    pub fn new(
+        archives: Vec<(ArchiveEntry, String)>,
+        mut tags: HashMap<String, Vec<String>>,
+    ) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            entries: archives
+                .into_iter()
+                .map(|(entry, body)| ExportedEntry {
+                    tags: tags.remove(&entry.name).unwrap_or_default(),
+                    entry,
+                    body,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// JSON のドキュメントを読み込む
+/// 古い形式は現在の形式に読み替え、このバージョンが知らない新しい形式は読み込まない
+pub fn parse(json: &str) -> anyhow::Result<Document> {
+    #[derive(Deserialize)]
+    struct Version {
+        format_version: u32,
+    }
+    let version: Version = serde_json::from_str(json)?;
+    if version.format_version > FORMAT_VERSION {
+        anyhow::bail!(
+            "format version {} is newer than supported version {}. upgrade dot-env-archive to import it",
+            version.format_version,
+            FORMAT_VERSION
+        );
+    }
+    let mut document: Document = serde_json::from_str(json)?;
+    document.format_version = FORMAT_VERSION;
+    // 本文の大きさはドキュメントに含めていないため本文から求める
+    for exported in &mut document.entries {
+        exported.entry.size = exported.body.len() as u64;
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::{Archive, EntryFilter, OpenOptions};
+    use chrono::{TimeZone, Utc};
+
+    fn entry() -> ArchiveEntry {
+        ArchiveEntry {
+            name: "test-name".to_string(),
+            path: "/home/user/api/.env".to_string(),
+            created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+            checksum: "checksum".to_string(),
+            message: Some("before rotating DB password".to_string()),
+            user: Some("alice".to_string()),
+            quarantined: false,
+            size: 7,
+        }
+    }
+
+    #[test]
+    fn ドキュメントは形式のバージョンと本文付きのエントリーを持つ() {
+        let tags = HashMap::from([("test-name".to_string(), vec!["prod".to_string()])]);
+        let document = Document::new(vec![(entry(), "FOO=BAR".to_string())], tags);
+
+        let json = serde_json::to_value(&document).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "format_version": 4,
+                "entries": [{
+                    "name": "test-name",
+                    "path": "/home/user/api/.env",
+                    "created_at": "2024-01-02T03:04:05Z",
+                    "checksum": "checksum",
+                    "message": "before rotating DB password",
+                    "user": "alice",
+                    "quarantined": false,
+                    "body": "FOO=BAR",
+                    "tags": ["prod"],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn 古い形式のドキュメントは省略された項目を補って読み込む() {
+        let json = r#"{
+            "format_version": 1,
+            "entries": [{
+                "name": "test-name",
+                "path": "/home/user/api/.env",
+                "created_at": "2024-01-02T03:04:05Z",
+                "checksum": "checksum",
+                "body": "FOO=BAR"
+            }]
+        }"#;
+        let document = parse(json).unwrap();
+        assert_eq!(document.format_version, FORMAT_VERSION);
+        assert_eq!(document.entries[0].entry.message, None);
+        assert_eq!(document.entries[0].entry.user, None);
+        assert!(document.entries[0].tags.is_empty());
+    }
+
+    #[test]
+    fn 新しい形式のドキュメントは読み込まない() {
+        let json = format!(
+            r#"{{"format_version": {}, "entries": []}}"#,
+            FORMAT_VERSION + 1
+        );
+        let error = parse(&json).unwrap_err().to_string();
+        assert!(error.contains("newer than supported"));
+    }
+
+    #[tokio::test]
+    async fn エクスポートしたドキュメントを別のデータベースに読み込むとすべての項目が復元される() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let source =
+            Archive::open(tmp_dir.path().join("source.db"), OpenOptions::default()).unwrap();
+        let entries = (0..3)
+            .map(|i| ArchiveEntry {
+                name: format!("name-{}", i),
+                path: format!("/home/user/project-{}/.env", i),
+                created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, i).unwrap()
+                    + chrono::Duration::nanoseconds(123_456_789),
+                checksum: crate::digest::bytes_checksum(format!("FOO={}", i).as_bytes()),
+                message: Some(format!("message {}", i)),
+                user: None,
+                quarantined: false,
+                size: format!("FOO={}", i).len() as u64,
+            })
+            .collect::<Vec<_>>();
+        for (i, entry) in entries.iter().enumerate() {
+            let tags = (0..=i).map(|t| format!("tag-{}", t)).collect::<Vec<_>>();
+            source
+                .restore(entry, &format!("FOO={}", i), &tags, false)
+                .await
+                .unwrap();
+        }
+
+        let document = Document::new(
+            source
+                .list_with_body(None, &EntryFilter::default())
+                .await
+                .unwrap(),
+            source.tags_by_name().await.unwrap(),
+        );
+        let json = serde_json::to_string(&document).unwrap();
+        let imported = parse(&json).unwrap();
+        assert_eq!(imported, document);
+
+        let destination = Archive::open(
+            tmp_dir.path().join("destination.db"),
+            OpenOptions::default(),
+        )
+        .unwrap();
+        for exported in &imported.entries {
+            destination
+                .restore(&exported.entry, &exported.body, &exported.tags, false)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            destination
+                .list_with_body(None, &EntryFilter::default())
+                .await
+                .unwrap(),
+            source
+                .list_with_body(None, &EntryFilter::default())
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            destination.tags_by_name().await.unwrap(),
+            source.tags_by_name().await.unwrap()
+        );
+        assert_eq!(
+            destination
+                .list_all(&EntryFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            entries.len()
+        );
+    }
+}
@@ -0,0 +1,857 @@
+use crate::archive::{ArchiveEntry, LatestEntry};
+use crate::helper::{format_size, relative_time};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 端末幅がこれを下回る場合は 1 レコードを 2 行に分けて表示する
+pub const NARROW_WIDTH: usize = 80;
+
+/// 2 行表示のときにパスの行に付けるインデント
+const PATH_INDENT: &str = "    ";
+
+/// パスを切り詰めて表示する場合の最小の文字数
+/// これより狭い場合はパスの行を省略する
+const MIN_PATH_WIDTH: usize = 12;
+
+/// 隔離されているアーカイブの日時の後ろに付ける印
+pub const QUARANTINED_MARK: &str = "[QUARANTINED]";
+
+/// 一覧に表示する日時の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// タイムゾーンにおける日時
+    Absolute(Tz),
+    /// この日時からの経過時間 (`3 days ago`)
+    Relative(DateTime<Utc>),
+    /// UTC の RFC3339 (--utc)
+    Utc,
+}
+
+impl TimeFormat {
+    pub fn format(&self, at: DateTime<Utc>) -> String {
+        match self {
+            TimeFormat::Absolute(timezone) => at.with_timezone(timezone).to_string(),
+            TimeFormat::Relative(now) => relative_time(at, *now),
+            TimeFormat::Utc => format_rfc3339(at),
+        }
+    }
+}
+
+/// 日時を UTC の RFC3339 (`2024-01-02T03:04:05Z`) にする
+/// JSON などの機械向けの出力は、表示の設定にかかわらずこの形式にする
+pub fn format_rfc3339(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+}
+
+/// アーカイブの一覧の出力形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum EntryFormat {
+    /// render_text による表示
+    #[default]
+    Text,
+    /// アーカイブのオブジェクトの配列
+    Json,
+    /// 1 行に 1 件のアーカイブのオブジェクト (JSON Lines)
+    Jsonl,
+    /// 見出しの行を付けたカンマ区切り
+    Csv,
+    /// 見出しの行を付けたタブ区切り
+    Tsv,
+    /// 名前、パス、登録日時をタブで区切り、1 件ごとに NUL で区切ったもの (--print0)
+    #[value(skip)]
+    Print0,
+    /// 名前だけを 1 件ごとに NUL で区切ったもの (--print0 --names-only)
+    #[value(skip)]
+    Print0Names,
+}
+
+/// 標準出力が接続されている端末の幅を取得する
+/// 端末に接続されていない場合は None を返す
+pub fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size().map(|(terminal_size::Width(width), _)| width as usize)
+}
+
+/// 1 行表示の列の間に置く空白
+const COLUMN_GAP: &str = "  ";
+
+/// アーカイブの一覧を表示用の文字列に整形する
+///
+/// width が None または NARROW_WIDTH 以上の場合は 1 レコードを 1 行で、列の幅を揃えた表にする (render_table)。
+/// NARROW_WIDTH 未満の場合は 1 行目に名前と日時、2 行目にインデントしたパスを表示し、
+/// 幅が足りなければ次の優先順で列を省略する。
+/// 名前は次のコマンドにコピーして使うため、常に省略せずに表示する。
+///
+/// 1. 本文の大きさ
+/// 2. 日時
+/// 3. パス (先頭側を `…` で切り詰め、それでも収まらない場合は行ごと省略)
+///
+/// メッセージのあるアーカイブは、1 行表示では末尾に、2 行表示ではパスの次の行に表示する。
+/// 日時の後ろには本文の大きさを付け、隔離されているアーカイブはさらに QUARANTINED_MARK を、
+/// パスのアーカイブの件数がある場合は `versions: N` を付ける。
+fn render_text(records: &[Record], time: &TimeFormat, width: Option<usize>) -> String {
+    let rows = records
+        .iter()
+        .map(|record| TextRow::new(record, time))
+        .collect::<Vec<_>>();
+    match width {
+        Some(width) if width < NARROW_WIDTH => {
+            rows.iter().map(|row| render_narrow(row, width)).collect()
+        }
+        _ => render_table(&rows, width),
+    }
+}
+
+/// テキストで表示する 1 レコードの列
+struct TextRow<'a> {
+    entry: &'a ArchiveEntry,
+    created_at: String,
+    size: String,
+    /// QUARANTINED_MARK と `versions: N` のうち付けるもの
+    marks: Vec<String>,
+}
+
+impl<'a> TextRow<'a> {
+    fn new(record: &Record<'a>, time: &TimeFormat) -> Self {
+        let mut marks = Vec::new();
+        if record.entry.quarantined {
+            marks.push(QUARANTINED_MARK.to_string());
+        }
+        if let Some(versions) = record.versions {
+            marks.push(format!("versions: {}", versions));
+        }
+        Self {
+            entry: record.entry,
+            created_at: time.format(record.entry.created_at),
+            size: format_size(record.entry.size),
+            marks,
+        }
+    }
+}
+
+/// 名前、パス、日時、大きさの列の幅を一覧の中で最も長いものに揃えて 1 行ずつ表示する
+/// パスは引用符で囲まずに表示し、名前とパスは左に、大きさは右に寄せる
+/// width を指定した場合は、行がその幅に収まるようパスの中ほどを `…` で切り詰める
+/// 印とメッセージは幅を揃えずに末尾に付ける
+fn render_table(rows: &[TextRow], width: Option<usize>) -> String {
+    let column_width = |len: fn(&TextRow) -> usize| rows.iter().map(len).max().unwrap_or(0);
+    let name_width = column_width(|row| row.entry.name.chars().count());
+    let created_at_width = column_width(|row| row.created_at.chars().count());
+    let size_width = column_width(|row| row.size.chars().count());
+    let mut path_width = column_width(|row| row.entry.path.chars().count());
+    if let Some(width) = width {
+        let others = name_width + created_at_width + size_width + COLUMN_GAP.len() * 3;
+        path_width = path_width.min(width.saturating_sub(others).max(MIN_PATH_WIDTH));
+    }
+
+    let mut output = String::new();
+    for row in rows {
+        output.push_str(&format!(
+            "{:<name_width$}{gap}{:<path_width$}{gap}{:<created_at_width$}{gap}{:>size_width$}",
+            row.entry.name,
+            truncate_middle(&row.entry.path, path_width),
+            row.created_at,
+            row.size,
+            gap = COLUMN_GAP,
+        ));
+        if !row.marks.is_empty() {
+            output.push_str(&format!("{}{}", COLUMN_GAP, row.marks.join(" ")));
+        }
+        if let Some(message) = &row.entry.message {
+            output.push_str(&format!("{}{:?}", COLUMN_GAP, message));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn render_narrow(row: &TextRow, width: usize) -> String {
+    let entry = row.entry;
+    let mut output = String::new();
+    let marks = row
+        .marks
+        .iter()
+        .map(|mark| format!(" {}", mark))
+        .collect::<String>();
+    let headers = [
+        format!("{} {}{}", row.created_at, row.size, marks),
+        format!("{}{}", row.created_at, marks),
+    ];
+    let name_len = entry.name.chars().count();
+    match headers
+        .iter()
+        .find(|header| name_len + 2 + header.chars().count() <= width)
+    {
+        Some(header) => output.push_str(&format!("{}  {}\n", entry.name, header)),
+        None => output.push_str(&format!("{}\n", entry.name)),
+    }
+
+    let path_width = width.saturating_sub(PATH_INDENT.len());
+    if let Some(path) = truncate_head(&entry.path, path_width) {
+        output.push_str(&format!("{}{}\n", PATH_INDENT, path));
+    }
+    if let Some(message) = &entry.message {
+        output.push_str(&format!(
+            "{}{}\n",
+            PATH_INDENT,
+            truncate_tail(message, path_width)
+        ));
+    }
+    output
+}
+
+/// 文字列が width 文字に収まるよう末尾側を `…` に置き換える
+fn truncate_tail(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(width.saturating_sub(1)).collect();
+    format!("{}…", head)
+}
+
+/// 文字列が width 文字に収まるよう中ほどを `…` に置き換える
+/// ファイルパスの場合、プロジェクトを表す先頭側とファイル名の末尾側を残す
+fn truncate_middle(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_string();
+    }
+    let kept = width.saturating_sub(1);
+    let head = kept / 2;
+    let tail = kept - head;
+    let head_text: String = text.chars().take(head).collect();
+    let tail_text: String = text.chars().skip(len - tail).collect();
+    format!("{}…{}", head_text, tail_text)
+}
+
+/// 文字列が width 文字に収まるよう先頭側を `…` に置き換える
+/// MIN_PATH_WIDTH を下回る場合は None を返す
+fn truncate_head(text: &str, width: usize) -> Option<String> {
+    let len = text.chars().count();
+    if len <= width {
+        return Some(text.to_string());
+    }
+    if width < MIN_PATH_WIDTH {
+        return None;
+    }
+    let tail: String = text.chars().skip(len - (width - 1)).collect();
+    Some(format!("…{}", tail))
+}
+
+/// 表示するアーカイブの一覧
+#[derive(Debug, Clone, Copy)]
+pub enum Listing<'a> {
+    /// アーカイブをそのまま並べた一覧
+    Entries(&'a [ArchiveEntry]),
+    /// パスごとに最新のアーカイブを並べた一覧
+    /// それぞれのパスのアーカイブの件数を versions の列に表示する
+    Latest(&'a [LatestEntry]),
+}
+
+impl<'a> Listing<'a> {
+    fn records(&self) -> Vec<Record<'a>> {
+        match self {
+            Listing::Entries(entries) => entries
+                .iter()
+                .map(|entry| Record {
+                    entry,
+                    versions: None,
+                })
+                .collect(),
+            Listing::Latest(entries) => entries
+                .iter()
+                .map(|latest| Record {
+                    entry: &latest.entry,
+                    versions: Some(latest.versions),
+                })
+                .collect(),
+        }
+    }
+
+    /// JSON, CSV, TSV で出力する列の名前
+    fn columns(&self) -> Vec<&'static str> {
+        let mut columns = COLUMNS.to_vec();
+        if let Listing::Latest(_) = self {
+            columns.push(VERSIONS_COLUMN);
+        }
+        columns
+    }
+}
+
+/// アーカイブの一覧を format の形式に整形する
+/// Text の場合は render_text で time と width に合わせて表示する
+/// JSON, CSV, TSV の日時は time によらず UTC の RFC3339 にする
+pub fn render(
+    listing: Listing,
+    format: EntryFormat,
+    time: &TimeFormat,
+    width: Option<usize>,
+) -> serde_json::Result<String> {
+    let records = listing.records();
+    let output = match format {
+        EntryFormat::Text => render_text(&records, time, width),
+        EntryFormat::Json => format!("{}\n", serde_json::to_string_pretty(&records)?),
+        EntryFormat::Jsonl => {
+            let mut output = String::new();
+            for record in &records {
+                output.push_str(&serde_json::to_string(record)?);
+                output.push('\n');
+            }
+            output
+        }
+        EntryFormat::Csv => render_delimited(&listing.columns(), &records, ',', quote_csv),
+        EntryFormat::Tsv => render_delimited(&listing.columns(), &records, '\t', escape_tsv),
+        EntryFormat::Print0 => render_print0(&records),
+        EntryFormat::Print0Names => records
+            .iter()
+            .map(|Record { entry, .. }| format!("{}\0", entry.name))
+            .collect(),
+    };
+    Ok(output)
+}
+
+/// 名前、パス、UTC の RFC3339 の登録日時をタブで区切り、各レコードの後ろに NUL を置く
+/// 見出しの行は付けない
+fn render_print0(records: &[Record]) -> String {
+    let mut output = String::new();
+    for Record { entry, .. } in records {
+        output.push_str(&format!(
+            "{}\t{}\t{}\0",
+            entry.name,
+            entry.path,
+            format_rfc3339(entry.created_at)
+        ));
+    }
+    output
+}
+
+/// JSON, CSV, TSV で出力する列の名前
+/// 列は COLUMNS と column_values に同じ順で加えれば、すべての形式に同じ名前と順序で現れる
+const COLUMNS: [&str; 8] = [
+    "name",
+    "path",
+    "created_at",
+    "checksum",
+    "message",
+    "user",
+    "quarantined",
+    "size",
+];
+
+/// Listing::Latest で COLUMNS の後ろに加える、パスのアーカイブの件数の列
+const VERSIONS_COLUMN: &str = "versions";
+
+/// COLUMNS の各列の値
+/// created_at は UTC の RFC3339 に、size はバイト数にする
+fn column_values(entry: &ArchiveEntry) -> [serde_json::Value; COLUMNS.len()] {
+    [
+        entry.name.as_str().into(),
+        entry.path.as_str().into(),
+        format_rfc3339(entry.created_at).into(),
+        entry.checksum.as_str().into(),
+        entry.message.as_deref().into(),
+        entry.user.as_deref().into(),
+        entry.quarantined.into(),
+        entry.size.into(),
+    ]
+}
+
+/// 一覧の 1 行に出力するアーカイブ
+#[derive(Debug, Clone, Copy)]
+struct Record<'a> {
+    entry: &'a ArchiveEntry,
+    /// Listing::Latest の場合のパスのアーカイブの件数
+    versions: Option<u64>,
+}
+
+impl Record<'_> {
+    /// Listing::columns の順に並べた列の値
+    fn values(&self) -> Vec<serde_json::Value> {
+        let mut values = column_values(self.entry).to_vec();
+        if let Some(versions) = self.versions {
+            values.push(versions.into());
+        }
+        values
+    }
+}
+
+impl Serialize for Record<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let names = COLUMNS
+            .iter()
+            .chain(self.versions.map(|_| &VERSIONS_COLUMN));
+        let values = self.values();
+        let mut map = serializer.serialize_map(Some(values.len()))?;
+        for (name, value) in names.zip(values) {
+            map.serialize_entry(name, &value)?;
+        }
+        map.end()
+    }
+}
+
+/// 見出しの行に続けて、アーカイブを 1 行ずつ delimiter で区切って並べる
+/// 値のない列は空にし、各フィールドは escape で区切り文字などを含めても崩れないようにする
+fn render_delimited(
+    columns: &[&str],
+    records: &[Record],
+    delimiter: char,
+    escape: fn(&str) -> String,
+) -> String {
+    let mut output = String::new();
+    let mut push_row = |fields: Vec<String>| {
+        output.push_str(&fields.join(&delimiter.to_string()));
+        output.push('\n');
+    };
+    push_row(columns.iter().map(|name| escape(name)).collect());
+    for record in records {
+        push_row(
+            record
+                .values()
+                .into_iter()
+                .map(|value| match value {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(value) => escape(&value),
+                    value => escape(&value.to_string()),
+                })
+                .collect(),
+        );
+    }
+    output
+}
+
+/// RFC 4180 に従い、カンマや引用符、改行を含むフィールドを引用符で囲む
+fn quote_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// TSV は引用符で囲む方法がないため、バックスラッシュとタブ、改行をエスケープする
+fn escape_tsv(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// 木構造で表示するときの 1 階層ごとのインデント
+const TREE_INDENT: &str = "  ";
+
+/// 木構造のディレクトリ
+#[derive(Default)]
+struct TreeNode<'a> {
+    dirs: BTreeMap<String, TreeNode<'a>>,
+    /// このディレクトリ直下のファイルの名前とアーカイブ
+    files: Vec<(String, &'a LatestEntry)>,
+}
+
+impl<'a> TreeNode<'a> {
+    fn insert(&mut self, dirs: &[String], file: String, entry: &'a LatestEntry) {
+        match dirs.split_first() {
+            Some((dir, rest)) => self
+                .dirs
+                .entry(dir.clone())
+                .or_default()
+                .insert(rest, file, entry),
+            None => self.files.push((file, entry)),
+        }
+    }
+}
+
+/// パスごとの最新の一覧を root からのディレクトリの木構造に整形する
+///
+/// ディレクトリの行の下に、そのディレクトリ直下のファイルを 1 階層深くインデントして
+/// 名前、日時、本文の大きさ、アーカイブの件数と共に表示し、続けて下位のディレクトリを表示する。
+/// ファイルがなくディレクトリを 1 つだけ含むディレクトリは `a/b/` のように 1 行にまとめる。
+/// root 配下にないパス (大文字と小文字を区別せずに一致したものなど) は、
+/// 木構造の後に親ディレクトリごとにまとめて表示する。
+pub fn render_tree(root: &Path, entries: &[LatestEntry], time: &TimeFormat) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+    let mut tree = TreeNode::default();
+    let mut outside = BTreeMap::<String, TreeNode>::new();
+    for latest in entries {
+        let path = Path::new(&latest.entry.path);
+        let file = path.file_name().map_or_else(
+            || latest.entry.path.clone(),
+            |name| name.to_string_lossy().to_string(),
+        );
+        match path.strip_prefix(root) {
+            Ok(relative) => {
+                let dirs = relative
+                    .parent()
+                    .into_iter()
+                    .flat_map(Path::iter)
+                    .map(|dir| dir.to_string_lossy().to_string())
+                    .collect::<Vec<_>>();
+                tree.insert(&dirs, file, latest);
+            }
+            Err(_) => {
+                let parent = path
+                    .parent()
+                    .map(|parent| parent.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                outside.entry(parent).or_default().insert(&[], file, latest);
+            }
+        }
+    }
+
+    let mut output = String::new();
+    if !tree.files.is_empty() || !tree.dirs.is_empty() {
+        output.push_str(&dir_line(&root.to_string_lossy(), 0));
+        render_tree_node(&mut tree, 1, time, &mut output);
+    }
+    for (parent, mut node) in outside {
+        output.push_str(&dir_line(&parent, 0));
+        render_tree_node(&mut node, 1, time, &mut output);
+    }
+    output
+}
+
+fn render_tree_node(node: &mut TreeNode, depth: usize, time: &TimeFormat, output: &mut String) {
+    let indent = TREE_INDENT.repeat(depth);
+    node.files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (file, latest) in &node.files {
+        output.push_str(&format!(
+            "{}{} {} {} {} versions: {}\n",
+            indent,
+            file,
+            latest.entry.name,
+            time.format(latest.entry.created_at),
+            format_size(latest.entry.size),
+            latest.versions
+        ));
+    }
+    for (dir, child) in std::mem::take(&mut node.dirs) {
+        let (mut label, mut child) = (dir, child);
+        while child.files.is_empty() && child.dirs.len() == 1 {
+            let (sub, grandchild) = child.dirs.pop_first().expect("dirs has one entry");
+            label = format!("{}/{}", label, sub);
+            child = grandchild;
+        }
+        output.push_str(&dir_line(&label, depth));
+        render_tree_node(&mut child, depth + 1, time, output);
+    }
+}
+
+/// ディレクトリの行 ディレクトリであることがわかるよう末尾に `/` を付ける
+fn dir_line(dir: &str, depth: usize) -> String {
+    format!(
+        "{}{}/\n",
+        TREE_INDENT.repeat(depth),
+        dir.trim_end_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    const UTC: TimeFormat = TimeFormat::Absolute(Tz::UTC);
+
+    fn render_entries(entries: &[ArchiveEntry], time: &TimeFormat, width: Option<usize>) -> String {
+        render_text(&Listing::Entries(entries).records(), time, width)
+    }
+
+    fn fixture() -> Vec<ArchiveEntry> {
+        vec![
+            ArchiveEntry {
+                name: "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB".to_string(),
+                path: "/home/user/projects/api/.env".to_string(),
+                created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+                checksum: "checksum".to_string(),
+                message: None,
+                user: None,
+                quarantined: false,
+                size: 12,
+            },
+            ArchiveEntry {
+                name: "backup.01HMZ3Q0J5Y8W6T9Z2K4N7P1AC".to_string(),
+                path: "/home/user/projects/client-alpha/services/payment-api/.env.production"
+                    .to_string(),
+                created_at: Utc.with_ymd_and_hms(2024, 6, 1, 12, 3, 0).unwrap(),
+                checksum: "checksum".to_string(),
+                message: None,
+                user: None,
+                quarantined: false,
+                size: 1229,
+            },
+        ]
+    }
+
+    #[test]
+    fn 幅60では2行表示になり長いパスは先頭が切り詰められる() {
+        let output = render_entries(&fixture(), &UTC, Some(60));
+        assert_eq!(
+            output,
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  2024-01-02 03:04:05 UTC 12 B\n\
+             \x20   /home/user/projects/api/.env\n\
+             backup.01HMZ3Q0J5Y8W6T9Z2K4N7P1AC  2024-06-01 12:03:00 UTC\n\
+             \x20   …jects/client-alpha/services/payment-api/.env.production\n"
+        );
+    }
+
+    #[test]
+    fn 幅100では1行表示になり長いパスは中ほどが切り詰められる() {
+        let output = render_entries(&fixture(), &UTC, Some(100));
+        assert_eq!(
+            output,
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB         /home/user/projects/api/.env     2024-01-02 03:04:05 UTC     12 B\n\
+             backup.01HMZ3Q0J5Y8W6T9Z2K4N7P1AC  /home/user/proj….env.production  2024-06-01 12:03:00 UTC  1.2 KiB\n"
+        );
+    }
+
+    #[test]
+    fn 幅200では列の幅を揃えて切り詰めずに表示する() {
+        let output = render_entries(&fixture(), &UTC, Some(200));
+        assert_eq!(
+            output,
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB         /home/user/projects/api/.env                                           2024-01-02 03:04:05 UTC     12 B\n\
+             backup.01HMZ3Q0J5Y8W6T9Z2K4N7P1AC  /home/user/projects/client-alpha/services/payment-api/.env.production  2024-06-01 12:03:00 UTC  1.2 KiB\n"
+        );
+        // 端末でない場合は幅の制限なく表示する
+        assert_eq!(output, render_entries(&fixture(), &UTC, None));
+    }
+
+    #[test]
+    fn relativeでは日時をnowからの経過時間で表示する() {
+        let entries = fixture();
+        let now = entries[0].created_at + chrono::Duration::days(3);
+        assert_eq!(
+            render_entries(&entries[..1], &TimeFormat::Relative(now), None),
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  /home/user/projects/api/.env  3 days ago  12 B\n"
+        );
+        // JSON では経過時間にせず日時を出力する
+        let json = render(
+            Listing::Entries(&entries[..1]),
+            EntryFormat::Jsonl,
+            &TimeFormat::Relative(now),
+            None,
+        )
+        .unwrap();
+        assert!(json.contains("\"created_at\":\"2024-01-02T03:04:05Z\""));
+    }
+
+    #[test]
+    fn utcでは日時をutcのrfc3339で表示しjsonは表示の形式によらない() {
+        let entries = fixture();
+        let tokyo = TimeFormat::Absolute(chrono_tz::Asia::Tokyo);
+        assert_eq!(
+            render_entries(&entries[..1], &TimeFormat::Utc, None),
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  /home/user/projects/api/.env  2024-01-02T03:04:05Z  12 B\n"
+        );
+        assert_eq!(
+            render_entries(&entries[..1], &tokyo, None),
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  /home/user/projects/api/.env  2024-01-02 12:04:05 JST  12 B\n"
+        );
+        let json = |time: &TimeFormat| {
+            render(Listing::Entries(&entries), EntryFormat::Json, time, None).unwrap()
+        };
+        assert_eq!(json(&TimeFormat::Utc), json(&tokyo));
+        assert!(json(&tokyo).contains("\"created_at\": \"2024-01-02T03:04:05Z\""));
+    }
+
+    #[test]
+    fn 中ほどの切り詰めは先頭と末尾を残す() {
+        assert_eq!(truncate_middle("/home/user/.env", 15), "/home/user/.env");
+        assert_eq!(truncate_middle("/home/user/.env", 8), "/ho….env");
+        assert_eq!(truncate_middle("/あいう/えお/.env", 9), "/あいう….env");
+    }
+
+    #[test]
+    fn 極端に狭い幅では日時とパスを省略しても名前は省略しない() {
+        let output = render_entries(&fixture()[1..], &UTC, Some(14));
+        assert_eq!(output, "backup.01HMZ3Q0J5Y8W6T9Z2K4N7P1AC\n");
+    }
+
+    #[test]
+    fn 隔離されているアーカイブは日時の後ろに印を付ける() {
+        let mut entries = fixture();
+        entries.truncate(1);
+        entries[0].quarantined = true;
+
+        assert_eq!(
+            render_entries(&entries, &UTC, None),
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  /home/user/projects/api/.env  2024-01-02 03:04:05 UTC  12 B  [QUARANTINED]\n"
+        );
+        assert_eq!(
+            render_entries(&entries, &UTC, Some(70)),
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  2024-01-02 03:04:05 UTC 12 B [QUARANTINED]\n\
+             \x20   /home/user/projects/api/.env\n"
+        );
+    }
+
+    #[test]
+    fn メッセージのあるアーカイブはメッセージも表示する() {
+        let mut entries = fixture();
+        entries.truncate(1);
+        entries[0].message = Some("before rotating DB password".to_string());
+
+        let output = render_entries(&entries, &UTC, None);
+        assert_eq!(
+            output,
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  /home/user/projects/api/.env  2024-01-02 03:04:05 UTC  12 B  \"before rotating DB password\"\n"
+        );
+
+        let output = render_entries(&entries, &UTC, Some(30));
+        assert_eq!(
+            output,
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB\n\
+             \x20   …me/user/projects/api/.env\n\
+             \x20   before rotating DB passwo…\n"
+        );
+    }
+
+    #[test]
+    fn jsonとjsonlでは同じ形のオブジェクトを出力する() {
+        let entries = fixture();
+        let render = |entries: &[ArchiveEntry], format| {
+            render(Listing::Entries(entries), format, &UTC, None)
+        };
+        let lines = render(&entries, EntryFormat::Jsonl).unwrap();
+        let lines = lines
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["name"], "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB");
+        assert_eq!(lines[0]["path"], "/home/user/projects/api/.env");
+        assert_eq!(lines[0]["created_at"], "2024-01-02T03:04:05Z");
+        assert_eq!(lines[0]["checksum"], "checksum");
+        // 大きさは表示用の単位を付けずにバイト数で出力する
+        assert_eq!(lines[1]["size"], 1229);
+
+        let array = render(&entries, EntryFormat::Json).unwrap();
+        let array = serde_json::from_str::<serde_json::Value>(&array).unwrap();
+        assert_eq!(array, serde_json::Value::Array(lines));
+        assert_eq!(render(&[], EntryFormat::Json).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn print0ではフィールドをタブで区切りレコードをnulで区切る() {
+        let mut entries = fixture();
+        entries[0].path = "/home/user/my project/.env".to_string();
+        let output = render(Listing::Entries(&entries), EntryFormat::Print0, &UTC, None).unwrap();
+        assert_eq!(
+            output,
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB\t/home/user/my project/.env\t2024-01-02T03:04:05Z\0\
+             backup.01HMZ3Q0J5Y8W6T9Z2K4N7P1AC\t/home/user/projects/client-alpha/services/payment-api/.env.production\t2024-06-01T12:03:00Z\0"
+        );
+
+        entries[0].name = "my name".to_string();
+        let output = render(
+            Listing::Entries(&entries),
+            EntryFormat::Print0Names,
+            &UTC,
+            None,
+        )
+        .unwrap();
+        assert_eq!(output, "my name\0backup.01HMZ3Q0J5Y8W6T9Z2K4N7P1AC\0");
+    }
+
+    #[test]
+    fn csvとtsvではカンマや引用符を含むパスも列が崩れない() {
+        let mut entries = fixture();
+        entries.truncate(1);
+        entries[0].path = "/home/user/a,b/\"quoted\"\t/.env".to_string();
+        entries[0].message = Some("line1\nline2".to_string());
+
+        let csv = render(Listing::Entries(&entries), EntryFormat::Csv, &UTC, None).unwrap();
+        assert_eq!(
+            csv,
+            "name,path,created_at,checksum,message,user,quarantined,size\n\
+             01HMZ3Q0J5Y8W6T9Z2K4N7P1AB,\"/home/user/a,b/\"\"quoted\"\"\t/.env\",2024-01-02T03:04:05Z,checksum,\"line1\nline2\",,false,12\n"
+        );
+
+        let tsv = render(Listing::Entries(&entries), EntryFormat::Tsv, &UTC, None).unwrap();
+        assert_eq!(
+            tsv,
+            "name\tpath\tcreated_at\tchecksum\tmessage\tuser\tquarantined\tsize\n\
+             01HMZ3Q0J5Y8W6T9Z2K4N7P1AB\t/home/user/a,b/\"quoted\"\\t/.env\t2024-01-02T03:04:05Z\tchecksum\tline1\\nline2\t\tfalse\t12\n"
+        );
+
+        // 一覧が空でも見出しの行は出力する
+        assert_eq!(
+            render(Listing::Entries(&[]), EntryFormat::Csv, &UTC, None).unwrap(),
+            "name,path,created_at,checksum,message,user,quarantined,size\n"
+        );
+    }
+
+    #[test]
+    fn treeではディレクトリごとにまとめてroot配下にないパスは最後に表示する() {
+        let latest = |path: &str, name: &str, versions: u64| LatestEntry {
+            entry: ArchiveEntry {
+                name: name.to_string(),
+                path: path.to_string(),
+                created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+                checksum: "checksum".to_string(),
+                message: None,
+                user: None,
+                quarantined: false,
+                size: 1229,
+            },
+            versions,
+        };
+        let entries = [
+            latest("/src/mono/services/pay/api/.env", "pay", 1),
+            latest("/src/mono/web/.env.local", "web-local", 2),
+            latest("/src/mono/.env", "root", 3),
+            latest("/src/mono/web/.env", "web", 1),
+            latest("/src/mono-old/.env", "old", 4),
+            latest("/src/Mono/.env", "upper", 1),
+        ];
+
+        assert_eq!(
+            render_tree(Path::new("/src/mono/"), &entries, &UTC),
+            [
+                "/src/mono/",
+                "  .env root 2024-01-02 03:04:05 UTC 1.2 KiB versions: 3",
+                "  services/pay/api/",
+                "    .env pay 2024-01-02 03:04:05 UTC 1.2 KiB versions: 1",
+                "  web/",
+                "    .env web 2024-01-02 03:04:05 UTC 1.2 KiB versions: 1",
+                "    .env.local web-local 2024-01-02 03:04:05 UTC 1.2 KiB versions: 2",
+                "/src/Mono/",
+                "  .env upper 2024-01-02 03:04:05 UTC 1.2 KiB versions: 1",
+                "/src/mono-old/",
+                "  .env old 2024-01-02 03:04:05 UTC 1.2 KiB versions: 4",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(render_tree(Path::new("/src/mono"), &[], &UTC), "");
+    }
+
+    #[test]
+    fn パスごとの最新の一覧ではアーカイブの件数も出力する() {
+        let mut entries = fixture();
+        entries.truncate(1);
+        let latest = [LatestEntry {
+            entry: entries.remove(0),
+            versions: 3,
+        }];
+
+        let text = render(Listing::Latest(&latest), EntryFormat::Text, &UTC, None).unwrap();
+        assert_eq!(
+            text,
+            "01HMZ3Q0J5Y8W6T9Z2K4N7P1AB  /home/user/projects/api/.env  2024-01-02 03:04:05 UTC  12 B  versions: 3\n"
+        );
+
+        let csv = render(Listing::Latest(&latest), EntryFormat::Csv, &UTC, None).unwrap();
+        assert_eq!(
+            csv,
+            "name,path,created_at,checksum,message,user,quarantined,size,versions\n\
+             01HMZ3Q0J5Y8W6T9Z2K4N7P1AB,/home/user/projects/api/.env,2024-01-02T03:04:05Z,checksum,,,false,12,3\n"
+        );
+
+        let json = render(Listing::Latest(&latest), EntryFormat::Jsonl, &UTC, None).unwrap();
+        assert!(json.ends_with(",\"versions\":3}\n"));
+    }
+}
@@ -0,0 +1,211 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// パスワードマネージャーから取り出した .env ファイルの内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretItem {
+    /// パスワードマネージャー上のアイテムの ID
+    pub id: String,
+    pub body: String,
+}
+
+/// パスワードマネージャーの CLI からアイテムを取り出す
+pub trait PasswordManager {
+    /// アーカイブのメッセージに記録するパスワードマネージャーの名前
+    fn name(&self) -> &'static str;
+    /// item のセキュアノート、または field で指定したフィールドの内容を取り出す
+    fn fetch(&self, item: &str, field: Option<&str>) -> anyhow::Result<SecretItem>;
+}
+
+/// 1Password CLI (`op`)
+pub struct OnePassword {
+    pub program: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordItem {
+    id: String,
+    #[serde(default)]
+    fields: Vec<OnePasswordField>,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordField {
+    id: String,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+}
+
+impl PasswordManager for OnePassword {
+    fn name(&self) -> &'static str {
+        "1Password"
+    }
+
+    fn fetch(&self, item: &str, field: Option<&str>) -> anyhow::Result<SecretItem> {
+        let output = run(&self.program, &["item", "get", item, "--format", "json"])?;
+        let parsed: OnePasswordItem = serde_json::from_str(&output).map_err(|e| {
+            anyhow::anyhow!("unexpected output from {}: {}", self.program.display(), e)
+        })?;
+        let field = field.unwrap_or("notesPlain");
+        let body = parsed
+            .fields
+            .into_iter()
+            .find(|f| f.id == field || f.label.as_deref() == Some(field))
+            .and_then(|f| f.value)
+            .ok_or_else(|| anyhow::anyhow!("field {} not found in item {}", field, item))?;
+        Ok(SecretItem {
+            id: parsed.id,
+            body,
+        })
+    }
+}
+
+/// Bitwarden CLI (`bw`)
+pub struct Bitwarden {
+    pub program: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct BitwardenItem {
+    id: String,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    fields: Vec<BitwardenField>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenField {
+    name: String,
+    #[serde(default)]
+    value: Option<String>,
+}
+
+impl PasswordManager for Bitwarden {
+    fn name(&self) -> &'static str {
+        "Bitwarden"
+    }
+
+    fn fetch(&self, item: &str, field: Option<&str>) -> anyhow::Result<SecretItem> {
+        let output = run(&self.program, &["get", "item", item])?;
+        let parsed: BitwardenItem = serde_json::from_str(&output).map_err(|e| {
+            anyhow::anyhow!("unexpected output from {}: {}", self.program.display(), e)
+        })?;
+        let body = match field {
+            Some(field) => parsed
+                .fields
+                .into_iter()
+                .find(|f| f.name == field)
+                .and_then(|f| f.value)
+                .ok_or_else(|| anyhow::anyhow!("field {} not found in item {}", field, item))?,
+            None => parsed
+                .notes
+                .ok_or_else(|| anyhow::anyhow!("item {} has no notes", item))?,
+        };
+        Ok(SecretItem {
+            id: parsed.id,
+            body,
+        })
+    }
+}
+
+/// CLI を実行して標準出力を返す
+/// 秘密情報は引数や一時ファイルを経由させず、パイプで受け取る
+fn run(program: &PathBuf, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", program.display(), e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} exited with {}: {}",
+            program.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| anyhow::anyhow!("output from {} is not UTF-8", program.display()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// 決まった出力と終了コードを返す偽の CLI を作る
+    fn fake_cli(dir: &std::path::Path, stdout: &str, exit_code: i32) -> PathBuf {
+        let program = dir.join("fake-cli");
+        let script = format!(
+            "#!/bin/sh\ncat <<'JSON'\n{}\nJSON\necho 'fake error' >&2\nexit {}\n",
+            stdout, exit_code
+        );
+        std::fs::write(&program, script).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        program
+    }
+
+    #[test]
+    fn onepasswordのセキュアノートを取り出せる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let program = fake_cli(
+            tmp_dir.path(),
+            r#"{"id":"op-item-id","fields":[{"id":"notesPlain","label":"notesPlain","value":"FOO=BAR\nBAZ=QUX"},{"id":"x1","label":"env","value":"ENV=1"}]}"#,
+            0,
+        );
+        let manager = OnePassword { program };
+
+        let item = manager.fetch("my-item", None).unwrap();
+        assert_eq!(
+            item,
+            SecretItem {
+                id: "op-item-id".to_string(),
+                body: "FOO=BAR\nBAZ=QUX".to_string(),
+            }
+        );
+        let item = manager.fetch("my-item", Some("env")).unwrap();
+        assert_eq!(item.body, "ENV=1");
+    }
+
+    #[test]
+    fn bitwardenのノートを取り出せる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let program = fake_cli(
+            tmp_dir.path(),
+            r#"{"id":"bw-item-id","notes":"FOO=BAR","fields":[{"name":"env","value":"ENV=1"}]}"#,
+            0,
+        );
+        let manager = Bitwarden { program };
+
+        let item = manager.fetch("my-item", None).unwrap();
+        assert_eq!(item.id, "bw-item-id");
+        assert_eq!(item.body, "FOO=BAR");
+        assert_eq!(manager.fetch("my-item", Some("env")).unwrap().body, "ENV=1");
+    }
+
+    #[test]
+    fn cliが失敗した場合は標準エラー出力を含むエラーになる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let program = fake_cli(tmp_dir.path(), "", 1);
+        let manager = OnePassword { program };
+
+        let error = manager.fetch("my-item", None).unwrap_err().to_string();
+        assert!(error.contains("fake error"));
+    }
+
+    #[test]
+    fn 出力が解釈できない場合はエラーになる() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let program = fake_cli(tmp_dir.path(), "not json", 0);
+        let manager = Bitwarden { program };
+
+        let error = manager.fetch("my-item", None).unwrap_err().to_string();
+        assert!(error.contains("unexpected output"));
+    }
+}
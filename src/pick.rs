@@ -0,0 +1,210 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+/// 選択肢のアーカイブ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// 選択したときに出力する名前
+    pub name: String,
+    /// 一覧に表示し、絞り込みの対象にする文字列 (名前、パス、日時)
+    pub label: String,
+}
+
+/// pick の画面の状態
+#[derive(Debug)]
+pub struct State {
+    candidates: Vec<Candidate>,
+    /// 絞り込みの文字列
+    query: String,
+    /// query に一致する candidates の位置
+    matches: Vec<usize>,
+    /// matches のうち選択している位置
+    selected: usize,
+}
+
+/// キー入力の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// アーカイブを選んだ
+    Selected(String),
+    /// 選ばずに閉じた
+    Cancelled,
+}
+
+impl State {
+    pub fn new(candidates: Vec<Candidate>) -> Self {
+        let matches = (0..candidates.len()).collect();
+        Self {
+            candidates,
+            query: String::new(),
+            matches,
+            selected: 0,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Candidate> {
+        self.matches
+            .get(self.selected)
+            .map(|&index| &self.candidates[index])
+    }
+
+    fn refilter(&mut self) {
+        self.matches = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| fuzzy_match(&self.query, &candidate.label))
+            .map(|(index, _)| index)
+            .collect();
+        self.selected = 0;
+    }
+}
+
+/// キー入力で状態を更新し、選択が終わった場合はその結果を返す
+pub fn update(state: &mut State, key: KeyEvent) -> Option<Outcome> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Esc => return Some(Outcome::Cancelled),
+        KeyCode::Char('c') if ctrl => return Some(Outcome::Cancelled),
+        KeyCode::Enter => {
+            return state
+                .selected()
+                .map(|candidate| Outcome::Selected(candidate.name.clone()));
+        }
+        KeyCode::Up => state.selected = state.selected.saturating_sub(1),
+        KeyCode::Char('p') if ctrl => state.selected = state.selected.saturating_sub(1),
+        KeyCode::Down => state.selected = moved_down(state),
+        KeyCode::Char('n') if ctrl => state.selected = moved_down(state),
+        KeyCode::Backspace if !state.query.is_empty() => {
+            state.query.pop();
+            state.refilter();
+        }
+        KeyCode::Char(c) if !ctrl => {
+            state.query.push(c);
+            state.refilter();
+        }
+        _ => {}
+    }
+    None
+}
+
+fn moved_down(state: &State) -> usize {
+    (state.selected + 1).min(state.matches.len().saturating_sub(1))
+}
+
+/// query の文字が大文字と小文字を区別せずにこの順で text に含まれるかどうか
+/// 空白は区切りとして読み飛ばす
+pub fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .all(|q| text.any(|c| c == q))
+}
+
+/// 画面を描く
+pub fn render(frame: &mut Frame, state: &State) {
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(Paragraph::new(format!("> {}▏", state.query)), header);
+
+    let items = state
+        .matches
+        .iter()
+        .map(|&index| ListItem::new(state.candidates[index].label.as_str()));
+    let mut list_state = ListState::default().with_selected(Some(state.selected));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "archives {}/{}",
+                state.matches.len(),
+                state.candidates.len()
+            )))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+        body,
+        &mut list_state,
+    );
+
+    frame.render_widget(
+        Paragraph::new("type to filter, ↑↓: move, enter: select, esc: cancel"),
+        footer,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn picker() -> State {
+        State::new(
+            [
+                ("a", "a  /srv/api/.env  3 days ago"),
+                ("b", "b  /srv/web/.env  1 day ago"),
+                ("c", "c  /srv/api-worker/.env  just now"),
+            ]
+            .into_iter()
+            .map(|(name, label)| Candidate {
+                name: name.to_string(),
+                label: label.to_string(),
+            })
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn 入力した文字を順に含むものに絞り込み選んだ名前を返す() {
+        let mut state = picker();
+        for c in "APIwk".chars() {
+            assert_eq!(update(&mut state, key(KeyCode::Char(c))), None);
+        }
+        assert_eq!(state.selected().map(|c| c.name.as_str()), Some("c"));
+
+        update(&mut state, key(KeyCode::Backspace));
+        update(&mut state, key(KeyCode::Backspace));
+        assert_eq!(state.matches, [0, 2]);
+        update(&mut state, key(KeyCode::Down));
+        update(&mut state, key(KeyCode::Down));
+        assert_eq!(
+            update(&mut state, key(KeyCode::Enter)),
+            Some(Outcome::Selected("c".to_string()))
+        );
+    }
+
+    #[test]
+    fn 一致するものがなければenterでは閉じずescで取り消す() {
+        let mut state = picker();
+        update(&mut state, key(KeyCode::Char('z')));
+        assert_eq!(update(&mut state, key(KeyCode::Enter)), None);
+        assert_eq!(
+            update(&mut state, key(KeyCode::Esc)),
+            Some(Outcome::Cancelled)
+        );
+        assert_eq!(
+            update(
+                &mut picker(),
+                KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)
+            ),
+            Some(Outcome::Cancelled)
+        );
+    }
+
+    #[test]
+    fn fuzzy_matchは空白を区切りとして読み飛ばす() {
+        assert!(fuzzy_match("", "anything"));
+        assert!(fuzzy_match("api env", "/srv/api/.env"));
+        assert!(!fuzzy_match("envapi", "/srv/api/.env"));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::dotenv;
+use serde::Deserialize;
+use std::path::Path;
+
+/// アーカイブする .env ファイルの内容に課すポリシーの設定
+///
+/// ```toml
+/// [policy]
+/// enforce_on_push = "block"
+///
+/// [[policies]]
+/// name = "api"
+/// paths = ["**/api/.env*"]
+/// require = ["SENTRY_DSN"]
+/// forbid = ["DEBUG_*"]
+/// formats = [{ keys = ["*_URL"], pattern = "^https://" }]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    policy: Settings,
+    #[serde(default)]
+    policies: Vec<PolicyConfig>,
+}
+
+/// 設定ファイルの `[policy]`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default)]
+    enforce_on_push: Enforcement,
+}
+
+/// 設定ファイルの `[[policies]]` の 1 つ
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    name: Option<String>,
+    /// 対象にするアーカイブ元のパスのグロブ
+    paths: Vec<String>,
+    #[serde(default)]
+    require: Vec<String>,
+    #[serde(default)]
+    forbid: Vec<String>,
+    #[serde(default)]
+    formats: Vec<FormatConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FormatConfig {
+    keys: Vec<String>,
+    pattern: String,
+}
+
+/// push するときにポリシーを適用するかどうか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Enforcement {
+    /// 適用しない
+    #[default]
+    Off,
+    /// 違反を表示して登録する
+    Warn,
+    /// 違反があれば登録しない
+    Block,
+}
+
+/// 読み込んで検証したポリシーの一覧
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policies {
+    pub enforce_on_push: Enforcement,
+    policies: Vec<Policy>,
+}
+
+/// グロブは Policies::parse で検証済みのもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Policy {
+    name: String,
+    paths: Vec<String>,
+    require: Vec<String>,
+    forbid: Vec<String>,
+    formats: Vec<Format>,
+}
+
+#[derive(Debug, Clone)]
+struct Format {
+    keys: Vec<String>,
+    pattern: regex::Regex,
+}
+
+impl PartialEq for Format {
+    fn eq(&self, other: &Self) -> bool {
+        self.keys == other.keys && self.pattern.as_str() == other.pattern.as_str()
+    }
+}
+
+impl Eq for Format {}
+
+/// ポリシーに違反している箇所
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// 違反したポリシーの名前
+    pub policy: String,
+    pub key: String,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// require のキーが定義されていない
+    Missing,
+    /// forbid に一致するキーが定義されている
+    Forbidden { pattern: String },
+    /// 値が formats の pattern に一致しない
+    Format { pattern: String },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ViolationKind::Missing => {
+                write!(f, "{}: {} must be defined", self.policy, self.key)
+            }
+            ViolationKind::Forbidden { pattern } => write!(
+                f,
+                "{}: {} must not be defined (forbidden by {})",
+                self.policy, self.key, pattern
+            ),
+            ViolationKind::Format { pattern } => write!(
+                f,
+                "{}: value of {} must match {}",
+                self.policy, self.key, pattern
+            ),
+        }
+    }
+}
+
+impl Policies {
+    /// TOML のポリシーを読み込み、グロブと正規表現を検証する
+    pub fn parse(toml: &str) -> anyhow::Result<Self> {
+        let config: Config = toml::from_str(toml)?;
+        Self::new(config.policy, config.policies)
+    }
+
+    /// 設定ファイルの `[policy]` と `[[policies]]` を読み込み、グロブと正規表現を検証する
+    pub fn new(settings: Settings, policies: Vec<PolicyConfig>) -> anyhow::Result<Self> {
+        let policies = policies
+            .into_iter()
+            .enumerate()
+            .map(|(i, policy)| Policy::compile(policy, i))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            enforce_on_push: settings.enforce_on_push,
+            policies,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// path のアーカイブの本文 body が、path に一致するすべてのポリシーを満たすかを確かめる
+    pub fn check(&self, path: &Path, body: &str) -> Vec<Violation> {
+        let values = dotenv::values(body);
+
+        let mut violations = Vec::new();
+        for policy in &self.policies {
+            if !policy.paths.iter().any(|glob| is_match(glob, path)) {
+                continue;
+            }
+            for key in &policy.require {
+                if !values.iter().any(|(k, _)| k == key) {
+                    violations.push(policy.violation(key, ViolationKind::Missing));
+                }
+            }
+            for (key, value) in &values {
+                let key_path = Path::new(key);
+                if let Some(forbid) = policy.forbid.iter().find(|glob| is_match(glob, key_path)) {
+                    violations.push(policy.violation(
+                        key,
+                        ViolationKind::Forbidden {
+                            pattern: forbid.clone(),
+                        },
+                    ));
+                }
+                for format in &policy.formats {
+                    if format.keys.iter().any(|glob| is_match(glob, key_path))
+                        && !format.pattern.is_match(value)
+                    {
+                        violations.push(policy.violation(
+                            key,
+                            ViolationKind::Format {
+                                pattern: format.pattern.to_string(),
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+impl Policy {
+    /// name が省略された場合は `policies[i]` を名前にする
+    fn compile(config: PolicyConfig, i: usize) -> anyhow::Result<Self> {
+        let name = config.name.unwrap_or_else(|| format!("policies[{}]", i));
+        if config.paths.is_empty() {
+            anyhow::bail!("policy {} has no paths", name);
+        }
+        for glob in config.paths.iter().chain(&config.forbid) {
+            validate_glob(glob, &name)?;
+        }
+        let formats = config
+            .formats
+            .into_iter()
+            .map(|format| {
+                for glob in &format.keys {
+                    validate_glob(glob, &name)?;
+                }
+                let pattern = regex::Regex::new(&format.pattern).map_err(|e| {
+                    anyhow::anyhow!("policy {} has an invalid pattern: {}", name, e)
+                })?;
+                Ok(Format {
+                    keys: format.keys,
+                    pattern,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            name,
+            paths: config.paths,
+            require: config.require,
+            forbid: config.forbid,
+            formats,
+        })
+    }
+
+    fn violation(&self, key: &str, kind: ViolationKind) -> Violation {
+        Violation {
+            policy: self.name.clone(),
+            key: key.to_string(),
+            kind,
+        }
+    }
+}
+
+fn validate_glob(glob: &str, policy: &str) -> anyhow::Result<()> {
+    globmatch::Builder::new(glob)
+        .build_glob()
+        .map_err(|e| anyhow::anyhow!("policy {} has an invalid glob: {}", policy, e))?;
+    Ok(())
+}
+
+/// 検証済みのグロブが target に一致するかどうか
+fn is_match(glob: &str, target: &Path) -> bool {
+    globmatch::Builder::new(glob)
+        .build_glob()
+        .is_ok_and(|glob| glob.is_match(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policies(toml: &str) -> Policies {
+        Policies::parse(toml).unwrap()
+    }
+
+    #[test]
+    fn requireのキーがなければ違反になる() {
+        let policies = policies(
+            r#"
+            [[policies]]
+            name = "api"
+            paths = ["**/api/.env*"]
+            require = ["SENTRY_DSN", "DATABASE_URL"]
+            "#,
+        );
+        let violations = policies.check(Path::new("/work/api/.env"), "DATABASE_URL=x\n");
+        assert_eq!(
+            violations,
+            vec![Violation {
+                policy: "api".to_string(),
+                key: "SENTRY_DSN".to_string(),
+                kind: ViolationKind::Missing,
+            }]
+        );
+        assert_eq!(violations[0].to_string(), "api: SENTRY_DSN must be defined");
+        // パスが一致しないアーカイブには適用しない
+        assert!(policies
+            .check(Path::new("/work/web/.env"), "DATABASE_URL=x\n")
+            .is_empty());
+    }
+
+    #[test]
+    fn forbidのグロブに一致するキーがあれば違反になる() {
+        let policies = policies(
+            r#"
+            [[policies]]
+            paths = ["**"]
+            forbid = ["DEBUG_*"]
+            "#,
+        );
+        let violations = policies.check(
+            Path::new("/work/.env"),
+            "# DEBUG_SQL=1\nDEBUG_SQL=1\nDEBUGGER=1\n",
+        );
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "policies[0]: DEBUG_SQL must not be defined (forbidden by DEBUG_*)"
+        );
+    }
+
+    #[test]
+    fn formatsのパターンに値が一致しなければ違反になる() {
+        let policies = policies(
+            r#"
+            [[policies]]
+            name = "https"
+            paths = ["**"]
+            formats = [{ keys = ["*_URL"], pattern = "^https://" }]
+            "#,
+        );
+        let body = "API_URL=\"https://api.example.com\"\nWEB_URL=http://example.com\nWEB_URL='http://example.org'\nOTHER=http://x\n";
+        let violations = policies.check(Path::new("/work/.env"), body);
+        assert_eq!(
+            violations,
+            vec![Violation {
+                policy: "https".to_string(),
+                key: "WEB_URL".to_string(),
+                kind: ViolationKind::Format {
+                    pattern: "^https://".to_string(),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn 不正なポリシーはエラーになる() {
+        let error = |toml: &str| Policies::parse(toml).unwrap_err().to_string();
+        assert_eq!(
+            error("[[policies]]\nname = \"x\"\npaths = []"),
+            "policy x has no paths"
+        );
+        assert!(error(
+            "[[policies]]\npaths = [\"**\"]\nformats = [{ keys = [\"A\"], pattern = \"(\" }]"
+        )
+        .starts_with("policy policies[0] has an invalid pattern"));
+        assert!(error("[policy]\nenforce_on_push = \"always\"").contains("unknown variant"));
+
+        let empty = policies("");
+        assert!(empty.is_empty());
+        assert_eq!(empty.enforce_on_push, Enforcement::Off);
+    }
+}
@@ -0,0 +1,477 @@
+use crate::output::TimeFormat;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::io::IsTerminal as _;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// 時間のかかる操作の進捗を受け取る
+///
+/// CLI は受け取ったイベントを標準出力に表示し、GUI などから使う場合は独自の実装で受け取る。
+pub trait ProgressSink {
+    fn emit(&mut self, event: Event);
+}
+
+/// 進捗のイベント
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// crawl で .env ファイルの処理を始めた
+    CrawlFileStarted { path: PathBuf },
+    /// crawl で .env ファイルを処理した
+    /// name は登録したアーカイブの名前で、登録しなかった場合は None
+    CrawlFileResult {
+        path: PathBuf,
+        status: CrawlStatus,
+        name: Option<String>,
+    },
+    /// crawl で .env ファイルを読み込めなかった
+    /// 権限がないファイルやリンク切れのシンボリックリンクなど
+    CrawlFileFailed { path: PathBuf, message: String },
+    /// crawl で .env ファイルがポリシーに違反した
+    /// blocked の場合は登録しなかった
+    CrawlPolicyViolated {
+        path: PathBuf,
+        violations: Vec<String>,
+        blocked: bool,
+    },
+    /// crawl で読み取れずにスキップしたパス
+    CrawlPathSkipped {
+        path: PathBuf,
+        permission_denied: bool,
+        message: String,
+    },
+    /// crawl が終わった
+    /// dry_run の場合、pushed は登録するはずだった件数
+    CrawlFinished {
+        files: usize,
+        pushed: usize,
+        unchanged: usize,
+        too_large: usize,
+        empty: usize,
+        failed: usize,
+        dry_run: bool,
+        permission_denied: usize,
+    },
+    /// recover でアーカイブをファイルに復元しようとしている
+    RecoverPlanned {
+        name: String,
+        archive_path: String,
+        target_path: PathBuf,
+        target_exists: bool,
+        dry_run: bool,
+    },
+    /// recover で復元先のディレクトリを作成した
+    RecoverDirectoryCreated { path: PathBuf, dry_run: bool },
+    /// recover で復元先が既にアーカイブと同じ内容だったため書き込まなかった
+    RecoverUnchanged { target_path: PathBuf },
+    /// recover で上書きが了承されなかった
+    RecoverAborted { target_path: PathBuf },
+    /// recover で上書きする前の復元先をアーカイブに登録した
+    /// name はバックアップの名前で、--no-backup の場合は None
+    RecoverBackedUp {
+        target_path: PathBuf,
+        name: Option<String>,
+        dry_run: bool,
+    },
+    /// recover でアーカイブを復元先に書き込んだ
+    RecoverWritten {
+        target_path: PathBuf,
+        name: String,
+        dry_run: bool,
+    },
+    /// recover --only-keys で反映したキー
+    RecoverKeysMerged {
+        updated: Vec<String>,
+        appended: Vec<String>,
+        unchanged: Vec<String>,
+    },
+    /// verify --all で total 件のアーカイブの検証を始めた
+    VerifyStarted { total: u64 },
+    /// verify --all で total 件のうち checked 件のアーカイブを検証した
+    VerifyProgress { checked: u64, total: u64 },
+    /// verify で本文がチェックサムと一致しないアーカイブが見つかった
+    VerifyMismatch {
+        name: String,
+        path: String,
+        created_at: DateTime<Utc>,
+    },
+    /// verify --all でコールドストレージの本文を読み出せず検証できなかったアーカイブがあった
+    VerifyUnavailable {
+        name: String,
+        path: String,
+        created_at: DateTime<Utc>,
+    },
+    /// verify --all が終わった
+    VerifyFinished {
+        checked: u64,
+        corrupt: u64,
+        unavailable: u64,
+    },
+}
+
+/// crawl で .env ファイルを処理した結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlStatus {
+    /// 最新のアーカイブと同じ内容のため登録しなかった
+    Unchanged,
+    /// アーカイブに登録した
+    Pushed,
+    /// --dry-run のため登録しなかった
+    DryRun,
+    /// --max-size を超える大きさのため読み込まなかった
+    TooLarge,
+    /// 空のファイルのため登録しなかった
+    Empty,
+    /// アーカイブのデータベース自体のため登録しなかった
+    ArchiveDatabase,
+}
+
+/// crawl の結果の件数を要約する
+/// 大きすぎる、または空のためスキップしたファイルは 1 件以上ある場合だけ表示する
+pub fn crawl_summary(
+    dry_run: bool,
+    pushed: usize,
+    unchanged: usize,
+    too_large: usize,
+    empty: usize,
+    failed: usize,
+) -> String {
+    let skipped = |count: usize, reason: &str| {
+        if count > 0 {
+            format!(", skipped {} ({})", count, reason)
+        } else {
+            String::new()
+        }
+    };
+    format!(
+        "{} {}, skipped {} (unchanged){}{}, failed {}",
+        if dry_run { "would push" } else { "pushed" },
+        pushed,
+        unchanged,
+        skipped(too_large, "too large"),
+        skipped(empty, "empty"),
+        failed
+    )
+}
+
+/// 権限がなくスキップしたディレクトリの件数を要約する
+/// スキップしたものがなければ None を返す
+pub fn permission_summary(count: usize) -> Option<String> {
+    if count == 0 {
+        return None;
+    }
+    Some(format!(
+        "skipped {} subtree(s) due to insufficient permissions",
+        count
+    ))
+}
+
+/// verify --all の進捗を残り時間の見込みと共に 1 行で表す
+/// 端末では同じ行を書き換えて棒グラフにし、それ以外では 1 件ごとに行を改める
+pub fn verify_progress(checked: u64, total: u64, eta: Option<Duration>, bar: bool) -> String {
+    let eta = eta.map(|eta| format!(" ETA {}", format_eta(eta)));
+    if !bar {
+        return format!(
+            "checked {}/{} entries{}",
+            checked,
+            total,
+            eta.unwrap_or_default()
+        );
+    }
+    let filled = (checked * PROGRESS_BAR_WIDTH)
+        .checked_div(total)
+        .unwrap_or(PROGRESS_BAR_WIDTH)
+        .min(PROGRESS_BAR_WIDTH) as usize;
+    format!(
+        "[{}{}] {}/{}{}",
+        "#".repeat(filled),
+        ".".repeat(PROGRESS_BAR_WIDTH as usize - filled),
+        checked,
+        total,
+        eta.unwrap_or_default()
+    )
+}
+
+/// 進捗の棒グラフの長さ
+const PROGRESS_BAR_WIDTH: u64 = 30;
+
+/// elapsed かけて total 件のうち checked 件を処理した速さのまま、残りにかかる時間
+/// まだ 1 件も処理していなければ見込めないため None を返す
+pub fn estimate_remaining(elapsed: Duration, checked: u64, total: u64) -> Option<Duration> {
+    if checked == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(checked);
+    Some(elapsed.mul_f64(remaining as f64 / checked as f64))
+}
+
+/// 残り時間を `1h05m`, `3m07s`, `42s` のように表す
+pub fn format_eta(eta: Duration) -> String {
+    let seconds = eta.as_secs();
+    match seconds {
+        3600.. => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+        60.. => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}s", seconds),
+    }
+}
+
+/// イベントを標準出力に表示する
+/// 途中経過は結果と混ざらないよう標準エラー出力に表示する
+#[derive(Debug)]
+pub struct PrintProgress {
+    /// パスを伏せて表示する (--private)
+    private: bool,
+    /// 日時の表示の形式
+    time: TimeFormat,
+    /// 標準出力を JSON のレポートのために空けておき、verify の結果の行も標準エラー出力に表示する
+    results_on_stderr: bool,
+    /// verify --all を始めた時刻 残り時間の見込みに使う
+    verify_started: Option<Instant>,
+    /// 端末に進捗の棒グラフを表示したまま改行していない
+    bar_drawn: bool,
+}
+
+impl Default for PrintProgress {
+    fn default() -> Self {
+        Self::new(false, TimeFormat::Absolute(Tz::UTC))
+    }
+}
+
+impl PrintProgress {
+    pub fn new(private: bool, time: TimeFormat) -> Self {
+        Self {
+            private,
+            time,
+            results_on_stderr: false,
+            verify_started: None,
+            bar_drawn: false,
+        }
+    }
+
+    /// verify の結果の行を標準エラー出力に表示する
+    pub fn with_results_on_stderr(self, results_on_stderr: bool) -> Self {
+        Self {
+            results_on_stderr,
+            ..self
+        }
+    }
+
+    /// verify の結果の行を表示する
+    /// 進捗の棒グラフを表示していれば、その行を消してから表示する
+    fn print_result(&mut self, line: &str) {
+        if self.bar_drawn {
+            eprint!("\r\x1b[K");
+            self.bar_drawn = false;
+        }
+        if self.results_on_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+impl ProgressSink for PrintProgress {
+    fn emit(&mut self, event: Event) {
+        match event {
+            Event::CrawlFileStarted { .. } => {}
+            Event::CrawlFileResult { path, status, .. } => match status {
+                CrawlStatus::Unchanged => println!("[SKIP] {}", path.display()),
+                CrawlStatus::Pushed => println!("[PUSHED] {}", path.display()),
+                CrawlStatus::DryRun => println!("[PUSH DRY RUN] {}", path.display()),
+                CrawlStatus::TooLarge => println!("[SKIP too large] {}", path.display()),
+                CrawlStatus::Empty => println!("[SKIP empty] {}", path.display()),
+                CrawlStatus::ArchiveDatabase => {
+                    println!("[SKIP archive database] {}", path.display())
+                }
+            },
+            Event::CrawlFileFailed { path, message } => {
+                eprintln!("[ERROR] {}: {}", path.display(), message);
+            }
+            Event::CrawlPolicyViolated {
+                path,
+                violations,
+                blocked,
+            } => {
+                if blocked {
+                    println!("[BLOCKED] {}", path.display());
+                    for violation in violations {
+                        println!("[VIOLATION] {}", violation);
+                    }
+                } else {
+                    for violation in violations {
+                        eprintln!("warning: {}: {}", path.display(), violation);
+                    }
+                }
+            }
+            Event::CrawlPathSkipped {
+                path,
+                permission_denied,
+                message,
+            } => {
+                if permission_denied {
+                    println!("[PERMISSION DENIED] {}", path.display());
+                } else {
+                    println!("[WALK ERROR] {}", message);
+                }
+            }
+            Event::CrawlFinished {
+                pushed,
+                unchanged,
+                too_large,
+                empty,
+                failed,
+                dry_run,
+                permission_denied,
+                ..
+            } => {
+                println!(
+                    "{}",
+                    crawl_summary(dry_run, pushed, unchanged, too_large, empty, failed)
+                );
+                if let Some(summary) = permission_summary(permission_denied) {
+                    println!("{}", summary);
+                }
+            }
+            Event::RecoverPlanned {
+                archive_path,
+                target_path,
+                target_exists,
+                dry_run,
+                ..
+            } => {
+                if self.private {
+                    println!(
+                        "archive_path: {}\ntarget_path: {:?}",
+                        crate::redact::redact_path(&archive_path),
+                        crate::redact::redact_path(&target_path.to_string_lossy())
+                    );
+                } else {
+                    println!(
+                        "archive_path: {}\ntarget_path: {:?}",
+                        archive_path, target_path
+                    );
+                }
+                if dry_run {
+                    println!("target_exists: {}", target_exists);
+                }
+            }
+            Event::RecoverDirectoryCreated { path, dry_run } => {
+                if dry_run {
+                    println!("[MKDIR DRY RUN] {}", path.display());
+                }
+            }
+            Event::RecoverUnchanged { target_path } => {
+                println!("[SKIP] same checksum. {}", target_path.display());
+            }
+            Event::RecoverAborted { target_path } => {
+                println!("[ABORT] {}", target_path.display());
+            }
+            Event::RecoverBackedUp {
+                target_path,
+                name,
+                dry_run,
+            } => match name {
+                None => println!("[NO BACKUP] {}", target_path.display()),
+                Some(name) if dry_run => println!(
+                    "[BACKUP DRY RUN] {} with name {}",
+                    target_path.display(),
+                    name
+                ),
+                Some(name) => println!("[BACKUP] {} with name {}", target_path.display(), name),
+            },
+            Event::RecoverWritten {
+                target_path,
+                name,
+                dry_run,
+            } => {
+                if dry_run {
+                    println!("[RECOVER DRY RUN] {} from {}", target_path.display(), name);
+                } else {
+                    println!("[RECOVERED] {} from {}", target_path.display(), name);
+                }
+            }
+            Event::RecoverKeysMerged {
+                updated,
+                appended,
+                unchanged,
+            } => {
+                for key in updated {
+                    println!("[UPDATED] {}", key);
+                }
+                for key in appended {
+                    println!("[APPENDED] {}", key);
+                }
+                for key in unchanged {
+                    println!("[UNCHANGED] {}", key);
+                }
+            }
+            Event::VerifyStarted { .. } => self.verify_started = Some(Instant::now()),
+            Event::VerifyProgress { checked, total } => {
+                let eta = self
+                    .verify_started
+                    .and_then(|started| estimate_remaining(started.elapsed(), checked, total));
+                if std::io::stderr().is_terminal() {
+                    eprint!("\r{}", verify_progress(checked, total, eta, true));
+                    self.bar_drawn = true;
+                } else {
+                    eprintln!("{}", verify_progress(checked, total, eta, false));
+                }
+            }
+            Event::VerifyMismatch {
+                name,
+                path,
+                created_at,
+            } => {
+                let path = if self.private {
+                    crate::redact::redact_path(&path)
+                } else {
+                    path
+                };
+                let line = format!(
+                    "[CORRUPT] {} {:?} {}",
+                    name,
+                    path,
+                    self.time.format(created_at)
+                );
+                self.print_result(&line);
+            }
+            Event::VerifyUnavailable {
+                name,
+                path,
+                created_at,
+            } => {
+                let path = if self.private {
+                    crate::redact::redact_path(&path)
+                } else {
+                    path
+                };
+                let line = format!(
+                    "[UNAVAILABLE] {} {:?} {}",
+                    name,
+                    path,
+                    self.time.format(created_at)
+                );
+                self.print_result(&line);
+            }
+            Event::VerifyFinished {
+                checked,
+                corrupt,
+                unavailable,
+            } => {
+                let line = if unavailable > 0 {
+                    format!(
+                        "checked: {}, corrupt: {}, unavailable: {}",
+                        checked, corrupt, unavailable
+                    )
+                } else {
+                    format!("checked: {}, corrupt: {}", checked, corrupt)
+                };
+                self.print_result(&line);
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::archive::ArchiveEntry;
+use crate::dotenv::{self, Item};
+use std::path::{Component, Path};
+
+/// プライベートモードで出力の先頭に表示する見出し
+pub const PRIVATE_MODE_HEADER: &str = "# private mode: paths and values are redacted";
+
+/// 値を伏せるときに置き換える文字列
+pub const MASK: &str = "********";
+
+/// パスのうち末尾から何要素までを表示するか
+const VISIBLE_PATH_COMPONENTS: usize = 2;
+
+/// プライベートモードで表示するためにアーカイブの各項目を伏せる
+///
+/// 出力される項目を漏れなく扱うため、フィールドを追加した場合はここで
+/// 伏せ方を決めないとコンパイルが通らないように分解している
+pub fn redact_entry(entry: ArchiveEntry) -> ArchiveEntry {
+    let ArchiveEntry {
+        name,
+        path,
+        created_at,
+        checksum,
+        message,
+        user,
+        quarantined,
+        size,
+    } = entry;
+    // メッセージにはクライアント名など、パスと同じくプロジェクトを特定する情報が書かれることがあるため伏せる
+    // ユーザー名は共有しているホストのアカウント名で、プロジェクトを特定する情報ではないため伏せない
+    ArchiveEntry {
+        name,
+        path: redact_path(&path),
+        created_at,
+        checksum,
+        message: message.map(|_| MASK.to_string()),
+        user,
+        quarantined,
+        size,
+    }
+}
+
+/// パスの末尾 2 要素だけを残し、それより前を `…` に置き換える
+pub fn redact_path(path: &str) -> String {
+    let components = Path::new(path)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if components.len() <= VISIBLE_PATH_COMPONENTS {
+        return components.join("/");
+    }
+    let visible = &components[components.len() - VISIBLE_PATH_COMPONENTS..];
+    format!("…/{}", visible.join("/"))
+}
+
+/// .env ファイルの内容のうち値を伏せる
+/// キー名、コメント、空行、行の順序はそのまま残す
+/// 複数行にわたるクォートされた値は 1 行の `KEY=********` にまとめる
+pub fn redact_body(body: &str) -> String {
+    dotenv::parse(body)
+        .into_iter()
+        .map(|item| match item {
+            Item::Assignment { prefix, value, .. } => masked(&prefix, &value),
+            Item::Other(text) => {
+                let trimmed = text.trim_start();
+                if trimmed.starts_with('#') {
+                    return text;
+                }
+                // キーとして解釈できない行でも、値が漏れないよう `=` より後ろを伏せる
+                match text.split_once('=') {
+                    Some((key, value)) => masked(&format!("{}=", key), value),
+                    None => text,
+                }
+            }
+        })
+        .collect()
+}
+
+/// `=` までの部分 prefix に続けて伏せた値を置き、value の改行を残す
+fn masked(prefix: &str, value: &str) -> String {
+    let newline = if value.ends_with('\n') { "\n" } else { "" };
+    format!("{}{}{}", prefix, MASK, newline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn redact_entryはパスとメッセージを伏せる() {
+        let entry = ArchiveEntry {
+            name: "test-name".to_string(),
+            path: "/home/user/client-alpha/api/.env".to_string(),
+            created_at: Utc::now(),
+            checksum: "checksum".to_string(),
+            message: Some("before rotating DB password".to_string()),
+            user: None,
+            quarantined: false,
+            size: 7,
+        };
+        let redacted = redact_entry(entry.clone());
+        assert_eq!(
+            redacted,
+            ArchiveEntry {
+                path: "…/api/.env".to_string(),
+                message: Some(MASK.to_string()),
+                ..entry
+            }
+        );
+    }
+
+    #[test]
+    fn redact_pathは末尾2要素より前を伏せる() {
+        assert_eq!(
+            redact_path("/home/user/client-alpha/api/.env"),
+            "…/api/.env"
+        );
+        assert_eq!(redact_path("/api/.env"), "api/.env");
+        assert_eq!(redact_path(".env"), ".env");
+    }
+
+    #[test]
+    fn redact_bodyは値だけを伏せてコメントと行の順序を残す() {
+        let body =
+            "# comment\nexport FOO=bar\n\nBAZ=\"quoted value\"\nMULTI=\"line1\nline2\"\nLAST='x'\n";
+        assert_eq!(
+            redact_body(body),
+            "# comment\nexport FOO=********\n\nBAZ=********\nMULTI=********\nLAST=********\n"
+        );
+    }
+
+    #[test]
+    fn redact_bodyはエスケープしたクォートを含む複数行の値の続きを表示しない() {
+        let body =
+            "ESCAPED=\"say \\\"hi\\\"\nsecret line\"\nSINGLE='a\nb' \nnot a key=secret\nLAST=x";
+        assert_eq!(
+            redact_body(body),
+            "ESCAPED=********\nSINGLE=********\nnot a key=********\nLAST=********"
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::archive::{GrowthSlot, Summary};
+use crate::output::TimeFormat;
+use chrono::{Datelike, TimeZone};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+/// 集計の単位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Bucket {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// stats の出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatsFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+/// 集計単位ごとの増加量と累計
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GrowthRow {
+    pub bucket: String,
+    pub entries: u64,
+    pub bytes: u64,
+    pub total_entries: u64,
+    pub total_bytes: u64,
+}
+
+/// 15 分単位の集計を timezone における bucket の境界で再集計する
+pub fn bucket_growth(slots: &[GrowthSlot], bucket: Bucket, timezone: &Tz) -> Vec<GrowthRow> {
+    let mut rows: Vec<GrowthRow> = Vec::new();
+    let mut total_entries = 0;
+    let mut total_bytes = 0;
+    for slot in slots {
+        total_entries += slot.entries;
+        total_bytes += slot.bytes;
+        let label = bucket_label(&timezone.from_utc_datetime(&slot.start.naive_utc()), bucket);
+        match rows.last_mut() {
+            Some(row) if row.bucket == label => {
+                row.entries += slot.entries;
+                row.bytes += slot.bytes;
+                row.total_entries = total_entries;
+                row.total_bytes = total_bytes;
+            }
+            _ => rows.push(GrowthRow {
+                bucket: label,
+                entries: slot.entries,
+                bytes: slot.bytes,
+                total_entries,
+                total_bytes,
+            }),
+        }
+    }
+    rows
+}
+
+fn bucket_label(timestamp: &chrono::DateTime<Tz>, bucket: Bucket) -> String {
+    match bucket {
+        Bucket::Day => timestamp.format("%Y-%m-%d").to_string(),
+        Bucket::Week => {
+            let week = timestamp.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+        Bucket::Month => timestamp.format("%Y-%m").to_string(),
+        Bucket::Year => timestamp.format("%Y").to_string(),
+    }
+}
+
+/// 表形式で出力する
+pub fn render_table(rows: &[GrowthRow]) -> String {
+    let mut output = format!(
+        "{:<10} {:>8} {:>12} {:>14} {:>12}\n",
+        "bucket", "entries", "bytes", "total_entries", "total_bytes"
+    );
+    for row in rows {
+        output.push_str(&format!(
+            "{:<10} {:>8} {:>12} {:>14} {:>12}\n",
+            row.bucket, row.entries, row.bytes, row.total_entries, row.total_bytes
+        ));
+    }
+    output
+}
+
+/// CSV 形式で出力する
+pub fn render_csv(rows: &[GrowthRow]) -> String {
+    let mut output = String::from("bucket,entries,bytes,total_entries,total_bytes\n");
+    for row in rows {
+        output.push_str(&format!(
+            "{},{},{},{},{}\n",
+            row.bucket, row.entries, row.bytes, row.total_entries, row.total_bytes
+        ));
+    }
+    output
+}
+
+/// 概要で表示する、アーカイブの件数が多いパスの数
+pub const TOP_PATHS: usize = 10;
+
+/// アーカイブ全体の概要
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Overview {
+    #[serde(flatten)]
+    pub summary: Summary,
+    /// データベースファイルのバイト数
+    pub database_bytes: u64,
+}
+
+/// 概要を表形式で出力する
+pub fn render_overview(overview: &Overview, time: &TimeFormat) -> String {
+    let summary = &overview.summary;
+    let created_at = |timestamp: Option<chrono::DateTime<chrono::Utc>>| {
+        timestamp
+            .map(|timestamp| time.format(timestamp))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let mut output = format!(
+        "{:<15} {}\n{:<15} {}\n{:<15} {}\n{:<15} {}\n{:<15} {}\n{:<15} {}\n",
+        "entries:",
+        summary.entries,
+        "paths:",
+        summary.paths,
+        "bytes:",
+        summary.bytes,
+        "database bytes:",
+        overview.database_bytes,
+        "oldest:",
+        created_at(summary.oldest),
+        "newest:",
+        created_at(summary.newest),
+    );
+    if !summary.top_paths.is_empty() {
+        output.push_str("\ntop paths:\n");
+        for path in &summary.top_paths {
+            output.push_str(&format!("{:>8} {}\n", path.versions, path.path));
+        }
+    }
+    output
+}
+
+/// ASCII チャートの棒の最大の長さ
+const CHART_WIDTH: u64 = 40;
+
+/// 集計単位ごとの登録件数を ASCII の棒グラフで出力する
+pub fn render_chart(rows: &[GrowthRow]) -> String {
+    let max = rows.iter().map(|row| row.entries).max().unwrap_or(0);
+    let mut output = String::new();
+    for row in rows {
+        let len = if max == 0 {
+            0
+        } else {
+            (row.entries * CHART_WIDTH).div_ceil(max)
+        };
+        output.push_str(&format!(
+            "{:<10} {} {}\n",
+            row.bucket,
+            "#".repeat(len as usize),
+            row.entries
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn slot(timestamp: (i32, u32, u32, u32, u32), entries: u64, bytes: u64) -> GrowthSlot {
+        let (y, m, d, h, min) = timestamp;
+        GrowthSlot {
+            start: Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap(),
+            entries,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn 月の境界は表示タイムゾーンに揃えて集計される() {
+        let slots = [
+            slot((2023, 12, 10, 0, 0), 1, 10),
+            // 東京では 2024-01-31 23:45
+            slot((2024, 1, 31, 14, 45), 2, 20),
+            // 東京では 2024-02-01 00:00
+            slot((2024, 1, 31, 15, 0), 3, 30),
+            slot((2024, 3, 5, 0, 0), 4, 40),
+        ];
+
+        let rows = bucket_growth(&slots, Bucket::Month, &chrono_tz::Asia::Tokyo);
+        let summary = rows
+            .iter()
+            .map(|row| (row.bucket.as_str(), row.entries, row.total_entries))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("2023-12", 1, 1),
+                ("2024-01", 2, 3),
+                ("2024-02", 3, 6),
+                ("2024-03", 4, 10)
+            ]
+        );
+
+        let rows = bucket_growth(&slots, Bucket::Month, &Tz::UTC);
+        let summary = rows
+            .iter()
+            .map(|row| (row.bucket.as_str(), row.bytes, row.total_bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("2023-12", 10, 10),
+                ("2024-01", 50, 60),
+                ("2024-03", 40, 100)
+            ]
+        );
+    }
+
+    #[test]
+    fn 週と年で集計できる() {
+        let slots = [
+            slot((2024, 1, 1, 0, 0), 1, 10),
+            slot((2024, 1, 7, 0, 0), 1, 10),
+            slot((2024, 1, 8, 0, 0), 1, 10),
+        ];
+        let weeks = bucket_growth(&slots, Bucket::Week, &Tz::UTC)
+            .into_iter()
+            .map(|row| row.bucket)
+            .collect::<Vec<_>>();
+        assert_eq!(weeks, vec!["2024-W01", "2024-W02"]);
+
+        let years = bucket_growth(&slots, Bucket::Year, &Tz::UTC);
+        assert_eq!(years.len(), 1);
+        assert_eq!(years[0].entries, 3);
+    }
+
+    #[test]
+    fn 概要を表形式で出力できる() {
+        let overview = Overview {
+            summary: Summary {
+                entries: 3,
+                paths: 2,
+                bytes: 30,
+                oldest: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+                newest: Some(Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap()),
+                top_paths: vec![
+                    crate::archive::PathVersions {
+                        path: "/app/.env".to_string(),
+                        versions: 2,
+                    },
+                    crate::archive::PathVersions {
+                        path: "/web/.env".to_string(),
+                        versions: 1,
+                    },
+                ],
+            },
+            database_bytes: 8192,
+        };
+        assert_eq!(
+            render_overview(&overview, &TimeFormat::Absolute(chrono_tz::Asia::Tokyo)),
+            "entries:        3\n\
+             paths:          2\n\
+             bytes:          30\n\
+             database bytes: 8192\n\
+             oldest:         2024-01-01 09:00:00 JST\n\
+             newest:         2024-02-01 09:00:00 JST\n\
+             \n\
+             top paths:\n       \
+             2 /app/.env\n       \
+             1 /web/.env\n"
+        );
+
+        let json = serde_json::to_value(&overview).unwrap();
+        assert_eq!(json["entries"], 3);
+        assert_eq!(json["database_bytes"], 8192);
+        assert_eq!(json["oldest"], "2024-01-01T00:00:00Z");
+        assert_eq!(json["top_paths"][0]["versions"], 2);
+
+        let empty = Overview {
+            summary: Summary {
+                entries: 0,
+                paths: 0,
+                bytes: 0,
+                oldest: None,
+                newest: None,
+                top_paths: Vec::new(),
+            },
+            database_bytes: 0,
+        };
+        assert!(render_overview(&empty, &TimeFormat::Utc).ends_with("newest:         -\n"));
+    }
+
+    #[test]
+    fn csvとチャートで出力できる() {
+        let rows = bucket_growth(
+            &[
+                slot((2024, 1, 1, 0, 0), 1, 10),
+                slot((2024, 2, 1, 0, 0), 2, 20),
+            ],
+            Bucket::Month,
+            &Tz::UTC,
+        );
+        assert_eq!(
+            render_csv(&rows),
+            "bucket,entries,bytes,total_entries,total_bytes\n2024-01,1,10,1,10\n2024-02,2,20,3,30\n"
+        );
+        assert_eq!(
+            render_chart(&rows),
+            format!(
+                "2024-01    {} 1\n2024-02    {} 2\n",
+                "#".repeat(20),
+                "#".repeat(40)
+            )
+        );
+    }
+}
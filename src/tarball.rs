@@ -0,0 +1,123 @@
+use crate::archive::ArchiveEntry;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// tar に含めるマニフェストのファイル名
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// tar に書き出すファイルのパーミッション
+/// .env ファイルは秘密の値を含むため所有者だけが読み書きできるようにする
+const FILE_MODE: u32 = 0o600;
+
+/// tar に含めたファイルごとのアーカイブの情報
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// tar の中でのパス
+    pub path: String,
+    pub name: String,
+    pub checksum: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// アーカイブを base からの相対パスに置いた gzip 圧縮の tar を書き出し、書き出したファイルの情報を返す
+///
+/// base の配下にないアーカイブは含めない
+pub fn write<W: Write>(
+    writer: W,
+    base: &Path,
+    archives: &[(ArchiveEntry, String)],
+) -> anyhow::Result<Vec<ManifestFile>> {
+    let encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut manifest = Vec::new();
+    for (entry, body) in archives {
+        let Ok(relative) = Path::new(&entry.path).strip_prefix(base) else {
+            continue;
+        };
+        append(&mut builder, relative, body.as_bytes(), entry.created_at)?;
+        manifest.push(ManifestFile {
+            path: relative.to_string_lossy().to_string(),
+            name: entry.name.clone(),
+            checksum: entry.checksum.clone(),
+            created_at: entry.created_at,
+        });
+    }
+    let json = serde_json::to_vec_pretty(&manifest)?;
+    append(&mut builder, Path::new(MANIFEST_NAME), &json, Utc::now())?;
+    builder.into_inner()?.finish()?.flush()?;
+    Ok(manifest)
+}
+
+fn append<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    data: &[u8],
+    modified_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(FILE_MODE);
+    header.set_mtime(modified_at.timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, PathBuf::from(path), data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    fn entry(name: &str, path: &str, body: &str) -> (ArchiveEntry, String) {
+        (
+            ArchiveEntry {
+                name: name.to_string(),
+                path: path.to_string(),
+                created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+                checksum: crate::digest::bytes_checksum(body.as_bytes()),
+                message: None,
+                user: None,
+                quarantined: false,
+                size: body.len() as u64,
+            },
+            body.to_string(),
+        )
+    }
+
+    #[test]
+    fn writeしたtarを展開するとbaseからの相対パスにファイルとマニフェストが入っている() {
+        let archives = vec![
+            entry("api", "/home/user/project/api/.env", "API=1\n"),
+            entry("web", "/home/user/project/web/.env.local", "WEB=1\n"),
+            entry("other", "/home/user/project-other/.env", "OTHER=1\n"),
+        ];
+        let mut gzip = Vec::new();
+        let manifest = write(&mut gzip, Path::new("/home/user/project"), &archives).unwrap();
+        assert_eq!(manifest.len(), 2);
+
+        let mut files = HashMap::new();
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(gzip.as_slice()));
+        for file in tar.entries().unwrap() {
+            let mut file = file.unwrap();
+            assert_eq!(file.header().mode().unwrap(), FILE_MODE);
+            let path = file.path().unwrap().to_string_lossy().to_string();
+            let mut content = String::new();
+            file.read_to_string(&mut content).unwrap();
+            files.insert(path, content);
+        }
+
+        assert_eq!(files.len(), 3);
+        assert_eq!(files["api/.env"], "API=1\n");
+        assert_eq!(files["web/.env.local"], "WEB=1\n");
+        let read: Vec<ManifestFile> = serde_json::from_str(&files[MANIFEST_NAME]).unwrap();
+        assert_eq!(read, manifest);
+        assert_eq!(read[0].path, "api/.env");
+        assert_eq!(read[0].name, "api");
+        assert_eq!(read[0].checksum, archives[0].0.checksum);
+        assert_eq!(read[1].created_at, archives[1].0.created_at);
+    }
+}
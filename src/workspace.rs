@@ -0,0 +1,185 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+/// bootstrap で読み込むワークスペースのマニフェスト
+///
+/// ```toml
+/// [[projects]]
+/// dir = "~/src/api"
+/// git = "git@github.com:me/api.git"
+/// files = [".env", { path = "web/.env.local", name = "web-prod" }]
+/// ```
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub projects: Vec<Project>,
+}
+
+/// マニフェストに書かれたプロジェクト
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    /// プロジェクトのディレクトリ
+    /// 相対パスはマニフェストのあるディレクトリを基準にし、`~/` はホームディレクトリにする
+    pub dir: PathBuf,
+    /// ディレクトリがない場合に clone するリポジトリの URL
+    pub git: Option<String>,
+    /// 復元する .env ファイル
+    /// 省略した場合はディレクトリ配下のパスごとに最新のアーカイブをすべて復元する
+    #[serde(default)]
+    pub files: Vec<FileSpec>,
+}
+
+/// 復元する .env ファイル
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum FileSpec {
+    /// プロジェクトのディレクトリからの相対パス
+    /// そのパスの最新のアーカイブを復元する
+    Path(PathBuf),
+    /// name のアーカイブを、プロジェクトのディレクトリからの相対パス path に復元する
+    Named { path: PathBuf, name: String },
+}
+
+impl FileSpec {
+    pub fn path(&self) -> &Path {
+        match self {
+            FileSpec::Path(path) | FileSpec::Named { path, .. } => path,
+        }
+    }
+}
+
+impl Manifest {
+    /// TOML のマニフェストを読み込んで検証し、プロジェクトのディレクトリを絶対パスにする
+    /// base はマニフェストのあるディレクトリ
+    pub fn parse(toml: &str, base: &Path, home: Option<&Path>) -> anyhow::Result<Self> {
+        let mut manifest: Manifest = toml::from_str(toml)?;
+        for project in &mut manifest.projects {
+            project.dir = resolve_dir(&project.dir, base, home)?;
+        }
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.projects.is_empty() {
+            anyhow::bail!("manifest has no projects");
+        }
+        let mut dirs = HashSet::new();
+        for project in &self.projects {
+            if !dirs.insert(&project.dir) {
+                anyhow::bail!("project {} is listed twice", project.dir.display());
+            }
+            if project.git.as_deref().is_some_and(str::is_empty) {
+                anyhow::bail!("project {} has an empty git URL", project.dir.display());
+            }
+            let mut paths = HashSet::new();
+            for file in &project.files {
+                let path = file.path();
+                let inside = path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)));
+                if path.as_os_str().is_empty() || !inside {
+                    anyhow::bail!(
+                        "file {} of project {} must be a relative path inside the project",
+                        path.display(),
+                        project.dir.display()
+                    );
+                }
+                if !paths.insert(path) {
+                    anyhow::bail!(
+                        "file {} of project {} is listed twice",
+                        path.display(),
+                        project.dir.display()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn resolve_dir(dir: &Path, base: &Path, home: Option<&Path>) -> anyhow::Result<PathBuf> {
+    let dir = match dir.strip_prefix("~") {
+        Ok(rest) => home
+            .ok_or_else(|| anyhow::anyhow!("failed to get home directory for {}", dir.display()))?
+            .join(rest),
+        Err(_) => dir.to_path_buf(),
+    };
+    Ok(crate::helper::normalize_path(&dir, base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn マニフェストのディレクトリを絶対パスにしファイルの指定を読み込む() {
+        let toml = r#"
+            [[projects]]
+            dir = "api"
+            git = "git@example.com:me/api.git"
+            files = [".env", { path = "web/.env.local", name = "web-prod" }]
+
+            [[projects]]
+            dir = "~/src/tools"
+        "#;
+        let manifest =
+            Manifest::parse(toml, Path::new("/work"), Some(Path::new("/home/me"))).unwrap();
+        assert_eq!(
+            manifest,
+            Manifest {
+                projects: vec![
+                    Project {
+                        dir: PathBuf::from("/work/api"),
+                        git: Some("git@example.com:me/api.git".to_string()),
+                        files: vec![
+                            FileSpec::Path(PathBuf::from(".env")),
+                            FileSpec::Named {
+                                path: PathBuf::from("web/.env.local"),
+                                name: "web-prod".to_string(),
+                            },
+                        ],
+                    },
+                    Project {
+                        dir: PathBuf::from("/home/me/src/tools"),
+                        git: None,
+                        files: Vec::new(),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn 不正なマニフェストはエラーになる() {
+        let parse = |toml: &str| {
+            Manifest::parse(toml, Path::new("/work"), None)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(parse("projects = []"), "manifest has no projects");
+        assert_eq!(
+            parse("[[projects]]\ndir = \"a\"\n[[projects]]\ndir = \"./a\""),
+            "project /work/a is listed twice"
+        );
+        assert_eq!(
+            parse("[[projects]]\ndir = \"a\"\nfiles = [\"../.env\"]"),
+            "file ../.env of project /work/a must be a relative path inside the project"
+        );
+        assert_eq!(
+            parse("[[projects]]\ndir = \"a\"\nfiles = [\"/etc/.env\"]"),
+            "file /etc/.env of project /work/a must be a relative path inside the project"
+        );
+        assert_eq!(
+            parse("[[projects]]\ndir = \"a\"\nfiles = [\".env\", \".env\"]"),
+            "file .env of project /work/a is listed twice"
+        );
+        assert_eq!(
+            parse("[[projects]]\ndir = \"~/a\""),
+            "failed to get home directory for ~/a"
+        );
+        assert!(parse("[[projects]]\ndir = \"a\"\nrepo = \"x\"").contains("unknown field"));
+    }
+}
@@ -0,0 +1,104 @@
+//! 1 つのデータベースを複数のユーザーで共有した場合に、一覧や検索のコマンドが
+//! --user, --all-users, --visibility に従って他のユーザーのアーカイブを表示するかを確かめる
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 一時ディレクトリの中だけを使い、利用者の設定や環境変数の影響を受けずにコマンドを実行する
+struct Cli {
+    dir: tempfile::TempDir,
+}
+
+impl Cli {
+    fn new() -> Self {
+        let cli = Self {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        cli.run("alice", &["init"]);
+        cli
+    }
+
+    fn database(&self) -> PathBuf {
+        self.dir.path().join("archive.db")
+    }
+
+    /// user として args を実行し、標準出力を返す
+    fn run(&self, user: &str, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_dot-env-archive"))
+            .arg("--database")
+            .arg(self.database())
+            .args(args)
+            .current_dir(self.dir.path())
+            .env("HOME", self.dir.path())
+            .env("XDG_CONFIG_HOME", self.dir.path().join("config"))
+            .env("XDG_DATA_HOME", self.dir.path().join("data"))
+            .env("USER", user)
+            .env_remove("ENV_ARCHIVE_DATABASE")
+            .env_remove("ENV_ARCHIVE_VISIBILITY")
+            .env_remove("ENV_ARCHIVE_POLICIES")
+            .env_remove("ENV_ARCHIVE_TZ")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// user として path に body を書き込み、name で登録する
+    fn push(&self, user: &str, path: &Path, body: &str, name: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, body).unwrap();
+        self.run(user, &["push", "--name", name, &path.to_string_lossy()]);
+    }
+}
+
+/// 出力に現れたアーカイブの名前
+fn names(output: &str) -> Vec<&'static str> {
+    ["alice-env", "bob-env"]
+        .into_iter()
+        .filter(|name| output.contains(name))
+        .collect()
+}
+
+#[test]
+fn 一覧と検索のコマンドは既定で自分のアーカイブだけを表示する() {
+    let cli = Cli::new();
+    let env_file_path = cli.dir.path().join("project/.env");
+    cli.push("alice", &env_file_path, "SHARED_KEY=alice\n", "alice-env");
+    cli.push("bob", &env_file_path, "SHARED_KEY=bob\n", "bob-env");
+    let env_file_path = env_file_path.to_string_lossy().to_string();
+
+    // 一覧と検索のコマンドと、どちらのユーザーのアーカイブも表示する場合に表示されるもの
+    // list と latest はパスごとに最新のアーカイブだけを表示するため、後から登録した bob のものだけになる
+    let commands: [(&[&str], &[&str]); 8] = [
+        (&["list"], &["bob-env"]),
+        (&["list-all"], &["alice-env", "bob-env"]),
+        (&["search", "project"], &["alice-env", "bob-env"]),
+        (&["latest"], &["bob-env"]),
+        (&["history", &env_file_path], &["alice-env", "bob-env"]),
+        (&["export", "--output", "-"], &["alice-env", "bob-env"]),
+        (&["grep", "SHARED_KEY"], &["alice-env", "bob-env"]),
+        (&["which-key", "SHARED_KEY"], &["alice-env", "bob-env"]),
+    ];
+    for (command, all) in commands {
+        let run = |extra: &[&str]| {
+            let args = [command, extra].concat();
+            names(&cli.run("alice", &args))
+        };
+        assert_eq!(run(&[]), ["alice-env"], "{:?}", command);
+        assert_eq!(run(&["--all-users"]), all, "{:?}", command);
+        assert_eq!(run(&["--visibility", "all"]), all, "{:?}", command);
+        assert_eq!(run(&["--user", "bob"]), ["bob-env"], "{:?}", command);
+        // --user は --visibility all より優先する
+        assert_eq!(
+            run(&["--visibility", "all", "--user", "alice"]),
+            ["alice-env"],
+            "{:?}",
+            command
+        );
+    }
+}